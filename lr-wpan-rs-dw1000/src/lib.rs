@@ -1,17 +1,21 @@
 #![no_std]
 
-use core::fmt::{Debug, Display};
+use core::{
+    fmt::{Debug, Display},
+    num::NonZeroU32,
+};
 
 pub use dw1000;
 use dw1000::{
-    AutoDoubleBufferReceiving, Ready, RxConfig, TxConfig, configs::PulseRepetitionFrequency,
+    AutoDoubleBufferReceiving, Ready, RxConfig, Sending, TxConfig,
+    configs::{BitRate, PreambleLength, PulseRepetitionFrequency, SfdSequence},
 };
 use embassy_futures::select::{Either, select};
 use embedded_hal::{delay::DelayNs as DelayNsSync, digital::ErrorType, spi::SpiDevice};
 use embedded_hal_async::{delay::DelayNs, digital::Wait};
 use lr_wpan_rs::{
     ChannelPage,
-    phy::{ModulationType, Phy, ReceivedMessage, SendContinuation},
+    phy::{FrameFilter, ModulationType, Phy, ReceivedMessage, SendContinuation, SendResult},
     pib::{
         CcaMode, ChannelDescription, NativePrf, PhyPib, PhyPibWrite, TXPowerTolerance,
         UwbCurrentPulseShape,
@@ -21,17 +25,62 @@ use lr_wpan_rs::{
 #[allow(unused_imports)]
 use micromath::F32Ext;
 
-const TIME_CHECK_INTERVAL_MILLIS: u32 = 5000;
+/// The default interval of the time checks that keep track of the wraparound of the DW1000 clock,
+/// see [DW1000Phy::new]
+pub const DEFAULT_TIME_CHECK_INTERVAL_MILLIS: NonZeroU32 = NonZeroU32::new(5000).unwrap();
 const TIME_CHECK_MILLIS_PER_DELAY: u32 = 100;
+/// The default number of spurious interrupts in a row after which the interrupts are reset
+const DEFAULT_SPURIOUS_IRQ_THRESHOLD: u32 = 10;
+/// How long to back off before resetting the interrupts
+const SPURIOUS_IRQ_BACKOFF_MILLIS: u32 = 10;
+/// How long the DW1000 needs to get from SLEEP back to IDLE, which is mostly the crystal starting up
+/// and the configuration being restored (section 2.4 of the datasheet)
+const WAKE_UP_MILLIS: u32 = 5;
+/// How often waking up is tried before giving up, like `dwt_spicswakeup` of the Decawave driver does
+const WAKE_UP_ATTEMPTS: u32 = 3;
+/// The time it takes to turn the receiver on over SPI after a transmission
+const TURNAROUND_MICROS: u32 = 50;
+/// How far ahead a delayed send must at least be scheduled, see [Error::TimeTooCloseInFuture]
+const MIN_DELAYED_SEND_AHEAD: Duration = Duration::from_millis(10);
+/// The bits of the time of a delayed send the DW1000 ignores (the low 9 bits of DX_TIME, register 0x0A)
+const DELAYED_SEND_IGNORED_BITS: u64 = 0x1ff;
 
 const UWB_CHANNEL_PAGE: ChannelPage = ChannelPage::Uwb;
-
+/// The number of ticks of the [time](lr_wpan_rs::time) in a chip of the 499.2 MHz chipping rate
+const TICKS_PER_CHIP: i64 = 128;
+/// The bitrate whose data symbol is the symbol the MAC counts in, which is the mandatory one of the UWB PHY
+const MAC_SYMBOL_BITRATE: BitRate = BitRate::Kbps850;
+/// The bitrate the symbols per octet are given for
+const SYMBOLS_PER_OCTET_BITRATE: f32 = 850_000.0;
+/// The number of MAC symbols an octet takes at 850 kb/s, which is `8 bits / 850 kb/s / Tdsym`
+const SYMBOLS_PER_OCTET: f32 = 8.0 * lr_wpan_rs::time::TICKS_PER_SECOND as f32
+    / SYMBOLS_PER_OCTET_BITRATE
+    / data_symbol_duration(MAC_SYMBOL_BITRATE).ticks() as f32;
+
+/// The preamble length after a reset, see [DW1000Phy::set_preamble]
+const DEFAULT_PREAMBLE_LENGTH: PreambleLength = PreambleLength::Symbols1024;
+/// The SFD sequence after a reset, see [DW1000Phy::set_preamble]
+const DEFAULT_SFD_SEQUENCE: SfdSequence = SfdSequence::IEEE;
+/// The bitrate after a reset, see [DW1000Phy::set_data_rate]
+const DEFAULT_BITRATE: BitRate = BitRate::Kbps850;
+
+/// A [Phy] for the DW1000 UWB transceiver.
+///
+/// The hardware FCS is turned off, so frames are sent exactly as the MAC gives them and received
+/// frames are passed on with their last two octets. To work with devices that expect an FCS,
+/// let the MAC calculate and check it with [MacConfig::mac_fcs](lr_wpan_rs::mac::MacConfig::mac_fcs).
+///
+/// To wake up from [Phy::sleep], the SPI clock must be at most 2 MHz, see [Phy::wake].
 pub struct DW1000Phy<SPI: SpiDevice, IRQ: Wait, DELAY: DelayNs> {
     dw1000: DW1000<SPI>,
     irq: IRQ,
     delay: DELAY,
     last_instant: u64,
+    time_check_interval_millis: Option<NonZeroU32>,
     millis_until_next_time_check: u32,
+    spurious_irqs: SpuriousIrqCounter,
+    receive_overruns: u32,
+    sleeping: bool,
 
     current_tx_config: TxConfig,
     current_rx_config: RxConfig,
@@ -39,35 +88,68 @@ pub struct DW1000Phy<SPI: SpiDevice, IRQ: Wait, DELAY: DelayNs> {
 }
 
 impl<SPI: SpiDevice, IRQ: Wait, DELAY: DelayNs> DW1000Phy<SPI, IRQ, DELAY> {
-    pub async fn new(spi: SPI, irq: IRQ, mut delay: DELAY) -> Result<Self, Error<SPI, IRQ>>
+    /// Initialize the DW1000 and create the phy for it.
+    ///
+    /// The `time_check_interval_millis` sets how often [Phy::wait] wakes up to check the time when nothing
+    /// else happens. [None] disables the checks. The clock of the DW1000 wraps around about every 17 seconds.
+    /// Every check reads it, so the wraparounds can be counted. Use [DEFAULT_TIME_CHECK_INTERVAL_MILLIS] when
+    /// in doubt. Only disable the checks or make them less frequent when something else makes sure
+    /// [Phy::get_instant] is called more often than the clock wraps around, like the MAC running with
+    /// periodic beacons. Without the checks, [Phy::wait] only completes on an interrupt, which saves power.
+    pub async fn new(
+        spi: SPI,
+        irq: IRQ,
+        mut delay: DELAY,
+        time_check_interval_millis: Option<NonZeroU32>,
+    ) -> Result<Self, Error<SPI, IRQ>>
     where
         DELAY: DelayNsSync,
     {
         let dw1000 = dw1000::DW1000::new(spi).init(&mut delay)?;
 
-        Self::new_from_existing(dw1000, irq, delay).await
+        Self::new_from_existing(dw1000, irq, delay, time_check_interval_millis).await
     }
 
+    /// Create the phy for a DW1000 that has already been initialized, see [Self::new]
     pub async fn new_from_existing(
         dw1000: dw1000::DW1000<SPI, Ready>,
         irq: IRQ,
         delay: DELAY,
+        time_check_interval_millis: Option<NonZeroU32>,
     ) -> Result<Self, Error<SPI, IRQ>> {
-        let mut s = Self {
-            dw1000: DW1000::Ready(dw1000),
+        let mut s = Self::from_parts(
+            DW1000::Ready(dw1000),
+            irq,
+            delay,
+            time_check_interval_millis,
+        );
+
+        s.reset().await?;
+
+        Ok(s)
+    }
+
+    fn from_parts(
+        dw1000: DW1000<SPI>,
+        irq: IRQ,
+        delay: DELAY,
+        time_check_interval_millis: Option<NonZeroU32>,
+    ) -> Self {
+        Self {
+            dw1000,
             irq,
             delay,
             last_instant: 0,
-            millis_until_next_time_check: TIME_CHECK_INTERVAL_MILLIS,
+            time_check_interval_millis,
+            millis_until_next_time_check: time_check_interval_millis.map_or(0, NonZeroU32::get),
+            spurious_irqs: SpuriousIrqCounter::new(DEFAULT_SPURIOUS_IRQ_THRESHOLD),
+            receive_overruns: 0,
+            sleeping: false,
 
             current_tx_config: TxConfig::default(),
             current_rx_config: RxConfig::default(),
             phy_pib: PhyPib::unspecified_new(), // TODO: Init with capabilities of this chip
-        };
-
-        s.reset().await?;
-
-        Ok(s)
+        }
     }
 
     async fn convert_to_mac_time(
@@ -88,6 +170,355 @@ impl<SPI: SpiDevice, IRQ: Wait, DELAY: DelayNs> DW1000Phy<SPI, IRQ, DELAY> {
 
         Ok(Instant::from_ticks(mac_time))
     }
+
+    /// Set the preamble length and SFD sequence used for sending and receiving.
+    ///
+    /// A longer preamble gives more range, but makes every frame take longer to send.
+    /// The SHR duration and max frame duration in the phy pib are updated to match,
+    /// so the timings the MAC derives from them (like the ack wait duration) follow along.
+    pub async fn set_preamble(
+        &mut self,
+        preamble_length: PreambleLength,
+        sfd_sequence: SfdSequence,
+    ) -> Result<(), Error<SPI, IRQ>> {
+        // The receive config is only applied when starting to receive
+        let was_receiving = matches!(self.dw1000, DW1000::Receiving(_));
+        self.stop_receive().await?;
+
+        self.current_tx_config.preamble_length = preamble_length;
+        self.current_tx_config.sfd_sequence = sfd_sequence;
+        self.current_rx_config.expected_preamble_length = preamble_length;
+        self.current_rx_config.sfd_sequence = sfd_sequence;
+
+        self.update_timings();
+
+        if was_receiving {
+            self.start_receive().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Set the data rate used for sending and receiving, encoded like the `data_rate` of [Phy::send_with_data_rate].
+    ///
+    /// The DW1000 supports 0.11 (1), 0.85 (2) and 6.81 (3) Mb/s. Devices only hear each other when they use the
+    /// same data rate. To send just a single frame at another rate, use [Phy::send_with_data_rate].
+    /// The timings in the phy pib are updated to match, like with [Self::set_preamble].
+    pub async fn set_data_rate(&mut self, data_rate: u8) -> Result<(), Error<SPI, IRQ>> {
+        let bitrate = bitrate_of(data_rate).ok_or(Error::UnsupportedDataRate)?;
+
+        // The receive config is only applied when starting to receive
+        let was_receiving = matches!(self.dw1000, DW1000::Receiving(_));
+        self.stop_receive().await?;
+
+        self.current_tx_config.bitrate = bitrate;
+        self.current_rx_config.bitrate = bitrate;
+        self.update_timings();
+
+        if was_receiving {
+            self.start_receive().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Update the timings in the phy pib to the current tx config
+    fn update_timings(&mut self) {
+        let ShrTimings {
+            shr_duration,
+            max_frame_duration,
+            symbols_per_octet,
+        } = ShrTimings::new(
+            self.current_tx_config.preamble_length,
+            self.current_tx_config.sfd_sequence,
+            self.current_tx_config.bitrate,
+        );
+        self.phy_pib.shr_duration = shr_duration;
+        self.phy_pib.max_frame_duration = max_frame_duration;
+        self.phy_pib.symbols_per_octet = symbols_per_octet;
+    }
+
+    /// Measure the temperature and supply voltage of the DW1000 with its onboard sensors.
+    ///
+    /// This is useful for e.g. compensating the antenna delay over temperature.
+    /// The receiver is stopped for the measurement and started again if it was running.
+    /// See [Diagnostics] for how the readings are converted.
+    ///
+    /// The measurement follows section 6.4 of the DW1000 user manual:
+    /// - Enable the sensors by writing 0x80 to 0x28:0x11, then 0x0A and 0x0F to 0x28:0x12
+    /// - Start a sample by setting `SAR_CTRL` in `TC_SARC` (0x2A:0x00) from 0 to 1
+    /// - Read the voltage (`SAR_LVBAT`) and temperature (`SAR_LTEMP`) from `TC_SARL` (0x2A:0x03)
+    /// - Clear `SAR_CTRL` again
+    /// - Read the calibration values measured in production from the OTP memory
+    pub async fn read_diagnostics(&mut self) -> Result<Diagnostics, Error<SPI, IRQ>> {
+        let was_receiving = matches!(self.dw1000, DW1000::Receiving(_));
+        self.stop_receive().await?;
+
+        let dw1000 = self.dw1000.as_ready_mut().ok_or(Error::WrongState)?;
+        let diagnostics = measure_sensors(dw1000.ll()).map_err(dw1000::Error::from)?;
+
+        if was_receiving {
+            self.start_receive().await?;
+        }
+
+        Ok(diagnostics)
+    }
+
+    /// Let the DW1000 transmit a continuous wave on the channel, e.g. for regulatory testing
+    /// or measuring the frequency offset of the crystal.
+    ///
+    /// This takes the radio out of normal operation. Nothing can be sent or received until
+    /// the wave is stopped with [Self::stop_continuous_wave] and the phy is reset with [Phy::reset].
+    ///
+    /// The configuration follows `dwt_configcwmode` of the Decawave driver:
+    /// - Disable the transmit and receive sequencing by forcing the system clock to the crystal
+    /// - Set up the PLL (`FS_PLLCFG` and `FS_PLLTUNE`) and the transmitter (`RF_TXCTRL`) for the channel
+    /// - Power up the PLL and the transmit blocks through `RF_CONF`
+    /// - Switch the system and transmit clocks to the PLL
+    /// - Disable the fine grain transmit sequencing (`PMSC_TXFSEQ`)
+    /// - Enable the continuous wave test mode by writing 0x13 to `TC_PGTEST` (0x2A:0x0D)
+    pub async fn transmit_continuous_wave(&mut self, channel: u8) -> Result<(), Error<SPI, IRQ>> {
+        let channel: dw1000::configs::UwbChannel = channel
+            .try_into()
+            .map_err(|_| Error::UnsupportedChannelNumber)?;
+
+        self.stop_receive().await?;
+
+        let dw1000 = self.dw1000.as_ready_mut().ok_or(Error::WrongState)?;
+        start_continuous_wave(dw1000.ll(), channel).map_err(dw1000::Error::from)?;
+
+        Ok(())
+    }
+
+    /// Stop the continuous wave started with [Self::transmit_continuous_wave].
+    ///
+    /// The clocks and the transmitter are put back in automatic mode, but the radio must still be reset
+    /// with [Phy::reset] before it can be used normally again.
+    pub async fn stop_continuous_wave(&mut self) -> Result<(), Error<SPI, IRQ>> {
+        let dw1000 = self.dw1000.as_ready_mut().ok_or(Error::WrongState)?;
+        stop_continuous_wave(dw1000.ll()).map_err(dw1000::Error::from)?;
+
+        Ok(())
+    }
+
+    /// Set after how many spurious interrupts in a row the interrupts are reset.
+    ///
+    /// An interrupt is spurious when there turns out to be nothing to handle, which can happen on noisy hardware.
+    /// When the threshold is reached, the driver backs off for a bit and then resets the interrupt configuration
+    /// of the radio, so a storm of interrupts doesn't keep the MAC spinning.
+    pub fn set_spurious_irq_threshold(&mut self, threshold: u32) {
+        self.spurious_irqs = SpuriousIrqCounter::new(threshold);
+    }
+
+    /// The number of receive overruns since the phy was created. The count wraps around.
+    ///
+    /// The receiver uses both receive buffers of the DW1000. When a frame comes in while both are still
+    /// full, because the MAC didn't process the earlier frames in time, the receiver overruns and frames are lost.
+    /// Every overrun is also returned from [Phy::process] as [Error::ReceiveOverrun], after which the receiver
+    /// is restarted.
+    ///
+    /// An overrun can be reproduced by letting another radio send frames back to back
+    /// while not calling [Phy::wait] and [Phy::process] for a while.
+    /// A rising count means the processing is too slow for the traffic, not that the radio is at fault.
+    pub fn receive_overruns(&self) -> u32 {
+        self.receive_overruns
+    }
+
+    /// Back off and reset the interrupt configuration after too many spurious interrupts
+    async fn reset_interrupts(&mut self) -> Result<(), Error<SPI, IRQ>> {
+        self.delay.delay_ms(SPURIOUS_IRQ_BACKOFF_MILLIS).await;
+
+        let was_receiving = matches!(self.dw1000, DW1000::Receiving(_));
+        self.stop_receive().await?;
+
+        if let Some(dw1000) = self.dw1000.as_ready_mut() {
+            dw1000.disable_interrupts()?;
+        }
+
+        // Starting the receiver enables the rx interrupts again
+        if was_receiving {
+            self.start_receive().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Any SPI access wakes the DW1000 up without the configuration being restored, so it's refused while sleeping
+    fn check_awake(&self) -> Result<(), Error<SPI, IRQ>> {
+        if self.sleeping {
+            return Err(Error::Sleeping);
+        }
+
+        Ok(())
+    }
+
+    fn check_frame_length(data: &[u8]) -> Result<(), Error<SPI, IRQ>> {
+        if data.is_empty() {
+            return Err(Error::FrameEmpty);
+        }
+
+        if data.len() > lr_wpan_rs::consts::MAX_PHY_PACKET_SIZE {
+            return Err(Error::FrameTooLong);
+        }
+
+        Ok(())
+    }
+
+    async fn dw1000_send_time(
+        &mut self,
+        send_time: Option<Instant>,
+    ) -> Result<dw1000::hl::SendTime, Error<SPI, IRQ>> {
+        Ok(match send_time {
+            Some(target_time) => {
+                let now = self.get_instant().await?;
+                let time_diff = target_time.duration_since(now);
+                const MAX_TIME_DIFF: Duration = Duration::from_ticks(dw1000::time::TIME_MAX as i64);
+
+                if time_diff > MAX_TIME_DIFF {
+                    return Err(Error::TimeTooFarInFuture);
+                }
+
+                if time_diff < MIN_DELAYED_SEND_AHEAD {
+                    return Err(Error::TimeTooCloseInFuture);
+                }
+
+                dw1000::hl::SendTime::Delayed(
+                    dw1000::time::Instant::new(target_time.ticks() & dw1000::time::TIME_MAX)
+                        .unwrap(),
+                )
+            }
+            None => dw1000::hl::SendTime::Now,
+        })
+    }
+
+    /// Transmit the data and wait until it has been sent.
+    ///
+    /// With a response window, the radio turns its receiver on by itself after the transmission
+    /// (see [start_wait_for_response]) and the response must be picked up with [Self::receive_response].
+    ///
+    /// The radio must be in the ready state.
+    async fn transmit(
+        &mut self,
+        data: &[u8],
+        send_time: dw1000::hl::SendTime,
+        ranging: bool,
+        response_window: Option<ResponseWindow>,
+    ) -> Result<dw1000::time::Instant, Error<SPI, IRQ>> {
+        self.current_tx_config.ranging_enable = ranging;
+        let mut dw1000 = self.dw1000.take_ready().ok_or(Error::WrongState)?;
+        dw1000.enable_tx_interrupts()?;
+
+        // The driver can't set WAIT4RESP, which must be set together with TXSTRT. So the driver sets up
+        // a delayed send that's restarted with WAIT4RESP before it goes out.
+        let delayed = matches!(send_time, dw1000::hl::SendTime::Delayed(_));
+        let driver_send_time = match send_time {
+            dw1000::hl::SendTime::Now if response_window.is_some() => {
+                let restart_deadline =
+                    dw1000.sys_time()?.value() + lr_wpan_rs::time::TICKS_PER_MILLI;
+                dw1000::hl::SendTime::Delayed(
+                    dw1000::time::Instant::new(restart_deadline & dw1000::time::TIME_MAX).unwrap(),
+                )
+            }
+            send_time => send_time,
+        };
+
+        let mut sending = dw1000.send_raw(
+            |buffer| {
+                buffer[..data.len()].copy_from_slice(data);
+                data.len()
+            },
+            driver_send_time,
+            self.current_tx_config,
+        )?;
+        let restart = match response_window {
+            Some(response_window) => {
+                start_wait_for_response(sending.ll(), delayed, response_window)
+            }
+            None => Ok(()),
+        };
+
+        // Keep the radio in self while waiting, so the send can be aborted if this future is dropped
+        self.dw1000 = DW1000::Sending(sending);
+        restart.map_err(dw1000::Error::from)?;
+
+        let raw_tx_time = loop {
+            self.irq.wait_for_high().await.map_err(|e| Error::Irq(e))?;
+            let DW1000::Sending(dw1000) = &mut self.dw1000 else {
+                return Err(Error::WrongState);
+            };
+            match dw1000.wait_transmit() {
+                Ok(raw_tx_time) => break raw_tx_time,
+                Err(nb::Error::WouldBlock) => continue,
+                Err(nb::Error::Other(e)) => return Err(e.into()),
+            }
+        };
+
+        let dw1000 = self.dw1000.take_sending().ok_or(Error::WrongState)?;
+        self.dw1000 = match dw1000.finish_sending() {
+            Ok(dw1000) => DW1000::Ready(dw1000),
+            Err((_dw1000, e)) => {
+                // No real recovery possible...
+                #[cfg(feature = "defmt-03")]
+                defmt::panic!("Could not finish sending: {}", defmt::Debug2Format(&e));
+                #[cfg(not(feature = "defmt-03"))]
+                panic!("Could not finish sending: {:?}", e);
+            }
+        };
+
+        Ok(raw_tx_time)
+    }
+
+    /// Wait for the response after a [Self::transmit] with a response window.
+    ///
+    /// Afterwards the receiver is off and the radio is ready again.
+    async fn receive_response(&mut self) -> Result<Option<ReceivedMessage>, Error<SPI, IRQ>> {
+        let mut buffer = [0; 127];
+
+        let response = loop {
+            self.irq.wait_for_high().await.map_err(|e| Error::Irq(e))?;
+            let dw1000 = self.dw1000.as_ready_mut().ok_or(Error::WrongState)?;
+            match read_response(dw1000.ll(), &mut buffer).map_err(dw1000::Error::from)? {
+                Response::Pending => continue,
+                response => break response,
+            }
+        };
+
+        let dw1000 = self.dw1000.as_ready_mut().ok_or(Error::WrongState)?;
+        finish_wait_for_response(dw1000.ll()).map_err(dw1000::Error::from)?;
+        dw1000.disable_interrupts()?;
+
+        let Response::Received {
+            length,
+            rx_time,
+            crc_error,
+            ranging,
+            data_rate,
+        } = response
+        else {
+            return Ok(None);
+        };
+
+        // The FCS the radio added and checked isn't part of the frame of the MAC
+        let length = if self.current_rx_config.append_crc {
+            length.saturating_sub(2)
+        } else {
+            length
+        };
+        let timestamp = self
+            .convert_to_mac_time(dw1000::time::Instant::new(rx_time).unwrap())
+            .await?;
+
+        Ok(Some(ReceivedMessage {
+            timestamp,
+            data: buffer[..length].try_into().unwrap(),
+            lqi: 255, // TODO
+            channel: self.phy_pib.current_channel,
+            page: self.phy_pib.current_page,
+            crc_ok: self.current_rx_config.append_crc.then_some(!crc_error),
+            ranging,
+            data_rate,
+        }))
+    }
 }
 
 impl<SPI: SpiDevice, IRQ: Wait, DELAY: DelayNs> Phy for DW1000Phy<SPI, IRQ, DELAY> {
@@ -101,15 +532,21 @@ impl<SPI: SpiDevice, IRQ: Wait, DELAY: DelayNs> Phy for DW1000Phy<SPI, IRQ, DELA
         // Assumptions:
         // Always using 850kbps datarate
         // Always using 16mhz PRF
-        // Always using 1024 preamble length
-
-        const NUM_PREAMBLE_SYMBOLS: u32 = 31; // Valid for PRF16
-        const NUM_SFD_SYMBOLS: u32 = 8; // For 850kbps datarate
-        const SYMBOLS_PER_OCTET: f32 = 9.17648; // Not too sure... This is `8 * (1s / symbol period in secs (Tdsym)) / 850_000`
-        const SHR_DURATION: u32 = NUM_PREAMBLE_SYMBOLS + NUM_SFD_SYMBOLS;
-        let max_frame_duration = SHR_DURATION
-            + (((lr_wpan_rs::consts::MAX_PHY_PACKET_SIZE + 1) as f32 * SYMBOLS_PER_OCTET).ceil()
-                as u32);
+        // Starting out with 1024 preamble length, which can be changed with `set_preamble`
+        //
+        // The SHR duration follows the preamble that's really sent. It used to be 39 symbols,
+        // which mistook the 31 chips of a preamble symbol for the preamble length.
+        // With the default preamble it's now 1032 symbols, which makes the ack wait duration
+        // and the max frame duration about a 1000 symbols (roughly 1 ms) longer.
+        let ShrTimings {
+            shr_duration,
+            max_frame_duration,
+            symbols_per_octet,
+        } = ShrTimings::new(
+            DEFAULT_PREAMBLE_LENGTH,
+            DEFAULT_SFD_SEQUENCE,
+            DEFAULT_BITRATE,
+        );
 
         self.phy_pib = PhyPib {
             pib_write: PhyPibWrite {
@@ -142,8 +579,8 @@ impl<SPI: SpiDevice, IRQ: Wait, DELAY: DelayNs> Phy for DW1000Phy<SPI, IRQ, DELA
                 channel_numbers: &[1, 2, 3, 4, 5, 7],
             }],
             max_frame_duration,
-            shr_duration: SHR_DURATION,
-            symbols_per_octet: SYMBOLS_PER_OCTET,
+            shr_duration,
+            symbols_per_octet,
             preamble_symbol_length: 0, // 31 for PRF16 and 127 for PRF64 (but only PRF16 is ever used)
             uwb_data_rates_supported: &[0b00, 0b01, 0b10],
             css_low_data_rate_supported: false,
@@ -156,21 +593,23 @@ impl<SPI: SpiDevice, IRQ: Wait, DELAY: DelayNs> Phy for DW1000Phy<SPI, IRQ, DELA
         };
 
         self.current_rx_config = RxConfig {
-            bitrate: dw1000::configs::BitRate::Kbps850,
+            bitrate: DEFAULT_BITRATE,
             frame_filtering: false,
             pulse_repetition_frequency: PulseRepetitionFrequency::Mhz16,
-            expected_preamble_length: dw1000::configs::PreambleLength::Symbols1024,
+            expected_preamble_length: DEFAULT_PREAMBLE_LENGTH,
             channel: dw1000::configs::UwbChannel::Channel5,
-            sfd_sequence: dw1000::configs::SfdSequence::IEEE,
+            sfd_sequence: DEFAULT_SFD_SEQUENCE,
+            // The FCS is left to the MAC
             append_crc: false,
         };
         self.current_tx_config = TxConfig {
-            bitrate: dw1000::configs::BitRate::Kbps850,
+            bitrate: DEFAULT_BITRATE,
             ranging_enable: true,
             pulse_repetition_frequency: PulseRepetitionFrequency::Mhz16,
-            preamble_length: dw1000::configs::PreambleLength::Symbols1024,
+            preamble_length: DEFAULT_PREAMBLE_LENGTH,
             channel: dw1000::configs::UwbChannel::Channel5,
-            sfd_sequence: dw1000::configs::SfdSequence::IEEE,
+            sfd_sequence: DEFAULT_SFD_SEQUENCE,
+            // The FCS is left to the MAC
             append_crc: false,
         };
 
@@ -181,30 +620,56 @@ impl<SPI: SpiDevice, IRQ: Wait, DELAY: DelayNs> Phy for DW1000Phy<SPI, IRQ, DELA
     }
 
     async fn get_instant(&mut self) -> Result<lr_wpan_rs::time::Instant, Self::Error> {
+        self.check_awake()?;
+
         let sys_time = match &mut self.dw1000 {
             DW1000::Empty => return Err(Error::WrongState),
             DW1000::Ready(dw1000) => dw1000.sys_time()?.value(),
             DW1000::Receiving(dw1000) => dw1000.sys_time()?.value(),
+            DW1000::Sending(dw1000) => dw1000.sys_time()?.value(),
         };
 
-        let mut last_major_bits = self.last_instant & !dw1000::time::TIME_MAX;
-        let last_minor_bits = self.last_instant & dw1000::time::TIME_MAX;
-
-        if sys_time < last_minor_bits {
-            // Wraparound has happened
-            last_major_bits += dw1000::time::TIME_MAX + 1;
-        }
-
-        let current_time = last_major_bits | sys_time;
+        let current_time = next_instant(self.last_instant, sys_time);
 
         self.last_instant = current_time;
-        self.millis_until_next_time_check = TIME_CHECK_INTERVAL_MILLIS;
+        self.millis_until_next_time_check =
+            self.time_check_interval_millis.map_or(0, NonZeroU32::get);
 
         Ok(Instant::from_ticks(current_time))
     }
 
+    /// The symbol period is the duration of a data symbol at 850 kb/s, whatever the configured data rate is.
+    ///
+    /// This keeps the timings of the MAC, like the beacon interval, the same for every data rate.
+    /// The data rate is accounted for by the symbols per octet in the pib instead.
     fn symbol_period(&self) -> Duration {
-        Duration::from_ticks(65536)
+        data_symbol_duration(MAC_SYMBOL_BITRATE)
+    }
+
+    /// The receiver is turned on by the driver after the transmission is done, which takes a couple of
+    /// SPI transactions. That's a lot longer than the 12 symbols (about 12 us) of the UWB standard.
+    fn turnaround_time_symbols(&self) -> u32 {
+        (Duration::from_micros(TURNAROUND_MICROS as i64).ticks() / self.symbol_period().ticks())
+            as u32
+    }
+
+    fn wake_up_time_symbols(&self) -> u32 {
+        (Duration::from_millis(WAKE_UP_MILLIS as i64).ticks() / self.symbol_period().ticks()) as u32
+    }
+
+    /// A delayed send is refused with [Error::TimeTooCloseInFuture] when it's less than 10 ms ahead
+    fn send_setup_time_symbols(&self) -> u32 {
+        let symbol_ticks = self.symbol_period().ticks();
+        ((MIN_DELAYED_SEND_AHEAD.ticks() + symbol_ticks - 1) / symbol_ticks) as u32
+    }
+
+    /// The DW1000 ignores the low 9 bits of the time of a delayed send, which makes its resolution about 8 ns.
+    /// The send time it reports includes the transmit antenna delay, which is the tx RMARKER offset in the pib.
+    fn scheduled_send_time(&self, send_time: Instant) -> Instant {
+        Instant::from_ticks(
+            (send_time.ticks() & !DELAYED_SEND_IGNORED_BITS)
+                + self.phy_pib.tx_rmarker_offset as u64,
+        )
     }
 
     async fn send(
@@ -215,81 +680,117 @@ impl<SPI: SpiDevice, IRQ: Wait, DELAY: DelayNs> Phy for DW1000Phy<SPI, IRQ, DELA
         use_csma: bool,
         continuation: lr_wpan_rs::phy::SendContinuation,
     ) -> Result<lr_wpan_rs::phy::SendResult, Self::Error> {
-        assert!(!use_csma, "Not supported");
-        assert!(
-            !matches!(continuation, SendContinuation::WaitForResponse { .. }),
-            "Not yet implemented"
-        );
+        // With the ALOHA CCA mode the channel is always clear, so the CCA of CSMA never stops the send
+        let _ = use_csma;
 
-        let send_time = match send_time {
-            Some(target_time) => {
-                let now = self.get_instant().await?;
-                let time_diff = target_time.duration_since(now);
-                const MAX_TIME_DIFF: Duration = Duration::from_ticks(dw1000::time::TIME_MAX as i64);
-                const MIN_TIME_DIFF: Duration = Duration::from_millis(10);
+        self.check_awake()?;
+        Self::check_frame_length(data)?;
 
-                if time_diff > MAX_TIME_DIFF {
-                    return Err(Error::TimeTooFarInFuture);
-                }
+        let send_time = self.dw1000_send_time(send_time).await?;
 
-                if time_diff < MIN_TIME_DIFF {
-                    return Err(Error::TimeTooCloseInFuture);
-                }
+        self.stop_receive().await?;
 
-                dw1000::hl::SendTime::Delayed(
-                    dw1000::time::Instant::new(target_time.ticks() & dw1000::time::TIME_MAX)
-                        .unwrap(),
-                )
-            }
-            None => dw1000::hl::SendTime::Now,
+        let response_window = ResponseWindow::of(continuation);
+        let raw_tx_time = self
+            .transmit(data, send_time, ranging, response_window)
+            .await?;
+
+        let response = match response_window {
+            Some(_) => self.receive_response().await?,
+            None => None,
         };
 
-        self.stop_receive().await?;
+        if matches!(continuation, SendContinuation::ReceiveContinuous) {
+            // The receiver of the driver can't be started by WAIT4RESP, so it's started before anything else,
+            // to keep the gap in which a frame can be missed as small as possible.
+            self.start_receive().await?;
+        }
 
-        self.current_tx_config.ranging_enable = ranging;
-        let mut dw1000 = self.dw1000.take_ready().ok_or(Error::WrongState)?;
-        dw1000.enable_tx_interrupts()?;
+        let tx_time = self.convert_to_mac_time(raw_tx_time).await?;
 
-        let mut dw1000 = dw1000.send_raw(
-            |buffer| {
-                buffer[..data.len()].copy_from_slice(data);
-                data.len()
-            },
-            send_time,
-            self.current_tx_config,
-        )?;
+        Ok(lr_wpan_rs::phy::SendResult::Success(tx_time, response))
+    }
 
-        let raw_tx_time = loop {
-            self.irq.wait_for_high().await.map_err(|e| Error::Irq(e))?;
-            match dw1000.wait_transmit() {
-                Ok(raw_tx_time) => break raw_tx_time,
-                Err(nb::Error::WouldBlock) => continue,
-                Err(nb::Error::Other(e)) => return Err(e.into()),
-            }
-        };
+    async fn send_with_data_rate(
+        &mut self,
+        data: &[u8],
+        send_time: Option<Instant>,
+        ranging: bool,
+        use_csma: bool,
+        data_rate: u8,
+        continuation: SendContinuation,
+    ) -> Result<SendResult, Self::Error> {
+        let bitrate = bitrate_of(data_rate).ok_or(Error::UnsupportedDataRate)?;
+
+        // Only this frame uses the data rate, the receiver keeps using the configured one.
+        // The timings in the phy pib follow the data rate for as long as the frame is being sent.
+        let configured_bitrate = self.current_tx_config.bitrate;
+        self.current_tx_config.bitrate = bitrate;
+        self.update_timings();
+        let result = self
+            .send(data, send_time, ranging, use_csma, continuation)
+            .await;
+        self.current_tx_config.bitrate = configured_bitrate;
+        self.update_timings();
+
+        result
+    }
 
-        self.dw1000 = match dw1000.finish_sending() {
-            Ok(dw1000) => DW1000::Ready(dw1000),
-            Err((_dw1000, e)) => {
-                // No real recovery possible...
-                #[cfg(feature = "defmt-03")]
-                defmt::panic!("Could not finish sending: {}", defmt::Debug2Format(&e));
-                #[cfg(not(feature = "defmt-03"))]
-                panic!("Could not finish sending: {:?}", e);
-            }
-        };
+    async fn send_back_to_back(
+        &mut self,
+        first: &[u8],
+        second: &[u8],
+        send_time: Option<Instant>,
+        ranging: bool,
+        use_csma: bool,
+        gap: Duration,
+        continuation: SendContinuation,
+    ) -> Result<(SendResult, Option<SendResult>), Self::Error> {
+        // With the ALOHA CCA mode the channel is always clear, so the CCA of CSMA never stops the send
+        let _ = use_csma;
 
-        let tx_time = self.convert_to_mac_time(raw_tx_time).await?;
+        Self::check_frame_length(first)?;
+        Self::check_frame_length(second)?;
 
+        let send_time = self.dw1000_send_time(send_time).await?;
+
+        self.stop_receive().await?;
+
+        // The gap is much shorter than the minimum time a delayed send needs to be scheduled ahead,
+        // so the second frame is sent the gap after the interrupt that the first one is done.
+        // The mac time conversion is postponed until both frames are sent to keep the gap as small as possible.
+        let first_raw_tx_time = self.transmit(first, send_time, ranging, None).await?;
+        self.delay.delay_us(gap.micros().max(0) as u32).await;
+        let response_window = ResponseWindow::of(continuation);
+        let second_raw_tx_time = self
+            .transmit(second, dw1000::hl::SendTime::Now, ranging, response_window)
+            .await?;
+
+        // Like in a single send, the receiver goes first
+        let response = match response_window {
+            Some(_) => self.receive_response().await?,
+            None => None,
+        };
         if matches!(continuation, SendContinuation::ReceiveContinuous) {
-            // This should use the hardware acceleration, but driver doesn't implement that
             self.start_receive().await?;
         }
 
-        Ok(lr_wpan_rs::phy::SendResult::Success(tx_time, None))
+        let first_tx_time = self.convert_to_mac_time(first_raw_tx_time).await?;
+        let second_tx_time = self.convert_to_mac_time(second_raw_tx_time).await?;
+
+        Ok((
+            SendResult::Success(first_tx_time, None),
+            Some(SendResult::Success(second_tx_time, response)),
+        ))
     }
 
     async fn start_receive(&mut self) -> Result<(), Self::Error> {
+        // The mac calls this whenever it wants the receiver on, so being on already is fine
+        if matches!(self.dw1000, DW1000::Receiving(_)) {
+            return Ok(());
+        }
+        self.check_awake()?;
+
         let mut ready_radio = self.dw1000.take_ready().ok_or(Error::WrongState)?;
 
         ready_radio.enable_rx_interrupts()?;
@@ -301,28 +802,109 @@ impl<SPI: SpiDevice, IRQ: Wait, DELAY: DelayNs> Phy for DW1000Phy<SPI, IRQ, DELA
     }
 
     async fn stop_receive(&mut self) -> Result<(), Self::Error> {
-        if let Some(dw1000) = self.dw1000.take_receiving() {
-            match dw1000.finish_receiving() {
-                Ok(dw1000) => self.dw1000 = DW1000::Ready(dw1000),
-                Err((dw1000, e)) => {
-                    self.dw1000 = DW1000::Receiving(dw1000);
-                    return Err(e.into());
-                }
-            };
+        Ok(self.dw1000.stop_receiving()?)
+    }
+
+    /// Put the DW1000 in its SLEEP state, in which only the always-on (AON) memory keeps its contents.
+    ///
+    /// The configuration is saved to the AON memory first, so it's restored when waking up.
+    /// The system clock doesn't run while sleeping, so [Phy::get_instant] continues after the next
+    /// wraparound of the clock when woken up. While sleeping, the radio can't be used and refuses
+    /// with [Error::Sleeping], because any SPI access wakes it up. [Phy::wait] doesn't complete until woken up.
+    async fn sleep(&mut self) -> Result<(), Self::Error> {
+        if self.sleeping {
+            return Ok(());
         }
 
+        self.stop_receive().await?;
+
+        let dw1000 = self.dw1000.as_ready_mut().ok_or(Error::WrongState)?;
+        enter_sleep(dw1000.ll()).map_err(dw1000::Error::from)?;
+        self.sleeping = true;
+
         Ok(())
     }
 
-    async fn wait(&mut self) -> Result<Self::ProcessingContext, Self::Error> {
-        let wait_for_time = async {
-            while self.millis_until_next_time_check > 0 {
-                self.millis_until_next_time_check = self
-                    .millis_until_next_time_check
-                    .saturating_sub(TIME_CHECK_MILLIS_PER_DELAY);
-                self.delay.delay_ms(TIME_CHECK_MILLIS_PER_DELAY).await;
+    /// Wake the DW1000 up by holding the SPI chip select low, following `dwt_spicswakeup` of the Decawave driver.
+    ///
+    /// The chip select is held low by a long read, which is only long enough (500 µs) when the SPI clock is at most 2 MHz.
+    /// After that, it takes about 5 ms until the configuration is restored and the radio is idle again.
+    /// The device id is read to check the radio is really awake. If it isn't, waking up is tried again
+    /// a couple of times before failing with [Error::WakeUpFailed], which is what a too fast SPI clock leads to.
+    async fn wake(&mut self) -> Result<(), Self::Error> {
+        if !self.sleeping {
+            return Ok(());
+        }
+
+        let dw1000 = self.dw1000.as_ready_mut().ok_or(Error::WrongState)?;
+        for _ in 0..WAKE_UP_ATTEMPTS {
+            wake_up(dw1000.ll()).map_err(dw1000::Error::from)?;
+            self.delay.delay_ms(WAKE_UP_MILLIS).await;
+
+            if is_awake(dw1000.ll()).map_err(dw1000::Error::from)? {
+                self.sleeping = false;
+                self.last_instant = instant_before_wraparound(self.last_instant);
+                return Ok(());
             }
-        };
+        }
+
+        Err(Error::WakeUpFailed)
+    }
+
+    async fn abort_send(&mut self) -> Result<(), Self::Error> {
+        if let Some(dw1000) = self.dw1000.take_sending() {
+            // Finishing a send that isn't done yet aborts it
+            match dw1000.finish_sending() {
+                Ok(dw1000) => self.dw1000 = DW1000::Ready(dw1000),
+                Err((dw1000, e)) => {
+                    self.dw1000 = DW1000::Sending(dw1000);
+                    return Err(e.into());
+                }
+            }
+        }
+
+        self.stop_receive().await
+    }
+
+    async fn set_frame_filter(&mut self, filter: Option<FrameFilter>) -> Result<(), Self::Error> {
+        // The address registers and the rx config can only be changed while the radio isn't receiving
+        let was_receiving = matches!(self.dw1000, DW1000::Receiving(_));
+        self.dw1000.stop_receiving()?;
+
+        if let Some(filter) = filter {
+            let dw1000 = self.dw1000.as_ready_mut().ok_or(Error::WrongState)?;
+            write_frame_filter(dw1000.ll(), &filter).map_err(dw1000::Error::from)?;
+        }
+
+        self.current_rx_config.frame_filtering = filter.is_some();
+
+        if was_receiving {
+            self.start_receive().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn measure_energy(&mut self) -> Result<u8, Self::Error> {
+        // The driver has no energy detection
+        Err(Error::Unsupported)
+    }
+
+    fn is_unsupported(error: &Self::Error) -> bool {
+        matches!(error, Error::Unsupported)
+    }
+
+    async fn wait(&mut self) -> Result<Self::ProcessingContext, Self::Error> {
+        if self.sleeping {
+            // No interrupts come in and the clock is stopped, so there's nothing to wait for
+            return core::future::pending().await;
+        }
+
+        let wait_for_time = wait_for_time_check(
+            &mut self.delay,
+            &mut self.millis_until_next_time_check,
+            self.time_check_interval_millis,
+        );
 
         // Do the cancellable waiting
         Ok(select(self.irq.wait_for_high(), wait_for_time).await)
@@ -345,29 +927,57 @@ impl<SPI: SpiDevice, IRQ: Wait, DELAY: DelayNs> Phy for DW1000Phy<SPI, IRQ, DELA
                         // Spurious interrupt?
                         dw1000.disable_interrupts()?;
                     }
+                    DW1000::Sending(_) => {
+                        // The interrupts of a send are handled in the send itself
+                    }
                     DW1000::Receiving(dw1000) => {
                         let mut buffer = [0; 127];
-                        return match dw1000.wait_receive_raw(&mut buffer) {
+                        match dw1000.wait_receive_raw(&mut buffer) {
                             Ok(message) => {
+                                self.spurious_irqs.reset();
+                                // The ranging bit and the data rate of the PHR of the frame that was just received
+                                let rx_finfo =
+                                    dw1000.ll().rx_finfo().read().map_err(dw1000::Error::from)?;
+                                let ranging = rx_finfo.rng() == 1;
+                                let data_rate = rx_finfo.rxbr() + 1;
                                 let timestamp = self.convert_to_mac_time(message.rx_time).await?;
 
-                                Ok(Some(lr_wpan_rs::phy::ReceivedMessage {
+                                return Ok(Some(lr_wpan_rs::phy::ReceivedMessage {
                                     timestamp,
                                     data: message.bytes.try_into().unwrap(),
                                     lqi: 255, // TODO
                                     channel: self.phy_pib.current_channel,
                                     page: self.phy_pib.current_page,
-                                }))
+                                    // Only when the receiver checks the CRC is it known to be ok.
+                                    // A failed check comes out of the driver as an error.
+                                    crc_ok: self.current_rx_config.append_crc.then_some(true),
+                                    ranging,
+                                    data_rate,
+                                }));
                             }
                             Err(nb::Error::WouldBlock) => {
                                 // Just wait a bit more
-                                Ok(None)
                             }
-                            Err(nb::Error::Other(e)) => Err(e.into()),
-                        };
+                            Err(nb::Error::Other(dw1000::Error::Overrun)) => {
+                                self.spurious_irqs.reset();
+                                self.receive_overruns = self.receive_overruns.wrapping_add(1);
+
+                                // The buffers are out of sync after an overrun, so the receiver must be restarted
+                                self.stop_receive().await?;
+                                self.start_receive().await?;
+
+                                return Err(Error::ReceiveOverrun);
+                            }
+                            Err(nb::Error::Other(e)) => return Err(e.into()),
+                        }
                     }
                 }
 
+                // Nothing came out of the interrupt
+                if self.spurious_irqs.register() {
+                    self.reset_interrupts().await?;
+                }
+
                 Ok(None)
             }
             Either::Second(_check_for_time) => {
@@ -466,10 +1076,11 @@ impl<SPI: SpiDevice, IRQ: Wait, DELAY: DelayNs> Phy for DW1000Phy<SPI, IRQ, DELA
             // Nothing to react to
             let _ = rframe_processing_time;
 
-            if let Some(dw1000) = self.dw1000.take_receiving() {
-                self.dw1000 = DW1000::Ready(dw1000.finish_receiving().unwrap());
-            }
-            self.dw1000.as_ready_mut().unwrap().set_antenna_delay(
+            // The radio must be ready to change its settings. Scans switch channels while receiving,
+            // so a frame that's being received is discarded instead of failing the update.
+            self.dw1000.stop_receiving()?;
+            let dw1000 = self.dw1000.as_ready_mut().ok_or(Error::WrongState)?;
+            dw1000.set_antenna_delay(
                 (*rx_rmarker_offset)
                     .try_into()
                     .map_err(|_| Error::RMarkerOffsetTooLarge)?,
@@ -498,10 +1109,516 @@ impl<SPI: SpiDevice, IRQ: Wait, DELAY: DelayNs> Phy for DW1000Phy<SPI, IRQ, DELA
     }
 }
 
+/// Extend the raw system time of the radio to a full instant based on the last returned instant.
+///
+/// The result is never smaller than the last instant, so time keeps being monotonic.
+fn next_instant(last_instant: u64, sys_time: u64) -> u64 {
+    let mut last_major_bits = last_instant & !dw1000::time::TIME_MAX;
+    let last_minor_bits = last_instant & dw1000::time::TIME_MAX;
+
+    if sys_time < last_minor_bits {
+        // Wraparound has happened
+        last_major_bits += dw1000::time::TIME_MAX + 1;
+    }
+
+    // Clamp in case the bookkeeping ever gets confused
+    (last_major_bits | sys_time).max(last_instant)
+}
+
+/// The last instant to use after waking up, so the [next_instant] is after the next wraparound.
+///
+/// The system clock restarts when the radio wakes up. Without this, whether a wraparound is counted
+/// depends on where the clock was when going to sleep.
+fn instant_before_wraparound(last_instant: u64) -> u64 {
+    last_instant | dw1000::time::TIME_MAX
+}
+
+/// The OTP address of the voltage reading at 3.3 V, measured in production
+const OTP_VBAT_ADDRESS: u16 = 0x008;
+/// The OTP address of the temperature reading at 23 °C, measured in production
+const OTP_VTEMP_ADDRESS: u16 = 0x009;
+
+/// The readings of the onboard sensors of the DW1000, see [DW1000Phy::read_diagnostics].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct Diagnostics {
+    /// The temperature of the chip in °C.
+    ///
+    /// `(raw_temperature - OTP[0x009]) * 1.14 + 23`, where OTP\[0x009\] is the reading at 23 °C.
+    pub temp_c: f32,
+    /// The supply voltage in V.
+    ///
+    /// `(raw_voltage - OTP[0x008]) / 173 + 3.3`, where OTP\[0x008\] is the reading at 3.3 V.
+    pub voltage: f32,
+    /// The uncalibrated reading of the temperature sensor (`SAR_LTEMP`)
+    pub raw_temperature: u8,
+    /// The uncalibrated reading of the voltage sensor (`SAR_LVBAT`)
+    pub raw_voltage: u8,
+}
+
+impl Diagnostics {
+    fn new(
+        raw_temperature: u8,
+        raw_voltage: u8,
+        temperature_calibration: u8,
+        voltage_calibration: u8,
+    ) -> Self {
+        Self {
+            temp_c: (raw_temperature as f32 - temperature_calibration as f32) * 1.14 + 23.0,
+            voltage: (raw_voltage as f32 - voltage_calibration as f32) / 173.0 + 3.3,
+            raw_temperature,
+            raw_voltage,
+        }
+    }
+}
+
+fn measure_sensors<SPI: SpiDevice>(
+    ll: &mut dw1000::ll::DW1000<SPI>,
+) -> Result<Diagnostics, dw1000::ll::Error<SPI>> {
+    // Enable the sensors
+    ll.rf_sensor_ctrl().write(|w| w.value(0x80))?;
+    ll.rf_sensor_mode().write(|w| w.value(0x0A))?;
+    ll.rf_sensor_mode().write(|w| w.value(0x0F))?;
+
+    // Take a sample. The readings are only valid while SAR_CTRL is set.
+    ll.tc_sarc().write(|w| w.sar_ctrl(0))?;
+    ll.tc_sarc().write(|w| w.sar_ctrl(1))?;
+    let tc_sarl = ll.tc_sarl().read()?;
+    ll.tc_sarc().write(|w| w.sar_ctrl(0))?;
+
+    // Only the low byte of the calibration values is used
+    let voltage_calibration = read_otp(ll, OTP_VBAT_ADDRESS)? as u8;
+    let temperature_calibration = read_otp(ll, OTP_VTEMP_ADDRESS)? as u8;
+
+    Ok(Diagnostics::new(
+        tc_sarl.sar_ltemp(),
+        tc_sarl.sar_lvbat(),
+        temperature_calibration,
+        voltage_calibration,
+    ))
+}
+
+fn start_continuous_wave<SPI: SpiDevice>(
+    ll: &mut dw1000::ll::DW1000<SPI>,
+    channel: dw1000::configs::UwbChannel,
+) -> Result<(), dw1000::ll::Error<SPI>> {
+    // Disable the sequencing
+    ll.pmsc_ctrl0().modify(|_, w| w.sysclks(0b01))?;
+    ll.pmsc_ctrl1().modify(|_, w| w.pktseq(0))?;
+
+    // Configure the channel
+    ll.fs_pllcfg()
+        .write(|w| w.value(channel.get_recommended_fs_pllcfg()))?;
+    ll.fs_plltune()
+        .write(|w| w.value(channel.get_recommended_fs_plltune()))?;
+    ll.rf_txctrl()
+        .write(|w| w.value(channel.get_recommended_rf_txctrl()))?;
+
+    // Power up the PLL first and then all of the transmitter
+    ll.rf_conf().write(|w| w.pllfen(0b111).ldofen(0b11111))?;
+    ll.rf_conf()
+        .write(|w| w.txfen(0b11111).pllfen(0b111).ldofen(0b11111).txrxsw(0b10))?;
+
+    // Run the system and transmit clocks from the PLL
+    ll.pmsc_ctrl0()
+        .modify(|_, w| w.sysclks(0b10).txclks(0b10))?;
+
+    // Otherwise the fine grain sequencing would turn the transmitter off again
+    ll.pmsc_txfseq().write(|w| w.value(PMSC_TXFSEQ_DISABLED))?;
+
+    ll.tc_pgtest().write(|w| w.value(0x13))?;
+
+    Ok(())
+}
+
+fn stop_continuous_wave<SPI: SpiDevice>(
+    ll: &mut dw1000::ll::DW1000<SPI>,
+) -> Result<(), dw1000::ll::Error<SPI>> {
+    ll.tc_pgtest().write(|w| w.value(0x00))?;
+
+    // Back to automatic control of the transmitter and the clocks
+    ll.rf_conf()
+        .write(|w| w.txfen(0).pllfen(0).ldofen(0).txrxsw(0))?;
+    ll.pmsc_ctrl0()
+        .modify(|_, w| w.sysclks(0b00).txclks(0b00))?;
+    ll.pmsc_txfseq().write(|w| w.value(PMSC_TXFSEQ_ENABLED))?;
+    ll.pmsc_ctrl1().modify(|_, w| w.pktseq(0xE7))?;
+
+    Ok(())
+}
+
+/// The value of `PMSC_TXFSEQ` (0x36:0x26) that turns the fine grain transmit sequencing off
+const PMSC_TXFSEQ_DISABLED: u16 = 0x0000;
+/// The default value of `PMSC_TXFSEQ`, with the fine grain transmit sequencing on
+const PMSC_TXFSEQ_ENABLED: u16 = 0x0B74;
+
+/// Save the configuration to the AON memory and go to sleep, as described in section 7.2.46 of the DW1000 user manual.
+///
+/// On waking up, the configuration and the LDE microcode are loaded again.
+/// Waking up is done on the SPI chip select.
+fn enter_sleep<SPI: SpiDevice>(
+    ll: &mut dw1000::ll::DW1000<SPI>,
+) -> Result<(), dw1000::ll::Error<SPI>> {
+    ll.aon_wcfg()
+        .write(|w| w.onw_ldc(1).onw_llde(1).pres_sleep(1))?;
+    ll.aon_cfg0().write(|w| w.sleep_en(1).wake_spi(1))?;
+
+    // Uploading the configuration to the AON memory puts the radio to sleep
+    ll.aon_ctrl().write(|w| w.save(0))?;
+    ll.aon_ctrl().write(|w| w.save(1))?;
+
+    Ok(())
+}
+
+/// Program the addresses and the frame filter config (SYS_CFG, section 7.2.6 of the DW1000 user manual).
+///
+/// Filtering itself is switched on with the rx config. All frame types we process are let through
+/// and a PAN coordinator also accepts frames without a destination address (5.1.6.2).
+fn write_frame_filter<SPI: SpiDevice>(
+    ll: &mut dw1000::ll::DW1000<SPI>,
+    filter: &FrameFilter,
+) -> Result<(), dw1000::ll::Error<SPI>> {
+    ll.panadr()
+        .write(|w| w.pan_id(filter.pan_id.0).short_addr(filter.short_address.0))?;
+    ll.eui().write(|w| w.value(filter.extended_address.0))?;
+    ll.sys_cfg().modify(|_, w| {
+        w.ffbc(filter.pan_coordinator as u8)
+            .ffab(1)
+            .ffad(1)
+            .ffaa(1)
+            .ffam(1)
+    })?;
+
+    Ok(())
+}
+
+/// The receive window after a transmission, in the UWB microseconds (512/499.2 us) of the radio
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ResponseWindow {
+    /// The time between the end of the transmission and turning on the receiver (W4R_TIM)
+    rx_after_tx_delay: u32,
+    /// How long the receiver waits for a frame (RX_FWTO)
+    timeout: u16,
+}
+
+impl ResponseWindow {
+    /// The ticks in a UWB microsecond
+    const UWB_MICROSECOND: i64 = 512 * 128;
+
+    fn of(continuation: SendContinuation) -> Option<Self> {
+        match continuation {
+            SendContinuation::WaitForResponse {
+                turnaround_time,
+                timeout,
+            } => Some(Self {
+                rx_after_tx_delay: (turnaround_time.ticks() / Self::UWB_MICROSECOND)
+                    .clamp(0, 0xF_FFFF) as u32,
+                // A timeout of 0 would wait forever, so it's rounded up
+                timeout: (timeout.ticks().max(1) as u64)
+                    .div_ceil(Self::UWB_MICROSECOND as u64)
+                    .min(u16::MAX as u64) as u16,
+            }),
+            SendContinuation::Idle | SendContinuation::ReceiveContinuous => None,
+        }
+    }
+}
+
+/// Restart the send the driver has set up, so the radio turns on its receiver by itself after the transmission.
+///
+/// This is what the WAIT4RESP bit of SYS_CTRL does, with the delay of ACK_RESP_T and the frame wait timeout
+/// of RX_FWTO (section 7.2.15 of the DW1000 user manual). The receiver only takes one frame into the first
+/// receive buffer, see [read_response].
+fn start_wait_for_response<SPI: SpiDevice>(
+    ll: &mut dw1000::ll::DW1000<SPI>,
+    delayed: bool,
+    window: ResponseWindow,
+) -> Result<(), dw1000::ll::Error<SPI>> {
+    // Cancel the send of the driver. The frame and its config stay in the radio.
+    ll.sys_ctrl().write(|w| w.trxoff(1))?;
+
+    ll.sys_cfg()
+        .modify(|_, w| w.dis_drxb(1).rxautr(0).rxwtoe(1))?;
+    ll.rx_fwto().write(|w| w.value(window.timeout))?;
+    ll.ack_resp_t()
+        .modify(|_, w| w.w4r_tim(window.rx_after_tx_delay))?;
+    ll.sys_mask().modify(|_, w| {
+        w.mrxdfr(1)
+            .mrxphe(1)
+            .mrxrfsl(1)
+            .mrxrfto(1)
+            .mrxsfdto(1)
+            .mrxpto(1)
+    })?;
+
+    ll.sys_ctrl()
+        .write(|w| w.txstrt(1).txdlys(delayed as u8).wait4resp(1))?;
+
+    Ok(())
+}
+
+/// The outcome of waiting for a response, see [read_response]
+enum Response {
+    /// The receiver is still waiting
+    Pending,
+    /// A frame came in and was copied into the buffer
+    Received {
+        length: usize,
+        rx_time: u64,
+        crc_error: bool,
+        ranging: bool,
+        data_rate: u8,
+    },
+    /// The receiver timed out or received something that wasn't a frame
+    Nothing,
+}
+
+/// Read the response after [start_wait_for_response]
+fn read_response<SPI: SpiDevice>(
+    ll: &mut dw1000::ll::DW1000<SPI>,
+    buffer: &mut [u8; 127],
+) -> Result<Response, dw1000::ll::Error<SPI>> {
+    let sys_status = ll.sys_status().read()?;
+
+    let response = if sys_status.rxdfr() == 1 {
+        let rx_finfo = ll.rx_finfo().read()?;
+        let length = (rx_finfo.rxflen() as usize).min(buffer.len());
+        buffer[..length].copy_from_slice(&ll.rx_buffer().read()?.data()[..length]);
+
+        Response::Received {
+            length,
+            rx_time: ll.rx_time().read()?.rx_stamp(),
+            crc_error: sys_status.rxfce() == 1,
+            ranging: rx_finfo.rng() == 1,
+            data_rate: rx_finfo.rxbr() + 1,
+        }
+    } else if sys_status.rxphe() == 1
+        || sys_status.rxrfsl() == 1
+        || sys_status.rxrfto() == 1
+        || sys_status.rxsfdto() == 1
+        || sys_status.rxpto() == 1
+    {
+        Response::Nothing
+    } else {
+        return Ok(Response::Pending);
+    };
+
+    clear_rx_events(ll)?;
+
+    Ok(response)
+}
+
+/// Turn the transceiver off right away, dropping a frame that's being received.
+///
+/// Like `dwt_forcetrxoff` of the Decawave driver, TRXOFF is written to SYS_CTRL and then the events of
+/// the dropped frame are cleared, so they don't show up as an interrupt later.
+fn force_trx_off<SPI: SpiDevice>(
+    ll: &mut dw1000::ll::DW1000<SPI>,
+) -> Result<(), dw1000::ll::Error<SPI>> {
+    ll.sys_ctrl().write(|w| w.trxoff(1))?;
+    clear_rx_events(ll)
+}
+
+/// Clear all the receive events in SYS_STATUS, which are cleared by writing a 1
+fn clear_rx_events<SPI: SpiDevice>(
+    ll: &mut dw1000::ll::DW1000<SPI>,
+) -> Result<(), dw1000::ll::Error<SPI>> {
+    ll.sys_status().write(|w| {
+        w.rxprd(1)
+            .rxsfdd(1)
+            .ldedone(1)
+            .rxphd(1)
+            .rxphe(1)
+            .rxdfr(1)
+            .rxfcg(1)
+            .rxfce(1)
+            .rxrfsl(1)
+            .rxrfto(1)
+            .ldeerr(1)
+            .rxovrr(1)
+            .rxpto(1)
+            .rxsfdto(1)
+            .affrej(1)
+    })
+}
+
+/// Turn the receiver off after [start_wait_for_response] and undo its frame wait timeout,
+/// which would otherwise also stop the normal receiver of the driver.
+fn finish_wait_for_response<SPI: SpiDevice>(
+    ll: &mut dw1000::ll::DW1000<SPI>,
+) -> Result<(), dw1000::ll::Error<SPI>> {
+    ll.sys_ctrl().write(|w| w.trxoff(1))?;
+    ll.sys_cfg().modify(|_, w| w.rxwtoe(0))?;
+
+    Ok(())
+}
+
+/// Hold the chip select low long enough to wake the radio up, by reading the whole receive buffer
+fn wake_up<SPI: SpiDevice>(ll: &mut dw1000::ll::DW1000<SPI>) -> Result<(), dw1000::ll::Error<SPI>> {
+    ll.rx_buffer().read()?;
+
+    Ok(())
+}
+
+/// Does the radio answer with its device id? A sleeping radio doesn't answer at all.
+fn is_awake<SPI: SpiDevice>(
+    ll: &mut dw1000::ll::DW1000<SPI>,
+) -> Result<bool, dw1000::ll::Error<SPI>> {
+    Ok(ll.dev_id().read()?.ridtag() == DEV_ID_RIDTAG)
+}
+
+/// The register identification tag in the device id of every DW1000
+const DEV_ID_RIDTAG: u16 = 0xDECA;
+
+/// Read a 32-bit word from the OTP memory, as described in section 6.3.3 of the DW1000 user manual
+fn read_otp<SPI: SpiDevice>(
+    ll: &mut dw1000::ll::DW1000<SPI>,
+    address: u16,
+) -> Result<u32, dw1000::ll::Error<SPI>> {
+    ll.otp_addr().write(|w| w.value(address))?;
+    ll.otp_ctrl().write(|w| w.otprden(1).otpread(1))?;
+    let value = ll.otp_rdat().read()?.value();
+    ll.otp_ctrl().write(|w| w.otprden(0))?;
+
+    Ok(value)
+}
+
+/// Wait until the next time check is due. Never completes when the time checks are disabled.
+///
+/// The waiting is done in small steps that update the remaining time, so it can be cancelled
+/// without the next wait starting over.
+async fn wait_for_time_check(
+    delay: &mut impl DelayNs,
+    millis_until_next_time_check: &mut u32,
+    time_check_interval_millis: Option<NonZeroU32>,
+) {
+    if time_check_interval_millis.is_none() {
+        return core::future::pending().await;
+    }
+
+    while *millis_until_next_time_check > 0 {
+        *millis_until_next_time_check =
+            millis_until_next_time_check.saturating_sub(TIME_CHECK_MILLIS_PER_DELAY);
+        delay.delay_ms(TIME_CHECK_MILLIS_PER_DELAY).await;
+    }
+}
+
+/// Counts the spurious interrupts in a row
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SpuriousIrqCounter {
+    count: u32,
+    threshold: u32,
+}
+
+impl SpuriousIrqCounter {
+    const fn new(threshold: u32) -> Self {
+        Self {
+            count: 0,
+            threshold,
+        }
+    }
+
+    /// Register a spurious interrupt.
+    ///
+    /// Returns true when the threshold is reached, after which the counting starts over.
+    fn register(&mut self) -> bool {
+        self.count += 1;
+
+        if self.count >= self.threshold {
+            self.count = 0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// An interrupt was handled, so the spurious ones are no longer in a row
+    fn reset(&mut self) {
+        self.count = 0;
+    }
+}
+
+/// The timings of the phy pib that depend on the synchronization header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ShrTimings {
+    shr_duration: u32,
+    max_frame_duration: u32,
+    symbols_per_octet: f32,
+}
+
+impl ShrTimings {
+    fn new(preamble_length: PreambleLength, sfd_sequence: SfdSequence, bitrate: BitRate) -> Self {
+        let num_preamble_symbols = match preamble_length {
+            PreambleLength::Symbols64 => 64,
+            PreambleLength::Symbols128 => 128,
+            PreambleLength::Symbols256 => 256,
+            PreambleLength::Symbols512 => 512,
+            PreambleLength::Symbols1024 => 1024,
+            PreambleLength::Symbols1536 => 1536,
+            PreambleLength::Symbols2048 => 2048,
+            PreambleLength::Symbols4096 => 4096,
+        };
+        let num_sfd_symbols = match (sfd_sequence, bitrate) {
+            (_, BitRate::Kbps110) => 64,
+            (SfdSequence::IEEE, _) => 8,
+            // The Decawave sequence is longer at 850kbps
+            (_, BitRate::Kbps850) => 16,
+            (_, BitRate::Kbps6800) => 8,
+        };
+        let symbols_per_octet = SYMBOLS_PER_OCTET * SYMBOLS_PER_OCTET_BITRATE
+            / match bitrate {
+                BitRate::Kbps110 => 110_000.0,
+                BitRate::Kbps850 => 850_000.0,
+                BitRate::Kbps6800 => 6_800_000.0,
+            };
+
+        let shr_duration = num_preamble_symbols + num_sfd_symbols;
+        let max_frame_duration = shr_duration
+            + (((lr_wpan_rs::consts::MAX_PHY_PACKET_SIZE + 1) as f32 * symbols_per_octet).ceil()
+                as u32);
+
+        Self {
+            shr_duration,
+            max_frame_duration,
+            symbols_per_octet,
+        }
+    }
+}
+
+/// The duration of a data symbol (Tdsym) at the given bitrate.
+///
+/// A data symbol takes a whole number of chips of the 499.2 MHz chipping rate
+/// (table 15-3 of IEEE 802.15.4-2020):
+/// - 110 kb/s: 4096 chips, 8205.13 ns
+/// - 850 kb/s: 512 chips, 1025.64 ns
+/// - 6.8 Mb/s: 64 chips, 128.21 ns
+///
+/// The PRF and the preamble length don't change it. A higher PRF puts more pulses in a burst,
+/// but the bursts are spread over the same symbol. The preamble has its own symbols.
+pub const fn data_symbol_duration(bitrate: BitRate) -> Duration {
+    let chips = match bitrate {
+        BitRate::Kbps110 => 4096,
+        BitRate::Kbps850 => 512,
+        BitRate::Kbps6800 => 64,
+    };
+
+    Duration::from_ticks(chips * TICKS_PER_CHIP)
+}
+
+/// The bitrate for a `data_rate` of [Phy::send_with_data_rate]
+fn bitrate_of(data_rate: u8) -> Option<BitRate> {
+    match data_rate {
+        1 => Some(BitRate::Kbps110),
+        2 => Some(BitRate::Kbps850),
+        3 => Some(BitRate::Kbps6800),
+        _ => None,
+    }
+}
+
 enum DW1000<SPI> {
     Empty,
     Ready(dw1000::DW1000<SPI, Ready>),
     Receiving(dw1000::DW1000<SPI, AutoDoubleBufferReceiving>),
+    Sending(dw1000::DW1000<SPI, Sending>),
 }
 
 impl<SPI> DW1000<SPI> {
@@ -524,6 +1641,46 @@ impl<SPI> DW1000<SPI> {
         }
     }
 
+    fn take_sending(&mut self) -> Option<dw1000::DW1000<SPI, Sending>> {
+        match core::mem::replace(self, DW1000::Empty) {
+            Self::Sending(v) => Some(v),
+            val => {
+                *self = val;
+                None
+            }
+        }
+    }
+
+    /// Stop receiving if the radio is, so it becomes ready. A frame that's partially received is discarded.
+    ///
+    /// When the radio doesn't stop, the transceiver is forced off and it's tried once more.
+    /// If that doesn't help either, the radio is left receiving and the error is returned.
+    fn stop_receiving(&mut self) -> Result<(), dw1000::Error<SPI>>
+    where
+        SPI: SpiDevice,
+    {
+        let Some(dw1000) = self.take_receiving() else {
+            return Ok(());
+        };
+
+        let result = force_finish_receiving(
+            dw1000,
+            |dw1000| dw1000.finish_receiving(),
+            |dw1000| force_trx_off(dw1000.ll()).map_err(dw1000::Error::from),
+        );
+
+        match result {
+            Ok(dw1000) => {
+                *self = Self::Ready(dw1000);
+                Ok(())
+            }
+            Err((dw1000, e)) => {
+                *self = Self::Receiving(dw1000);
+                Err(e)
+            }
+        }
+    }
+
     fn as_ready_mut(&mut self) -> Option<&mut dw1000::DW1000<SPI, Ready>> {
         if let Self::Ready(v) = self {
             Some(v)
@@ -533,6 +1690,27 @@ impl<SPI> DW1000<SPI> {
     }
 }
 
+/// Turn a `receiving` radio into a ready one with `finish`.
+///
+/// If `finish` fails, the transceiver is forced off with `force_off` and `finish` is tried once more.
+/// The receiving radio is given back along with the error when that fails too.
+fn force_finish_receiving<Receiving, Ready, E>(
+    receiving: Receiving,
+    mut finish: impl FnMut(Receiving) -> Result<Ready, (Receiving, E)>,
+    force_off: impl FnOnce(&mut Receiving) -> Result<(), E>,
+) -> Result<Ready, (Receiving, E)> {
+    match finish(receiving) {
+        Ok(ready) => Ok(ready),
+        Err((mut receiving, _)) => {
+            if let Err(e) = force_off(&mut receiving) {
+                return Err((receiving, e));
+            }
+
+            finish(receiving)
+        }
+    }
+}
+
 pub enum Error<SPI: SpiDevice, IRQ: ErrorType> {
     DW1000(dw1000::Error<SPI>),
     Irq(IRQ::Error),
@@ -544,6 +1722,18 @@ pub enum Error<SPI: SpiDevice, IRQ: ErrorType> {
     RMarkerOffsetTooLarge,
     TimeTooFarInFuture,
     TimeTooCloseInFuture,
+    FrameTooLong,
+    FrameEmpty,
+    /// Frames were lost because both receive buffers were full. See [DW1000Phy::receive_overruns].
+    ReceiveOverrun,
+    /// The data rate is not one of the DW1000, see [DW1000Phy::set_data_rate]
+    UnsupportedDataRate,
+    /// The radio was used while it was sleeping, see [Phy::sleep]
+    Sleeping,
+    /// The radio didn't wake up, see [Phy::wake]
+    WakeUpFailed,
+    /// The radio can't do what was asked, like measuring the energy of a channel
+    Unsupported,
 }
 
 impl<SPI: SpiDevice, IRQ: ErrorType> From<dw1000::Error<SPI>> for Error<SPI, IRQ> {
@@ -568,6 +1758,13 @@ impl<SPI: SpiDevice, IRQ: ErrorType> defmt::Format for Error<SPI, IRQ> {
             Error::RMarkerOffsetTooLarge => defmt::write!(fmt, "RMarkerOffsetTooLarge"),
             Error::TimeTooFarInFuture => defmt::write!(fmt, "TimeTooFarInFuture"),
             Error::TimeTooCloseInFuture => defmt::write!(fmt, "TimeTooCloseInFuture"),
+            Error::FrameTooLong => defmt::write!(fmt, "FrameTooLong"),
+            Error::FrameEmpty => defmt::write!(fmt, "FrameEmpty"),
+            Error::ReceiveOverrun => defmt::write!(fmt, "ReceiveOverrun"),
+            Error::UnsupportedDataRate => defmt::write!(fmt, "UnsupportedDataRate"),
+            Error::Sleeping => defmt::write!(fmt, "Sleeping"),
+            Error::WakeUpFailed => defmt::write!(fmt, "WakeUpFailed"),
+            Error::Unsupported => defmt::write!(fmt, "Unsupported"),
         }
     }
 }
@@ -587,6 +1784,13 @@ impl<SPI: SpiDevice, IRQ: ErrorType> core::fmt::Debug for Error<SPI, IRQ> {
             Error::RMarkerOffsetTooLarge => f.debug_tuple("RMarkerOffsetTooLarge").finish(),
             Error::TimeTooFarInFuture => f.debug_tuple("TimeTooFarInFuture").finish(),
             Error::TimeTooCloseInFuture => f.debug_tuple("TimeTooCloseInFuture").finish(),
+            Error::FrameTooLong => f.debug_tuple("FrameTooLong").finish(),
+            Error::FrameEmpty => f.debug_tuple("FrameEmpty").finish(),
+            Error::ReceiveOverrun => f.debug_tuple("ReceiveOverrun").finish(),
+            Error::UnsupportedDataRate => f.debug_tuple("UnsupportedDataRate").finish(),
+            Error::Sleeping => f.debug_tuple("Sleeping").finish(),
+            Error::WakeUpFailed => f.debug_tuple("WakeUpFailed").finish(),
+            Error::Unsupported => f.debug_tuple("Unsupported").finish(),
         }
     }
 }
@@ -598,3 +1802,777 @@ impl<SPI: SpiDevice, IRQ: ErrorType> Display for Error<SPI, IRQ> {
 }
 
 impl<SPI: SpiDevice, IRQ: ErrorType> core::error::Error for Error<SPI, IRQ> {}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::cell::RefCell;
+    use std::vec::Vec;
+
+    use lr_wpan_rs::wire::{ExtendedAddress, PanId, ShortAddress};
+
+    use super::*;
+
+    #[test]
+    fn instant_is_monotonic() {
+        let mut last_instant = 0;
+
+        // Step through multiple wraparounds in uneven steps
+        let mut sys_time = 0;
+        for _ in 0..1000 {
+            sys_time = (sys_time + dw1000::time::TIME_MAX / 7) & dw1000::time::TIME_MAX;
+
+            let instant = next_instant(last_instant, sys_time);
+            assert!(instant >= last_instant);
+            assert_eq!(instant & dw1000::time::TIME_MAX, sys_time);
+
+            last_instant = instant;
+        }
+
+        // Reading the same time again doesn't move time
+        assert_eq!(next_instant(last_instant, sys_time), last_instant);
+    }
+
+    #[test]
+    fn diagnostics_are_calibrated() {
+        let diagnostics = Diagnostics::new(120, 60, 120, 60);
+        assert_eq!(diagnostics.temp_c, 23.0);
+        assert_eq!(diagnostics.voltage, 3.3);
+
+        let diagnostics = Diagnostics::new(130, 60 + 173, 120, 60);
+        assert!((diagnostics.temp_c - 34.4).abs() < 0.001);
+        assert!((diagnostics.voltage - 4.3).abs() < 0.001);
+        assert_eq!(diagnostics.raw_temperature, 130);
+        assert_eq!(diagnostics.raw_voltage, 60 + 173);
+    }
+
+    /// A delay that's over right away
+    struct NoDelay;
+
+    impl DelayNs for NoDelay {
+        async fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    #[test]
+    fn time_check_is_due_after_the_interval() {
+        let mut millis_until_next_time_check = 250;
+        let time_check = wait_for_time_check(
+            &mut NoDelay,
+            &mut millis_until_next_time_check,
+            NonZeroU32::new(250),
+        );
+        assert!(embassy_futures::poll_once(time_check).is_ready());
+        assert_eq!(millis_until_next_time_check, 0);
+    }
+
+    #[test]
+    fn disabled_time_check_never_completes() {
+        let mut millis_until_next_time_check = 0;
+        let time_check = wait_for_time_check(&mut NoDelay, &mut millis_until_next_time_check, None);
+        assert!(embassy_futures::poll_once(time_check).is_pending());
+
+        // So waiting only completes on the interrupt
+        let mut millis_until_next_time_check = 0;
+        let time_check = wait_for_time_check(&mut NoDelay, &mut millis_until_next_time_check, None);
+        let irq = core::future::ready(());
+        assert!(matches!(
+            embassy_futures::block_on(select(irq, time_check)),
+            Either::First(())
+        ));
+    }
+
+    /// An SPI bus that must not be used
+    struct NoSpi;
+
+    impl embedded_hal::spi::ErrorType for NoSpi {
+        type Error = core::convert::Infallible;
+    }
+
+    impl SpiDevice for NoSpi {
+        fn transaction(
+            &mut self,
+            _operations: &mut [embedded_hal::spi::Operation<'_, u8>],
+        ) -> Result<(), Self::Error> {
+            unreachable!("The SPI bus is used")
+        }
+    }
+
+    /// An interrupt line that never changes
+    struct NoIrq;
+
+    impl ErrorType for NoIrq {
+        type Error = core::convert::Infallible;
+    }
+
+    impl Wait for NoIrq {
+        async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+            core::future::pending().await
+        }
+
+        async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+            core::future::pending().await
+        }
+
+        async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+            core::future::pending().await
+        }
+
+        async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+            core::future::pending().await
+        }
+
+        async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+            core::future::pending().await
+        }
+    }
+
+    fn phy_without_radio(
+        time_check_interval_millis: Option<NonZeroU32>,
+    ) -> DW1000Phy<NoSpi, NoIrq, NoDelay> {
+        DW1000Phy::from_parts(DW1000::Empty, NoIrq, NoDelay, time_check_interval_millis)
+    }
+
+    #[test]
+    fn wait_completes_for_the_time_check() {
+        let mut phy = phy_without_radio(NonZeroU32::new(250));
+        assert!(matches!(
+            embassy_futures::block_on(phy.wait()),
+            Ok(Either::Second(()))
+        ));
+    }
+
+    #[test]
+    fn wait_without_time_checks_waits_on_the_interrupt() {
+        let mut phy = phy_without_radio(None);
+        assert!(embassy_futures::poll_once(phy.wait()).is_pending());
+    }
+
+    #[test]
+    fn wait_while_sleeping_never_completes() {
+        // Without sleeping, the time check would complete the wait right away
+        let mut phy = phy_without_radio(NonZeroU32::new(250));
+        phy.sleeping = true;
+        assert!(embassy_futures::poll_once(phy.wait()).is_pending());
+    }
+
+    #[test]
+    fn sleeping_radio_is_not_accessed() {
+        let mut phy = phy_without_radio(None);
+        phy.sleeping = true;
+        assert!(matches!(
+            embassy_futures::block_on(phy.get_instant()),
+            Err(Error::Sleeping)
+        ));
+    }
+
+    #[test]
+    fn send_setup_time_covers_the_minimum_delay() {
+        let phy = phy_without_radio(None);
+        let setup_time = phy.symbol_period() * phy.send_setup_time_symbols() as i64;
+        assert!(setup_time >= MIN_DELAYED_SEND_AHEAD);
+        assert!(setup_time < MIN_DELAYED_SEND_AHEAD + phy.symbol_period());
+    }
+
+    #[test]
+    fn scheduled_send_time_is_rounded_and_includes_the_antenna_delay() {
+        let mut phy = phy_without_radio(None);
+        phy.phy_pib.tx_rmarker_offset = 16400;
+
+        assert_eq!(
+            phy.scheduled_send_time(Instant::from_ticks(0x1_0000_0200)),
+            Instant::from_ticks(0x1_0000_0200 + 16400)
+        );
+        assert_eq!(
+            phy.scheduled_send_time(Instant::from_ticks(0x1_0000_03ff)),
+            Instant::from_ticks(0x1_0000_0200 + 16400)
+        );
+    }
+
+    #[test]
+    fn csma_send_is_not_refused() {
+        // Without a radio, the send only fails once it gets to the transmission
+        let mut phy = phy_without_radio(None);
+        assert!(matches!(
+            embassy_futures::block_on(phy.send(&[1], None, false, true, SendContinuation::Idle)),
+            Err(Error::WrongState)
+        ));
+        assert!(matches!(
+            embassy_futures::block_on(phy.send_back_to_back(
+                &[1],
+                &[2],
+                None,
+                false,
+                true,
+                Duration::from_micros(TURNAROUND_MICROS as i64),
+                SendContinuation::Idle
+            )),
+            Err(Error::WrongState)
+        ));
+    }
+
+    #[test]
+    fn response_window_is_in_uwb_microseconds() {
+        let window = |turnaround_time, timeout| {
+            ResponseWindow::of(SendContinuation::WaitForResponse {
+                turnaround_time,
+                timeout,
+            })
+            .unwrap()
+        };
+
+        // A UWB microsecond is 512/499.2 us
+        assert_eq!(
+            window(Duration::from_micros(1026), Duration::from_micros(10_257)),
+            ResponseWindow {
+                rx_after_tx_delay: 1000,
+                timeout: 10_001,
+            }
+        );
+
+        // The timeout is never 0, which would disable it, and saturates at its maximum of about 67 ms
+        assert_eq!(
+            window(Duration::from_ticks(0), Duration::from_ticks(0)).timeout,
+            1
+        );
+        assert_eq!(
+            window(Duration::from_ticks(0), Duration::from_millis(100)).timeout,
+            u16::MAX
+        );
+
+        assert_eq!(ResponseWindow::of(SendContinuation::Idle), None);
+        assert_eq!(
+            ResponseWindow::of(SendContinuation::ReceiveContinuous),
+            None
+        );
+    }
+
+    /// The SPI header of a write to a register without a sub-index
+    fn write_header(register: u8) -> u8 {
+        0x80 | register
+    }
+
+    #[test]
+    fn send_is_restarted_with_wait_for_response() {
+        let window = ResponseWindow {
+            rx_after_tx_delay: 0x5_4321,
+            timeout: 0x1234,
+        };
+
+        for delayed in [false, true] {
+            let transactions = RefCell::new(Vec::new());
+            let mut ll = dw1000::ll::DW1000::new(RecordingSpi {
+                transactions: &transactions,
+                answer: [0; 4],
+            });
+
+            start_wait_for_response(&mut ll, delayed, window).unwrap();
+
+            let transactions = transactions.into_inner();
+            assert_eq!(transactions.len(), 9);
+
+            // SYS_CTRL (0x0D) first gets TRXOFF to cancel the send of the driver
+            assert_eq!(transactions[0], [write_header(0x0D), 0x40, 0, 0, 0]);
+
+            // SYS_CFG (0x04) with DIS_DRXB and RXWTOE, but not RXAUTR
+            assert_eq!(transactions[2][0], write_header(0x04));
+            let sys_cfg = u32::from_le_bytes(transactions[2][1..5].try_into().unwrap());
+            assert_eq!(
+                sys_cfg & ((1 << 12) | (1 << 28) | (1 << 29)),
+                (1 << 12) | (1 << 28)
+            );
+
+            // RX_FWTO (0x0C) and W4R_TIM of ACK_RESP_T (0x1A)
+            assert_eq!(transactions[3], [write_header(0x0C), 0x34, 0x12]);
+            assert_eq!(transactions[5][0], write_header(0x1A));
+            let ack_resp_t = u32::from_le_bytes(transactions[5][1..5].try_into().unwrap());
+            assert_eq!(ack_resp_t & 0xF_FFFF, 0x5_4321);
+
+            // Then the send is started again with WAIT4RESP in SYS_CTRL, delayed like before
+            let sys_ctrl = 0x80 | 0x02 | ((delayed as u8) << 2);
+            assert_eq!(transactions[8], [write_header(0x0D), sys_ctrl, 0, 0, 0]);
+        }
+    }
+
+    #[test]
+    fn response_timeout_is_undone() {
+        let transactions = RefCell::new(Vec::new());
+        let mut ll = dw1000::ll::DW1000::new(RecordingSpi {
+            transactions: &transactions,
+            answer: [0xFF; 4],
+        });
+
+        finish_wait_for_response(&mut ll).unwrap();
+
+        let transactions = transactions.into_inner();
+        assert_eq!(transactions.len(), 3);
+        assert_eq!(transactions[0], [write_header(0x0D), 0x40, 0, 0, 0]);
+        // Only RXWTOE is cleared in SYS_CFG
+        let sys_cfg = u32::from_le_bytes(transactions[2][1..5].try_into().unwrap());
+        assert_eq!(sys_cfg, !(1 << 28));
+    }
+
+    #[test]
+    fn time_continues_after_a_wraparound_when_woken_up() {
+        const WRAPAROUND: u64 = dw1000::time::TIME_MAX + 1;
+
+        for last_minor_bits in [0, 1000, dw1000::time::TIME_MAX / 2, dw1000::time::TIME_MAX] {
+            let last_instant = 3 * WRAPAROUND + last_minor_bits;
+
+            // Wherever the clock restarts, the wraparound is counted
+            for sys_time in [0, 1000, dw1000::time::TIME_MAX / 2] {
+                let instant = next_instant(instant_before_wraparound(last_instant), sys_time);
+                assert_eq!(instant, 4 * WRAPAROUND + sys_time);
+                assert!(instant > last_instant);
+            }
+        }
+    }
+
+    /// An SPI bus that records the bytes written in every transaction and answers every read with the same bytes
+    struct RecordingSpi<'a> {
+        transactions: &'a RefCell<Vec<Vec<u8>>>,
+        answer: [u8; 4],
+    }
+
+    impl RecordingSpi<'_> {
+        fn answer(&self, buffer: &mut [u8]) {
+            for (byte, answer) in buffer.iter_mut().zip(self.answer.iter().cycle()) {
+                *byte = *answer;
+            }
+        }
+    }
+
+    impl embedded_hal::spi::ErrorType for RecordingSpi<'_> {
+        type Error = core::convert::Infallible;
+    }
+
+    impl SpiDevice for RecordingSpi<'_> {
+        fn transaction(
+            &mut self,
+            operations: &mut [embedded_hal::spi::Operation<'_, u8>],
+        ) -> Result<(), Self::Error> {
+            use embedded_hal::spi::Operation;
+
+            let mut written = Vec::new();
+            for operation in operations {
+                match operation {
+                    Operation::Read(buffer) => self.answer(buffer),
+                    Operation::Write(data) => written.extend_from_slice(data),
+                    Operation::Transfer(read, write) => {
+                        written.extend_from_slice(write);
+                        self.answer(read);
+                    }
+                    Operation::TransferInPlace(buffer) => {
+                        written.extend_from_slice(buffer);
+                        self.answer(buffer);
+                    }
+                    Operation::DelayNs(_) => {}
+                }
+            }
+            self.transactions.borrow_mut().push(written);
+
+            Ok(())
+        }
+    }
+
+    /// The SPI header of a write to a sub-register of the AON register file (0x2C), see section 2.2.1.2 of the DW1000 user manual
+    fn aon_write_header(sub_index: u8) -> [u8; 2] {
+        [0x80 | 0x40 | 0x2C, sub_index]
+    }
+
+    #[test]
+    fn sleep_saves_the_config_to_the_aon_memory() {
+        let transactions = RefCell::new(Vec::new());
+        let mut ll = dw1000::ll::DW1000::new(RecordingSpi {
+            transactions: &transactions,
+            answer: [0; 4],
+        });
+
+        enter_sleep(&mut ll).unwrap();
+
+        // The register layouts of section 7.2.46 of the DW1000 user manual. Whether the radio really
+        // sleeps and wakes up with them can only be checked with the hardware.
+        let transactions = transactions.into_inner();
+        assert_eq!(transactions.len(), 4);
+
+        // AON_WCFG (0x2C:00) has no sub-index in its header. ONW_LDC, PRES_SLEEP and ONW_LLDE are set,
+        // out of all the defined bits.
+        assert_eq!(transactions[0][0], 0x80 | 0x2C);
+        let aon_wcfg = u16::from_le_bytes([transactions[0][1], transactions[0][2]]);
+        assert_eq!(aon_wcfg & 0x19CB, 0x0940);
+
+        // AON_CFG0 (0x2C:06) has SLEEP_EN and WAKE_SPI set, but not WAKE_PIN, WAKE_CNT and LPDIV_EN
+        assert_eq!(transactions[1][..2], aon_write_header(0x06));
+        assert_eq!(transactions[1][2] & 0x1F, 0x05);
+
+        // The SAVE bit of AON_CTRL (0x2C:02) must go from 0 to 1 to upload the config
+        assert_eq!(transactions[2][..2], aon_write_header(0x02));
+        assert_eq!(transactions[2][2..], [0x00]);
+        assert_eq!(transactions[3][..2], aon_write_header(0x02));
+        assert_eq!(transactions[3][2..], [0x02]);
+    }
+
+    #[test]
+    fn frame_filter_lets_all_frame_types_through() {
+        for pan_coordinator in [false, true] {
+            let transactions = RefCell::new(Vec::new());
+            let mut ll = dw1000::ll::DW1000::new(RecordingSpi {
+                transactions: &transactions,
+                answer: [0; 4],
+            });
+
+            let filter = FrameFilter {
+                pan_id: PanId(0x1234),
+                short_address: ShortAddress(0x5678),
+                extended_address: ExtendedAddress(0x0102030405060708),
+                pan_coordinator,
+            };
+            write_frame_filter(&mut ll, &filter).unwrap();
+
+            let transactions = transactions.into_inner();
+            assert_eq!(transactions.len(), 4);
+
+            // PANADR (0x03) has the short address in the low half and the PAN id in the high half
+            assert_eq!(transactions[0], [0x80 | 0x03, 0x78, 0x56, 0x34, 0x12]);
+            // EUI (0x01)
+            assert_eq!(transactions[1][0], 0x80 | 0x01);
+            assert_eq!(transactions[1][1..], 0x0102030405060708u64.to_le_bytes());
+
+            // SYS_CFG (0x04) is read and written back with FFAB, FFAD, FFAA and FFAM set,
+            // and FFBC only for a PAN coordinator. FFEN is left to the rx config.
+            assert_eq!(transactions[2][0], 0x04);
+            assert_eq!(transactions[3][0], 0x80 | 0x04);
+            let sys_cfg = transactions[3][1] & 0x3F;
+            assert_eq!(sys_cfg, 0x3C | ((pan_coordinator as u8) << 1));
+        }
+    }
+
+    #[test]
+    fn sensors_are_read_while_sampling() {
+        let transactions = RefCell::new(Vec::new());
+        // SAR_LVBAT, SAR_LTEMP and the OTP words all answer with these bytes
+        let mut ll = dw1000::ll::DW1000::new(RecordingSpi {
+            transactions: &transactions,
+            answer: [170, 130, 0, 0],
+        });
+
+        let diagnostics = measure_sensors(&mut ll).unwrap();
+        assert_eq!(diagnostics.raw_voltage, 170);
+        assert_eq!(diagnostics.raw_temperature, 130);
+        assert_eq!(diagnostics, Diagnostics::new(130, 170, 170, 170));
+
+        // The steps of section 6.4 of the DW1000 user manual
+        let transactions = transactions.into_inner();
+        let rf_conf_write = |sub_index, value| [0x80 | 0x40 | 0x28, sub_index, value];
+        assert_eq!(transactions[0][..], rf_conf_write(0x11, 0x80));
+        assert_eq!(transactions[1][..], rf_conf_write(0x12, 0x0A));
+        assert_eq!(transactions[2][..], rf_conf_write(0x12, 0x0F));
+
+        // SAR_CTRL in TC_SARC (0x2A:00) goes from 0 to 1, then TC_SARL (0x2A:03) is read
+        // and SAR_CTRL is cleared again
+        assert_eq!(transactions[3][..2], [0x80 | 0x2A, 0x00]);
+        assert_eq!(transactions[4][..2], [0x80 | 0x2A, 0x01]);
+        assert_eq!(transactions[5], [0x40 | 0x2A, 0x03]);
+        assert_eq!(transactions[6][..2], [0x80 | 0x2A, 0x00]);
+
+        // Then the two calibration values come from the OTP memory, with 4 transactions each
+        assert_eq!(transactions.len(), 7 + 8);
+    }
+
+    #[test]
+    fn continuous_wave_configures_the_transmitter() {
+        let transactions = RefCell::new(Vec::new());
+        let mut ll = dw1000::ll::DW1000::new(RecordingSpi {
+            transactions: &transactions,
+            answer: [0; 4],
+        });
+
+        start_continuous_wave(&mut ll, dw1000::configs::UwbChannel::Channel5).unwrap();
+
+        // The register writes of `dwt_configcwmode`, the reads are of the registers that are modified
+        let transactions = transactions.into_inner();
+        let writes = transactions
+            .iter()
+            .filter(|transaction| transaction[0] & 0x80 != 0)
+            .collect::<Vec<_>>();
+        assert_eq!(writes.len(), 10);
+
+        // PMSC_CTRL0 (0x36:00) forces the system clock to the crystal and
+        // PMSC_CTRL1 (0x36:04) has all of PKTSEQ cleared
+        assert_eq!(writes[0][..2], [0x80 | 0x36, 0x01]);
+        assert_eq!(writes[1][..2], [0x80 | 0x40 | 0x36, 0x04]);
+        assert_eq!(u16::from_le_bytes([writes[1][2], writes[1][3]]) & 0x07F8, 0);
+
+        // The recommended values of channel 5 in FS_PLLCFG (0x2B:07), FS_PLLTUNE (0x2B:0B) and RF_TXCTRL (0x28:0C)
+        assert_eq!(writes[2][..2], [0x80 | 0x40 | 0x2B, 0x07]);
+        assert_eq!(writes[2][2..6], 0x0800041Du32.to_le_bytes());
+        assert_eq!(writes[3][..3], [0x80 | 0x40 | 0x2B, 0x0B, 0xBE]);
+        assert_eq!(writes[4][..2], [0x80 | 0x40 | 0x28, 0x0C]);
+        assert_eq!(writes[4][2..5], [0xE0, 0x3F, 0x1E]);
+
+        // RF_CONF (0x28:00) first powers up the PLL (TXPLLPOWEN) and then all of the transmitter (TXALLEN)
+        assert_eq!(writes[5][0], 0x80 | 0x28);
+        assert_eq!(writes[5][1..5], 0x001F_E000u32.to_le_bytes());
+        assert_eq!(writes[6][0], 0x80 | 0x28);
+        assert_eq!(writes[6][1..5], 0x005F_FF00u32.to_le_bytes());
+
+        // The system and transmit clocks come from the PLL
+        assert_eq!(writes[7][..2], [0x80 | 0x36, 0x22]);
+
+        // The fine grain transmit sequencing in PMSC_TXFSEQ (0x36:26) is off
+        assert_eq!(writes[8][..], [0x80 | 0x40 | 0x36, 0x26, 0x00, 0x00]);
+
+        // And finally TC_PGTEST (0x2A:0D) is set to continuous wave
+        assert_eq!(writes[9][..], [0x80 | 0x40 | 0x2A, 0x0D, 0x13]);
+    }
+
+    #[test]
+    fn awake_radio_answers_with_its_device_id() {
+        let transactions = RefCell::new(Vec::new());
+
+        // DEV_ID (0x00) of a DW1000 is 0xDECA0130
+        let mut ll = dw1000::ll::DW1000::new(RecordingSpi {
+            transactions: &transactions,
+            answer: 0xDECA0130u32.to_le_bytes(),
+        });
+        assert!(is_awake(&mut ll).unwrap());
+
+        // A sleeping radio doesn't drive the bus
+        let mut ll = dw1000::ll::DW1000::new(RecordingSpi {
+            transactions: &transactions,
+            answer: [0; 4],
+        });
+        assert!(!is_awake(&mut ll).unwrap());
+
+        // Both are a read of DEV_ID
+        assert!(
+            transactions
+                .borrow()
+                .iter()
+                .all(|transaction| transaction[0] == 0x00)
+        );
+    }
+
+    #[test]
+    fn wake_up_holds_the_chip_select_with_a_long_read() {
+        let transactions = RefCell::new(Vec::new());
+        let mut ll = dw1000::ll::DW1000::new(RecordingSpi {
+            transactions: &transactions,
+            answer: [0; 4],
+        });
+
+        wake_up(&mut ll).unwrap();
+
+        // A single read of RX_BUFFER (0x11), which is 1024 bytes long
+        let transactions = transactions.into_inner();
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0][0], 0x11);
+    }
+
+    #[test]
+    fn forcing_the_transceiver_off_clears_the_rx_events() {
+        let transactions = RefCell::new(Vec::new());
+        let mut ll = dw1000::ll::DW1000::new(RecordingSpi {
+            transactions: &transactions,
+            answer: [0; 4],
+        });
+
+        force_trx_off(&mut ll).unwrap();
+
+        let transactions = transactions.into_inner();
+        assert_eq!(transactions.len(), 2);
+
+        // TRXOFF in SYS_CTRL (0x0D)
+        assert_eq!(transactions[0], [0x80 | 0x0D, 0x40, 0, 0, 0]);
+
+        // Then all rx events in SYS_STATUS (0x0F) are cleared and nothing else: bits 8 to 18, RXOVRR (20),
+        // RXPTO (21), RXSFDTO (26) and AFFREJ (29)
+        assert_eq!(transactions[1][0], 0x80 | 0x0F);
+        let sys_status = u32::from_le_bytes(transactions[1][1..5].try_into().unwrap());
+        assert_eq!(sys_status, 0x2437_FF00);
+        assert_eq!(transactions[1][5], 0);
+    }
+
+    #[test]
+    fn repeated_spurious_irqs_reach_threshold() {
+        let mut counter = SpuriousIrqCounter::new(3);
+
+        // A storm of spurious interrupts triggers a reset every time the threshold is reached
+        let resets = (0..9).filter(|_| counter.register()).count();
+        assert_eq!(resets, 3);
+
+        // A handled interrupt in between starts the counting over
+        assert!(!counter.register());
+        assert!(!counter.register());
+        counter.reset();
+        assert!(!counter.register());
+        assert!(!counter.register());
+        assert!(counter.register());
+    }
+
+    #[test]
+    fn default_config_has_the_timings_of_its_preamble() {
+        let timings = ShrTimings::new(
+            DEFAULT_PREAMBLE_LENGTH,
+            DEFAULT_SFD_SEQUENCE,
+            DEFAULT_BITRATE,
+        );
+
+        // 1024 preamble symbols and an SFD of 8 symbols
+        assert_eq!(timings.shr_duration, 1032);
+        // Plus 128 octets of 9.18 symbols each
+        assert_eq!(timings.max_frame_duration, 2207);
+    }
+
+    #[test]
+    fn shorter_preamble_shortens_timings() {
+        let long = ShrTimings::new(
+            PreambleLength::Symbols1024,
+            SfdSequence::IEEE,
+            BitRate::Kbps850,
+        );
+        let short = ShrTimings::new(
+            PreambleLength::Symbols128,
+            SfdSequence::IEEE,
+            BitRate::Kbps850,
+        );
+
+        assert_eq!(long.shr_duration - short.shr_duration, 1024 - 128);
+        assert_eq!(
+            long.max_frame_duration - short.max_frame_duration,
+            1024 - 128
+        );
+    }
+
+    #[test]
+    fn sfd_sequence_changes_timings() {
+        let ieee = ShrTimings::new(
+            PreambleLength::Symbols256,
+            SfdSequence::IEEE,
+            BitRate::Kbps850,
+        );
+        let decawave = ShrTimings::new(
+            PreambleLength::Symbols256,
+            SfdSequence::Decawave,
+            BitRate::Kbps850,
+        );
+
+        assert!(decawave.shr_duration > ieee.shr_duration);
+        assert!(decawave.max_frame_duration > ieee.max_frame_duration);
+    }
+
+    #[test]
+    fn data_rate_changes_timings() {
+        let timings =
+            |bitrate| ShrTimings::new(PreambleLength::Symbols256, SfdSequence::IEEE, bitrate);
+        let slow = timings(BitRate::Kbps110);
+        let default = timings(BitRate::Kbps850);
+        let fast = timings(BitRate::Kbps6800);
+
+        assert_eq!(default.symbols_per_octet, SYMBOLS_PER_OCTET);
+        assert!(slow.max_frame_duration > default.max_frame_duration);
+        assert!(fast.max_frame_duration < default.max_frame_duration);
+        // The long SFD of 110kbps
+        assert_eq!(slow.shr_duration - default.shr_duration, 64 - 8);
+    }
+
+    #[test]
+    fn data_symbol_durations_follow_the_standard() {
+        // 1025.64 ns at 850 kb/s with a PRF of 16 MHz
+        let symbol_period = data_symbol_duration(BitRate::Kbps850);
+        assert_eq!(symbol_period.ticks(), 65536);
+        assert_eq!((symbol_period * 100_000).micros(), 102_564);
+
+        assert_eq!(data_symbol_duration(BitRate::Kbps110), symbol_period * 8);
+        assert_eq!(data_symbol_duration(BitRate::Kbps6800), symbol_period / 8);
+
+        // An octet takes a bit more than 8 symbols because of the Reed-Solomon coding
+        assert!((SYMBOLS_PER_OCTET - 9.17647).abs() < 0.0001);
+    }
+
+    #[test]
+    fn data_rates_map_to_bitrates() {
+        assert!(bitrate_of(0).is_none());
+        assert!(matches!(bitrate_of(1), Some(BitRate::Kbps110)));
+        assert!(matches!(bitrate_of(2), Some(BitRate::Kbps850)));
+        assert!(matches!(bitrate_of(3), Some(BitRate::Kbps6800)));
+        // 27.24 Mb/s isn't supported by the DW1000
+        assert!(bitrate_of(4).is_none());
+    }
+
+    /// A radio that fails to stop receiving the given number of times, like when it's in the middle of a frame
+    struct StubbornReceiver {
+        failures_left: u32,
+        forced_off: bool,
+    }
+
+    fn finish(mut receiver: StubbornReceiver) -> Result<bool, (StubbornReceiver, ())> {
+        if receiver.failures_left > 0 {
+            receiver.failures_left -= 1;
+            Err((receiver, ()))
+        } else {
+            Ok(receiver.forced_off)
+        }
+    }
+
+    #[test]
+    fn channel_switch_while_receiving_forces_the_receiver_off() {
+        let receiver = StubbornReceiver {
+            failures_left: 1,
+            forced_off: false,
+        };
+
+        let result = force_finish_receiving(receiver, finish, |receiver| {
+            receiver.forced_off = true;
+            Ok(())
+        });
+
+        assert!(matches!(result, Ok(true)));
+    }
+
+    #[test]
+    fn receiver_that_does_not_stop_is_given_back() {
+        let receiver = StubbornReceiver {
+            failures_left: 2,
+            forced_off: false,
+        };
+
+        let result = force_finish_receiving(receiver, finish, |receiver| {
+            receiver.forced_off = true;
+            Ok(())
+        });
+
+        let Err((receiver, ())) = result else {
+            panic!("The receiver must not be ready");
+        };
+        assert!(receiver.forced_off);
+
+        // When forcing the receiver off fails, stopping isn't tried again
+        let receiver = StubbornReceiver {
+            failures_left: 1,
+            forced_off: false,
+        };
+        let result = force_finish_receiving(receiver, finish, |_| Err(()));
+        assert!(matches!(
+            result,
+            Err((
+                StubbornReceiver {
+                    failures_left: 0,
+                    ..
+                },
+                ()
+            ))
+        ));
+    }
+
+    #[test]
+    fn receiver_that_stops_is_not_forced_off() {
+        let receiver = StubbornReceiver {
+            failures_left: 0,
+            forced_off: false,
+        };
+
+        let result = force_finish_receiving(receiver, finish, |_| panic!("Must not be forced off"));
+        assert!(matches!(result, Ok(false)));
+    }
+}
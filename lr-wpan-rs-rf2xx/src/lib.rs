@@ -0,0 +1,909 @@
+//! [Phy] implementation for the AT86RF233, a 2.4 GHz O-QPSK radio of the AT86RF2xx family.
+//!
+//! The radio is used in its basic operating mode. The automatic acknowledgement and retransmission
+//! features of the extended operating mode are not used, since the MAC does that itself.
+//!
+//! The radio has no timer that can be read by the host, so the time has to be provided by a [Clock].
+//! This makes the timestamps and the timed sends only as accurate as the clock and the latency of the host allow.
+
+#![no_std]
+
+use core::fmt::{Debug, Display};
+
+use embassy_futures::select::{Either, select};
+use embedded_hal_async::{
+    delay::DelayNs,
+    digital::{ErrorType, Wait},
+    spi::{Operation, SpiDevice},
+};
+use lr_wpan_rs::{
+    ChannelPage,
+    phy::{ModulationType, Phy, ReceivedMessage, SendContinuation, SendResult},
+    pib::{
+        CcaMode, ChannelDescription, NativePrf, PhyPib, PhyPibWrite, TXPowerTolerance,
+        UwbCurrentPulseShape,
+    },
+    time::{Duration, Instant, TICKS_PER_MILLI},
+};
+
+mod registers;
+
+use registers::{irq, phy_cc_cca, phy_ed_level, phy_rssi, trx_cmd, trx_ctrl_1, trx_status};
+
+const OQPSK_CHANNEL_PAGE: ChannelPage = ChannelPage::Mhz868_915_2450;
+const OQPSK_CHANNELS: &[u8] = &[
+    11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26,
+];
+
+/// The size of the FCS that the radio appends and checks automatically
+const FCS_LENGTH: usize = 2;
+/// How many times the state is polled before a state transition is considered failed
+const STATE_TRANSITION_POLLS: u32 = 100;
+
+/// A source of time for the radio
+pub trait Clock {
+    /// Get the current time. This must never go backwards.
+    fn now(&mut self) -> Instant;
+}
+
+/// The [Phy] for the AT86RF233
+///
+/// The radio adds the FCS to every frame it sends and strips it from every frame it receives,
+/// flagging the result of its check in [ReceivedMessage::crc_ok]. So leave
+/// [MacConfig::mac_fcs](lr_wpan_rs::mac::MacConfig::mac_fcs) off, or every frame gets two FCSs.
+pub struct Rf2xxPhy<SPI: SpiDevice, IRQ: Wait, DELAY: DelayNs, CLOCK: Clock> {
+    spi: SPI,
+    irq: IRQ,
+    delay: DELAY,
+    clock: CLOCK,
+    receiving: bool,
+    /// If false, the channel is always assumed to be clear
+    cca_enabled: bool,
+    phy_pib: PhyPib,
+}
+
+impl<SPI: SpiDevice, IRQ: Wait, DELAY: DelayNs, CLOCK: Clock> Rf2xxPhy<SPI, IRQ, DELAY, CLOCK> {
+    /// Create a new phy.
+    ///
+    /// The irq pin must be connected to the IRQ output of the radio, which must be active high (the default).
+    pub async fn new(
+        spi: SPI,
+        irq: IRQ,
+        delay: DELAY,
+        clock: CLOCK,
+    ) -> Result<Self, Error<SPI, IRQ>> {
+        let mut s = Self {
+            spi,
+            irq,
+            delay,
+            clock,
+            receiving: false,
+            cca_enabled: true,
+            phy_pib: PhyPib::unspecified_new(),
+        };
+
+        let part_num = s.read_register(registers::PART_NUM).await?;
+        if part_num != registers::PART_NUM_AT86RF233 {
+            return Err(Error::UnknownPartNumber(part_num));
+        }
+
+        s.reset().await?;
+
+        Ok(s)
+    }
+
+    async fn read_register(&mut self, register: u8) -> Result<u8, Error<SPI, IRQ>> {
+        let mut value = [0];
+        self.spi
+            .transaction(&mut [
+                Operation::Write(&[registers::command::REGISTER_READ | register]),
+                Operation::Read(&mut value),
+            ])
+            .await
+            .map_err(Error::Spi)?;
+
+        Ok(value[0])
+    }
+
+    async fn write_register(&mut self, register: u8, value: u8) -> Result<(), Error<SPI, IRQ>> {
+        self.spi
+            .write(&[registers::command::REGISTER_WRITE | register, value])
+            .await
+            .map_err(Error::Spi)
+    }
+
+    async fn modify_register(
+        &mut self,
+        register: u8,
+        mask: u8,
+        value: u8,
+    ) -> Result<(), Error<SPI, IRQ>> {
+        let old_value = self.read_register(register).await?;
+        self.write_register(register, (old_value & !mask) | (value & mask))
+            .await
+    }
+
+    /// Give the radio a state command and wait until it has reached the expected state
+    async fn set_state(&mut self, command: u8, expected_status: u8) -> Result<(), Error<SPI, IRQ>> {
+        self.write_register(registers::TRX_STATE, command).await?;
+
+        for _ in 0..STATE_TRANSITION_POLLS {
+            let status = self.read_register(registers::TRX_STATUS).await? & trx_status::MASK;
+
+            if status == expected_status {
+                return Ok(());
+            }
+
+            self.delay.delay_us(10).await;
+        }
+
+        Err(Error::StateTransitionFailed)
+    }
+
+    async fn read_irq_status(&mut self) -> Result<u8, Error<SPI, IRQ>> {
+        // Reading the status clears the interrupts
+        self.read_register(registers::IRQ_STATUS).await
+    }
+
+    /// Wait until the radio signals the end of a transmission or reception
+    async fn wait_for_trx_end(&mut self) -> Result<(), Error<SPI, IRQ>> {
+        loop {
+            self.irq.wait_for_high().await.map_err(Error::Irq)?;
+
+            if self.read_irq_status().await? & irq::TRX_END != 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    async fn write_frame(&mut self, data: &[u8]) -> Result<(), Error<SPI, IRQ>> {
+        // The PHR contains the length of the PSDU, which includes the FCS the radio adds
+        let phr = (data.len() + FCS_LENGTH) as u8;
+
+        self.spi
+            .transaction(&mut [
+                Operation::Write(&[registers::command::FRAME_BUFFER_WRITE, phr]),
+                Operation::Write(data),
+            ])
+            .await
+            .map_err(Error::Spi)
+    }
+
+    /// Read the received frame from the frame buffer.
+    ///
+    /// Frames with an invalid FCS are still returned, but flagged with `crc_ok`.
+    async fn read_frame(
+        &mut self,
+        end_time: Instant,
+    ) -> Result<Option<ReceivedMessage>, Error<SPI, IRQ>> {
+        let crc_ok = self.read_register(registers::PHY_RSSI).await? & phy_rssi::RX_CRC_VALID != 0;
+
+        let mut phr = [0];
+        self.spi
+            .transaction(&mut [
+                Operation::Write(&[registers::command::FRAME_BUFFER_READ]),
+                Operation::Read(&mut phr),
+            ])
+            .await
+            .map_err(Error::Spi)?;
+
+        let psdu_length = (phr[0] as usize).min(lr_wpan_rs::consts::MAX_PHY_PACKET_SIZE);
+        if psdu_length <= FCS_LENGTH {
+            return Ok(None);
+        }
+
+        // PHR + PSDU + LQI
+        let mut buffer = [0; lr_wpan_rs::consts::MAX_PHY_PACKET_SIZE + 2];
+        self.spi
+            .transaction(&mut [
+                Operation::Write(&[registers::command::FRAME_BUFFER_READ]),
+                Operation::Read(&mut buffer[..psdu_length + 2]),
+            ])
+            .await
+            .map_err(Error::Spi)?;
+
+        let data = &buffer[1..1 + psdu_length - FCS_LENGTH];
+        let lqi = buffer[1 + psdu_length];
+
+        // The interrupt came at the end of the frame, but the timestamp must be at the start of it.
+        // The SHR is 5 octets and then there's the PHR.
+        let frame_octets = 5 + 1 + psdu_length as i64;
+        let timestamp = end_time
+            - self.symbol_period() * (frame_octets * self.phy_pib.symbols_per_octet as i64);
+
+        Ok(Some(ReceivedMessage {
+            timestamp,
+            data: data.try_into().unwrap(),
+            lqi,
+            channel: self.phy_pib.current_channel,
+            page: self.phy_pib.current_page,
+            crc_ok: Some(crc_ok),
+            // The radio doesn't support ranging
+            ranging: false,
+            // The O-QPSK phy has only one data rate
+            data_rate: 0,
+        }))
+    }
+
+    /// Do a clear channel assessment. Returns true if the channel is clear.
+    ///
+    /// The radio is in the RX_ON state afterwards.
+    async fn clear_channel_assessment(&mut self) -> Result<bool, Error<SPI, IRQ>> {
+        self.set_state(trx_cmd::RX_ON, trx_status::RX_ON).await?;
+        self.modify_register(
+            registers::PHY_CC_CCA,
+            phy_cc_cca::CCA_REQUEST,
+            phy_cc_cca::CCA_REQUEST,
+        )
+        .await?;
+
+        // The CCA takes 8 symbols
+        self.delay.delay_us(128).await;
+
+        for _ in 0..STATE_TRANSITION_POLLS {
+            let status = self.read_register(registers::TRX_STATUS).await?;
+
+            if status & trx_status::CCA_DONE != 0 {
+                return Ok(status & trx_status::CCA_STATUS != 0);
+            }
+
+            self.delay.delay_us(10).await;
+        }
+
+        Err(Error::StateTransitionFailed)
+    }
+
+    async fn delay_until(&mut self, time: Instant) {
+        let now = self.clock.now();
+
+        // If we're late, we're sending as soon as possible
+        if time > now {
+            self.delay
+                .delay_us(duration_to_micros(time.duration_since(now)))
+                .await;
+        }
+    }
+
+    /// Transmit the data at the given time and wait until it has been sent.
+    ///
+    /// Returns the time the transmission was started.
+    async fn transmit(
+        &mut self,
+        data: &[u8],
+        send_time: Option<Instant>,
+    ) -> Result<Instant, Error<SPI, IRQ>> {
+        self.set_state(trx_cmd::FORCE_PLL_ON, trx_status::PLL_ON)
+            .await?;
+        self.write_frame(data).await?;
+
+        if let Some(send_time) = send_time {
+            self.delay_until(send_time).await;
+        }
+
+        // Clear any stale interrupts
+        self.read_irq_status().await?;
+
+        self.write_register(registers::TRX_STATE, trx_cmd::TX_START)
+            .await?;
+        let tx_time = self.clock.now();
+
+        self.wait_for_trx_end().await?;
+
+        Ok(tx_time)
+    }
+
+    async fn continue_after_send(
+        &mut self,
+        continuation: SendContinuation,
+    ) -> Result<Option<ReceivedMessage>, Error<SPI, IRQ>> {
+        match continuation {
+            SendContinuation::Idle => {
+                self.stop_receive().await?;
+                Ok(None)
+            }
+            SendContinuation::WaitForResponse {
+                turnaround_time,
+                timeout,
+            } => {
+                self.delay
+                    .delay_us(duration_to_micros(turnaround_time))
+                    .await;
+                self.set_state(trx_cmd::RX_ON, trx_status::RX_ON).await?;
+
+                let response = match select(
+                    self.irq.wait_for_high(),
+                    self.delay.delay_us(duration_to_micros(timeout)),
+                )
+                .await
+                {
+                    Either::First(irq_result) => {
+                        irq_result.map_err(Error::Irq)?;
+                        let end_time = self.clock.now();
+
+                        if self.read_irq_status().await? & irq::TRX_END != 0 {
+                            self.read_frame(end_time).await?
+                        } else {
+                            None
+                        }
+                    }
+                    Either::Second(()) => None,
+                };
+
+                self.set_state(trx_cmd::FORCE_TRX_OFF, trx_status::TRX_OFF)
+                    .await?;
+                self.receiving = false;
+
+                Ok(response)
+            }
+            SendContinuation::ReceiveContinuous => {
+                self.set_state(trx_cmd::RX_ON, trx_status::RX_ON).await?;
+                self.receiving = true;
+                Ok(None)
+            }
+        }
+    }
+
+    fn check_frame_length(data: &[u8]) -> Result<(), Error<SPI, IRQ>> {
+        if data.is_empty() {
+            return Err(Error::FrameEmpty);
+        }
+
+        // The FCS is added by the radio
+        if data.len() + FCS_LENGTH > lr_wpan_rs::consts::MAX_PHY_PACKET_SIZE {
+            return Err(Error::FrameTooLong);
+        }
+
+        Ok(())
+    }
+}
+
+impl<SPI: SpiDevice, IRQ: Wait, DELAY: DelayNs, CLOCK: Clock> Phy
+    for Rf2xxPhy<SPI, IRQ, DELAY, CLOCK>
+{
+    type Error = Error<SPI, IRQ>;
+
+    /// The time at which the interrupt came
+    type ProcessingContext = Instant;
+
+    const MODULATION: ModulationType = ModulationType::OQPSK;
+
+    async fn reset(&mut self) -> Result<(), Self::Error> {
+        // Values for the 2450 MHz O-QPSK PHY
+        const SHR_DURATION: u32 = 10;
+        const SYMBOLS_PER_OCTET: f32 = 2.0;
+        const MAX_FRAME_DURATION: u32 =
+            SHR_DURATION + (lr_wpan_rs::consts::MAX_PHY_PACKET_SIZE as u32 + 1) * 2;
+
+        self.set_state(trx_cmd::FORCE_TRX_OFF, trx_status::TRX_OFF)
+            .await?;
+        self.receiving = false;
+
+        self.modify_register(
+            registers::TRX_CTRL_1,
+            trx_ctrl_1::TX_AUTO_CRC_ON,
+            trx_ctrl_1::TX_AUTO_CRC_ON,
+        )
+        .await?;
+        self.write_register(registers::IRQ_MASK, irq::TRX_END)
+            .await?;
+        self.read_irq_status().await?;
+
+        self.phy_pib = PhyPib {
+            pib_write: PhyPibWrite {
+                current_channel: 11,
+                tx_power_tolerance: TXPowerTolerance::DB3,
+                tx_power: 4,
+                cca_mode: CcaMode::EnergyAboveThreshold,
+                current_page: OQPSK_CHANNEL_PAGE,
+                uwb_current_pulse_shape: UwbCurrentPulseShape::Mandatory,
+                uwb_cou_pulse: lr_wpan_rs::pib::UwbCouPulse::CCh1,
+                uwb_cs_pulse: lr_wpan_rs::pib::UwbCsPulse::No1,
+                uwb_lcp_weight1: 0,
+                uwb_lcp_weight2: 0,
+                uwb_lcp_weight3: 0,
+                uwb_lcp_weight4: 0,
+                uwb_lcp_delay2: 0,
+                uwb_lcp_delay3: 0,
+                uwb_lcp_delay4: 0,
+                current_code: 0,
+                native_prf: NativePrf::NonUwb,
+                uwb_scan_bins_per_channel: 0,
+                uwb_inserted_preamble_interval: 0,
+                tx_rmarker_offset: 0,
+                rx_rmarker_offset: 0,
+                rframe_processing_time: 0,
+                cca_duration: 0,
+            },
+            channels_supported: &[ChannelDescription {
+                page: OQPSK_CHANNEL_PAGE,
+                channel_numbers: OQPSK_CHANNELS,
+            }],
+            max_frame_duration: MAX_FRAME_DURATION,
+            shr_duration: SHR_DURATION,
+            symbols_per_octet: SYMBOLS_PER_OCTET,
+            preamble_symbol_length: 0,
+            uwb_data_rates_supported: &[],
+            css_low_data_rate_supported: false,
+            uwb_cou_supported: false,
+            uwb_cs_supported: false,
+            uwb_lcp_supported: false,
+            ranging: false,
+            ranging_crystal_offset: false,
+            ranging_dps: false,
+        };
+
+        // Apply the pib
+        self.update_phy_pib(|_| {}).await?;
+
+        Ok(())
+    }
+
+    async fn get_instant(&mut self) -> Result<Instant, Self::Error> {
+        Ok(self.clock.now())
+    }
+
+    fn symbol_period(&self) -> Duration {
+        // 62.5 ksymbol/s
+        Duration::from_micros(16)
+    }
+
+    async fn send(
+        &mut self,
+        data: &[u8],
+        send_time: Option<Instant>,
+        ranging: bool,
+        use_csma: bool,
+        continuation: SendContinuation,
+    ) -> Result<SendResult, Self::Error> {
+        Self::check_frame_length(data)?;
+
+        // Ranging is not supported by this radio
+        let _ = ranging;
+
+        // Only a single CCA is done, the random backoffs of the CSMA-CA algorithm are done by the MAC
+        if use_csma && self.cca_enabled && !self.clear_channel_assessment().await? {
+            // The CCA left the receiver on
+            if matches!(continuation, SendContinuation::ReceiveContinuous) {
+                self.receiving = true;
+            } else {
+                self.stop_receive().await?;
+            }
+
+            return Ok(SendResult::ChannelAccessFailure);
+        }
+
+        let tx_time = self.transmit(data, send_time).await?;
+        let response = self.continue_after_send(continuation).await?;
+
+        Ok(SendResult::Success(tx_time, response))
+    }
+
+    async fn start_receive(&mut self) -> Result<(), Self::Error> {
+        if self.receiving {
+            return Ok(());
+        }
+
+        self.read_irq_status().await?;
+        self.set_state(trx_cmd::RX_ON, trx_status::RX_ON).await?;
+        self.receiving = true;
+
+        Ok(())
+    }
+
+    async fn stop_receive(&mut self) -> Result<(), Self::Error> {
+        self.set_state(trx_cmd::FORCE_TRX_OFF, trx_status::TRX_OFF)
+            .await?;
+        self.receiving = false;
+
+        Ok(())
+    }
+
+    async fn abort_send(&mut self) -> Result<(), Self::Error> {
+        // Forcing the transceiver off also aborts a transmission that is waiting or ongoing
+        self.stop_receive().await
+    }
+
+    async fn measure_energy(&mut self) -> Result<u8, Self::Error> {
+        self.set_state(trx_cmd::RX_ON, trx_status::RX_ON).await?;
+
+        // Writing any value starts the measurement
+        self.write_register(registers::PHY_ED_LEVEL, 0).await?;
+
+        // The measurement takes 8 symbols
+        self.delay.delay_us(128).await;
+
+        let mut ed_level = phy_ed_level::IN_PROGRESS;
+        for _ in 0..STATE_TRANSITION_POLLS {
+            ed_level = self.read_register(registers::PHY_ED_LEVEL).await?;
+
+            if ed_level != phy_ed_level::IN_PROGRESS {
+                break;
+            }
+
+            self.delay.delay_us(10).await;
+        }
+
+        if ed_level == phy_ed_level::IN_PROGRESS {
+            return Err(Error::StateTransitionFailed);
+        }
+
+        if !self.receiving {
+            self.set_state(trx_cmd::FORCE_TRX_OFF, trx_status::TRX_OFF)
+                .await?;
+        }
+
+        // Scale to the full range of the ED value of the standard
+        Ok((ed_level.min(phy_ed_level::MAX) as u16 * 0xFF / phy_ed_level::MAX as u16) as u8)
+    }
+
+    async fn wait(&mut self) -> Result<Self::ProcessingContext, Self::Error> {
+        self.irq.wait_for_high().await.map_err(Error::Irq)?;
+        // Take the time right away. Processing may happen a lot later, which must not shift the timestamp.
+        Ok(self.clock.now())
+    }
+
+    async fn process(
+        &mut self,
+        end_time: Self::ProcessingContext,
+    ) -> Result<Option<ReceivedMessage>, Self::Error> {
+        let irq_status = self.read_irq_status().await?;
+
+        if irq_status & irq::TRX_END == 0 || !self.receiving {
+            // Spurious interrupt?
+            return Ok(None);
+        }
+
+        // In the basic operating mode, the radio goes back to RX_ON by itself
+        self.read_frame(end_time).await
+    }
+
+    async fn update_phy_pib<U>(
+        &mut self,
+        f: impl FnOnce(&mut PhyPibWrite) -> U,
+    ) -> Result<U, Self::Error> {
+        let old_pib = self.phy_pib.pib_write.clone();
+
+        let return_value = f(&mut self.phy_pib.pib_write);
+
+        let PhyPibWrite {
+            current_channel,
+            tx_power_tolerance,
+            tx_power,
+            cca_mode,
+            current_page,
+            uwb_current_pulse_shape,
+            uwb_cou_pulse,
+            uwb_cs_pulse,
+            uwb_lcp_weight1,
+            uwb_lcp_weight2,
+            uwb_lcp_weight3,
+            uwb_lcp_weight4,
+            uwb_lcp_delay2,
+            uwb_lcp_delay3,
+            uwb_lcp_delay4,
+            current_code,
+            native_prf,
+            uwb_scan_bins_per_channel,
+            uwb_inserted_preamble_interval,
+            tx_rmarker_offset,
+            rx_rmarker_offset,
+            rframe_processing_time,
+            cca_duration,
+        } = self.phy_pib.pib_write.clone();
+
+        // Check everything before touching the radio
+        let check_settings = || {
+            if current_page != OQPSK_CHANNEL_PAGE {
+                return Err(Error::UnsupportedChannelPage);
+            }
+
+            if !OQPSK_CHANNELS.contains(&current_channel) {
+                return Err(Error::UnsupportedChannelNumber);
+            }
+
+            let cca_mode = match cca_mode {
+                CcaMode::EnergyAboveThreshold => Some(1),
+                CcaMode::CarrierSenseOnly => Some(2),
+                CcaMode::CarrierSenseEnergyAboveTheshold => Some(3),
+                CcaMode::Aloha => None,
+                CcaMode::UwbPreambleSenseShr | CcaMode::UwbPreambleSensePacket => {
+                    return Err(Error::UnsupportedCcaMode);
+                }
+            };
+
+            // Pick the strongest setting that doesn't exceed the requested power
+            let tx_power_setting = registers::TX_POWER_TABLE
+                .iter()
+                .position(|power| *power <= tx_power.saturating_mul(10))
+                .unwrap_or(registers::TX_POWER_TABLE.len() - 1)
+                as u8;
+
+            // TODO: Not reflected in hardware
+            let _ = tx_power_tolerance;
+
+            // Only relevant for UWB
+            let _ = (
+                uwb_current_pulse_shape,
+                uwb_cou_pulse,
+                uwb_cs_pulse,
+                uwb_lcp_weight1,
+                uwb_lcp_weight2,
+                uwb_lcp_weight3,
+                uwb_lcp_weight4,
+                uwb_lcp_delay2,
+                uwb_lcp_delay3,
+                uwb_lcp_delay4,
+                current_code,
+                native_prf,
+                uwb_scan_bins_per_channel,
+                uwb_inserted_preamble_interval,
+                tx_rmarker_offset,
+                rx_rmarker_offset,
+                rframe_processing_time,
+                cca_duration,
+            );
+
+            Ok((cca_mode, tx_power_setting))
+        };
+
+        let (cca_mode, tx_power_setting) = match check_settings() {
+            Ok(settings) => settings,
+            Err(e) => {
+                self.phy_pib.pib_write = old_pib;
+                return Err(e);
+            }
+        };
+
+        self.cca_enabled = cca_mode.is_some();
+        self.modify_register(
+            registers::PHY_CC_CCA,
+            phy_cc_cca::CCA_MODE_MASK | phy_cc_cca::CHANNEL_MASK,
+            (cca_mode.unwrap_or(1) << phy_cc_cca::CCA_MODE_OFFSET) | current_channel,
+        )
+        .await?;
+        self.write_register(registers::PHY_TX_PWR, tx_power_setting)
+            .await?;
+
+        Ok(return_value)
+    }
+
+    fn get_phy_pib(&mut self) -> &PhyPib {
+        &self.phy_pib
+    }
+}
+
+fn duration_to_micros(duration: Duration) -> u32 {
+    (duration.ticks().max(0) as u64 * 1000 / TICKS_PER_MILLI).min(u32::MAX as u64) as u32
+}
+
+pub enum Error<SPI: SpiDevice, IRQ: ErrorType> {
+    Spi(SPI::Error),
+    Irq(IRQ::Error),
+    UnknownPartNumber(u8),
+    StateTransitionFailed,
+    UnsupportedChannelNumber,
+    UnsupportedChannelPage,
+    UnsupportedCcaMode,
+    FrameTooLong,
+    FrameEmpty,
+}
+
+#[cfg(feature = "defmt-03")]
+impl<SPI: SpiDevice, IRQ: ErrorType> defmt::Format for Error<SPI, IRQ> {
+    fn format(&self, fmt: defmt::Formatter) {
+        match self {
+            Error::Spi(error) => defmt::write!(fmt, "Spi: {}", defmt::Debug2Format(error)),
+            Error::Irq(error) => defmt::write!(fmt, "Irq: {}", defmt::Debug2Format(error)),
+            Error::UnknownPartNumber(part_num) => {
+                defmt::write!(fmt, "UnknownPartNumber({})", part_num)
+            }
+            Error::StateTransitionFailed => defmt::write!(fmt, "StateTransitionFailed"),
+            Error::UnsupportedChannelNumber => defmt::write!(fmt, "UnsupportedChannelNumber"),
+            Error::UnsupportedChannelPage => defmt::write!(fmt, "UnsupportedChannelPage"),
+            Error::UnsupportedCcaMode => defmt::write!(fmt, "UnsupportedCcaMode"),
+            Error::FrameTooLong => defmt::write!(fmt, "FrameTooLong"),
+            Error::FrameEmpty => defmt::write!(fmt, "FrameEmpty"),
+        }
+    }
+}
+
+impl<SPI: SpiDevice, IRQ: ErrorType> core::fmt::Debug for Error<SPI, IRQ> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::Spi(arg0) => f.debug_tuple("Spi").field(arg0).finish(),
+            Error::Irq(arg0) => f.debug_tuple("Irq").field(arg0).finish(),
+            Error::UnknownPartNumber(arg0) => {
+                f.debug_tuple("UnknownPartNumber").field(arg0).finish()
+            }
+            Error::StateTransitionFailed => f.debug_tuple("StateTransitionFailed").finish(),
+            Error::UnsupportedChannelNumber => f.debug_tuple("UnsupportedChannelNumber").finish(),
+            Error::UnsupportedChannelPage => f.debug_tuple("UnsupportedChannelPage").finish(),
+            Error::UnsupportedCcaMode => f.debug_tuple("UnsupportedCcaMode").finish(),
+            Error::FrameTooLong => f.debug_tuple("FrameTooLong").finish(),
+            Error::FrameEmpty => f.debug_tuple("FrameEmpty").finish(),
+        }
+    }
+}
+
+impl<SPI: SpiDevice, IRQ: ErrorType> Display for Error<SPI, IRQ> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        <Self as Debug>::fmt(self, f)
+    }
+}
+
+impl<SPI: SpiDevice, IRQ: ErrorType> core::error::Error for Error<SPI, IRQ> {}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::{vec, vec::Vec};
+
+    use embedded_hal_async::spi::ErrorType as SpiErrorType;
+
+    use super::*;
+
+    /// An SPI bus that answers the reads with the given data, in order
+    struct ScriptedSpi {
+        reads: Vec<Vec<u8>>,
+    }
+
+    impl SpiErrorType for ScriptedSpi {
+        type Error = core::convert::Infallible;
+    }
+
+    impl SpiDevice for ScriptedSpi {
+        async fn transaction(
+            &mut self,
+            operations: &mut [Operation<'_, u8>],
+        ) -> Result<(), Self::Error> {
+            for operation in operations {
+                if let Operation::Read(buffer) = operation {
+                    let answer = self.reads.remove(0);
+                    buffer.copy_from_slice(&answer[..buffer.len()]);
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    /// An interrupt line that never changes
+    struct NoIrq;
+
+    impl ErrorType for NoIrq {
+        type Error = core::convert::Infallible;
+    }
+
+    impl Wait for NoIrq {
+        async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+            core::future::pending().await
+        }
+
+        async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+            core::future::pending().await
+        }
+
+        async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+            core::future::pending().await
+        }
+
+        async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+            core::future::pending().await
+        }
+
+        async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+            core::future::pending().await
+        }
+    }
+
+    /// A delay that's over right away
+    struct NoDelay;
+
+    impl DelayNs for NoDelay {
+        async fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    /// A clock that doesn't move
+    struct FixedClock(Instant);
+
+    impl Clock for FixedClock {
+        fn now(&mut self) -> Instant {
+            self.0
+        }
+    }
+
+    type TestPhy = Rf2xxPhy<ScriptedSpi, NoIrq, NoDelay, FixedClock>;
+
+    fn phy_with_reads(reads: Vec<Vec<u8>>) -> TestPhy {
+        Rf2xxPhy {
+            spi: ScriptedSpi { reads },
+            irq: NoIrq,
+            delay: NoDelay,
+            clock: FixedClock(Instant::from_seconds(1)),
+            receiving: true,
+            cca_enabled: true,
+            phy_pib: PhyPib {
+                symbols_per_octet: 2.0,
+                ..PhyPib::unspecified_new()
+            },
+        }
+    }
+
+    #[test]
+    fn durations_are_converted_to_micros() {
+        assert_eq!(duration_to_micros(Duration::from_micros(20)), 20);
+        assert_eq!(duration_to_micros(Duration::from_millis(5)), 5000);
+
+        // Negative durations are over right away and long ones saturate
+        assert_eq!(duration_to_micros(Duration::from_micros(-20)), 0);
+        assert_eq!(duration_to_micros(Duration::from_seconds(10_000)), u32::MAX);
+    }
+
+    #[test]
+    fn frame_length_leaves_room_for_the_fcs() {
+        assert!(matches!(
+            TestPhy::check_frame_length(&[]),
+            Err(Error::FrameEmpty)
+        ));
+        assert!(TestPhy::check_frame_length(&[0; 125]).is_ok());
+        assert!(matches!(
+            TestPhy::check_frame_length(&[0; 126]),
+            Err(Error::FrameTooLong)
+        ));
+    }
+
+    #[test]
+    fn received_frame_is_read_without_fcs_and_timestamped_at_its_start() {
+        let mut phy = phy_with_reads(vec![
+            // PHY_RSSI with a valid CRC
+            vec![phy_rssi::RX_CRC_VALID],
+            // The PHR with the length of the PSDU
+            vec![5],
+            // PHR + PSDU (with the FCS) + LQI
+            vec![5, 1, 2, 3, 0xAA, 0xBB, 200],
+        ]);
+        let end_time = Instant::from_seconds(1);
+
+        let message = embassy_futures::block_on(phy.read_frame(end_time))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(&message.data[..], [1, 2, 3]);
+        assert_eq!(message.lqi, 200);
+        assert_eq!(message.crc_ok, Some(true));
+        // The SHR (5 octets), PHR and PSDU all take 2 symbols per octet
+        assert_eq!(
+            message.timestamp,
+            end_time - phy.symbol_period() * ((5 + 1 + 5) * 2)
+        );
+    }
+
+    #[test]
+    fn received_frame_without_payload_is_dropped() {
+        let mut phy = phy_with_reads(vec![vec![0], vec![FCS_LENGTH as u8]]);
+
+        assert!(matches!(
+            embassy_futures::block_on(phy.read_frame(Instant::from_seconds(1))),
+            Ok(None)
+        ));
+    }
+
+    #[test]
+    fn received_frame_length_is_limited_to_the_frame_buffer() {
+        let max_length = lr_wpan_rs::consts::MAX_PHY_PACKET_SIZE;
+
+        // A corrupt PHR claims more than the buffer holds
+        let mut frame_buffer = vec![0xFF; max_length + 2];
+        frame_buffer[max_length + 1] = 100;
+        let mut phy = phy_with_reads(vec![vec![0], vec![0xFF], frame_buffer]);
+
+        let message = embassy_futures::block_on(phy.read_frame(Instant::from_seconds(1)))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(message.data.len(), max_length - FCS_LENGTH);
+        assert_eq!(message.lqi, 100);
+        assert_eq!(message.crc_ok, Some(false));
+    }
+}
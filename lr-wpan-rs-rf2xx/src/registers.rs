@@ -0,0 +1,79 @@
+//! Register map of the AT86RF233
+
+pub const TRX_STATUS: u8 = 0x01;
+pub const TRX_STATE: u8 = 0x02;
+pub const TRX_CTRL_1: u8 = 0x04;
+pub const PHY_TX_PWR: u8 = 0x05;
+pub const PHY_RSSI: u8 = 0x06;
+pub const PHY_ED_LEVEL: u8 = 0x07;
+pub const PHY_CC_CCA: u8 = 0x08;
+pub const IRQ_MASK: u8 = 0x0E;
+pub const IRQ_STATUS: u8 = 0x0F;
+pub const PART_NUM: u8 = 0x1C;
+
+/// The value of the [PART_NUM] register for the AT86RF233
+pub const PART_NUM_AT86RF233: u8 = 0x0B;
+
+/// SPI command bits
+pub mod command {
+    pub const REGISTER_READ: u8 = 0x80;
+    pub const REGISTER_WRITE: u8 = 0xC0;
+    pub const FRAME_BUFFER_READ: u8 = 0x20;
+    pub const FRAME_BUFFER_WRITE: u8 = 0x60;
+}
+
+/// Values of the TRX_STATUS field in the [TRX_STATUS](super::TRX_STATUS) register
+pub mod trx_status {
+    pub const MASK: u8 = 0x1F;
+    pub const RX_ON: u8 = 0x06;
+    pub const TRX_OFF: u8 = 0x08;
+    pub const PLL_ON: u8 = 0x09;
+    pub const STATE_TRANSITION_IN_PROGRESS: u8 = 0x1F;
+
+    pub const CCA_DONE: u8 = 1 << 7;
+    pub const CCA_STATUS: u8 = 1 << 6;
+}
+
+/// Commands for the TRX_CMD field in the [TRX_STATE](super::TRX_STATE) register
+pub mod trx_cmd {
+    pub const TX_START: u8 = 0x02;
+    pub const FORCE_TRX_OFF: u8 = 0x03;
+    pub const FORCE_PLL_ON: u8 = 0x04;
+    pub const RX_ON: u8 = 0x06;
+}
+
+/// Bits of the [TRX_CTRL_1](super::TRX_CTRL_1) register
+pub mod trx_ctrl_1 {
+    pub const TX_AUTO_CRC_ON: u8 = 1 << 5;
+}
+
+/// Bits of the [PHY_RSSI](super::PHY_RSSI) register
+pub mod phy_rssi {
+    pub const RX_CRC_VALID: u8 = 1 << 7;
+}
+
+/// Values of the [PHY_ED_LEVEL](super::PHY_ED_LEVEL) register
+pub mod phy_ed_level {
+    /// The highest energy level that can be measured
+    pub const MAX: u8 = 0x54;
+    /// The register reads this value while a measurement is in progress
+    pub const IN_PROGRESS: u8 = 0xFF;
+}
+
+/// Fields of the [PHY_CC_CCA](super::PHY_CC_CCA) register
+pub mod phy_cc_cca {
+    pub const CCA_REQUEST: u8 = 1 << 7;
+    pub const CCA_MODE_OFFSET: u8 = 5;
+    pub const CCA_MODE_MASK: u8 = 0b11 << CCA_MODE_OFFSET;
+    pub const CHANNEL_MASK: u8 = 0x1F;
+}
+
+/// Bits of the [IRQ_MASK](super::IRQ_MASK) and [IRQ_STATUS](super::IRQ_STATUS) registers
+pub mod irq {
+    pub const TRX_END: u8 = 1 << 3;
+}
+
+/// The output power of every [PHY_TX_PWR](super::PHY_TX_PWR) setting in tenths of a dBm
+pub const TX_POWER_TABLE: [i16; 16] = [
+    40, 37, 34, 30, 25, 20, 10, 0, -10, -20, -30, -40, -60, -80, -120, -170,
+];
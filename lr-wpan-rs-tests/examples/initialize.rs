@@ -0,0 +1,49 @@
+//! Bring up the stack with [MacCommander::initialize], like you would on a board.
+//!
+//! On hardware, the phy is created first (e.g. with `DW1000Phy::new`) and handed to the mac engine.
+//! Here the radio is simulated by the aether, so the example can run anywhere:
+//!
+//! ```sh
+//! cargo run -p lr-wpan-rs-tests --example initialize
+//! ```
+
+use lr_wpan_rs::{
+    mac::MacCommander,
+    pib::PibValue,
+    sap::get::GetRequest,
+    wire::{PanId, ShortAddress},
+};
+
+fn main() {
+    let (commanders, _, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    runner.attach_test_task(async {
+        let commander = commanders[0];
+
+        // Reset the phy and the mac and set the values this device needs in one go
+        if let Err(detail) = commander
+            .initialize(&[
+                PibValue::MacPanId(PanId(0x1234)),
+                PibValue::MacShortAddress(ShortAddress(0x0001)),
+                PibValue::MacRxOnWhenIdle(true),
+            ])
+            .await
+        {
+            panic!(
+                "Could not initialize the stack: {:?} ({})",
+                detail.status, detail.description
+            );
+        }
+
+        print_pib_value(commander, PibValue::MAC_PAN_ID).await;
+        print_pib_value(commander, PibValue::MAC_SHORT_ADDRESS).await;
+        print_pib_value(commander, PibValue::MAC_RX_ON_WHEN_IDLE).await;
+    });
+
+    runner.run();
+}
+
+async fn print_pib_value(commander: &MacCommander, pib_attribute: &'static str) {
+    let value = commander.request(GetRequest { pib_attribute }).await.value;
+    println!("{pib_attribute}: {value:?}");
+}
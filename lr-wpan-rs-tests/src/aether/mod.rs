@@ -48,13 +48,14 @@ use std::{
         Arc, Mutex, MutexGuard,
         atomic::{AtomicUsize, Ordering},
     },
+    time::SystemTime,
 };
 
 use async_channel::{Sender, TrySendError, bounded};
 use byte::TryRead;
 use heapless::Vec;
-use log::warn;
-use lr_wpan_rs::{pib::PhyPib, time::Instant, wire::Frame};
+use log::{trace, warn};
+use lr_wpan_rs::{phy::FrameFilter, pib::PhyPib, time::Instant, wire::Frame};
 use pcap_file::{
     DataLink,
     pcapng::{
@@ -70,10 +71,14 @@ mod radio;
 mod space_time;
 
 pub use radio::AetherRadio;
+use radio::LocalClock;
 pub use space_time::{Coordinate, Meters};
 
 use crate::time::SimulationTime;
 
+/// The number of frames a radio can have waiting to be received when it's created with [Aether::radio]
+const DEFAULT_ANTENNA_CAPACITY: usize = 16;
+
 /// A medium to which radios are connected
 ///
 /// This takes care of routing the packets to the right radios.
@@ -86,6 +91,10 @@ impl Aether {
     pub fn new(simulation_time: &'static SimulationTime) -> Self {
         let inner = AetherInner {
             nodes: Default::default(),
+            interference: Default::default(),
+            radios_broken: false,
+            frames_to_corrupt: 0,
+            panic_on_antenna_overflow: false,
             pcap_trace: None,
             simulation_time,
         };
@@ -99,6 +108,10 @@ impl Aether {
     pub fn new_own_simulation_time() -> Self {
         let inner = AetherInner {
             nodes: Default::default(),
+            interference: Default::default(),
+            radios_broken: false,
+            frames_to_corrupt: 0,
+            panic_on_antenna_overflow: false,
             pcap_trace: None,
             simulation_time: Box::leak(Box::new(SimulationTime::new())),
         };
@@ -110,15 +123,25 @@ impl Aether {
 
     /// Create a radio which lives in the Aether
     pub fn radio(&mut self) -> AetherRadio {
-        let (tx, rx) = bounded(16);
+        self.radio_with_capacity(DEFAULT_ANTENNA_CAPACITY)
+    }
+
+    /// Create a radio which lives in the Aether and can have `capacity` frames waiting to be received.
+    ///
+    /// Frames that come in while the queue is full are dropped, see [Aether::set_panic_on_antenna_overflow].
+    pub fn radio_with_capacity(&mut self, capacity: usize) -> AetherRadio {
+        let (tx, rx) = bounded(capacity);
 
         let pib = PhyPib::unspecified_new();
         let local_pib = pib.clone();
+        let channels_supported = pib.channels_supported;
         let node = Node {
             position: Coordinate::default(),
             antenna: tx,
             pib,
             rx_enable: false,
+            tx_pending: false,
+            frame_filter: None,
         };
         let inner = Arc::clone(&self.inner);
         let node_id = NodeId::new();
@@ -131,11 +154,75 @@ impl Aether {
             node_id,
             antenna: rx,
             local_pib,
+            clock: LocalClock::new(),
+            turnaround_time_symbols: lr_wpan_rs::consts::TURNAROUND_TIME,
+            channels_supported,
+            wake_up_time_symbols: 0,
+            send_setup_time_symbols: 0,
+            send_time_resolution_ticks: 1,
+            sleeping: false,
         }
     }
 
+    /// Put a constant source of interference on the channel that radios will measure with the given energy.
+    /// While there's interference, every send with CSMA-CA on the channel ends in a channel access failure.
+    ///
+    /// An energy of 0 removes the interference.
+    pub fn set_interference(&mut self, channel: u8, energy: u8) {
+        self.inner().interference.insert(channel, energy);
+    }
+
+    /// Break all radios, so they return [AetherError::RadioBroken] on every operation until fixed again.
+    ///
+    /// This can be used to test how the MAC deals with phy errors.
+    pub fn set_radios_broken(&mut self, broken: bool) {
+        self.inner().radios_broken = broken;
+    }
+
+    /// Corrupt the next `count` frames that are sent, as if they were hit by a bit error.
+    ///
+    /// The radios receive them with a failed CRC check.
+    pub fn corrupt_next_frames(&mut self, count: usize) {
+        self.inner().frames_to_corrupt = count;
+    }
+
+    /// Panic instead of only logging a warning when a frame is dropped because the receive queue of a radio is full.
+    ///
+    /// This makes sure tests don't pass by accident because some frames never arrived.
+    pub fn set_panic_on_antenna_overflow(&mut self, panic: bool) {
+        self.inner().panic_on_antenna_overflow = panic;
+    }
+
+    /// The frame filters of all radios, in no particular order
+    pub fn frame_filters(&self) -> std::vec::Vec<Option<FrameFilter>> {
+        self.inner()
+            .nodes
+            .values()
+            .map(|node| node.frame_filter)
+            .collect()
+    }
+
+    /// For all radios whether they have their receiver on, in no particular order
+    pub fn receivers_on(&self) -> std::vec::Vec<bool> {
+        self.inner()
+            .nodes
+            .values()
+            .map(|node| node.rx_enable)
+            .collect()
+    }
+
+    /// Start writing all sent frames to a pcap file with the given name.
+    ///
+    /// The timestamps in the trace are the simulation time, which starts at the unix epoch.
     pub fn start_trace(&mut self, name: &str) {
-        self.inner().start_trace(name);
+        self.start_trace_with_epoch(name, SystemTime::UNIX_EPOCH);
+    }
+
+    /// Like [Aether::start_trace], but the start of the simulation time is written as the given epoch.
+    ///
+    /// This aligns the trace to a real start time, so it can be correlated with other logs.
+    pub fn start_trace_with_epoch(&mut self, name: &str, epoch: SystemTime) {
+        self.inner().start_trace(name, epoch);
     }
 
     pub fn stop_trace(&mut self) -> File {
@@ -174,7 +261,8 @@ impl Aether {
                             .0,
                         );
                     }
-                    _ => todo!(),
+                    // Other blocks, like the statistics, don't hold frames
+                    _ => {}
                 }
             }
 
@@ -189,7 +277,15 @@ impl Aether {
 
 pub struct AetherInner {
     nodes: HashMap<NodeId, Node>,
-    pcap_trace: Option<(PcapNgWriter<File>, HashMap<NodeId, u32>)>,
+    /// The energy of the interference per channel
+    interference: HashMap<u8, u8>,
+    /// If true, all radio operations fail
+    radios_broken: bool,
+    /// The number of frames that are still to be corrupted when sent
+    frames_to_corrupt: usize,
+    /// If true, dropping a frame because the antenna of a radio is full panics
+    panic_on_antenna_overflow: bool,
+    pcap_trace: Option<PcapTrace>,
     pub simulation_time: &'static SimulationTime,
 }
 
@@ -197,13 +293,23 @@ impl Debug for AetherInner {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
         f.debug_struct("AetherInner")
             .field("nodes", &self.nodes)
-            .field("pcap_dump", &self.pcap_trace.as_ref().map(|(_, h)| ((), h)))
+            .field("interference", &self.interference)
+            .field("radios_broken", &self.radios_broken)
+            .field("frames_to_corrupt", &self.frames_to_corrupt)
+            .field("panic_on_antenna_overflow", &self.panic_on_antenna_overflow)
+            .field(
+                "pcap_dump",
+                &self
+                    .pcap_trace
+                    .as_ref()
+                    .map(|trace| (&trace.interfaces, trace.epoch)),
+            )
             .finish()
     }
 }
 
 impl AetherInner {
-    pub fn start_trace(&mut self, name: &str) {
+    pub fn start_trace(&mut self, name: &str, epoch: SystemTime) {
         if self.pcap_trace.is_some() {
             panic!("Already capturing pcap");
         }
@@ -225,13 +331,19 @@ impl AetherInner {
 
         log::info!("Writing aether trace to: {}", trace_file_path.display());
 
-        self.pcap_trace = Some((PcapNgWriter::new(file).unwrap(), HashMap::new()));
+        self.pcap_trace = Some(PcapTrace {
+            writer: PcapNgWriter::new(file).unwrap(),
+            interfaces: HashMap::new(),
+            epoch: epoch
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .expect("The epoch can't be before the unix epoch"),
+        });
     }
 
     /// Stops the trace and returns the file handle that was written to
     pub fn stop_trace(&mut self) -> File {
-        let (trace_file, _) = self.pcap_trace.take().expect("No trace in progress");
-        let mut file = trace_file.into_inner();
+        let trace = self.pcap_trace.take().expect("No trace in progress");
+        let mut file = trace.writer.into_inner();
         file.seek(std::io::SeekFrom::Start(0)).unwrap();
         file.flush().unwrap();
 
@@ -239,7 +351,12 @@ impl AetherInner {
     }
 
     fn trace(&mut self, node_id: &NodeId, pkt: &AirPacket) {
-        let Some((pcap, nodes)) = &mut self.pcap_trace else {
+        let Some(PcapTrace {
+            writer: pcap,
+            interfaces: nodes,
+            epoch,
+        }) = &mut self.pcap_trace
+        else {
             return;
         };
 
@@ -259,7 +376,7 @@ impl AetherInner {
 
         let block = EnhancedPacketBlock {
             interface_id,
-            timestamp: pkt.time_stamp.duration_since_epoch().into(),
+            timestamp: *epoch + pkt.time_stamp.duration_since_epoch().into(),
             original_len: pkt.data.len().try_into().unwrap(),
             data: Cow::Borrowed(pkt.data.as_ref()),
             options: vec![],
@@ -267,7 +384,16 @@ impl AetherInner {
         pcap.write_pcapng_block(block).unwrap();
     }
 
-    fn send(&mut self, from: &NodeId, data: AirPacket) -> Instant {
+    fn energy_on(&self, channel: u8) -> u8 {
+        self.interference.get(&channel).copied().unwrap_or(0)
+    }
+
+    fn send(&mut self, from: &NodeId, mut data: AirPacket) -> Instant {
+        if self.frames_to_corrupt > 0 {
+            self.frames_to_corrupt -= 1;
+            data.corrupt();
+        }
+
         self.trace(from, &data);
 
         let mut closed_radios = vec![];
@@ -280,6 +406,12 @@ impl AetherInner {
                 continue;
             }
 
+            // Like a real radio, the receiver is busy waiting to send
+            if node.tx_pending {
+                trace!("Radio {to:?} misses a frame because it has a delayed send pending");
+                continue;
+            }
+
             let mut delayed_data = data.clone();
             let dist = node.position.dist(from_pos);
             delayed_data.time_stamp += dist.as_duration();
@@ -289,6 +421,9 @@ impl AetherInner {
                     at_least_one_received = true;
                 }
                 Err(TrySendError::Closed(_)) => closed_radios.push(to.clone()),
+                Err(TrySendError::Full(_)) if self.panic_on_antenna_overflow => {
+                    panic!("Radio antenna of {to:?} is full, so a frame was dropped")
+                }
                 Err(TrySendError::Full(_)) => {
                     log::warn!("Radio antenna of {to:?} is full")
                 }
@@ -307,6 +442,15 @@ impl AetherInner {
     }
 }
 
+/// A pcap file that's being written
+struct PcapTrace {
+    writer: PcapNgWriter<File>,
+    /// The pcap interface id of every radio that has sent something
+    interfaces: HashMap<NodeId, u32>,
+    /// The time since the unix epoch at which the simulation time starts
+    epoch: std::time::Duration,
+}
+
 #[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Clone)]
 pub struct NodeId(usize);
 
@@ -324,6 +468,11 @@ pub struct Node {
     antenna: Sender<AirPacket>,
     pib: PhyPib,
     rx_enable: bool,
+    /// True while the radio waits for the send time of a delayed send. It doesn't receive anything in the meantime.
+    tx_pending: bool,
+    /// The filter set with [Phy::set_frame_filter](lr_wpan_rs::phy::Phy::set_frame_filter).
+    /// It's only recorded, the aether doesn't filter.
+    frame_filter: Option<FrameFilter>,
 }
 
 #[derive(Debug, Clone)]
@@ -331,22 +480,76 @@ pub struct AirPacket {
     pub data: Vec<u8, 127>,
     pub time_stamp: Instant,
     pub channel: u8,
+    /// False if the packet got corrupted on its way
+    pub crc_ok: bool,
+    /// The ranging bit of the PHR
+    pub ranging: bool,
+    /// The data rate the packet was sent with, see [Phy::send_with_data_rate](lr_wpan_rs::phy::Phy::send_with_data_rate)
+    pub data_rate: u8,
 }
 
 impl AirPacket {
-    pub fn new(data: impl TryInto<Vec<u8, 127>>, time_stamp: Instant, channel: u8) -> Self {
-        let Ok(data) = data.try_into() else {
-            unreachable!("Test data always fits 127 bytes");
-        };
+    pub fn new(
+        data: &[u8],
+        time_stamp: Instant,
+        channel: u8,
+        ranging: bool,
+        data_rate: u8,
+    ) -> Result<Self, AetherError> {
+        if data.is_empty() {
+            return Err(AetherError::FrameEmpty);
+        }
 
-        Self {
+        let data = Vec::from_slice(data).map_err(|_| AetherError::FrameTooLong)?;
+
+        Ok(Self {
             data,
             time_stamp,
             channel,
-        }
+            crc_ok: true,
+            ranging,
+            data_rate,
+        })
     }
+
+    /// Flip a bit in the packet, which makes its CRC check fail
+    fn corrupt(&mut self) {
+        *self.data.last_mut().expect("packets are never empty") ^= 0x01;
+        self.crc_ok = false;
+    }
+}
+
+/// Errors the [AetherRadio] can return
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AetherError {
+    /// The frame to be sent is longer than the max PHY packet size
+    FrameTooLong,
+    /// The frame to be sent contains no data
+    FrameEmpty,
+    /// The radio was broken on purpose with [Aether::set_radios_broken]
+    RadioBroken,
+    /// A frame was sent with ranging, while the radio doesn't support it.
+    /// See [AetherRadio::set_ranging_supported]
+    RangingNotSupported,
+    /// A frame was sent with a data rate that's not in the phy pib
+    UnsupportedDataRate,
+    /// A delayed send was requested for a time that has already passed
+    SendTimePassed,
+    /// A delayed send was requested for a time that's too close to set it up.
+    /// See [AetherRadio::set_send_setup_time_symbols]
+    SendTimeTooClose,
+    /// The radio was used while it was sleeping. See [Phy::sleep](lr_wpan_rs::phy::Phy::sleep)
+    Sleeping,
 }
 
+impl core::fmt::Display for AetherError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        <Self as Debug>::fmt(self, f)
+    }
+}
+
+impl core::error::Error for AetherError {}
+
 #[cfg(test)]
 mod tests {
     use byte::TryWrite;
@@ -405,6 +608,77 @@ mod tests {
         assert_eq!(&pkt.data[..], &test_data[..]);
     }
 
+    #[futures_test::test]
+    async fn send_with_different_data_rates() {
+        let mut a = Aether::new_own_simulation_time();
+
+        let mut alice = a.radio();
+        let mut bob = a.radio();
+
+        bob.start_receive().await.unwrap();
+
+        // A plain send uses 0.85 Mb/s
+        alice
+            .send(b"Hello!", None, false, false, SendContinuation::Idle)
+            .await
+            .unwrap();
+        assert_eq!(receive_one(&mut bob).await.data_rate, 2);
+
+        // 6.81 Mb/s
+        alice
+            .send_with_data_rate(b"Hello!", None, false, false, 3, SendContinuation::Idle)
+            .await
+            .unwrap();
+        assert_eq!(receive_one(&mut bob).await.data_rate, 3);
+
+        // 27.24 Mb/s is not in the phy pib
+        assert_eq!(
+            alice
+                .send_with_data_rate(b"Hello!", None, false, false, 4, SendContinuation::Idle)
+                .await
+                .err(),
+            Some(AetherError::UnsupportedDataRate)
+        );
+    }
+
+    /// Send frames to bob, who doesn't pick them up
+    async fn flood(alice: &mut AetherRadio, bob: &mut AetherRadio, frames: usize) {
+        bob.start_receive().await.unwrap();
+
+        for _ in 0..frames {
+            alice
+                .send(b"Hello!", None, false, false, SendContinuation::Idle)
+                .await
+                .unwrap();
+        }
+    }
+
+    #[futures_test::test]
+    async fn frames_are_dropped_when_the_antenna_is_full() {
+        let mut a = Aether::new_own_simulation_time();
+
+        let mut alice = a.radio();
+        let mut bob = a.radio_with_capacity(2);
+
+        flood(&mut alice, &mut bob, 3).await;
+
+        receive_one(&mut bob).await;
+        receive_one(&mut bob).await;
+        assert!(bob.antenna.is_empty());
+    }
+
+    #[futures_test::test]
+    #[should_panic(expected = "is full")]
+    async fn antenna_overflow_can_panic() {
+        let mut a = Aether::new_own_simulation_time();
+        a.set_panic_on_antenna_overflow(true);
+
+        let mut alice = a.radio();
+        let mut bob = a.radio_with_capacity(2);
+
+        flood(&mut alice, &mut bob, 3).await;
+    }
+
     #[test]
     fn ignored_if_not_listening() {
         let (_, mut aether, mut runner) = crate::run::create_test_runner(0);
@@ -432,6 +706,224 @@ mod tests {
         runner.run();
     }
 
+    #[test]
+    fn aborted_delayed_send_is_not_sent() {
+        let (_, mut aether, mut runner) = crate::run::create_test_runner(0);
+
+        runner.attach_test_task(async {
+            let mut alice = aether.radio();
+            let mut bob = aether.radio();
+            bob.start_receive().await.unwrap();
+
+            let simulation_time = aether.inner().simulation_time;
+            let send_time = alice.get_instant().await.unwrap() + Duration::from_millis(10);
+
+            // Cancel the send before it fires
+            select! {
+                _ = alice.send(b"Hello!", Some(send_time), false, false, SendContinuation::Idle).fuse() => {
+                    panic!("The send must not be done yet");
+                }
+                _ = simulation_time.delay(Duration::from_millis(5)).fuse() => {}
+            }
+            alice.abort_send().await.unwrap();
+
+            select! {
+                _ = simulation_time.delay(Duration::from_millis(20)).fuse() => {
+                    // Nothing has been sent
+                }
+                _ = bob.wait().fuse() => {
+                    panic!("The aborted frame must not be sent");
+                }
+            }
+        });
+
+        runner.run();
+    }
+
+    #[test]
+    fn delayed_send_starts_at_the_send_time() {
+        let (_, mut aether, mut runner) = crate::run::create_test_runner(0);
+
+        runner.attach_test_task(async {
+            let mut alice = aether.radio();
+            let mut bob = aether.radio();
+            bob.move_to(Coordinate::new(0.0, 299_792_458.0));
+            bob.start_receive().await.unwrap();
+
+            let send_time = alice.get_instant().await.unwrap() + Duration::from_millis(10);
+            let SendResult::Success(tx_time, _) = alice
+                .send(
+                    b"Hello!",
+                    Some(send_time),
+                    false,
+                    false,
+                    SendContinuation::Idle,
+                )
+                .await
+                .unwrap()
+            else {
+                panic!("Failed to send packet!")
+            };
+            assert_eq!(tx_time, send_time);
+            assert_eq!(alice.get_instant().await.unwrap(), send_time);
+
+            let pkt = receive_one(&mut bob).await;
+            assert_eq!(pkt.timestamp, send_time + Duration::from_seconds(1));
+        });
+
+        runner.run();
+    }
+
+    #[test]
+    fn send_time_in_the_past_is_rejected() {
+        let (_, mut aether, mut runner) = crate::run::create_test_runner(0);
+
+        runner.attach_test_task(async {
+            let mut alice = aether.radio();
+
+            let simulation_time = aether.inner().simulation_time;
+            let send_time = alice.get_instant().await.unwrap();
+            simulation_time.delay(Duration::from_millis(1)).await;
+
+            assert_eq!(
+                alice
+                    .send(
+                        b"Hello!",
+                        Some(send_time),
+                        false,
+                        false,
+                        SendContinuation::Idle
+                    )
+                    .await
+                    .err(),
+                Some(AetherError::SendTimePassed)
+            );
+        });
+
+        runner.run();
+    }
+
+    #[test]
+    fn delayed_send_follows_the_timing_limits() {
+        let (_, mut aether, mut runner) = crate::run::create_test_runner(0);
+
+        runner.attach_test_task(async {
+            let mut alice = aether.radio();
+            alice.set_send_setup_time_symbols(100);
+            alice.set_send_time_resolution_ticks(512);
+
+            let now = alice.get_instant().await.unwrap();
+            let setup_time = alice.symbol_period() * 100;
+
+            assert_eq!(
+                alice
+                    .send(
+                        b"Hello!",
+                        Some(now + setup_time / 2),
+                        false,
+                        false,
+                        SendContinuation::Idle
+                    )
+                    .await
+                    .err(),
+                Some(AetherError::SendTimeTooClose)
+            );
+
+            let send_time = now + setup_time * 2 + Duration::from_ticks(100);
+            let Ok(SendResult::Success(sent_at, _)) = alice
+                .send(
+                    b"Hello!",
+                    Some(send_time),
+                    false,
+                    false,
+                    SendContinuation::Idle,
+                )
+                .await
+            else {
+                panic!("The delayed send failed");
+            };
+            assert_eq!(sent_at, alice.scheduled_send_time(send_time));
+            assert_eq!(sent_at.ticks() % 512, 0);
+            assert!(sent_at <= send_time);
+        });
+
+        runner.run();
+    }
+
+    #[test]
+    fn not_received_while_a_delayed_send_is_pending() {
+        let (_, mut aether, mut runner) = crate::run::create_test_runner(0);
+
+        runner.attach_test_task(async {
+            let mut alice = aether.radio();
+            let mut bob = aether.radio();
+            bob.start_receive().await.unwrap();
+
+            let simulation_time = aether.inner().simulation_time;
+            let beacon_time = bob.get_instant().await.unwrap() + Duration::from_millis(10);
+
+            // Alice sends while bob is waiting to send his beacon
+            let (beacon_result, _) = futures::join!(
+                bob.send(
+                    b"Beacon",
+                    Some(beacon_time),
+                    false,
+                    false,
+                    SendContinuation::Idle
+                ),
+                async {
+                    simulation_time.delay(Duration::from_millis(5)).await;
+                    alice
+                        .send(b"Missed", None, false, false, SendContinuation::Idle)
+                        .await
+                        .unwrap();
+                }
+            );
+            assert!(matches!(beacon_result, Ok(SendResult::Success(..))));
+            assert!(bob.antenna.is_empty());
+
+            // After the beacon went out, bob receives again
+            alice
+                .send(b"Hello!", None, false, false, SendContinuation::Idle)
+                .await
+                .unwrap();
+            let pkt = receive_one(&mut bob).await;
+            assert_eq!(&pkt.data[..], b"Hello!");
+        });
+
+        runner.run();
+    }
+
+    #[test]
+    fn aborted_delayed_send_is_no_longer_pending() {
+        let (_, mut aether, mut runner) = crate::run::create_test_runner(0);
+
+        runner.attach_test_task(async {
+            let mut alice = aether.radio();
+            let mut bob = aether.radio();
+            bob.start_receive().await.unwrap();
+
+            let simulation_time = aether.inner().simulation_time;
+            let send_time = bob.get_instant().await.unwrap() + Duration::from_millis(10);
+
+            select! {
+                _ = bob.send(b"Beacon", Some(send_time), false, false, SendContinuation::Idle).fuse() => {
+                    panic!("The send must not be done yet");
+                }
+                _ = simulation_time.delay(Duration::from_millis(5)).fuse() => {}
+            }
+
+            alice
+                .send(b"Hello!", None, false, false, SendContinuation::Idle)
+                .await
+                .unwrap();
+            let pkt = receive_one(&mut bob).await;
+            assert_eq!(&pkt.data[..], b"Hello!");
+        });
+
+        runner.run();
+    }
+
     #[test]
     fn arrives_delayed() {
         let (_, mut aether, mut runner) = crate::run::create_test_runner(0);
@@ -463,6 +955,340 @@ mod tests {
         runner.run();
     }
 
+    #[test]
+    fn clock_drift_is_applied() {
+        let (_, mut aether, mut runner) = crate::run::create_test_runner(0);
+
+        runner.attach_test_task(async {
+            let mut alice = aether.radio();
+            let mut bob = aether.radio();
+            bob.set_clock_drift_ppm(100.0);
+
+            let simulation_time = aether.inner().simulation_time;
+            simulation_time.delay(Duration::from_seconds(1)).await;
+
+            // Bob's clock has run 100 ppm faster
+            assert_eq!(alice.get_instant().await.unwrap(), Instant::from_seconds(1));
+            assert_eq!(
+                bob.get_instant().await.unwrap(),
+                Instant::from_seconds(1) + Duration::from_micros(100)
+            );
+
+            // Received timestamps are in the clock of the receiver
+            bob.start_receive().await.unwrap();
+            let SendResult::Success(tx_time, _) = alice
+                .send(b"Hello!", None, false, false, SendContinuation::Idle)
+                .await
+                .unwrap()
+            else {
+                panic!("Failed to send packet!")
+            };
+
+            let pkt = receive_one(&mut bob).await;
+            assert_eq!(pkt.timestamp, tx_time + Duration::from_micros(100));
+
+            // And the send time is as well
+            let send_time = bob.get_instant().await.unwrap() + Duration::from_millis(10);
+            let SendResult::Success(tx_time, _) = bob
+                .send(
+                    b"Hello!",
+                    Some(send_time),
+                    false,
+                    false,
+                    SendContinuation::Idle,
+                )
+                .await
+                .unwrap()
+            else {
+                panic!("Failed to send packet!")
+            };
+            assert!(tx_time.duration_since(send_time).abs() <= Duration::from_ticks(1));
+        });
+
+        runner.run();
+    }
+
+    #[test]
+    fn starting_to_receive_twice_keeps_receiving() {
+        let (_, mut aether, mut runner) = crate::run::create_test_runner(0);
+
+        runner.attach_test_task(async {
+            let mut alice = aether.radio();
+            let mut bob = aether.radio();
+
+            bob.start_receive().await.unwrap();
+            bob.start_receive().await.unwrap();
+
+            alice
+                .send(b"Hello!", None, false, false, SendContinuation::Idle)
+                .await
+                .unwrap();
+
+            let pkt = receive_one(&mut bob).await;
+            assert_eq!(&pkt.data[..], b"Hello!");
+        });
+
+        runner.run();
+    }
+
+    #[test]
+    fn sleep_and_wake_round_trip() {
+        let (_, mut aether, mut runner) = crate::run::create_test_runner(0);
+
+        runner.attach_test_task(async {
+            let mut alice = aether.radio();
+            let mut bob = aether.radio();
+            bob.set_wake_up_time_symbols(100);
+            bob.update_phy_pib(|pib| pib.current_channel = 3)
+                .await
+                .unwrap();
+            alice
+                .update_phy_pib(|pib| pib.current_channel = 3)
+                .await
+                .unwrap();
+            bob.start_receive().await.unwrap();
+
+            bob.sleep().await.unwrap();
+            assert!(bob.is_sleeping());
+            assert_eq!(bob.start_receive().await.err(), Some(AetherError::Sleeping));
+            assert_eq!(
+                bob.send(b"Hello!", None, false, false, SendContinuation::Idle)
+                    .await
+                    .err(),
+                Some(AetherError::Sleeping)
+            );
+
+            // A sleeping radio doesn't receive
+            alice
+                .send(b"Missed", None, false, false, SendContinuation::Idle)
+                .await
+                .unwrap();
+            assert!(bob.antenna.is_empty());
+
+            let sleep_end = bob.get_instant().await.unwrap();
+            bob.wake().await.unwrap();
+            assert!(!bob.is_sleeping());
+            assert_eq!(
+                bob.get_instant().await.unwrap(),
+                sleep_end + bob.symbol_period() * 100
+            );
+
+            // Waking up again does nothing
+            bob.wake().await.unwrap();
+            assert_eq!(
+                bob.get_instant().await.unwrap(),
+                sleep_end + bob.symbol_period() * 100
+            );
+
+            // The configuration survived the sleep
+            assert_eq!(bob.get_phy_pib().current_channel, 3);
+            bob.start_receive().await.unwrap();
+            alice
+                .send(b"Hello!", None, false, false, SendContinuation::Idle)
+                .await
+                .unwrap();
+            let pkt = receive_one(&mut bob).await;
+            assert_eq!(&pkt.data[..], b"Hello!");
+        });
+
+        runner.run();
+    }
+
+    #[test]
+    fn dropped_radio_is_not_targeted() {
+        let (_, mut aether, mut runner) = crate::run::create_test_runner(0);
+
+        runner.attach_test_task(async {
+            let mut alice = aether.radio();
+            let mut bob = aether.radio();
+            let mut carol = aether.radio();
+
+            bob.start_receive().await.unwrap();
+            carol.start_receive().await.unwrap();
+
+            // Stopping twice is fine
+            carol.stop_receive().await.unwrap();
+            carol.stop_receive().await.unwrap();
+            carol.start_receive().await.unwrap();
+
+            let bob_id = bob.node_id.clone();
+            drop(bob);
+
+            assert!(!aether.inner().nodes.contains_key(&bob_id));
+
+            alice
+                .send(b"Hello!", None, false, false, SendContinuation::Idle)
+                .await
+                .unwrap();
+
+            let pkt = receive_one(&mut carol).await;
+            assert_eq!(&pkt.data[..], b"Hello!");
+
+            let listening_nodes = aether
+                .inner()
+                .nodes
+                .iter()
+                .filter(|(_, node)| node.rx_enable)
+                .map(|(id, _)| id.clone())
+                .collect::<std::vec::Vec<_>>();
+            assert_eq!(listening_nodes, [carol.node_id.clone()]);
+        });
+
+        runner.run();
+    }
+
+    #[futures_test::test]
+    async fn invalid_frame_length_is_rejected() {
+        let mut a = Aether::new_own_simulation_time();
+
+        let mut alice = a.radio();
+
+        let too_long = [0; lr_wpan_rs::consts::MAX_PHY_PACKET_SIZE + 1];
+        assert!(matches!(
+            alice
+                .send(&too_long, None, false, false, SendContinuation::Idle)
+                .await,
+            Err(AetherError::FrameTooLong)
+        ));
+
+        assert!(matches!(
+            alice
+                .send(&[], None, false, false, SendContinuation::Idle)
+                .await,
+            Err(AetherError::FrameEmpty)
+        ));
+    }
+
+    #[test]
+    fn back_to_back_frames_are_separated_by_the_gap() {
+        let (_, mut aether, mut runner) = crate::run::create_test_runner(0);
+
+        runner.attach_test_task(async {
+            let mut alice = aether.radio();
+            let mut bob = aether.radio();
+
+            bob.start_receive().await.unwrap();
+
+            let gap = alice.symbol_period() * 40;
+            // The gap starts at the end of the first frame, which includes the FCS the phy adds
+            let first_duration = alice.symbol_period()
+                * alice
+                    .get_phy_pib()
+                    .frame_duration(b"First".len() + lr_wpan_rs::wire::fcs::FCS_LENGTH)
+                    as i64;
+
+            let (first_result, second_result) = alice
+                .send_back_to_back(
+                    b"First",
+                    b"Second",
+                    None,
+                    false,
+                    false,
+                    gap,
+                    SendContinuation::Idle,
+                )
+                .await
+                .unwrap();
+
+            let SendResult::Success(first_tx_time, _) = first_result else {
+                panic!("Failed to send the first packet!")
+            };
+            let Some(SendResult::Success(second_tx_time, _)) = second_result else {
+                panic!("Failed to send the second packet!")
+            };
+            assert_eq!(second_tx_time, first_tx_time + first_duration + gap);
+
+            let first = receive_one(&mut bob).await;
+            let second = receive_one(&mut bob).await;
+
+            assert_eq!(&first.data[..], b"First");
+            assert_eq!(&second.data[..], b"Second");
+            assert_eq!(
+                second.timestamp.duration_since(first.timestamp),
+                first_duration + gap
+            );
+        });
+
+        runner.run();
+    }
+
+    #[test]
+    fn receive_continuous_catches_an_immediate_response() {
+        let (_, mut aether, mut runner) = crate::run::create_test_runner(0);
+
+        runner.attach_test_task(async {
+            let mut alice = aether.radio();
+            let mut bob = aether.radio();
+
+            bob.start_receive().await.unwrap();
+
+            let SendResult::Success(request_time, _) = alice
+                .send(
+                    b"Request",
+                    None,
+                    false,
+                    false,
+                    SendContinuation::ReceiveContinuous,
+                )
+                .await
+                .unwrap()
+            else {
+                panic!("Failed to send the request!")
+            };
+
+            // Bob responds as fast as a radio can turn around
+            let request = receive_one(&mut bob).await;
+            assert_eq!(&request.data[..], b"Request");
+            let turnaround_time = bob.symbol_period() * bob.turnaround_time_symbols() as i64;
+            bob.send(
+                b"Response",
+                Some(request.timestamp + turnaround_time),
+                false,
+                false,
+                SendContinuation::Idle,
+            )
+            .await
+            .unwrap();
+
+            // Alice was already listening
+            let response = receive_one(&mut alice).await;
+            assert_eq!(&response.data[..], b"Response");
+            assert_eq!(response.timestamp, request_time + turnaround_time);
+        });
+
+        runner.run();
+    }
+
+    #[test]
+    fn trace_timestamps_start_at_the_epoch() {
+        let (_, mut aether, mut runner) = crate::run::create_test_runner(0);
+        let epoch = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+
+        runner.attach_test_task(async {
+            aether.start_trace_with_epoch("trace_epoch", epoch);
+            let mut alice = aether.radio();
+
+            let simulation_time = aether.inner().simulation_time;
+            simulation_time.delay(Duration::from_seconds(2)).await;
+            alice
+                .send(b"Hello!", None, false, false, SendContinuation::Idle)
+                .await
+                .unwrap();
+
+            let mut reader = PcapNgReader::new(aether.stop_trace()).unwrap();
+            let mut timestamps = vec![];
+            while let Some(block) = reader.next_block() {
+                if let Block::EnhancedPacket(packet) = block.unwrap() {
+                    timestamps.push(packet.timestamp);
+                }
+            }
+
+            assert_eq!(timestamps, [std::time::Duration::from_secs(1_700_000_002)]);
+        });
+
+        runner.run();
+    }
+
     #[futures_test::test]
     async fn log_beacon() {
         let beacon_frame = wire::Frame {
@@ -478,6 +1304,7 @@ mod tests {
                 destination: None,
                 source: None,
                 auxiliary_security_header: None,
+                time_correction: None,
             },
             content: wire::FrameContent::Beacon(wire::beacon::Beacon {
                 superframe_spec: wire::beacon::SuperframeSpecification {
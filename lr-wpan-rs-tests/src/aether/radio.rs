@@ -7,16 +7,20 @@ use async_channel::Receiver;
 use futures::FutureExt;
 use log::trace;
 use lr_wpan_rs::{
-    phy::{ModulationType, Phy, ReceivedMessage, SendContinuation, SendResult},
-    pib::{PhyPib, PhyPibWrite},
+    consts::MAX_PHY_PACKET_SIZE,
+    phy::{FrameFilter, ModulationType, Phy, ReceivedMessage, SendContinuation, SendResult},
+    pib::{ChannelDescription, PhyPib, PhyPibWrite},
     time::Instant,
 };
 
 use crate::{
-    aether::{AetherInner, AirPacket, Coordinate, Node, NodeId},
+    aether::{AetherError, AetherInner, AirPacket, Coordinate, Node, NodeId},
     time::SimulationTime,
 };
 
+/// The data rate of a plain [Phy::send], which is 0.85 Mb/s
+const DEFAULT_DATA_RATE: u8 = 2;
+
 /// Single radio connected to an [`super::Aether`]
 #[derive(Debug)]
 pub struct AetherRadio {
@@ -24,6 +28,13 @@ pub struct AetherRadio {
     pub(super) node_id: NodeId,
     pub(super) antenna: Receiver<AirPacket>,
     pub(super) local_pib: PhyPib,
+    pub(super) clock: LocalClock,
+    pub(super) turnaround_time_symbols: u32,
+    pub(super) channels_supported: &'static [ChannelDescription],
+    pub(super) wake_up_time_symbols: u32,
+    pub(super) send_setup_time_symbols: u32,
+    pub(super) send_time_resolution_ticks: u64,
+    pub(super) sleeping: bool,
 }
 
 impl AetherRadio {
@@ -31,6 +42,72 @@ impl AetherRadio {
         self.with_node(|node| node.position = position);
     }
 
+    /// Let the clock of this radio run faster (positive) or slower (negative) than the simulation time
+    /// by the given amount of parts per million, like a real crystal would.
+    ///
+    /// The drift applies to all instants going in and out of the radio, e.g. [Phy::get_instant], the send time
+    /// and the timestamps of received messages. Durations (like the timeout of a send) are not scaled.
+    /// The clock stays continuous, so changing the drift only affects the time from now on.
+    pub fn set_clock_drift_ppm(&mut self, drift_ppm: f32) {
+        let now = self.simulation_time().now();
+        self.clock.set_drift_ppm(now, drift_ppm);
+    }
+
+    /// Set whether this radio supports ranging (phyRanging).
+    ///
+    /// Sending a frame with ranging on a radio that doesn't support it fails with [AetherError::RangingNotSupported].
+    pub fn set_ranging_supported(&mut self, ranging: bool) {
+        self.local_pib.ranging = ranging;
+        let new_pib = self.local_pib.clone();
+        self.with_node(|node| node.pib = new_pib);
+    }
+
+    /// Set the channels this radio supports (phyChannelsSupported), which are kept over a reset.
+    ///
+    /// The aether only looks at the channel number to decide who receives a frame, so radios on different pages
+    /// still hear each other. Frames are received on the page the radio is on.
+    pub fn set_channels_supported(&mut self, channels_supported: &'static [ChannelDescription]) {
+        self.channels_supported = channels_supported;
+        self.local_pib.channels_supported = channels_supported;
+        self.with_node(|node| node.pib.channels_supported = channels_supported);
+    }
+
+    /// Set the time in symbols this radio needs to switch between sending and receiving.
+    ///
+    /// It's what the radio reports with [Phy::turnaround_time_symbols]. The aether itself switches instantly.
+    pub fn set_turnaround_time_symbols(&mut self, turnaround_time_symbols: u32) {
+        self.turnaround_time_symbols = turnaround_time_symbols;
+    }
+
+    /// Set the time in symbols this radio needs to wake up from sleep.
+    ///
+    /// It's what the radio reports with [Phy::wake_up_time_symbols] and how long [Phy::wake] takes.
+    pub fn set_wake_up_time_symbols(&mut self, wake_up_time_symbols: u32) {
+        self.wake_up_time_symbols = wake_up_time_symbols;
+    }
+
+    /// Set how far ahead this radio needs a delayed send to be scheduled.
+    ///
+    /// It's what the radio reports with [Phy::send_setup_time_symbols]. A send with a `send_time` that's closer
+    /// fails with [AetherError::SendTimeTooClose].
+    pub fn set_send_setup_time_symbols(&mut self, send_setup_time_symbols: u32) {
+        self.send_setup_time_symbols = send_setup_time_symbols;
+    }
+
+    /// Set the resolution in ticks with which this radio schedules a delayed send.
+    ///
+    /// The `send_time` of a send is rounded down to a multiple of it, like a real radio that ignores the low bits
+    /// of the send time. See [Phy::scheduled_send_time].
+    pub fn set_send_time_resolution_ticks(&mut self, send_time_resolution_ticks: u64) {
+        assert!(send_time_resolution_ticks > 0);
+        self.send_time_resolution_ticks = send_time_resolution_ticks;
+    }
+
+    /// Whether the radio is sleeping, see [Phy::sleep]
+    pub fn is_sleeping(&self) -> bool {
+        self.sleeping
+    }
+
     fn aether(&mut self) -> AetherGuard {
         AetherGuard {
             aether: self.inner.lock().unwrap(),
@@ -42,6 +119,22 @@ impl AetherRadio {
         self.inner.lock().unwrap().simulation_time
     }
 
+    fn check_broken(&self) -> Result<(), AetherError> {
+        if self.inner.lock().unwrap().radios_broken {
+            return Err(AetherError::RadioBroken);
+        }
+
+        Ok(())
+    }
+
+    fn check_awake(&self) -> Result<(), AetherError> {
+        if self.sleeping {
+            return Err(AetherError::Sleeping);
+        }
+
+        Ok(())
+    }
+
     fn with_node<R>(&mut self, f: impl FnOnce(&mut Node) -> R) -> R {
         let AetherGuard {
             mut aether,
@@ -57,42 +150,118 @@ impl AetherRadio {
 }
 
 impl Phy for AetherRadio {
-    type Error = core::convert::Infallible;
+    type Error = AetherError;
     type ProcessingContext = ReceivedMessage;
 
     const MODULATION: ModulationType = ModulationType::BPSK;
 
     async fn reset(&mut self) -> Result<(), Self::Error> {
         trace!("Radio reset {:?}", self.node_id);
+        self.check_broken()?;
 
         self.stop_receive().await?;
-        let new_pib = PhyPib::unspecified_new();
+        self.local_pib = PhyPib {
+            channels_supported: self.channels_supported,
+            ranging: self.local_pib.ranging,
+            ..PhyPib::unspecified_new()
+        };
+        let new_pib = self.local_pib.clone();
         self.with_node(|node| {
             node.pib = new_pib;
+            node.frame_filter = None;
         });
 
         Ok(())
     }
 
     async fn get_instant(&mut self) -> Result<Instant, Self::Error> {
-        Ok(self.aether().simulation_time().now())
+        Ok(self.clock.to_local(self.aether().simulation_time().now()))
     }
 
     fn symbol_period(&self) -> lr_wpan_rs::time::Duration {
         lr_wpan_rs::time::Duration::from_ticks(10000)
     }
 
+    fn turnaround_time_symbols(&self) -> u32 {
+        self.turnaround_time_symbols
+    }
+
+    fn wake_up_time_symbols(&self) -> u32 {
+        self.wake_up_time_symbols
+    }
+
+    fn send_setup_time_symbols(&self) -> u32 {
+        self.send_setup_time_symbols
+    }
+
+    fn scheduled_send_time(&self, send_time: Instant) -> Instant {
+        Instant::from_ticks(
+            send_time.ticks() / self.send_time_resolution_ticks * self.send_time_resolution_ticks,
+        )
+    }
+
     async fn send(
         &mut self,
         data: &[u8],
         send_time: Option<Instant>,
-        _ranging: bool,
-        _use_csma: bool,
+        ranging: bool,
+        use_csma: bool,
+        continuation: SendContinuation,
+    ) -> Result<SendResult, Self::Error> {
+        self.send_with_data_rate(
+            data,
+            send_time,
+            ranging,
+            use_csma,
+            DEFAULT_DATA_RATE,
+            continuation,
+        )
+        .await
+    }
+
+    async fn send_with_data_rate(
+        &mut self,
+        data: &[u8],
+        send_time: Option<Instant>,
+        ranging: bool,
+        use_csma: bool,
+        data_rate: u8,
         continuation: SendContinuation,
     ) -> Result<SendResult, Self::Error> {
         trace!("Radio send {:?}", self.node_id);
+        self.check_broken()?;
+        self.check_awake()?;
+
+        if ranging && !self.local_pib.ranging {
+            return Err(AetherError::RangingNotSupported);
+        }
+
+        if !self.local_pib.supports_data_rate(data_rate) {
+            return Err(AetherError::UnsupportedDataRate);
+        }
+
+        if data.is_empty() {
+            return Err(AetherError::FrameEmpty);
+        }
+
+        if data.len() > MAX_PHY_PACKET_SIZE {
+            return Err(AetherError::FrameTooLong);
+        }
 
         if let Some(send_time) = send_time {
+            let now = self.simulation_time().now();
+            let setup_time = self.symbol_period() * self.send_setup_time_symbols as i64;
+            let send_time = self.clock.to_global(self.scheduled_send_time(send_time));
+            // A real radio can't go back in time either, it reports a late delayed send
+            if send_time < now {
+                return Err(AetherError::SendTimePassed);
+            }
+
+            if send_time.duration_since(now) < setup_time {
+                return Err(AetherError::SendTimeTooClose);
+            }
+
+            let _tx_pending = TxPendingGuard::new(self);
             self.simulation_time().delay_until(send_time).await;
         }
 
@@ -102,7 +271,18 @@ impl Phy for AetherRadio {
 
         // TODO: Handle more than just data
         let channel = self.local_pib.current_channel;
-        self.aether().send(AirPacket::new(data, now, channel));
+
+        // Any interference on the channel makes it look busy to the CCA, so CSMA-CA gives up
+        if use_csma && self.aether().energy_on(channel) > 0 {
+            trace!(
+                "Radio send {:?} failed the CCA on channel {}",
+                self.node_id, channel
+            );
+            return Ok(SendResult::ChannelAccessFailure);
+        }
+
+        self.aether()
+            .send(AirPacket::new(data, now, channel, ranging, data_rate)?);
 
         let response = match continuation {
             SendContinuation::Idle => None,
@@ -110,7 +290,15 @@ impl Phy for AetherRadio {
                 turnaround_time,
                 timeout,
             } => {
-                let receive_start_time = self.simulation_time().delay(turnaround_time).await;
+                // Like on a real radio, the turnaround starts once the frame has been sent.
+                // It's unknown whether the data contains the FCS, so it's left out. That can only
+                // make the receiver start a bit early, never too late.
+                let frame_duration =
+                    self.symbol_period() * self.local_pib.frame_duration(data.len()) as i64;
+                let receive_start_time = self
+                    .simulation_time()
+                    .delay(frame_duration + turnaround_time)
+                    .await;
                 trace!("Wait for response start at: {}", receive_start_time);
                 self.start_receive().await?;
 
@@ -141,7 +329,7 @@ impl Phy for AetherRadio {
         };
 
         // TODO: Handle congestion
-        Ok(SendResult::Success(now, response))
+        Ok(SendResult::Success(self.clock.to_local(now), response))
     }
 
     async fn start_receive(&mut self) -> Result<(), Self::Error> {
@@ -150,6 +338,8 @@ impl Phy for AetherRadio {
             self.node_id,
             self.simulation_time().now(),
         );
+        self.check_broken()?;
+        self.check_awake()?;
 
         self.with_node(|node| {
             node.rx_enable = true;
@@ -165,6 +355,7 @@ impl Phy for AetherRadio {
             self.simulation_time().now(),
         );
 
+        // Stopping when we're not receiving is fine and simply does nothing
         self.with_node(|node| {
             node.rx_enable = false;
         });
@@ -172,6 +363,76 @@ impl Phy for AetherRadio {
         Ok(())
     }
 
+    async fn sleep(&mut self) -> Result<(), Self::Error> {
+        trace!(
+            "Radio sleep {:?} at: {}",
+            self.node_id,
+            self.simulation_time().now(),
+        );
+        self.check_broken()?;
+
+        self.stop_receive().await?;
+        self.sleeping = true;
+
+        Ok(())
+    }
+
+    async fn wake(&mut self) -> Result<(), Self::Error> {
+        self.check_broken()?;
+
+        if !self.sleeping {
+            return Ok(());
+        }
+
+        self.simulation_time()
+            .delay(self.symbol_period() * self.wake_up_time_symbols as i64)
+            .await;
+        self.sleeping = false;
+
+        trace!(
+            "Radio awake {:?} at: {}",
+            self.node_id,
+            self.simulation_time().now(),
+        );
+
+        Ok(())
+    }
+
+    async fn abort_send(&mut self) -> Result<(), Self::Error> {
+        trace!("Radio abort_send {:?}", self.node_id);
+
+        // A delayed send waits on the simulation time before it puts the packet in the air,
+        // so dropping its future already cancelled it (and ended the pending state).
+        // Only the receiver of a continuation can be left on.
+        self.stop_receive().await
+    }
+
+    async fn set_frame_filter(&mut self, filter: Option<FrameFilter>) -> Result<(), Self::Error> {
+        trace!("Radio set_frame_filter {:?}: {:?}", self.node_id, filter);
+        self.check_broken()?;
+
+        self.with_node(|node| {
+            node.frame_filter = filter;
+        });
+
+        Ok(())
+    }
+
+    async fn measure_energy(&mut self) -> Result<u8, Self::Error> {
+        self.check_broken()?;
+        self.check_awake()?;
+
+        let channel = self.local_pib.current_channel;
+        let energy = self.aether().energy_on(channel);
+
+        trace!(
+            "Radio measure_energy {:?} on channel {}: {}",
+            self.node_id, channel, energy
+        );
+
+        Ok(energy)
+    }
+
     async fn wait(&mut self) -> Result<Self::ProcessingContext, Self::Error> {
         loop {
             let msg = self
@@ -189,14 +450,20 @@ impl Phy for AetherRadio {
                 data: msg.data,
                 lqi: 255,
                 channel: msg.channel,
-                page: lr_wpan_rs::ChannelPage::Uwb,
+                page: self.local_pib.current_page,
+                crc_ok: Some(msg.crc_ok),
+                ranging: msg.ranging,
+                data_rate: msg.data_rate,
             };
 
             self.simulation_time()
                 .delay_until_at_least(msg.timestamp)
                 .await;
 
-            return Ok(msg);
+            return Ok(ReceivedMessage {
+                timestamp: self.clock.to_local(msg.timestamp),
+                ..msg
+            });
         }
     }
 
@@ -213,6 +480,8 @@ impl Phy for AetherRadio {
         &mut self,
         f: impl FnOnce(&mut PhyPibWrite) -> U,
     ) -> Result<U, Self::Error> {
+        self.check_broken()?;
+
         let res = f(&mut self.local_pib);
 
         let new_pib = self.local_pib.clone();
@@ -228,6 +497,103 @@ impl Phy for AetherRadio {
     }
 }
 
+impl Drop for AetherRadio {
+    fn drop(&mut self) {
+        trace!("Radio dropped {:?}", self.node_id);
+
+        // Don't panic again if another radio poisoned the aether while panicking
+        let Ok(mut aether) = self.inner.lock() else {
+            return;
+        };
+
+        // Stop receiving as a last gasp. Removing the node makes sure no send targets us anymore.
+        if let Some(node) = aether.nodes.get_mut(&self.node_id) {
+            node.rx_enable = false;
+        }
+        aether.nodes.remove(&self.node_id);
+    }
+}
+
+/// The clock of a single radio, which can drift away from the simulation time
+#[derive(Debug, Clone, Copy)]
+pub(super) struct LocalClock {
+    drift_ppm: f64,
+    /// The simulation time at which the current drift was set
+    global_origin: Instant,
+    /// The local time at which the current drift was set
+    local_origin: Instant,
+}
+
+impl LocalClock {
+    pub(super) const fn new() -> Self {
+        Self {
+            drift_ppm: 0.0,
+            global_origin: Instant::from_ticks(0),
+            local_origin: Instant::from_ticks(0),
+        }
+    }
+
+    fn rate(&self) -> f64 {
+        1.0 + self.drift_ppm / 1_000_000.0
+    }
+
+    fn set_drift_ppm(&mut self, now: Instant, drift_ppm: f32) {
+        self.local_origin = self.to_local(now);
+        self.global_origin = now;
+        self.drift_ppm = drift_ppm as f64;
+    }
+
+    /// Convert a simulation time to the time of this clock
+    fn to_local(self, global: Instant) -> Instant {
+        let elapsed = global.ticks() as f64 - self.global_origin.ticks() as f64;
+        Instant::from_ticks(
+            (self.local_origin.ticks() as f64 + elapsed * self.rate()).round() as u64,
+        )
+    }
+
+    /// Convert a time of this clock to the simulation time.
+    ///
+    /// This rounds up, so a local time gotten from [Self::to_local] never ends up before the original simulation time.
+    fn to_global(self, local: Instant) -> Instant {
+        let elapsed = local.ticks() as f64 - self.local_origin.ticks() as f64;
+        Instant::from_ticks(
+            (self.global_origin.ticks() as f64 + elapsed / self.rate()).ceil() as u64,
+        )
+    }
+}
+
+/// Marks a radio as having a delayed send pending for as long as it lives, see [Node::tx_pending].
+///
+/// It ends the pending state when dropped, so a cancelled send doesn't keep the radio deaf.
+struct TxPendingGuard {
+    inner: Arc<Mutex<AetherInner>>,
+    node_id: NodeId,
+}
+
+impl TxPendingGuard {
+    fn new(radio: &mut AetherRadio) -> Self {
+        radio.with_node(|node| node.tx_pending = true);
+
+        Self {
+            inner: Arc::clone(&radio.inner),
+            node_id: radio.node_id.clone(),
+        }
+    }
+}
+
+impl Drop for TxPendingGuard {
+    fn drop(&mut self) {
+        // Don't panic again if another radio poisoned the aether while panicking
+        let Ok(mut aether) = self.inner.lock() else {
+            return;
+        };
+
+        if let Some(node) = aether.nodes.get_mut(&self.node_id) {
+            node.tx_pending = false;
+        }
+    }
+}
+
 struct AetherGuard<'a> {
     aether: MutexGuard<'a, AetherInner>,
     node_id: NodeId,
@@ -241,4 +607,8 @@ impl AetherGuard<'_> {
     fn simulation_time(&self) -> &'static SimulationTime {
         self.aether.simulation_time
     }
+
+    fn energy_on(&self, channel: u8) -> u8 {
+        self.aether.energy_on(channel)
+    }
 }
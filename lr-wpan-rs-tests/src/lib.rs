@@ -1,3 +1,4 @@
 pub mod aether;
+pub mod pan;
 pub mod run;
 pub mod time;
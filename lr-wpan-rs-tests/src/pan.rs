@@ -0,0 +1,173 @@
+//! Helpers that stand up a PAN, so tests don't have to repeat all requests needed for it.
+//!
+//! ```rust,ignore
+//! let pan_started = spawn_coordinator(&mut runner, commanders[0], PanId(1), 5, BeaconOrder::BeaconOrder(10), SuperframeOrder::SuperframeOrder(10));
+//! let joined = spawn_end_device(&mut runner, commanders[1], PanId(1), 5, pan_started);
+//! ```
+
+use heapless::Vec;
+use log::{info, warn};
+use lr_wpan_rs::{
+    ChannelPage,
+    allocation::Allocation,
+    mac::MacCommander,
+    pib::PibValue,
+    sap::{
+        IndicationValue, SecurityInfo,
+        associate::{AssociateConfirm, AssociateIndication, AssociateRequest, AssociateResponse},
+        disassociate::DisassociateIndication,
+        scan::{ScanRequest, ScanType},
+        start::StartRequest,
+    },
+    wire::{
+        PanId, ShortAddress,
+        beacon::{BeaconOrder, SuperframeOrder},
+        command::{AssociationStatus, CapabilityInformation},
+    },
+};
+
+use crate::run::TestRunner;
+
+/// Start a PAN with the commander as its PAN coordinator.
+///
+/// The coordinator uses short address 0 and accepts every device that wants to associate,
+/// handing out the short addresses 1, 2, 3 and so on. It runs as a background task, so it keeps going
+/// until the test is done.
+///
+/// The returned receiver is closed once the PAN has started, so any number of devices can wait on it.
+pub fn spawn_coordinator(
+    runner: &mut TestRunner<'_>,
+    commander: &'static MacCommander,
+    pan_id: PanId,
+    channel: u8,
+    beacon_order: BeaconOrder,
+    superframe_order: SuperframeOrder,
+) -> async_channel::Receiver<()> {
+    let (started_sender, started_receiver) = async_channel::bounded(1);
+
+    runner.attach_background_task(async move {
+        commander
+            .initialize(&[
+                PibValue::MacShortAddress(ShortAddress(0)),
+                PibValue::MacAssociationPermit(true),
+            ])
+            .await
+            .unwrap();
+
+        commander
+            .request(StartRequest {
+                pan_id,
+                channel_number: channel,
+                channel_page: ChannelPage::Uwb,
+                start_time: 0,
+                beacon_order,
+                superframe_order,
+                pan_coordinator: true,
+                battery_life_extension: false,
+                coord_realignment: false,
+                coord_realign_security_info: SecurityInfo::new_none_security(),
+                beacon_security_info: SecurityInfo::new_none_security(),
+            })
+            .await
+            .status
+            .unwrap();
+
+        drop(started_sender);
+
+        let mut next_short_address = 1;
+        loop {
+            let indication_responder = commander.wait_for_indication().await;
+            match indication_responder.indication {
+                IndicationValue::Associate(_) => {
+                    let responder = indication_responder.into_concrete::<AssociateIndication>();
+                    info!(
+                        "Coordinator of PAN {:?} accepts {:?}",
+                        pan_id, responder.indication.device_address
+                    );
+
+                    let device_address = responder.indication.device_address;
+                    responder.respond(AssociateResponse {
+                        device_address,
+                        assoc_short_address: ShortAddress(next_short_address),
+                        status: AssociationStatus::Successful,
+                        security_info: SecurityInfo::new_none_security(),
+                    });
+                    next_short_address += 1;
+                }
+                IndicationValue::Disassociate(_) => indication_responder
+                    .into_concrete::<DisassociateIndication>()
+                    .respond(()),
+                indication => warn!("Coordinator ignores the indication: {indication:?}"),
+            }
+        }
+    });
+
+    started_receiver
+}
+
+/// Let the commander find the PAN on the channel and associate to its coordinator.
+///
+/// The device waits until the `pan_started` receiver of [spawn_coordinator] is closed.
+/// The returned receiver gets the confirm of the association.
+pub fn spawn_end_device(
+    runner: &mut TestRunner<'_>,
+    commander: &'static MacCommander,
+    pan_id: PanId,
+    channel: u8,
+    pan_started: async_channel::Receiver<()>,
+) -> async_channel::Receiver<AssociateConfirm> {
+    let (confirm_sender, confirm_receiver) = async_channel::bounded(1);
+
+    runner.attach_test_task(async move {
+        // With macAutoRequest we get a list of the found PANs instead of indications
+        commander
+            .initialize(&[PibValue::MacAutoRequest(true)])
+            .await
+            .unwrap();
+
+        let _ = pan_started.recv().await;
+
+        let mut scan_allocation = [None; 4];
+        let scan_confirm = commander
+            .request_with_allocation(
+                ScanRequest {
+                    scan_type: ScanType::Active,
+                    scan_channels: Vec::from_slice(&[channel]).unwrap(),
+                    pan_descriptor_list: Allocation::new(),
+                    scan_duration: 14,
+                    channel_page: ChannelPage::Uwb,
+                    scan_channel_pages: Vec::new(),
+                    security_info: SecurityInfo::new_none_security(),
+                    include_source_address: false,
+                },
+                &mut scan_allocation,
+            )
+            .await;
+
+        let coord_address = scan_confirm
+            .pan_descriptor_list()
+            .map(|pan_descriptor| pan_descriptor.coord_address)
+            .find(|coord_address| coord_address.pan_id() == pan_id)
+            .expect("The PAN must have been found");
+
+        let associate_confirm = commander
+            .request(AssociateRequest {
+                channel_number: channel,
+                channel_page: ChannelPage::Uwb,
+                coord_address,
+                capability_information: CapabilityInformation {
+                    full_function_device: false,
+                    mains_power: false,
+                    idle_receive: false,
+                    frame_protection: false,
+                    allocate_address: true,
+                },
+                security_info: SecurityInfo::new_none_security(),
+            })
+            .await;
+
+        confirm_sender.send(associate_confirm).await.unwrap();
+    });
+
+    confirm_receiver
+}
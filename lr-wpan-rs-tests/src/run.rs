@@ -2,7 +2,11 @@ use std::{future::Future, sync::Arc};
 
 use async_executor::{Executor, Task};
 use lr_wpan_rs::{
-    mac::{MacCommander, MacConfig},
+    mac::{
+        DutyCycleLimit, IndicationOverflowPolicy, MacCommander, MacConfig, PlanningHeadroom,
+        ShortAddressAssignment,
+    },
+    pib::{ChannelDescription, PhyPib, TxPolicy},
     wire::ExtendedAddress,
 };
 use rand::{SeedableRng, rngs::StdRng};
@@ -10,10 +14,25 @@ use rand::{SeedableRng, rngs::StdRng};
 use super::aether::Aether;
 use crate::{aether::Coordinate, time::SimulationTime};
 
-/// Run multiple mac engines
+/// Run multiple mac engines.
+///
+/// The rng of mac engine `i` is seeded with `i`.
+/// Use [create_test_runner_with] to pick the seeds or any other option yourself.
 pub fn create_test_runner<'a>(
     mac_stack_count: usize,
 ) -> (Arc<[&'static MacCommander]>, Aether, TestRunner<'a>) {
+    create_test_runner_with((0..mac_stack_count as u64).map(EngineOptions::new))
+}
+
+/// Run a mac engine for every given set of options.
+///
+/// Change only the options the test is about:
+/// `create_test_runner_with((0..2).map(|seed| EngineOptions { auto_ack: false, ..EngineOptions::new(seed) }))`
+pub fn create_test_runner_with<'a>(
+    engine_options: impl IntoIterator<Item = EngineOptions>,
+) -> (Arc<[&'static MacCommander]>, Aether, TestRunner<'a>) {
+    let engine_options = Vec::from_iter(engine_options);
+    let mac_stack_count = engine_options.len();
     let commanders = Arc::from_iter(
         (0..mac_stack_count).map(|_| Box::leak(Box::new(MacCommander::new())) as &_),
     );
@@ -23,20 +42,40 @@ pub fn create_test_runner<'a>(
     let mut aether = Aether::new(simulation_time);
     let executor = Executor::new();
 
-    let engine_handles = (0..mac_stack_count)
-        .map(|i| {
+    let engine_handles = engine_options
+        .into_iter()
+        .enumerate()
+        .map(|(i, options)| {
             let commanders = commanders.clone();
             executor.spawn({
                 let mut radio = aether.radio();
                 radio.move_to(Coordinate::new(i as f64, 0.0));
+                radio.set_clock_drift_ppm(options.clock_drift_ppm);
+                radio.set_ranging_supported(options.phy_ranging);
+                radio.set_turnaround_time_symbols(options.turnaround_time_symbols);
+                radio.set_send_setup_time_symbols(options.send_setup_time_symbols);
+                radio.set_send_time_resolution_ticks(options.send_time_resolution_ticks);
+                radio.set_channels_supported(options.channels_supported);
                 async move {
                     lr_wpan_rs::mac::run_mac_engine(
                         radio,
                         commanders[i],
                         MacConfig {
-                            extended_address: ExtendedAddress(i as _),
-                            rng: StdRng::seed_from_u64(i as _),
-                            delay: crate::time::Delay(simulation_time),
+                            auto_ack: options.auto_ack,
+                            sync_symbol_offset: options.sync_symbol_offset,
+                            indication_overflow_policy: options.indication_overflow_policy,
+                            max_indirect_indications: options.max_indirect_indications,
+                            tx_policy: options.tx_policy,
+                            mac_fcs: options.mac_fcs,
+                            ack_time_correction: options.ack_time_correction,
+                            duty_cycle_limit: options.duty_cycle_limit,
+                            planning_headroom: options.planning_headroom,
+                            short_address_assignment: options.short_address_assignment,
+                            ..MacConfig::new(
+                                ExtendedAddress(i as _),
+                                StdRng::seed_from_u64(options.seed),
+                                crate::time::Delay(simulation_time),
+                            )
                         },
                     )
                     .await;
@@ -51,16 +90,76 @@ pub fn create_test_runner<'a>(
         TestRunner {
             executor,
             task_handles: Vec::new(),
+            background_handles: Vec::new(),
             engine_handles,
             simulation_time,
         },
     )
 }
 
+/// The settings of a single mac engine and its radio.
+///
+/// The mac settings are those of [MacConfig], which are explained there.
+/// Mac engine `i` always gets the extended address `i`.
+pub struct EngineOptions {
+    /// The seed of the rng of the mac engine, which is the only source of randomness in the mac layer
+    /// (e.g. for the sequence numbers and the CSMA-CA backoffs).
+    /// Running with the same seeds will give the same behavior every time.
+    pub seed: u64,
+    pub auto_ack: bool,
+    /// See [AetherRadio::set_clock_drift_ppm](crate::aether::AetherRadio::set_clock_drift_ppm)
+    pub clock_drift_ppm: f32,
+    pub sync_symbol_offset: u16,
+    pub indication_overflow_policy: IndicationOverflowPolicy,
+    pub max_indirect_indications: usize,
+    /// See [AetherRadio::set_ranging_supported](crate::aether::AetherRadio::set_ranging_supported)
+    pub phy_ranging: bool,
+    pub tx_policy: TxPolicy,
+    pub mac_fcs: bool,
+    pub ack_time_correction: bool,
+    pub duty_cycle_limit: Option<DutyCycleLimit>,
+    pub planning_headroom: PlanningHeadroom,
+    /// See [AetherRadio::set_turnaround_time_symbols](crate::aether::AetherRadio::set_turnaround_time_symbols)
+    pub turnaround_time_symbols: u32,
+    /// See [AetherRadio::set_send_setup_time_symbols](crate::aether::AetherRadio::set_send_setup_time_symbols)
+    pub send_setup_time_symbols: u32,
+    /// See [AetherRadio::set_send_time_resolution_ticks](crate::aether::AetherRadio::set_send_time_resolution_ticks)
+    pub send_time_resolution_ticks: u64,
+    /// See [AetherRadio::set_channels_supported](crate::aether::AetherRadio::set_channels_supported)
+    pub channels_supported: &'static [ChannelDescription],
+    pub short_address_assignment: Option<ShortAddressAssignment>,
+}
+
+impl EngineOptions {
+    /// The default options, with the given seed
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            auto_ack: true,
+            clock_drift_ppm: 0.0,
+            sync_symbol_offset: 0,
+            indication_overflow_policy: IndicationOverflowPolicy::Block,
+            max_indirect_indications: 4,
+            phy_ranging: true,
+            tx_policy: TxPolicy::default(),
+            mac_fcs: false,
+            ack_time_correction: false,
+            duty_cycle_limit: None,
+            planning_headroom: PlanningHeadroom::default(),
+            turnaround_time_symbols: lr_wpan_rs::consts::TURNAROUND_TIME,
+            send_setup_time_symbols: 0,
+            send_time_resolution_ticks: 1,
+            channels_supported: PhyPib::unspecified_new().channels_supported,
+            short_address_assignment: None,
+        }
+    }
+}
+
 pub struct TestRunner<'a> {
     executor: Executor<'a>,
     engine_handles: Vec<Task<()>>,
     task_handles: Vec<Task<()>>,
+    background_handles: Vec<Task<()>>,
     pub simulation_time: &'static SimulationTime,
 }
 
@@ -69,6 +168,12 @@ impl<'a> TestRunner<'a> {
         self.task_handles.push(self.executor.spawn(f));
     }
 
+    /// Attach a task that runs alongside the test tasks, like the mac engines do.
+    /// The test is done when all test tasks are done, even if background tasks are still running.
+    pub fn attach_background_task(&mut self, f: impl Future<Output = ()> + Send + 'a) {
+        self.background_handles.push(self.executor.spawn(f));
+    }
+
     pub fn run(mut self) {
         loop {
             if !self.executor.try_tick() {
@@ -82,6 +187,13 @@ impl<'a> TestRunner<'a> {
                 }
             }
 
+            for i in (0..self.background_handles.len()).rev() {
+                if self.background_handles[i].is_finished() {
+                    // Check to see if it produced a result (and thus didn't panic)
+                    futures::executor::block_on(self.background_handles.remove(i).cancel());
+                }
+            }
+
             for i in (0..self.task_handles.len()).rev() {
                 if self.task_handles[i].is_finished() {
                     // Check to see if it produced a result (and thus didn't panic)
@@ -0,0 +1,506 @@
+use byte::{TryRead, TryWrite};
+use lr_wpan_rs::{
+    consts::{MAX_PHY_PACKET_SIZE, UNIT_BACKOFF_PERIOD},
+    mac::MacCommander,
+    phy::{Phy, SendContinuation, SendResult},
+    pib::PibValue,
+    sap::{Status, ack::AckRequest, get::GetRequest, reset::ResetRequest, set::SetRequest},
+    time::Duration,
+    wire::{
+        Address, ExtendedAddress, FooterMode, Frame, FrameContent, FrameSerDesContext, FrameType,
+        FrameVersion, Header, PanId, ShortAddress, TimeCorrection,
+        beacon::{BeaconOrder, SuperframeOrder},
+        command::Command,
+        fcs::FCS_LENGTH,
+    },
+};
+use lr_wpan_rs_tests::run::EngineOptions;
+
+#[test_log::test]
+fn ack_is_sent_after_sifs() {
+    let (commanders, mut aether, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    let device = commanders[0];
+    let simulation_time = runner.simulation_time;
+
+    runner.attach_test_task(async move {
+        let mut radio = aether.radio();
+
+        prepare_device(device).await;
+
+        let PibValue::MacSifsPeriod(sifs_period) = device
+            .request(GetRequest {
+                pib_attribute: PibValue::MAC_SIFS_PERIOD,
+            })
+            .await
+            .value
+        else {
+            panic!("Wrong pib value type");
+        };
+        assert_ne!(sifs_period, 0);
+
+        // Give the mac engine the time to turn on its receiver
+        simulation_time.delay(Duration::from_millis(1)).await;
+
+        let mut buffer = [0; MAX_PHY_PACKET_SIZE];
+        let length = write_ack_requesting_frame(&mut buffer, 42);
+
+        let SendResult::Success(send_time, Some(response)) = radio
+            .send(
+                &buffer[..length],
+                None,
+                false,
+                false,
+                SendContinuation::WaitForResponse {
+                    turnaround_time: Duration::from_ticks(0),
+                    timeout: Duration::from_millis(100),
+                },
+            )
+            .await
+            .unwrap()
+        else {
+            panic!("No response received");
+        };
+
+        let (ack, _) = Frame::try_read(&response.data, FooterMode::None).unwrap();
+        assert_eq!(ack.header.frame_type, FrameType::Acknowledgement);
+        assert_eq!(ack.header.seq, 42);
+
+        // The SIFS starts once the last symbol of our frame, including the FCS, has been sent
+        let frame_end_time = send_time
+            + radio.symbol_period()
+                * radio.get_phy_pib().frame_duration(length + FCS_LENGTH) as i64;
+        assert!(
+            response.timestamp.duration_since(frame_end_time)
+                >= radio.symbol_period() * sifs_period as i64
+        );
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn no_ack_is_sent_without_auto_ack() {
+    let (commanders, mut aether, mut runner) =
+        lr_wpan_rs_tests::run::create_test_runner_with([EngineOptions {
+            auto_ack: false,
+            ..EngineOptions::new(0)
+        }]);
+
+    let device = commanders[0];
+    let simulation_time = runner.simulation_time;
+
+    runner.attach_test_task(async move {
+        let mut radio = aether.radio();
+
+        prepare_device(device).await;
+
+        // Give the mac engine the time to turn on its receiver
+        simulation_time.delay(Duration::from_millis(1)).await;
+
+        let mut buffer = [0; MAX_PHY_PACKET_SIZE];
+        let length = write_ack_requesting_frame(&mut buffer, 42);
+
+        let SendResult::Success(send_time, response) = radio
+            .send(
+                &buffer[..length],
+                None,
+                false,
+                false,
+                SendContinuation::WaitForResponse {
+                    turnaround_time: Duration::from_ticks(0),
+                    timeout: Duration::from_millis(100),
+                },
+            )
+            .await
+            .unwrap()
+        else {
+            panic!("Could not send");
+        };
+
+        // The mac must leave the acking to us
+        assert!(response.is_none());
+
+        // Which is possible with the ack request
+        radio.start_receive().await.unwrap();
+
+        let ack_confirm = device
+            .request(AckRequest {
+                seq: 42,
+                receive_time: send_time,
+                frame_pending: false,
+            })
+            .await;
+        assert_eq!(ack_confirm.status, Status::Success);
+
+        let response = radio.wait().await.unwrap();
+        let response = radio.process(response).await.unwrap().unwrap();
+
+        let (ack, _) = Frame::try_read(&response.data, FooterMode::None).unwrap();
+        assert_eq!(ack.header.frame_type, FrameType::Acknowledgement);
+        assert_eq!(ack.header.seq, 42);
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn frame_with_bad_crc_is_flagged_and_dropped() {
+    let (commanders, mut aether, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    let device = commanders[0];
+    let simulation_time = runner.simulation_time;
+
+    runner.attach_test_task(async move {
+        let mut radio = aether.radio();
+        let mut sniffer = aether.radio();
+        sniffer.start_receive().await.unwrap();
+
+        prepare_device(device).await;
+
+        // Give the mac engine the time to turn on its receiver
+        simulation_time.delay(Duration::from_millis(1)).await;
+
+        let mut buffer = [0; MAX_PHY_PACKET_SIZE];
+        let length = write_ack_requesting_frame(&mut buffer, 42);
+        let continuation = SendContinuation::WaitForResponse {
+            turnaround_time: Duration::from_ticks(0),
+            timeout: Duration::from_millis(100),
+        };
+
+        aether.corrupt_next_frames(1);
+
+        let SendResult::Success(_, response) = radio
+            .send(&buffer[..length], None, false, false, continuation)
+            .await
+            .unwrap()
+        else {
+            panic!("Could not send");
+        };
+
+        // The mac must have dropped the frame, so it's not acked
+        assert!(response.is_none());
+
+        // A sniffer still sees the frame, but it's flagged
+        let sniffed = sniffer.wait().await.unwrap();
+        let sniffed = sniffer.process(sniffed).await.unwrap().unwrap();
+        assert_eq!(sniffed.crc_ok, Some(false));
+        assert_eq!(sniffed.data.len(), length);
+
+        // Without the corruption, the same frame is accepted
+        let SendResult::Success(_, Some(response)) = radio
+            .send(&buffer[..length], None, false, false, continuation)
+            .await
+            .unwrap()
+        else {
+            panic!("No response received");
+        };
+
+        assert_eq!(response.crc_ok, Some(true));
+        let (ack, _) = Frame::try_read(&response.data, FooterMode::None).unwrap();
+        assert_eq!(ack.header.frame_type, FrameType::Acknowledgement);
+        assert_eq!(ack.header.seq, 42);
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn stray_ack_is_ignored() {
+    let (commanders, mut aether, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    let device = commanders[0];
+    let simulation_time = runner.simulation_time;
+
+    runner.attach_test_task(async move {
+        let mut radio = aether.radio();
+
+        prepare_device(device).await;
+
+        // Give the mac engine the time to turn on its receiver
+        simulation_time.delay(Duration::from_millis(1)).await;
+
+        let continuation = SendContinuation::WaitForResponse {
+            turnaround_time: Duration::from_ticks(0),
+            timeout: Duration::from_millis(100),
+        };
+
+        // Nobody is waiting for this ack. It (wrongly) asks for an ack itself,
+        // which would be sent if the mac processed it like any other frame.
+        let mut buffer = [0; MAX_PHY_PACKET_SIZE];
+        let length = write_stray_ack(&mut buffer, 42);
+
+        let SendResult::Success(_, response) = radio
+            .send(&buffer[..length], None, false, false, continuation)
+            .await
+            .unwrap()
+        else {
+            panic!("Could not send");
+        };
+
+        assert!(response.is_none());
+
+        // The device is still listening and acks a normal frame
+        let length = write_ack_requesting_frame(&mut buffer, 43);
+
+        let SendResult::Success(_, Some(response)) = radio
+            .send(&buffer[..length], None, false, false, continuation)
+            .await
+            .unwrap()
+        else {
+            panic!("No response received");
+        };
+
+        let (ack, _) = Frame::try_read(&response.data, FooterMode::None).unwrap();
+        assert_eq!(ack.header.frame_type, FrameType::Acknowledgement);
+        assert_eq!(ack.header.seq, 43);
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn frames_with_unsupported_frame_types_are_discarded() {
+    let (commanders, mut aether, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    let device = commanders[0];
+    let simulation_time = runner.simulation_time;
+
+    runner.attach_test_task(async move {
+        let mut radio = aether.radio();
+
+        prepare_device(device).await;
+
+        // Give the mac engine the time to turn on its receiver
+        simulation_time.delay(Duration::from_millis(1)).await;
+
+        let continuation = SendContinuation::WaitForResponse {
+            turnaround_time: Duration::from_ticks(0),
+            timeout: Duration::from_millis(100),
+        };
+
+        // A reserved frame type and the multipurpose frame type, which isn't supported
+        for frame_type in [0b100, FrameType::Multipurpose as u8] {
+            let mut buffer = [0; MAX_PHY_PACKET_SIZE];
+            let length = write_ack_requesting_frame(&mut buffer, 42);
+            buffer[0] = (buffer[0] & !0b111) | frame_type;
+
+            let SendResult::Success(_, response) = radio
+                .send(&buffer[..length], None, false, false, continuation)
+                .await
+                .unwrap()
+            else {
+                panic!("Could not send");
+            };
+
+            // The frame is dropped, so it's not acked
+            assert!(response.is_none());
+        }
+
+        assert_eq!(device.discarded_frames(), 2);
+
+        // The device still acks a normal frame
+        let mut buffer = [0; MAX_PHY_PACKET_SIZE];
+        let length = write_ack_requesting_frame(&mut buffer, 43);
+
+        let SendResult::Success(_, Some(response)) = radio
+            .send(&buffer[..length], None, false, false, continuation)
+            .await
+            .unwrap()
+        else {
+            panic!("No response received");
+        };
+
+        let (ack, _) = Frame::try_read(&response.data, FooterMode::None).unwrap();
+        assert_eq!(ack.header.seq, 43);
+        assert_eq!(device.discarded_frames(), 2);
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn enhanced_ack_carries_the_time_correction() {
+    let (commanders, mut aether, mut runner) =
+        lr_wpan_rs_tests::run::create_test_runner_with([EngineOptions {
+            ack_time_correction: true,
+            ..EngineOptions::new(0)
+        }]);
+
+    let pan_started = lr_wpan_rs_tests::pan::spawn_coordinator(
+        &mut runner,
+        commanders[0],
+        PanId(1),
+        5,
+        BeaconOrder::BeaconOrder(5),
+        SuperframeOrder::SuperframeOrder(5),
+    );
+
+    runner.attach_test_task(async move {
+        let mut radio = aether.radio();
+        radio.start_receive().await.unwrap();
+        let _ = pan_started.recv().await;
+
+        // The superframe of the coordinator starts at its beacon
+        let beacon_time = loop {
+            let context = radio.wait().await.unwrap();
+            let Some(message) = radio.process(context).await.unwrap() else {
+                continue;
+            };
+            let (frame, _) = Frame::try_read(&message.data, FooterMode::None).unwrap();
+            if frame.header.frame_type == FrameType::Beacon {
+                break message.timestamp;
+            }
+        };
+        radio.stop_receive().await.unwrap();
+
+        // Send a frame a microsecond after a backoff period boundary
+        let backoff_period = radio.symbol_period() * UNIT_BACKOFF_PERIOD as i64;
+        let send_time = beacon_time + backoff_period * 10 + Duration::from_micros(1);
+
+        let mut buffer = [0; MAX_PHY_PACKET_SIZE];
+        let length = write_enhanced_ack_requesting_frame(&mut buffer, 42);
+
+        let SendResult::Success(_, Some(response)) = radio
+            .send(
+                &buffer[..length],
+                Some(send_time),
+                false,
+                false,
+                SendContinuation::WaitForResponse {
+                    turnaround_time: Duration::from_ticks(0),
+                    timeout: Duration::from_millis(100),
+                },
+            )
+            .await
+            .unwrap()
+        else {
+            panic!("No response received");
+        };
+
+        let (ack, _) = Frame::try_read(&response.data, FooterMode::None).unwrap();
+        assert_eq!(ack.header.frame_type, FrameType::Acknowledgement);
+        assert_eq!(ack.header.version, FrameVersion::Ieee802154);
+        assert_eq!(ack.header.seq, 42);
+        // The frame was late
+        assert_eq!(
+            ack.header.time_correction,
+            Some(TimeCorrection {
+                microseconds: -1,
+                nack: false,
+            })
+        );
+    });
+
+    runner.run();
+}
+
+async fn prepare_device(device: &MacCommander) {
+    device
+        .request(ResetRequest {
+            set_default_pib: true,
+        })
+        .await
+        .status
+        .unwrap();
+
+    // Keep the receiver on so the device hears our frame
+    device
+        .request(SetRequest {
+            pib_attribute: PibValue::MAC_RX_ON_WHEN_IDLE,
+            pib_attribute_value: PibValue::MacRxOnWhenIdle(true),
+        })
+        .await
+        .status
+        .unwrap();
+}
+
+/// Write a data request command that asks for an ack and return its length
+fn write_ack_requesting_frame(buffer: &mut [u8], seq: u8) -> usize {
+    let frame = Frame {
+        header: Header {
+            frame_type: FrameType::MacCommand,
+            frame_pending: false,
+            ack_request: true,
+            pan_id_compress: false,
+            seq_no_suppress: false,
+            ie_present: false,
+            version: FrameVersion::Ieee802154_2003,
+            seq,
+            destination: Some(Address::Short(PanId(0), ShortAddress(0))),
+            source: Some(Address::Extended(PanId(0), ExtendedAddress(100))),
+            auxiliary_security_header: None,
+            time_correction: None,
+        },
+        content: FrameContent::Command(Command::DataRequest),
+        payload: &[],
+        footer: [0, 0],
+    };
+
+    frame
+        .try_write(
+            buffer,
+            &mut FrameSerDesContext::no_security(FooterMode::None),
+        )
+        .unwrap()
+}
+
+/// Write a 2015 data frame for the coordinator that asks for an ack and return its length
+fn write_enhanced_ack_requesting_frame(buffer: &mut [u8], seq: u8) -> usize {
+    let frame = Frame {
+        header: Header {
+            frame_type: FrameType::Data,
+            frame_pending: false,
+            ack_request: true,
+            pan_id_compress: false,
+            seq_no_suppress: false,
+            ie_present: false,
+            version: FrameVersion::Ieee802154,
+            seq,
+            destination: Some(Address::Short(PanId(1), ShortAddress(0))),
+            source: Some(Address::Extended(PanId(1), ExtendedAddress(100))),
+            auxiliary_security_header: None,
+            time_correction: None,
+        },
+        content: FrameContent::Data,
+        payload: &[1, 2, 3],
+        footer: [0, 0],
+    };
+
+    frame
+        .try_write(
+            buffer,
+            &mut FrameSerDesContext::no_security(FooterMode::None),
+        )
+        .unwrap()
+}
+
+/// Write an ack that asks to be acked and return its length
+fn write_stray_ack(buffer: &mut [u8], seq: u8) -> usize {
+    let frame = Frame {
+        header: Header {
+            frame_type: FrameType::Acknowledgement,
+            frame_pending: false,
+            ack_request: true,
+            pan_id_compress: false,
+            seq_no_suppress: false,
+            ie_present: false,
+            version: FrameVersion::Ieee802154_2003,
+            seq,
+            destination: None,
+            source: None,
+            auxiliary_security_header: None,
+            time_correction: None,
+        },
+        content: FrameContent::Acknowledgement,
+        payload: &[],
+        footer: [0, 0],
+    };
+
+    frame
+        .try_write(
+            buffer,
+            &mut FrameSerDesContext::no_security(FooterMode::None),
+        )
+        .unwrap()
+}
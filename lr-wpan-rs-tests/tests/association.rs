@@ -1,25 +1,32 @@
+use byte::TryRead;
+use futures::FutureExt;
 use heapless::Vec;
 use log::info;
 use lr_wpan_rs::{
     ChannelPage,
     allocation::Allocation,
-    mac::MacCommander,
+    mac::{AssociatedDevice, MacCommander},
+    phy::Phy,
     pib::PibValue,
     sap::{
-        IndicationValue, SecurityInfo,
-        associate::{AssociateIndication, AssociateRequest, AssociateResponse},
+        IndicationValue, SecurityInfo, Status,
+        associate::{AssociateConfirm, AssociateIndication, AssociateRequest, AssociateResponse},
+        disassociate::{DisassociateIndication, DisassociateRequest},
         get::GetRequest,
+        operational_state::MacOperationalState,
         reset::ResetRequest,
         scan::ScanRequest,
         set::SetRequest,
         start::StartRequest,
     },
+    time::Duration,
     wire::{
-        ExtendedAddress, PanId, ShortAddress,
+        Address, ExtendedAddress, FooterMode, Frame, PanId, ShortAddress,
         beacon::{BeaconOrder, SuperframeOrder},
-        command::{AssociationStatus, CapabilityInformation},
+        command::{AssociationStatus, CapabilityInformation, DisassociationReason},
     },
 };
+use lr_wpan_rs_tests::{run::EngineOptions, time::SimulationTime};
 
 #[test_log::test]
 fn associate() {
@@ -29,69 +36,16 @@ fn associate() {
     let device = commanders[1];
 
     let (ready_sender, ready_receiver) = async_channel::bounded(1);
-    runner.attach_test_task(run_pan_coordinator(pan_coordinator, ready_sender));
+    runner.attach_test_task(run_pan_coordinator(
+        pan_coordinator,
+        ready_sender,
+        AssociationStatus::Successful,
+        None,
+    ));
 
     // Run the device
     runner.attach_test_task(async move {
-        // Reset the device
-        device
-            .request(ResetRequest {
-                set_default_pib: true,
-            })
-            .await
-            .status
-            .unwrap();
-
-        // Set macAutoRequest so we get a list of scanned beacons instead of indications
-        device
-            .request(SetRequest {
-                pib_attribute: PibValue::MAC_AUTO_REQUEST,
-                pib_attribute_value: PibValue::MacAutoRequest(true),
-            })
-            .await
-            .status
-            .unwrap();
-
-        // Wait until coordinator is ready
-        let _ = ready_receiver.recv().await;
-
-        // Scan for the PAN the coordinator is running
-        let mut scan_allocation = [None; 1];
-        let scan_confirm = device
-            .request_with_allocation(
-                ScanRequest {
-                    scan_type: lr_wpan_rs::sap::scan::ScanType::Active,
-                    scan_channels: Vec::from_slice(&[0]).unwrap(),
-                    pan_descriptor_list: Allocation::new(),
-                    scan_duration: 14,
-                    channel_page: ChannelPage::Mhz868_915_2450,
-                    security_info: SecurityInfo::new_none_security(),
-                },
-                &mut scan_allocation,
-            )
-            .await;
-
-        let scanned_coordinator = scan_confirm
-            .pan_descriptor_list()
-            .next()
-            .expect("One PAN must have been found");
-
-        // We've found the PAN, now associate with it
-        let associate_confirm = device
-            .request(AssociateRequest {
-                channel_number: 0,
-                channel_page: ChannelPage::Mhz868_915_2450,
-                coord_address: scanned_coordinator.coord_address,
-                capability_information: CapabilityInformation {
-                    full_function_device: true,
-                    mains_power: true,
-                    idle_receive: true,
-                    frame_protection: false,
-                    allocate_address: true,
-                },
-                security_info: SecurityInfo::new_none_security(),
-            })
-            .await;
+        let associate_confirm = scan_and_associate(device, ready_receiver).await;
 
         // Now assert we got the answer we expect
         assert_eq!(associate_confirm.status, Ok(AssociationStatus::Successful));
@@ -148,10 +102,870 @@ fn associate() {
     runner.run();
 }
 
+#[test_log::test]
+fn associate_with_mac_fcs() {
+    let (commanders, mut aether, mut runner) =
+        lr_wpan_rs_tests::run::create_test_runner_with((0..2).map(|seed| EngineOptions {
+            mac_fcs: true,
+            ..EngineOptions::new(seed)
+        }));
+
+    let pan_coordinator = commanders[0];
+    let device = commanders[1];
+
+    // Check that everything that goes over the air carries a valid FCS
+    let mut sniffer = aether.radio();
+    let (done_sender, done_receiver) = async_channel::bounded(1);
+    runner.attach_test_task(async move {
+        sniffer
+            .update_phy_pib(|pib| {
+                pib.current_channel = 0;
+                pib.current_page = ChannelPage::Mhz868_915_2450;
+            })
+            .await
+            .unwrap();
+        sniffer.start_receive().await.unwrap();
+
+        let mut frames = 0;
+        loop {
+            futures::select_biased! {
+                context = sniffer.wait().fuse() => {
+                    let message = sniffer.process(context.unwrap()).await.unwrap().unwrap();
+                    Frame::try_read(&message.data, FooterMode::Fcs).unwrap();
+                    frames += 1;
+                }
+                _ = done_receiver.recv().fuse() => break,
+            }
+        }
+
+        assert!(frames > 0);
+    });
+
+    let (ready_sender, ready_receiver) = async_channel::bounded(1);
+    runner.attach_test_task(run_pan_coordinator(
+        pan_coordinator,
+        ready_sender,
+        AssociationStatus::Successful,
+        None,
+    ));
+
+    // The whole association only works if both sides agree on the FCS of every frame and ack
+    runner.attach_test_task(async move {
+        let associate_confirm = scan_and_associate(device, ready_receiver).await;
+
+        assert_eq!(associate_confirm.status, Ok(AssociationStatus::Successful));
+        assert_eq!(associate_confirm.assoc_short_address, ShortAddress(1));
+
+        done_sender.send(()).await.unwrap();
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn associate_denied_network_at_capacity() {
+    associate_denied(
+        AssociationStatus::NetworkAtCapacity,
+        Status::NetworkAtCapacity,
+    );
+}
+
+#[test_log::test]
+fn associate_denied_access_denied() {
+    associate_denied(AssociationStatus::AccessDenied, Status::AccessDenied);
+}
+
+fn associate_denied(association_status: AssociationStatus, expected_status: Status) {
+    let (commanders, _, mut runner) = lr_wpan_rs_tests::run::create_test_runner(2);
+
+    let pan_coordinator = commanders[0];
+    let device = commanders[1];
+
+    let (ready_sender, ready_receiver) = async_channel::bounded(1);
+    runner.attach_test_task(run_pan_coordinator(
+        pan_coordinator,
+        ready_sender,
+        association_status,
+        None,
+    ));
+
+    runner.attach_test_task(async move {
+        let associate_confirm = scan_and_associate(device, ready_receiver).await;
+
+        // The denial must be reported as a failure
+        assert_eq!(associate_confirm.status, Err(expected_status));
+        assert_eq!(
+            associate_confirm.assoc_short_address,
+            ShortAddress::BROADCAST
+        );
+
+        // We're not part of the PAN, so the pib must not be updated
+        assert_eq!(
+            device
+                .request(GetRequest {
+                    pib_attribute: PibValue::MAC_SHORT_ADDRESS
+                })
+                .await
+                .value,
+            PibValue::MacShortAddress(ShortAddress::BROADCAST)
+        );
+        assert_eq!(
+            device
+                .request(GetRequest {
+                    pib_attribute: PibValue::MAC_PAN_ID
+                })
+                .await
+                .value,
+            PibValue::MacPanId(PanId::broadcast())
+        );
+    });
+
+    runner.run();
+}
+
+/// Scan for the PAN of the coordinator and send an association request to it
+async fn scan_and_associate(
+    device: &MacCommander,
+    ready_receiver: async_channel::Receiver<()>,
+) -> AssociateConfirm {
+    scan_and_associate_with_capabilities(
+        device,
+        ready_receiver,
+        CapabilityInformation {
+            full_function_device: true,
+            mains_power: true,
+            idle_receive: true,
+            frame_protection: false,
+            allocate_address: true,
+        },
+    )
+    .await
+}
+
+async fn scan_and_associate_with_capabilities(
+    device: &MacCommander,
+    ready_receiver: async_channel::Receiver<()>,
+    capability_information: CapabilityInformation,
+) -> AssociateConfirm {
+    // Reset the device
+    device
+        .request(ResetRequest {
+            set_default_pib: true,
+        })
+        .await
+        .status
+        .unwrap();
+
+    // Set macAutoRequest so we get a list of scanned beacons instead of indications
+    device
+        .request(SetRequest {
+            pib_attribute: PibValue::MAC_AUTO_REQUEST,
+            pib_attribute_value: PibValue::MacAutoRequest(true),
+        })
+        .await
+        .status
+        .unwrap();
+
+    // Wait until coordinator is ready
+    let _ = ready_receiver.recv().await;
+
+    // Scan for the PAN the coordinator is running
+    let mut scan_allocation = [None; 1];
+    let scan_confirm = device
+        .request_with_allocation(
+            ScanRequest {
+                scan_type: lr_wpan_rs::sap::scan::ScanType::Active,
+                scan_channels: Vec::from_slice(&[0]).unwrap(),
+                pan_descriptor_list: Allocation::new(),
+                scan_duration: 14,
+                // The aether doesn't look at the page, but only channels of the UWB phy can be scanned
+                channel_page: ChannelPage::Uwb,
+                scan_channel_pages: Vec::new(),
+                security_info: SecurityInfo::new_none_security(),
+                include_source_address: false,
+            },
+            &mut scan_allocation,
+        )
+        .await;
+
+    let scanned_coordinator = scan_confirm
+        .pan_descriptor_list()
+        .next()
+        .expect("One PAN must have been found");
+
+    // We've found the PAN, now associate with it
+    device
+        .request(AssociateRequest {
+            channel_number: 0,
+            channel_page: ChannelPage::Mhz868_915_2450,
+            coord_address: scanned_coordinator.coord_address,
+            capability_information,
+            security_info: SecurityInfo::new_none_security(),
+        })
+        .await
+}
+
+#[test_log::test]
+fn associate_allocates_short_address_when_asked() {
+    let (associate_confirm, associated_device) = associate_with_capabilities(true, true);
+
+    assert_eq!(associate_confirm.status, Ok(AssociationStatus::Successful));
+    assert_eq!(associate_confirm.assoc_short_address, ShortAddress(1));
+    assert_eq!(associated_device.short_address, ShortAddress(1));
+    assert!(associated_device.rx_on_when_idle);
+}
+
+#[test_log::test]
+fn associate_without_short_address_uses_extended_address() {
+    let (associate_confirm, associated_device) = associate_with_capabilities(false, false);
+
+    // The coordinator hands out short address 1, but the mac doesn't let it
+    assert_eq!(associate_confirm.status, Ok(AssociationStatus::Successful));
+    assert_eq!(associate_confirm.assoc_short_address, ShortAddress(0xfffe));
+    assert_eq!(associated_device.short_address, ShortAddress(0xfffe));
+    assert!(!associated_device.rx_on_when_idle);
+}
+
+/// Associate a device with the given capabilities and return its confirm and how the coordinator sees it
+fn associate_with_capabilities(
+    allocate_address: bool,
+    idle_receive: bool,
+) -> (AssociateConfirm, AssociatedDevice) {
+    let (commanders, _, mut runner) = lr_wpan_rs_tests::run::create_test_runner(2);
+
+    let pan_coordinator = commanders[0];
+    let device = commanders[1];
+    let simulation_time = runner.simulation_time;
+
+    let (ready_sender, ready_receiver) = async_channel::bounded(1);
+    runner.attach_test_task(run_pan_coordinator(
+        pan_coordinator,
+        ready_sender,
+        AssociationStatus::Successful,
+        None,
+    ));
+
+    let (result_sender, result_receiver) = async_channel::bounded(1);
+    runner.attach_test_task(async move {
+        let associate_confirm = scan_and_associate_with_capabilities(
+            device,
+            ready_receiver,
+            CapabilityInformation {
+                full_function_device: false,
+                mains_power: false,
+                idle_receive,
+                frame_protection: false,
+                allocate_address,
+            },
+        )
+        .await;
+
+        // The device takes on the address it was given
+        assert_eq!(
+            device
+                .request(GetRequest {
+                    pib_attribute: PibValue::MAC_SHORT_ADDRESS
+                })
+                .await
+                .value,
+            PibValue::MacShortAddress(associate_confirm.assoc_short_address)
+        );
+
+        // Let the coordinator process the ack of the association response
+        simulation_time.delay(Duration::from_millis(10)).await;
+        let associated_devices = pan_coordinator.associated_devices();
+        assert_eq!(associated_devices.len(), 1);
+
+        result_sender
+            .send((associate_confirm, associated_devices[0]))
+            .await
+            .unwrap();
+    });
+
+    runner.run();
+
+    result_receiver.try_recv().unwrap()
+}
+
+#[test_log::test]
+fn associate_with_short_address_assignment() {
+    /// Give the devices an address based on their extended address
+    fn assign_short_address(
+        extended_address: ExtendedAddress,
+        _capabilities: CapabilityInformation,
+    ) -> Option<ShortAddress> {
+        Some(ShortAddress(0x100 + extended_address.0 as u16))
+    }
+
+    let (commanders, _, mut runner) =
+        lr_wpan_rs_tests::run::create_test_runner_with((0..2).map(|seed| EngineOptions {
+            short_address_assignment: Some(assign_short_address),
+            ..EngineOptions::new(seed)
+        }));
+
+    let pan_coordinator = commanders[0];
+    let device = commanders[1];
+    let simulation_time = runner.simulation_time;
+
+    let (ready_sender, ready_receiver) = async_channel::bounded(1);
+    runner.attach_test_task(run_pan_coordinator(
+        pan_coordinator,
+        ready_sender,
+        AssociationStatus::Successful,
+        None,
+    ));
+
+    runner.attach_test_task(async move {
+        let associate_confirm = scan_and_associate(device, ready_receiver).await;
+
+        // The coordinator responded with the address of the assignment
+        assert_eq!(associate_confirm.status, Ok(AssociationStatus::Successful));
+        assert_eq!(associate_confirm.assoc_short_address, ShortAddress(0x101));
+
+        // Let the coordinator process the ack of the association response
+        simulation_time.delay(Duration::from_millis(10)).await;
+        assert_eq!(
+            pan_coordinator.associated_devices()[0].short_address,
+            ShortAddress(0x101)
+        );
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn short_address_assignment_cant_give_the_broadcast_address() {
+    fn assign_short_address(
+        _extended_address: ExtendedAddress,
+        _capabilities: CapabilityInformation,
+    ) -> Option<ShortAddress> {
+        Some(ShortAddress::BROADCAST)
+    }
+
+    let (commanders, _, mut runner) =
+        lr_wpan_rs_tests::run::create_test_runner_with((0..2).map(|seed| EngineOptions {
+            short_address_assignment: Some(assign_short_address),
+            ..EngineOptions::new(seed)
+        }));
+
+    let pan_coordinator = commanders[0];
+    let device = commanders[1];
+
+    let (ready_sender, ready_receiver) = async_channel::bounded(1);
+    runner.attach_test_task(run_pan_coordinator(
+        pan_coordinator,
+        ready_sender,
+        AssociationStatus::Successful,
+        None,
+    ));
+
+    runner.attach_test_task(async move {
+        let associate_confirm = scan_and_associate(device, ready_receiver).await;
+
+        // Nothing was assigned, so the coordinator used its own address
+        assert_eq!(associate_confirm.status, Ok(AssociationStatus::Successful));
+        assert_eq!(associate_confirm.assoc_short_address, ShortAddress(1));
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn short_address_assignment_cant_give_an_address_twice() {
+    fn assign_short_address(
+        _extended_address: ExtendedAddress,
+        _capabilities: CapabilityInformation,
+    ) -> Option<ShortAddress> {
+        Some(ShortAddress(0x100))
+    }
+
+    let (commanders, _, mut runner) =
+        lr_wpan_rs_tests::run::create_test_runner_with((0..3).map(|seed| EngineOptions {
+            short_address_assignment: Some(assign_short_address),
+            ..EngineOptions::new(seed)
+        }));
+
+    let pan_coordinator = commanders[0];
+    let devices = [commanders[1], commanders[2]];
+
+    let (ready_sender, ready_receiver) = async_channel::bounded(devices.len());
+
+    runner.attach_test_task(async move {
+        start_pan(pan_coordinator).await;
+
+        for _ in devices {
+            ready_sender.send(()).await.unwrap();
+        }
+
+        let mut assigned_short_addresses = std::vec::Vec::new();
+        for _ in devices {
+            let responder = pan_coordinator
+                .wait_for_indication()
+                .await
+                .into_concrete::<AssociateIndication>();
+            assigned_short_addresses.push(responder.indication.assigned_short_address);
+
+            let device_address = responder.indication.device_address;
+            responder.respond(AssociateResponse {
+                device_address,
+                assoc_short_address: ShortAddress(0x200 + device_address.0 as u16),
+                status: AssociationStatus::Successful,
+                security_info: SecurityInfo::new_none_security(),
+            });
+        }
+
+        // The second device can't get the address the first one is already getting
+        assert_eq!(assigned_short_addresses, [Some(ShortAddress(0x100)), None]);
+    });
+
+    for device in devices {
+        let ready_receiver = ready_receiver.clone();
+        runner.attach_test_task(async move {
+            let associate_confirm = scan_and_associate(device, ready_receiver).await;
+            assert_eq!(associate_confirm.status, Ok(AssociationStatus::Successful));
+        });
+    }
+
+    runner.run();
+}
+
+#[test_log::test]
+fn associate_response_too_late() {
+    let (commanders, _, mut runner) = lr_wpan_rs_tests::run::create_test_runner(2);
+
+    let pan_coordinator = commanders[0];
+    let device = commanders[1];
+
+    // Way longer than the default macResponseWaitTime of 32 superframe durations
+    let response_delay = Duration::from_seconds(1);
+
+    let (ready_sender, ready_receiver) = async_channel::bounded(1);
+    runner.attach_test_task(run_pan_coordinator(
+        pan_coordinator,
+        ready_sender,
+        AssociationStatus::Successful,
+        Some((runner.simulation_time, response_delay)),
+    ));
+
+    runner.attach_test_task(async move {
+        let associate_confirm = scan_and_associate(device, ready_receiver).await;
+
+        // The device must have given up waiting on the response
+        assert_eq!(associate_confirm.status, Err(Status::NoData));
+        assert_eq!(
+            associate_confirm.assoc_short_address,
+            ShortAddress::BROADCAST
+        );
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn associate_with_other_data_pending_first() {
+    let (commanders, _, mut runner) = lr_wpan_rs_tests::run::create_test_runner(2);
+
+    let pan_coordinator = commanders[0];
+    let device = commanders[1];
+    let simulation_time = runner.simulation_time;
+
+    let (ready_sender, ready_receiver) = async_channel::bounded(1);
+    runner.attach_test_task(async move {
+        start_pan(pan_coordinator).await;
+
+        // Something else is queued for the device before the association response,
+        // so the device only gets the response with its second data request
+        let disassociate_confirm = pan_coordinator
+            .request(DisassociateRequest {
+                device_address: Address::Extended(PanId(0), ExtendedAddress(1)),
+                disassociate_reason: DisassociationReason::CoordinatorLeave,
+                tx_indirect: true,
+                security_info: SecurityInfo::new_none_security(),
+            })
+            .await;
+        assert_eq!(disassociate_confirm.status, Status::Success);
+
+        ready_sender.send(()).await.unwrap();
+
+        respond_to_association(pan_coordinator, AssociationStatus::Successful, None).await;
+    });
+
+    runner.attach_test_task(async move {
+        let associate_confirm = scan_and_associate(device, ready_receiver).await;
+
+        assert_eq!(associate_confirm.status, Ok(AssociationStatus::Successful));
+        assert_eq!(associate_confirm.assoc_short_address, ShortAddress(1));
+
+        // Let the coordinator process the ack of the association response
+        simulation_time.delay(Duration::from_millis(10)).await;
+
+        // The notification that came first has been processed like any other frame.
+        // The extended address of the coordinator wasn't known before the response, so it's ignored.
+        assert_eq!(device.state().await, MacOperationalState::Associated);
+
+        // Both transactions have been delivered
+        assert_eq!(pan_coordinator.pending_transactions().count(), 0);
+        assert_eq!(pan_coordinator.associated_devices().len(), 1);
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn associate_with_other_data_pending_after_the_response() {
+    let (commanders, _, mut runner) = lr_wpan_rs_tests::run::create_test_runner(2);
+
+    let pan_coordinator = commanders[0];
+    let device = commanders[1];
+    let simulation_time = runner.simulation_time;
+
+    let (ready_sender, ready_receiver) = async_channel::bounded(1);
+    runner.attach_test_task(async move {
+        start_pan(pan_coordinator).await;
+
+        ready_sender.send(()).await.unwrap();
+
+        respond_to_association(pan_coordinator, AssociationStatus::Successful, None).await;
+
+        // Queued behind the association response, so the device picks it up with a follow-up data request
+        let disassociate_confirm = pan_coordinator
+            .request(DisassociateRequest {
+                device_address: Address::Extended(PanId(0), ExtendedAddress(1)),
+                disassociate_reason: DisassociationReason::CoordinatorLeave,
+                tx_indirect: true,
+                security_info: SecurityInfo::new_none_security(),
+            })
+            .await;
+        assert_eq!(disassociate_confirm.status, Status::Success);
+    });
+
+    runner.attach_test_task(async move {
+        let associate_confirm = scan_and_associate(device, ready_receiver).await;
+
+        assert_eq!(associate_confirm.status, Ok(AssociationStatus::Successful));
+        assert_eq!(associate_confirm.assoc_short_address, ShortAddress(1));
+
+        let responder = device
+            .wait_for_indication()
+            .await
+            .into_concrete::<DisassociateIndication>();
+        assert_eq!(responder.indication.device_address, ExtendedAddress(0));
+        assert_eq!(
+            responder.indication.disassociate_reason,
+            DisassociationReason::CoordinatorLeave
+        );
+        responder.respond(());
+
+        // Let the coordinator process the ack of the notification
+        simulation_time.delay(Duration::from_millis(10)).await;
+
+        assert_eq!(pan_coordinator.pending_transactions().count(), 0);
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn associate_more_devices_than_indirect_indication_capacity() {
+    let (commanders, _, mut runner) =
+        lr_wpan_rs_tests::run::create_test_runner_with((0..3).map(|seed| EngineOptions {
+            max_indirect_indications: 1,
+            ..EngineOptions::new(seed)
+        }));
+
+    let pan_coordinator = commanders[0];
+    let devices = [commanders[1], commanders[2]];
+    let simulation_time = runner.simulation_time;
+
+    let (ready_sender, ready_receiver) = async_channel::bounded(devices.len());
+    let (confirm_sender, confirm_receiver) = async_channel::bounded(devices.len());
+
+    runner.attach_test_task(async move {
+        start_pan(pan_coordinator).await;
+
+        for _ in devices {
+            ready_sender.send(()).await.unwrap();
+        }
+
+        // Only one association request fits. Hold on to it until the other one has been received too.
+        let responder = pan_coordinator
+            .wait_for_indication()
+            .await
+            .into_concrete::<AssociateIndication>();
+        let device_address = responder.indication.device_address;
+        simulation_time.delay(Duration::from_millis(100)).await;
+
+        responder.respond(AssociateResponse {
+            device_address,
+            assoc_short_address: ShortAddress(1),
+            status: AssociationStatus::Successful,
+            security_info: SecurityInfo::new_none_security(),
+        });
+
+        let confirms = [
+            confirm_receiver.recv().await.unwrap(),
+            confirm_receiver.recv().await.unwrap(),
+        ];
+
+        // The other request could not be indicated, so that device is told the PAN is at capacity
+        assert!(
+            confirms
+                .iter()
+                .any(|confirm| confirm.status == Ok(AssociationStatus::Successful))
+        );
+        assert!(
+            confirms
+                .iter()
+                .any(|confirm| confirm.status == Err(Status::NetworkAtCapacity))
+        );
+
+        // And it was never indicated
+        futures::select_biased! {
+            indication_responder = pan_coordinator.wait_for_indication().fuse() => {
+                panic!("Got an unexpected indication: {:?}", indication_responder.indication)
+            }
+            _ = simulation_time.delay(Duration::from_millis(10)).fuse() => {}
+        }
+    });
+
+    for device in devices {
+        let ready_receiver = ready_receiver.clone();
+        let confirm_sender = confirm_sender.clone();
+        runner.attach_test_task(async move {
+            let associate_confirm = scan_and_associate(device, ready_receiver).await;
+            confirm_sender.send(associate_confirm).await.unwrap();
+        });
+    }
+
+    runner.run();
+}
+
+#[test_log::test]
+fn associated_devices_are_in_the_device_table() {
+    let (commanders, _, mut runner) = lr_wpan_rs_tests::run::create_test_runner(3);
+
+    let pan_coordinator = commanders[0];
+    let devices = [commanders[1], commanders[2]];
+    let simulation_time = runner.simulation_time;
+
+    let (ready_sender, ready_receiver) = async_channel::bounded(devices.len());
+    let (associated_sender, associated_receiver) = async_channel::bounded(devices.len());
+    let (leave_sender, leave_receiver) = async_channel::bounded(1);
+
+    runner.attach_test_task(async move {
+        start_pan(pan_coordinator).await;
+        assert!(pan_coordinator.associated_devices().is_empty());
+
+        for _ in devices {
+            ready_sender.send(()).await.unwrap();
+        }
+
+        // Give every device a short address based on its extended address
+        for _ in devices {
+            let responder = pan_coordinator
+                .wait_for_indication()
+                .await
+                .into_concrete::<AssociateIndication>();
+            let device_address = responder.indication.device_address;
+
+            responder.respond(AssociateResponse {
+                device_address,
+                assoc_short_address: ShortAddress(0x100 + device_address.0 as u16),
+                status: AssociationStatus::Successful,
+                security_info: SecurityInfo::new_none_security(),
+            });
+        }
+
+        for _ in devices {
+            associated_receiver.recv().await.unwrap();
+        }
+
+        let mut associated_devices = pan_coordinator.associated_devices();
+        associated_devices.sort_unstable_by_key(|device| device.extended_address.0);
+        assert_eq!(
+            associated_devices
+                .iter()
+                .map(|device| (device.extended_address, device.short_address))
+                .collect::<std::vec::Vec<_>>(),
+            [
+                (ExtendedAddress(1), ShortAddress(0x101)),
+                (ExtendedAddress(2), ShortAddress(0x102)),
+            ]
+        );
+
+        // The first device leaves the PAN, which removes it from the table
+        leave_sender.send(()).await.unwrap();
+        let responder = pan_coordinator
+            .wait_for_indication()
+            .await
+            .into_concrete::<DisassociateIndication>();
+        assert_eq!(responder.indication.device_address, ExtendedAddress(1));
+        responder.respond(());
+
+        assert_eq!(
+            pan_coordinator
+                .associated_devices()
+                .iter()
+                .map(|device| device.extended_address)
+                .collect::<std::vec::Vec<_>>(),
+            [ExtendedAddress(2)]
+        );
+    });
+
+    for (index, device) in devices.into_iter().enumerate() {
+        let ready_receiver = ready_receiver.clone();
+        let associated_sender = associated_sender.clone();
+        let leave_receiver = leave_receiver.clone();
+
+        runner.attach_test_task(async move {
+            let associate_confirm = scan_and_associate(device, ready_receiver).await;
+            assert_eq!(associate_confirm.status, Ok(AssociationStatus::Successful));
+
+            // Let the coordinator process the ack of the association response
+            simulation_time.delay(Duration::from_millis(10)).await;
+            associated_sender.send(()).await.unwrap();
+
+            if index == 0 {
+                leave_receiver.recv().await.unwrap();
+
+                let disassociate_confirm = device
+                    .request(DisassociateRequest {
+                        device_address: Address::Short(PanId(0), ShortAddress(0)),
+                        disassociate_reason: DisassociationReason::DeviceLeave,
+                        tx_indirect: false,
+                        security_info: SecurityInfo::new_none_security(),
+                    })
+                    .await;
+                assert_eq!(disassociate_confirm.status, Status::Success);
+            }
+        });
+    }
+
+    runner.run();
+}
+
+#[test_log::test]
+fn disassociation_is_sent_directly_to_devices_that_keep_their_receiver_on() {
+    let (commanders, _, mut runner) = lr_wpan_rs_tests::run::create_test_runner(2);
+
+    let pan_coordinator = commanders[0];
+    let device = commanders[1];
+    let simulation_time = runner.simulation_time;
+
+    let (ready_sender, ready_receiver) = async_channel::bounded(1);
+    let (listening_sender, listening_receiver) = async_channel::bounded(1);
+
+    runner.attach_test_task(async move {
+        run_pan_coordinator(
+            pan_coordinator,
+            ready_sender,
+            AssociationStatus::Successful,
+            None,
+        )
+        .await;
+        listening_receiver.recv().await.unwrap();
+
+        // The higher layer asks for an indirect transmission,
+        // but the device said in its association request that it keeps its receiver on
+        let disassociate_confirm = pan_coordinator
+            .request(DisassociateRequest {
+                device_address: Address::Extended(PanId(0), ExtendedAddress(1)),
+                disassociate_reason: DisassociationReason::CoordinatorLeave,
+                tx_indirect: true,
+                security_info: SecurityInfo::new_none_security(),
+            })
+            .await;
+        assert_eq!(disassociate_confirm.status, Status::Success);
+        assert_eq!(pan_coordinator.pending_transactions().count(), 0);
+    });
+
+    runner.attach_test_task(async move {
+        let associate_confirm = scan_and_associate(device, ready_receiver).await;
+        assert_eq!(associate_confirm.status, Ok(AssociationStatus::Successful));
+
+        device
+            .request(SetRequest {
+                pib_attribute: PibValue::MAC_RX_ON_WHEN_IDLE,
+                pib_attribute_value: PibValue::MacRxOnWhenIdle(true),
+            })
+            .await
+            .status
+            .unwrap();
+
+        // Let the coordinator process the ack of the association response
+        simulation_time.delay(Duration::from_millis(10)).await;
+        listening_sender.send(()).await.unwrap();
+
+        // The notification arrives without polling for it
+        let responder = device
+            .wait_for_indication()
+            .await
+            .into_concrete::<DisassociateIndication>();
+        assert_eq!(
+            responder.indication.disassociate_reason,
+            DisassociationReason::CoordinatorLeave
+        );
+        responder.respond(());
+    });
+
+    runner.run();
+}
+
 async fn run_pan_coordinator(
     pan_coordinator: &MacCommander,
     ready_sender: async_channel::Sender<()>,
+    association_status: AssociationStatus,
+    response_delay: Option<(&'static SimulationTime, Duration)>,
+) {
+    start_pan(pan_coordinator).await;
+
+    // We've done our setup
+    ready_sender.send(()).await.unwrap();
+
+    respond_to_association(pan_coordinator, association_status, response_delay).await;
+
+    info!("Running PAN coordinator is done");
+}
+
+/// Wait for the association indication and respond to it with the given status
+async fn respond_to_association(
+    pan_coordinator: &MacCommander,
+    association_status: AssociationStatus,
+    response_delay: Option<(&'static SimulationTime, Duration)>,
 ) {
+    let indication_responder = pan_coordinator.wait_for_indication().await;
+    match indication_responder.indication {
+        IndicationValue::Associate(_) => {
+            let responder = indication_responder.into_concrete::<AssociateIndication>();
+
+            info!("Got an associate indication: {:?}", responder.indication);
+
+            let request_device_address = responder.indication.device_address;
+
+            if let Some((simulation_time, response_delay)) = response_delay {
+                simulation_time.delay(response_delay).await;
+            }
+
+            responder.respond(AssociateResponse {
+                device_address: request_device_address,
+                assoc_short_address: if association_status == AssociationStatus::Successful {
+                    responder
+                        .indication
+                        .assigned_short_address
+                        .unwrap_or(ShortAddress(1))
+                } else {
+                    ShortAddress::BROADCAST
+                },
+                status: association_status,
+                security_info: SecurityInfo::new_none_security(),
+            });
+        }
+        indication => panic!("Got an unexpected indication: {indication:?}"),
+    }
+}
+
+/// Start a PAN without beacons that is open for association
+async fn start_pan(pan_coordinator: &MacCommander) {
     // Reset the coordinator
     pan_coordinator
         .request(ResetRequest {
@@ -199,29 +1013,4 @@ async fn run_pan_coordinator(
         .await
         .status
         .unwrap();
-
-    // We've done our setup
-    ready_sender.send(()).await.unwrap();
-
-    // Wait for the association indication and respond/accept it
-    let indication_responder = pan_coordinator.wait_for_indication().await;
-    match indication_responder.indication {
-        IndicationValue::Associate(_) => {
-            let responder = indication_responder.into_concrete::<AssociateIndication>();
-
-            info!("Got an associate indication: {:?}", responder.indication);
-
-            let request_device_address = responder.indication.device_address;
-
-            responder.respond(AssociateResponse {
-                device_address: request_device_address,
-                assoc_short_address: ShortAddress(1),
-                status: lr_wpan_rs::wire::command::AssociationStatus::Successful,
-                security_info: SecurityInfo::new_none_security(),
-            });
-        }
-        indication => panic!("Got an unexpected indication: {indication:?}"),
-    }
-
-    info!("Running PAN coordinator is done");
 }
@@ -0,0 +1,73 @@
+use lr_wpan_rs::{
+    ChannelPage,
+    mac::MacCommander,
+    pib::PibValue,
+    sap::{SecurityInfo, start::StartRequest},
+    time::Duration,
+    wire::{
+        PanId, ShortAddress,
+        beacon::{BeaconOrder, SuperframeOrder},
+    },
+};
+
+#[test_log::test]
+fn receiver_turns_off_after_the_battery_life_extension_window() {
+    let (commanders, aether, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    let coordinator = commanders[0];
+    let simulation_time = runner.simulation_time;
+
+    runner.attach_test_task(async move {
+        start_pan(coordinator, true).await;
+
+        // A couple of beacons have been sent by now. The superframe is active all the time,
+        // but the window after the last beacon is only a few backoff periods long.
+        simulation_time.delay(Duration::from_millis(100)).await;
+        assert_eq!(aether.receivers_on(), [false]);
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn receiver_stays_on_during_the_superframe_without_battery_life_extension() {
+    let (commanders, aether, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    let coordinator = commanders[0];
+    let simulation_time = runner.simulation_time;
+
+    runner.attach_test_task(async move {
+        start_pan(coordinator, false).await;
+
+        simulation_time.delay(Duration::from_millis(100)).await;
+        assert_eq!(aether.receivers_on(), [true]);
+    });
+
+    runner.run();
+}
+
+/// Start a PAN of which the superframe takes up the whole beacon interval of about 40 millis
+async fn start_pan(coordinator: &MacCommander, battery_life_extension: bool) {
+    coordinator
+        .initialize(&[PibValue::MacShortAddress(ShortAddress(0))])
+        .await
+        .unwrap();
+
+    coordinator
+        .request(StartRequest {
+            pan_id: PanId(1),
+            channel_number: 5,
+            channel_page: ChannelPage::Uwb,
+            start_time: 0,
+            beacon_order: BeaconOrder::BeaconOrder(8),
+            superframe_order: SuperframeOrder::SuperframeOrder(8),
+            pan_coordinator: true,
+            battery_life_extension,
+            coord_realignment: false,
+            coord_realign_security_info: SecurityInfo::new_none_security(),
+            beacon_security_info: SecurityInfo::new_none_security(),
+        })
+        .await
+        .status
+        .unwrap();
+}
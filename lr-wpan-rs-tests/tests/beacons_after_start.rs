@@ -1,13 +1,17 @@
 use lr_wpan_rs::{
     ChannelPage,
     pib::PibValue,
-    sap::{SecurityInfo, Status, reset::ResetRequest, set::SetRequest, start::StartRequest},
+    sap::{
+        SecurityInfo, Status, get::GetRequest, reset::ResetRequest, set::SetRequest,
+        start::StartRequest,
+    },
     time::Duration,
     wire::{
         FrameType, PanId, ShortAddress,
         beacon::{BeaconOrder, SuperframeOrder},
     },
 };
+use lr_wpan_rs_tests::run::EngineOptions;
 
 #[test_log::test]
 fn test_beacons_simple_pancoordinator() {
@@ -90,3 +94,78 @@ fn test_beacons_simple_pancoordinator() {
 
     runner.run();
 }
+
+#[test_log::test]
+fn beacons_without_ranging_on_phy_without_ranging() {
+    let (commanders, mut aether, mut runner) =
+        lr_wpan_rs_tests::run::create_test_runner_with([EngineOptions {
+            phy_ranging: false,
+            ..EngineOptions::new(0)
+        }]);
+
+    runner.attach_test_task(async {
+        aether.start_trace("beacons_without_ranging");
+
+        let reset_response = commanders[0]
+            .request(ResetRequest {
+                set_default_pib: true,
+            })
+            .await;
+        assert_eq!(reset_response.status, Status::Success);
+
+        // The mac supports ranging, but the phy doesn't
+        let get_response = commanders[0]
+            .request(GetRequest {
+                pib_attribute: PibValue::MAC_RANGING_SUPPORTED,
+            })
+            .await;
+        assert_eq!(get_response.value, PibValue::MacRangingSupported(true));
+        let get_response = commanders[0]
+            .request(GetRequest {
+                pib_attribute: PibValue::PHY_RANGING,
+            })
+            .await;
+        assert_eq!(get_response.value, PibValue::PhyRanging(false));
+
+        let set_response = commanders[0]
+            .request(SetRequest {
+                pib_attribute: PibValue::MAC_SHORT_ADDRESS,
+                pib_attribute_value: PibValue::MacShortAddress(ShortAddress(0)),
+            })
+            .await;
+        assert_eq!(set_response.status, Status::Success);
+
+        let start_response = commanders[0]
+            .request(StartRequest {
+                pan_id: PanId(1234),
+                channel_number: 5,
+                channel_page: ChannelPage::Uwb,
+                start_time: 0,
+                beacon_order: BeaconOrder::BeaconOrder(5),
+                superframe_order: SuperframeOrder::SuperframeOrder(5),
+                pan_coordinator: true,
+                battery_life_extension: false,
+                coord_realignment: false,
+                coord_realign_security_info: SecurityInfo::new_none_security(),
+                beacon_security_info: SecurityInfo::new_none_security(),
+            })
+            .await;
+        assert_eq!(start_response.status, Status::Success);
+
+        runner
+            .simulation_time
+            .delay(Duration::from_seconds(2))
+            .await;
+
+        let trace = aether.stop_trace();
+
+        // The radio refuses frames with ranging, so every beacon that made it was sent without
+        let frames = Vec::from_iter(aether.parse_trace(trace));
+        assert!(frames.len() > 1);
+        for frame in frames {
+            assert_eq!(frame.header.frame_type, FrameType::Beacon);
+        }
+    });
+
+    runner.run();
+}
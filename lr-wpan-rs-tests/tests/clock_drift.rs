@@ -0,0 +1,187 @@
+use byte::TryRead;
+use futures::FutureExt;
+use lr_wpan_rs::{
+    ChannelPage,
+    consts::BASE_SUPERFRAME_DURATION,
+    mac::MacCommander,
+    phy::Phy,
+    pib::PibValue,
+    sap::{
+        SecurityInfo, Status, reset::ResetRequest, set::SetRequest, start::StartRequest,
+        sync::SyncRequest,
+    },
+    time::{Duration, Instant},
+    wire::{
+        FooterMode, Frame, FrameType, PanId, ShortAddress,
+        beacon::{BeaconOrder, SuperframeOrder},
+    },
+};
+use lr_wpan_rs_tests::run::EngineOptions;
+
+const COORDINATOR_DRIFT_PPM: f32 = 50.0;
+const BEACON_ORDER: u8 = 10;
+
+#[test_log::test]
+fn beacon_tracking_must_resync_on_drift() {
+    let (commanders, mut aether, mut runner) =
+        lr_wpan_rs_tests::run::create_test_runner_with([EngineOptions {
+            clock_drift_ppm: COORDINATOR_DRIFT_PPM,
+            ..EngineOptions::new(0)
+        }]);
+
+    let coordinator = commanders[0];
+
+    runner.attach_test_task(async move {
+        let mut radio = aether.radio();
+        radio
+            .update_phy_pib(|pib| {
+                pib.current_channel = 5;
+            })
+            .await
+            .unwrap();
+
+        start_pan(coordinator).await;
+        radio.start_receive().await.unwrap();
+
+        let mut beacon_times = Vec::new();
+        while beacon_times.len() < 5 {
+            let context = radio.wait().await.unwrap();
+            let message = radio.process(context).await.unwrap().unwrap();
+            let (frame, _) = Frame::try_read(&message.data, FooterMode::None).unwrap();
+
+            if frame.header.frame_type == FrameType::Beacon {
+                beacon_times.push(message.timestamp);
+            }
+        }
+
+        // The beacon interval of the coordinator is nominal in its own clock, but it runs fast in ours
+        let beacon_interval =
+            radio.symbol_period() * (BASE_SUPERFRAME_DURATION as i64) * (1 << BEACON_ORDER);
+        let shift_per_interval = Duration::from_ticks(
+            (beacon_interval.ticks() as f64
+                * (1.0 - 1.0 / (1.0 + COORDINATOR_DRIFT_PPM as f64 / 1_000_000.0)))
+                .round() as i64,
+        );
+        assert!(shift_per_interval > radio.symbol_period());
+
+        // The first beacon isn't aligned to a symbol boundary yet, so we don't use it
+        let beacon_times = &beacon_times[1..];
+
+        let first_beacon = beacon_times[0];
+        for (n, window) in beacon_times.windows(2).enumerate() {
+            let [previous, current] = window else {
+                unreachable!()
+            };
+
+            // Syncing on the previous beacon keeps the error within the drift of one interval
+            assert_close(*previous + beacon_interval - shift_per_interval, *current);
+
+            // But predicting from the first beacon only lets the error grow
+            let intervals = n as i64 + 1;
+            assert_close(
+                first_beacon + beacon_interval * intervals - shift_per_interval * intervals,
+                *current,
+            );
+        }
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn tracked_beacon_is_followed_across_drifting_clocks() {
+    // The coordinator runs fast and the device slow
+    let (commanders, _, mut runner) = lr_wpan_rs_tests::run::create_test_runner_with([
+        EngineOptions {
+            clock_drift_ppm: COORDINATOR_DRIFT_PPM,
+            ..EngineOptions::new(0)
+        },
+        EngineOptions {
+            clock_drift_ppm: -COORDINATOR_DRIFT_PPM,
+            ..EngineOptions::new(1)
+        },
+    ]);
+
+    let coordinator = commanders[0];
+    let device = commanders[1];
+    let simulation_time = runner.simulation_time;
+
+    runner.attach_test_task(async move {
+        start_pan(coordinator).await;
+
+        // Act like the device is associated to the coordinator
+        device
+            .initialize(&[
+                PibValue::MacPanId(PanId(1234)),
+                PibValue::MacShortAddress(ShortAddress(5)),
+                PibValue::MacCoordShortAddress(ShortAddress(0)),
+            ])
+            .await
+            .unwrap();
+
+        device
+            .request(SyncRequest {
+                channel_number: 5,
+                channel_page: ChannelPage::Uwb as u8,
+                track_beacon: true,
+            })
+            .await;
+
+        // The clocks drift apart by ~15 us every beacon interval of ~150 ms.
+        // The device has to resync on every beacon to keep up for 50 intervals,
+        // or it loses the sync and tells us with an indication.
+        futures::select_biased! {
+            indication_responder = device.wait_for_indication().fuse() => {
+                panic!("Got an unexpected indication: {:?}", indication_responder.indication);
+            }
+            _ = simulation_time.delay(Duration::from_millis(7500)).fuse() => {}
+        }
+    });
+
+    runner.run();
+}
+
+/// Check the instants are equal, apart from the rounding of the drifting clock
+#[track_caller]
+fn assert_close(expected: Instant, actual: Instant) {
+    let error = actual.duration_since(expected).abs();
+    assert!(
+        error <= Duration::from_ticks(4),
+        "Expected {expected}, got {actual}"
+    );
+}
+
+async fn start_pan(coordinator: &MacCommander) {
+    coordinator
+        .request(ResetRequest {
+            set_default_pib: true,
+        })
+        .await
+        .status
+        .unwrap();
+
+    let set_response = coordinator
+        .request(SetRequest {
+            pib_attribute: PibValue::MAC_SHORT_ADDRESS,
+            pib_attribute_value: PibValue::MacShortAddress(ShortAddress(0)),
+        })
+        .await;
+    assert_eq!(set_response.status, Status::Success);
+
+    let start_response = coordinator
+        .request(StartRequest {
+            pan_id: PanId(1234),
+            channel_number: 5,
+            channel_page: ChannelPage::Uwb,
+            start_time: 0,
+            beacon_order: BeaconOrder::BeaconOrder(BEACON_ORDER),
+            superframe_order: SuperframeOrder::SuperframeOrder(BEACON_ORDER),
+            pan_coordinator: true,
+            battery_life_extension: false,
+            coord_realignment: false,
+            coord_realign_security_info: SecurityInfo::new_none_security(),
+            beacon_security_info: SecurityInfo::new_none_security(),
+        })
+        .await;
+    assert_eq!(start_response.status, Status::Success);
+}
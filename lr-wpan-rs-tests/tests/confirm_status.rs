@@ -0,0 +1,141 @@
+//! The confirms must tell apart why a transmission failed.
+//!
+//! A [Status::NoData] for a missing association response is tested in `association.rs`.
+
+use lr_wpan_rs::{
+    ChannelPage,
+    pib::PibValue,
+    sap::{SecurityInfo, Status, associate::AssociateRequest, disassociate::DisassociateRequest},
+    wire::{
+        Address, PanId, ShortAddress,
+        command::{CapabilityInformation, DisassociationReason},
+    },
+};
+
+#[test_log::test]
+fn associate_without_coordinator_is_no_ack() {
+    assert_eq!(associate(Setup::Quiet), Err(Status::NoAck));
+}
+
+#[test_log::test]
+fn associate_on_busy_channel_is_channel_access_failure() {
+    assert_eq!(
+        associate(Setup::Interference),
+        Err(Status::ChannelAccessFailure)
+    );
+}
+
+#[test_log::test]
+fn associate_with_broken_radio_is_phy_error() {
+    assert_eq!(associate(Setup::BrokenRadio), Err(Status::PhyError));
+}
+
+#[test_log::test]
+fn disassociate_without_coordinator_is_no_ack() {
+    assert_eq!(disassociate(false), Status::NoAck);
+}
+
+#[test_log::test]
+fn disassociate_on_busy_channel_is_channel_access_failure() {
+    assert_eq!(disassociate(true), Status::ChannelAccessFailure);
+}
+
+/// The condition of the aether in which the request is done
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Setup {
+    /// Nobody is listening, so nothing gets acked
+    Quiet,
+    /// There's interference on the channel, so CSMA-CA never finds it idle
+    Interference,
+    /// The radio returns an error on everything
+    BrokenRadio,
+}
+
+/// Associate to a coordinator that doesn't exist and return the status of the confirm
+fn associate(setup: Setup) -> Result<(), Status> {
+    let (commanders, mut aether, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    match setup {
+        Setup::Quiet => {}
+        Setup::Interference => aether.set_interference(0, 200),
+        Setup::BrokenRadio => aether.set_radios_broken(true),
+    }
+
+    let (status_sender, status_receiver) = async_channel::bounded(1);
+
+    runner.attach_test_task(async move {
+        let device = commanders[0];
+
+        // A broken radio can't even be reset
+        if setup != Setup::BrokenRadio {
+            device.initialize(&[]).await.unwrap();
+        }
+
+        let associate_confirm = device
+            .request(AssociateRequest {
+                channel_number: 0,
+                channel_page: ChannelPage::Mhz868_915_2450,
+                coord_address: Address::Short(PanId(0), ShortAddress(0)),
+                capability_information: CapabilityInformation {
+                    full_function_device: true,
+                    mains_power: true,
+                    idle_receive: true,
+                    frame_protection: false,
+                    allocate_address: true,
+                },
+                security_info: SecurityInfo::new_none_security(),
+            })
+            .await;
+
+        status_sender
+            .send(associate_confirm.status.map(|_| ()))
+            .await
+            .unwrap();
+    });
+
+    runner.run();
+
+    status_receiver.try_recv().unwrap()
+}
+
+/// Disassociate from a coordinator that doesn't exist and return the status of the confirm
+fn disassociate(busy_channel: bool) -> Status {
+    let (commanders, mut aether, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    if busy_channel {
+        aether.set_interference(0, 200);
+    }
+
+    let (status_sender, status_receiver) = async_channel::bounded(1);
+
+    runner.attach_test_task(async move {
+        let device = commanders[0];
+
+        // Act like we're associated to the coordinator
+        device
+            .initialize(&[
+                PibValue::MacPanId(PanId(0)),
+                PibValue::MacCoordShortAddress(ShortAddress(0)),
+            ])
+            .await
+            .unwrap();
+
+        let disassociate_confirm = device
+            .request(DisassociateRequest {
+                device_address: Address::Short(PanId(0), ShortAddress(0)),
+                disassociate_reason: DisassociationReason::DeviceLeave,
+                tx_indirect: false,
+                security_info: SecurityInfo::new_none_security(),
+            })
+            .await;
+
+        status_sender
+            .send(disassociate_confirm.status)
+            .await
+            .unwrap();
+    });
+
+    runner.run();
+
+    status_receiver.try_recv().unwrap()
+}
@@ -0,0 +1,477 @@
+use byte::{TryRead, TryWrite};
+use lr_wpan_rs::{
+    DeviceAddress,
+    consts::{MAX_MAC_PAYLOAD_SIZE, MAX_PHY_PACKET_SIZE},
+    phy::{Phy, SendContinuation, SendResult},
+    pib::PibValue,
+    sap::{
+        IndicationValue, SecurityInfo, Status,
+        data::{
+            DataIndication, DataRequest, Ranging, ReceivedRanging, UwbPreambleSymbolRepetitions,
+            UwbPrf,
+        },
+        get::GetRequest,
+        reset::ResetRequest,
+        set::SetRequest,
+    },
+    time::Duration,
+    wire::{
+        Address, AddressMode, ExtendedAddress, FooterMode, Frame, FrameContent, FrameSerDesContext,
+        FrameType, FrameVersion, Header, PanId, ShortAddress,
+    },
+};
+use lr_wpan_rs_tests::run::EngineOptions;
+
+#[test_log::test]
+fn oversized_msdu_is_rejected() {
+    let (commanders, _, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    runner.attach_test_task(async {
+        let commander = commanders[0];
+
+        commander
+            .request(ResetRequest {
+                set_default_pib: true,
+            })
+            .await;
+
+        // The MSDU fits in the request, but not in a frame with extended addresses
+        let confirm = commander
+            .request(DataRequest {
+                src_addr_mode: AddressMode::Extended,
+                dst_pan_id: PanId(0x1234),
+                dst_addr: Some(DeviceAddress::Extended(ExtendedAddress(100))),
+                msdu: [0xAA; MAX_MAC_PAYLOAD_SIZE].into_iter().collect(),
+                msdu_handle: 42,
+                ack_tx: false,
+                gtstx: false,
+                indirect_tx: false,
+                security_info: SecurityInfo::new_none_security(),
+                uwbprf: UwbPrf::Off,
+                ranging: Ranging::NonRanging,
+                uwb_preamble_symbol_repetitions: UwbPreambleSymbolRepetitions::Reps0,
+                data_rate: 0,
+            })
+            .await;
+
+        assert_eq!(confirm.status, Status::FrameTooLong);
+        assert_eq!(confirm.msdu_handle, 42);
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn data_is_sent_with_the_requested_data_rate() {
+    let (commanders, _, mut runner) = lr_wpan_rs_tests::run::create_test_runner(2);
+
+    let simulation_time = runner.simulation_time;
+
+    runner.attach_test_task(async move {
+        for device in commanders.iter() {
+            device
+                .initialize(&[
+                    PibValue::MacPanId(PanId(1)),
+                    PibValue::MacRxOnWhenIdle(true),
+                ])
+                .await
+                .unwrap();
+        }
+
+        // Give the mac engines the time to turn on their receivers
+        simulation_time.delay(Duration::from_millis(1)).await;
+
+        let confirm = commanders[0]
+            .request(DataRequest {
+                src_addr_mode: AddressMode::Extended,
+                dst_pan_id: PanId(1),
+                dst_addr: Some(DeviceAddress::Extended(ExtendedAddress(1))),
+                msdu: [1, 2, 3, 4].into_iter().collect(),
+                msdu_handle: 42,
+                ack_tx: true,
+                gtstx: false,
+                indirect_tx: false,
+                security_info: SecurityInfo::new_none_security(),
+                uwbprf: UwbPrf::Off,
+                ranging: Ranging::NonRanging,
+                uwb_preamble_symbol_repetitions: UwbPreambleSymbolRepetitions::Reps0,
+                data_rate: 3,
+            })
+            .await;
+        assert_eq!(confirm.status, Status::Success);
+        assert_eq!(confirm.msdu_handle, 42);
+
+        let responder = commanders[1]
+            .wait_for_indication()
+            .await
+            .into_concrete::<DataIndication>();
+        assert_eq!(&responder.indication.msdu[..], &[1, 2, 3, 4]);
+        assert_eq!(responder.indication.data_rate, 3);
+        assert_eq!(
+            responder.indication.src_addr,
+            Some(DeviceAddress::Extended(ExtendedAddress(0)))
+        );
+        responder.respond(());
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn broadcast_is_received_by_all_without_ack() {
+    let (commanders, mut aether, mut runner) = lr_wpan_rs_tests::run::create_test_runner(2);
+
+    let simulation_time = runner.simulation_time;
+
+    runner.attach_test_task(async move {
+        let mut radio = aether.radio();
+
+        for device in commanders.iter() {
+            device
+                .request(ResetRequest {
+                    set_default_pib: true,
+                })
+                .await
+                .status
+                .unwrap();
+
+            device
+                .request(SetRequest {
+                    pib_attribute: PibValue::MAC_RX_ON_WHEN_IDLE,
+                    pib_attribute_value: PibValue::MacRxOnWhenIdle(true),
+                })
+                .await
+                .status
+                .unwrap();
+        }
+
+        // Give the mac engines the time to turn on their receivers
+        simulation_time.delay(Duration::from_millis(1)).await;
+
+        // Broadcasts must not be acked, even if the frame asks for it
+        let mut buffer = [0; MAX_PHY_PACKET_SIZE];
+        let length = write_broadcast_data_frame(&mut buffer, 7, &[1, 2, 3, 4]);
+
+        let SendResult::Success(_, response) = radio
+            .send(
+                &buffer[..length],
+                None,
+                false,
+                false,
+                SendContinuation::WaitForResponse {
+                    turnaround_time: Duration::from_ticks(0),
+                    timeout: Duration::from_millis(100),
+                },
+            )
+            .await
+            .unwrap()
+        else {
+            panic!("Could not send");
+        };
+
+        assert!(response.is_none());
+
+        for device in commanders.iter() {
+            let indication_responder = device.wait_for_indication().await;
+            let IndicationValue::Data(_) = indication_responder.indication else {
+                panic!(
+                    "Got an unexpected indication: {:?}",
+                    indication_responder.indication
+                );
+            };
+            let responder = indication_responder.into_concrete::<DataIndication>();
+
+            assert_eq!(&responder.indication.msdu[..], &[1, 2, 3, 4]);
+            assert_eq!(responder.indication.dsn, 7);
+            assert_eq!(
+                responder.indication.src_addr,
+                Some(DeviceAddress::Extended(ExtendedAddress(100)))
+            );
+            assert_eq!(
+                responder.indication.dst_addr,
+                Some(DeviceAddress::Short(ShortAddress::BROADCAST))
+            );
+            responder.respond(());
+        }
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn unicast_on_the_broadcast_pan_is_acked() {
+    let (commanders, mut aether, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    let device = commanders[0];
+    let simulation_time = runner.simulation_time;
+
+    runner.attach_test_task(async move {
+        let mut radio = aether.radio();
+
+        device
+            .initialize(&[PibValue::MacRxOnWhenIdle(true)])
+            .await
+            .unwrap();
+
+        // Give the mac engine the time to turn on its receiver
+        simulation_time.delay(Duration::from_millis(1)).await;
+
+        // Only the broadcast address makes a broadcast, not the broadcast PAN
+        let mut buffer = [0; MAX_PHY_PACKET_SIZE];
+        let length = write_data_frame(
+            &mut buffer,
+            7,
+            Address::Extended(PanId::broadcast(), ExtendedAddress(0)),
+            &[1, 2, 3, 4],
+        );
+
+        let SendResult::Success(_, response) = radio
+            .send(
+                &buffer[..length],
+                None,
+                false,
+                false,
+                SendContinuation::WaitForResponse {
+                    turnaround_time: Duration::from_ticks(0),
+                    timeout: Duration::from_millis(100),
+                },
+            )
+            .await
+            .unwrap()
+        else {
+            panic!("Could not send");
+        };
+
+        let response = response.expect("The frame must be acked");
+        let (ack, _) = Frame::try_read(&response.data, FooterMode::None).unwrap();
+        assert_eq!(ack.header.frame_type, FrameType::Acknowledgement);
+        assert_eq!(ack.header.seq, 7);
+
+        let responder = device
+            .wait_for_indication()
+            .await
+            .into_concrete::<DataIndication>();
+        assert_eq!(&responder.indication.msdu[..], &[1, 2, 3, 4]);
+        responder.respond(());
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn reported_timestamp_includes_sync_symbol_offset() {
+    const SYNC_SYMBOL_OFFSET: u16 = 100;
+
+    let (commanders, mut aether, mut runner) =
+        lr_wpan_rs_tests::run::create_test_runner_with([EngineOptions {
+            sync_symbol_offset: SYNC_SYMBOL_OFFSET,
+            ..EngineOptions::new(0)
+        }]);
+
+    let device = commanders[0];
+    let simulation_time = runner.simulation_time;
+
+    runner.attach_test_task(async move {
+        let mut radio = aether.radio();
+
+        device
+            .request(ResetRequest {
+                set_default_pib: true,
+            })
+            .await
+            .status
+            .unwrap();
+
+        let PibValue::MacSyncSymbolOffset(sync_symbol_offset) = device
+            .request(GetRequest {
+                pib_attribute: PibValue::MAC_SYNC_SYMBOL_OFFSET,
+            })
+            .await
+            .value
+        else {
+            panic!("Wrong pib value type");
+        };
+        assert_eq!(sync_symbol_offset, SYNC_SYMBOL_OFFSET);
+
+        device
+            .request(SetRequest {
+                pib_attribute: PibValue::MAC_RX_ON_WHEN_IDLE,
+                pib_attribute_value: PibValue::MacRxOnWhenIdle(true),
+            })
+            .await
+            .status
+            .unwrap();
+
+        // Give the mac engine the time to turn on its receiver
+        simulation_time.delay(Duration::from_millis(1)).await;
+
+        let mut buffer = [0; MAX_PHY_PACKET_SIZE];
+        let length = write_broadcast_data_frame(&mut buffer, 7, &[1, 2, 3, 4]);
+
+        let SendResult::Success(send_time, _) = radio
+            .send(
+                &buffer[..length],
+                None,
+                false,
+                false,
+                SendContinuation::Idle,
+            )
+            .await
+            .unwrap()
+        else {
+            panic!("Could not send");
+        };
+
+        let responder = device
+            .wait_for_indication()
+            .await
+            .into_concrete::<DataIndication>();
+
+        // The device is at the same position as our radio, so the phy received it at the send time
+        assert_eq!(
+            responder.indication.timestamp,
+            send_time + radio.symbol_period() * SYNC_SYMBOL_OFFSET as i64
+        );
+        responder.respond(());
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn ranging_bit_of_the_phy_header_is_indicated() {
+    let (commanders, mut aether, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    let device = commanders[0];
+    let simulation_time = runner.simulation_time;
+
+    runner.attach_test_task(async move {
+        let mut radio = aether.radio();
+
+        device
+            .initialize(&[PibValue::MacRxOnWhenIdle(true)])
+            .await
+            .unwrap();
+
+        // Give the mac engine the time to turn on its receiver
+        simulation_time.delay(Duration::from_millis(1)).await;
+
+        let mut buffer = [0; MAX_PHY_PACKET_SIZE];
+        for ranging in [true, false] {
+            let length = write_broadcast_data_frame(&mut buffer, 7, &[1, 2, 3, 4]);
+            radio
+                .send(
+                    &buffer[..length],
+                    None,
+                    ranging,
+                    false,
+                    SendContinuation::Idle,
+                )
+                .await
+                .unwrap();
+
+            let responder = device
+                .wait_for_indication()
+                .await
+                .into_concrete::<DataIndication>();
+
+            let expected_ranging = if ranging {
+                ReceivedRanging::RangingActive
+            } else {
+                ReceivedRanging::NoRangingRequested
+            };
+            assert_eq!(responder.indication.ranging_received, expected_ranging);
+            responder.respond(());
+        }
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn security_of_received_data_is_indicated() {
+    let (commanders, mut aether, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    let device = commanders[0];
+    let simulation_time = runner.simulation_time;
+
+    runner.attach_test_task(async move {
+        let mut radio = aether.radio();
+
+        device
+            .initialize(&[PibValue::MacRxOnWhenIdle(true)])
+            .await
+            .unwrap();
+
+        // Give the mac engine the time to turn on its receiver
+        simulation_time.delay(Duration::from_millis(1)).await;
+
+        let mut buffer = [0; MAX_PHY_PACKET_SIZE];
+        let length = write_broadcast_data_frame(&mut buffer, 7, &[1, 2, 3, 4]);
+        radio
+            .send(
+                &buffer[..length],
+                None,
+                false,
+                false,
+                SendContinuation::Idle,
+            )
+            .await
+            .unwrap();
+
+        // The mac has no keys to unsecure frames with yet, so only an unsecured frame makes it through.
+        // Its security info must say so, or the higher layer would accept it as secured.
+        let responder = device
+            .wait_for_indication()
+            .await
+            .into_concrete::<DataIndication>();
+        assert_eq!(&responder.indication.msdu[..], &[1, 2, 3, 4]);
+        assert_eq!(
+            responder.indication.security_info,
+            SecurityInfo::new_none_security()
+        );
+        responder.respond(());
+    });
+
+    runner.run();
+}
+
+/// Write a broadcast data frame that asks for an ack and return its length
+fn write_broadcast_data_frame(buffer: &mut [u8], seq: u8, payload: &[u8]) -> usize {
+    write_data_frame(
+        buffer,
+        seq,
+        Address::Short(PanId::broadcast(), ShortAddress::BROADCAST),
+        payload,
+    )
+}
+
+/// Write a data frame that asks for an ack and return its length
+fn write_data_frame(buffer: &mut [u8], seq: u8, destination: Address, payload: &[u8]) -> usize {
+    let frame = Frame {
+        header: Header {
+            frame_type: FrameType::Data,
+            frame_pending: false,
+            ack_request: true,
+            pan_id_compress: false,
+            seq_no_suppress: false,
+            ie_present: false,
+            version: FrameVersion::Ieee802154_2003,
+            seq,
+            destination: Some(destination),
+            source: Some(Address::Extended(PanId(0x1234), ExtendedAddress(100))),
+            auxiliary_security_header: None,
+            time_correction: None,
+        },
+        content: FrameContent::Data,
+        payload,
+        footer: [0, 0],
+    };
+
+    frame
+        .try_write(
+            buffer,
+            &mut FrameSerDesContext::no_security(FooterMode::None),
+        )
+        .unwrap()
+}
@@ -0,0 +1,357 @@
+use byte::{TryRead, TryWrite};
+use lr_wpan_rs::{
+    ChannelPage, DeviceAddress,
+    consts::MAX_PHY_PACKET_SIZE,
+    mac::{MacCommander, PendingTransactionKind},
+    phy::{Phy, SendContinuation},
+    pib::PibValue,
+    sap::{
+        SecurityInfo, Status,
+        disassociate::{DisassociateIndication, DisassociateRequest},
+        get::GetRequest,
+        reset::ResetRequest,
+        set::SetRequest,
+        start::StartRequest,
+    },
+    time::Duration,
+    wire::{
+        Address, ExtendedAddress, FooterMode, Frame, FrameContent, FrameSerDesContext, FrameType,
+        FrameVersion, Header, PanId, ShortAddress,
+        beacon::{BeaconOrder, SuperframeOrder},
+        command::{Command, DisassociationReason},
+        fcs::FCS_LENGTH,
+    },
+};
+
+const DEVICE_ADDRESS: ExtendedAddress = ExtendedAddress(100);
+
+#[test_log::test]
+fn indirect_disassociation() {
+    let (commanders, mut aether, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    let pan_coordinator = commanders[0];
+
+    runner.attach_test_task(async move {
+        // The device is played by a raw radio
+        let mut device = aether.radio();
+        device
+            .update_phy_pib(|pib| {
+                pib.current_channel = 0;
+                pib.current_page = ChannelPage::Mhz868_915_2450;
+            })
+            .await
+            .unwrap();
+
+        start_pan_coordinator(pan_coordinator).await;
+
+        // Queue the disassociation for the device
+        let disassociate_confirm = pan_coordinator
+            .request(DisassociateRequest {
+                device_address: Address::Extended(PanId(0), DEVICE_ADDRESS),
+                disassociate_reason: DisassociationReason::CoordinatorLeave,
+                tx_indirect: true,
+                security_info: SecurityInfo::new_none_security(),
+            })
+            .await;
+        assert_eq!(disassociate_confirm.status, Status::Success);
+
+        // Ask the coordinator for our data
+        let data_request = Frame {
+            header: Header {
+                frame_type: FrameType::MacCommand,
+                frame_pending: false,
+                ack_request: true,
+                pan_id_compress: true,
+                seq_no_suppress: false,
+                ie_present: false,
+                version: FrameVersion::Ieee802154_2003,
+                seq: 1,
+                destination: Some(Address::Short(PanId(0), ShortAddress(0))),
+                source: Some(Address::Extended(PanId(0), DEVICE_ADDRESS)),
+                auxiliary_security_header: None,
+                time_correction: None,
+            },
+            content: FrameContent::Command(Command::DataRequest),
+            payload: &[],
+            footer: [0, 0],
+        };
+        send_frame(
+            &mut device,
+            data_request,
+            SendContinuation::ReceiveContinuous,
+        )
+        .await;
+
+        // The ack must tell us there's data pending
+        let context = device.wait().await.unwrap();
+        let ack = device.process(context).await.unwrap().unwrap();
+        let (ack, _) = Frame::try_read(&ack.data, FooterMode::None).unwrap();
+        assert_eq!(ack.header.frame_type, FrameType::Acknowledgement);
+        assert_eq!(ack.header.seq, 1);
+        assert!(ack.header.frame_pending);
+
+        // Then the disassociation notification is sent to us
+        let context = device.wait().await.unwrap();
+        let notification = device.process(context).await.unwrap().unwrap();
+        let (notification_frame, _) =
+            Frame::try_read(&notification.data, FooterMode::None).unwrap();
+        assert_eq!(
+            notification_frame.content,
+            FrameContent::Command(Command::DisassociationNotification(
+                DisassociationReason::CoordinatorLeave
+            ))
+        );
+        assert_eq!(
+            notification_frame.header.destination,
+            Some(Address::Extended(PanId(0), DEVICE_ADDRESS))
+        );
+
+        // Ack the notification, well within the ack wait duration of the coordinator that starts
+        // once the notification has been sent
+        let ack = Frame {
+            header: Header {
+                frame_type: FrameType::Acknowledgement,
+                frame_pending: false,
+                ack_request: false,
+                pan_id_compress: false,
+                seq_no_suppress: false,
+                ie_present: false,
+                version: FrameVersion::Ieee802154_2003,
+                seq: notification_frame.header.seq,
+                destination: None,
+                source: None,
+                auxiliary_security_header: None,
+                time_correction: None,
+            },
+            content: FrameContent::Acknowledgement,
+            payload: &[],
+            footer: [0, 0],
+        };
+        let notification_duration = device
+            .get_phy_pib()
+            .frame_duration(notification.data.len() + FCS_LENGTH);
+        let ack_time =
+            notification.timestamp + device.symbol_period() * (notification_duration as i64 + 20);
+        let mut buffer = [0; MAX_PHY_PACKET_SIZE];
+        let length = ack
+            .try_write(
+                &mut buffer,
+                &mut FrameSerDesContext::no_security(FooterMode::None),
+            )
+            .unwrap();
+        device
+            .send(
+                &buffer[..length],
+                Some(ack_time),
+                false,
+                false,
+                SendContinuation::Idle,
+            )
+            .await
+            .unwrap();
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn indirect_disassociations_are_pending_transactions() {
+    let (commanders, _, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    let pan_coordinator = commanders[0];
+
+    runner.attach_test_task(async move {
+        start_pan_coordinator(pan_coordinator).await;
+        assert_eq!(pan_coordinator.pending_transactions().count(), 0);
+
+        let device_addresses = [
+            Address::Extended(PanId(0), DEVICE_ADDRESS),
+            Address::Short(PanId(0), ShortAddress(5)),
+        ];
+        for device_address in device_addresses {
+            let disassociate_confirm = pan_coordinator
+                .request(DisassociateRequest {
+                    device_address,
+                    disassociate_reason: DisassociationReason::CoordinatorLeave,
+                    tx_indirect: true,
+                    security_info: SecurityInfo::new_none_security(),
+                })
+                .await;
+            assert_eq!(disassociate_confirm.status, Status::Success);
+        }
+
+        // Nobody picks them up, so both are listed in the order they were queued
+        let pending_transactions = pan_coordinator.pending_transactions().collect::<Vec<_>>();
+        assert_eq!(pending_transactions.len(), 2);
+        assert_eq!(
+            pending_transactions[0].device,
+            DeviceAddress::Extended(DEVICE_ADDRESS)
+        );
+        assert_eq!(
+            pending_transactions[1].device,
+            DeviceAddress::Short(ShortAddress(5))
+        );
+        assert!(
+            pending_transactions
+                .iter()
+                .all(|transaction| transaction.kind
+                    == PendingTransactionKind::DisassociationNotification)
+        );
+        assert!(
+            pending_transactions[0].registration_time <= pending_transactions[1].registration_time
+        );
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn notification_must_come_from_the_coordinator() {
+    const COORDINATOR_ADDRESS: ExtendedAddress = ExtendedAddress(50);
+
+    let (commanders, mut aether, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    let device = commanders[0];
+    let simulation_time = runner.simulation_time;
+
+    runner.attach_test_task(async move {
+        // Both the coordinator and the impostor are played by a raw radio
+        let mut radio = aether.radio();
+        radio
+            .update_phy_pib(|pib| pib.current_channel = 0)
+            .await
+            .unwrap();
+
+        // Set up the device as if it's associated with the coordinator
+        device
+            .initialize(&[
+                PibValue::PhyCurrentChannel(0),
+                PibValue::MacPanId(PanId(0)),
+                PibValue::MacShortAddress(ShortAddress(1)),
+                PibValue::MacCoordShortAddress(ShortAddress(0)),
+                PibValue::MacCoordExtendedAddress(COORDINATOR_ADDRESS),
+                PibValue::MacRxOnWhenIdle(true),
+            ])
+            .await
+            .unwrap();
+
+        // Give the mac engine the time to turn on its receiver
+        simulation_time.delay(Duration::from_millis(1)).await;
+
+        send_frame(
+            &mut radio,
+            disassociation_notification(DEVICE_ADDRESS),
+            SendContinuation::Idle,
+        )
+        .await;
+        simulation_time.delay(Duration::from_millis(10)).await;
+        assert_eq!(pan_id(device).await, PanId(0));
+
+        send_frame(
+            &mut radio,
+            disassociation_notification(COORDINATOR_ADDRESS),
+            SendContinuation::Idle,
+        )
+        .await;
+        let responder = device
+            .wait_for_indication()
+            .await
+            .into_concrete::<DisassociateIndication>();
+        assert_eq!(responder.indication.device_address, COORDINATOR_ADDRESS);
+        responder.respond(());
+        assert_eq!(pan_id(device).await, PanId::broadcast());
+    });
+
+    runner.run();
+}
+
+/// A disassociation notification from the source to the device with address 0
+fn disassociation_notification(source: ExtendedAddress) -> Frame<'static> {
+    Frame {
+        header: Header {
+            frame_type: FrameType::MacCommand,
+            frame_pending: false,
+            ack_request: false,
+            pan_id_compress: true,
+            seq_no_suppress: false,
+            ie_present: false,
+            version: FrameVersion::Ieee802154_2003,
+            seq: 1,
+            destination: Some(Address::Extended(PanId(0), ExtendedAddress(0))),
+            source: Some(Address::Extended(PanId(0), source)),
+            auxiliary_security_header: None,
+            time_correction: None,
+        },
+        content: FrameContent::Command(Command::DisassociationNotification(
+            DisassociationReason::CoordinatorLeave,
+        )),
+        payload: &[],
+        footer: [0, 0],
+    }
+}
+
+async fn pan_id(commander: &MacCommander) -> PanId {
+    let PibValue::MacPanId(pan_id) = commander
+        .request(GetRequest {
+            pib_attribute: PibValue::MAC_PAN_ID,
+        })
+        .await
+        .value
+    else {
+        panic!("Wrong pib value type");
+    };
+
+    pan_id
+}
+
+async fn send_frame(device: &mut impl Phy, frame: Frame<'_>, continuation: SendContinuation) {
+    let mut buffer = [0; MAX_PHY_PACKET_SIZE];
+    let length = frame
+        .try_write(
+            &mut buffer,
+            &mut FrameSerDesContext::no_security(FooterMode::None),
+        )
+        .unwrap();
+
+    device
+        .send(&buffer[..length], None, false, false, continuation)
+        .await
+        .unwrap();
+}
+
+async fn start_pan_coordinator(pan_coordinator: &MacCommander) {
+    pan_coordinator
+        .request(ResetRequest {
+            set_default_pib: true,
+        })
+        .await
+        .status
+        .unwrap();
+
+    pan_coordinator
+        .request(SetRequest {
+            pib_attribute: PibValue::MAC_SHORT_ADDRESS,
+            pib_attribute_value: PibValue::MacShortAddress(ShortAddress(0)),
+        })
+        .await
+        .status
+        .unwrap();
+
+    pan_coordinator
+        .request(StartRequest {
+            pan_id: PanId(0),
+            channel_number: 0,
+            channel_page: ChannelPage::Mhz868_915_2450,
+            start_time: 0,
+            beacon_order: BeaconOrder::OnDemand,
+            superframe_order: SuperframeOrder::Inactive,
+            pan_coordinator: true,
+            battery_life_extension: false,
+            coord_realignment: false,
+            coord_realign_security_info: SecurityInfo::new_none_security(),
+            beacon_security_info: SecurityInfo::new_none_security(),
+        })
+        .await
+        .status
+        .unwrap();
+}
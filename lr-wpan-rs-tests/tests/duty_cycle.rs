@@ -0,0 +1,147 @@
+use futures::FutureExt;
+use heapless::Vec;
+use lr_wpan_rs::{
+    ChannelPage,
+    allocation::Allocation,
+    mac::{DutyCycleLimit, MacCommander},
+    phy::Phy,
+    pib::{PhyPib, PibValue},
+    sap::{
+        SecurityInfo, Status,
+        disassociate::DisassociateRequest,
+        scan::{ScanRequest, ScanType},
+    },
+    time::Duration,
+    wire::{Address, PanId, ShortAddress, command::DisassociationReason},
+};
+use lr_wpan_rs_tests::run::EngineOptions;
+
+#[test_log::test]
+fn transmissions_are_blocked_until_the_window_slides() {
+    // A disassociation notification is 17 octets, plus the FCS the phy adds.
+    // The symbol period of the aether radios is 10000 ticks.
+    let frame_time =
+        Duration::from_ticks(10000) * PhyPib::unspecified_new().frame_duration(19) as i64;
+
+    // 1% of the window fits one and a half notification
+    let limit = DutyCycleLimit {
+        window: frame_time * 150,
+        max_permille: 10,
+    };
+
+    let (commanders, mut aether, mut runner) =
+        lr_wpan_rs_tests::run::create_test_runner_with([EngineOptions {
+            duty_cycle_limit: Some(limit),
+            ..EngineOptions::new(0)
+        }]);
+
+    let device = commanders[0];
+    let simulation_time = runner.simulation_time;
+    let (transmissions_sender, transmissions_receiver) = async_channel::bounded(1);
+    let (done_sender, done_receiver) = async_channel::bounded(1);
+
+    // The coordinator is played by a raw radio that counts the notifications, but never acks them
+    let mut coordinator = aether.radio();
+    runner.attach_test_task(async move {
+        coordinator.start_receive().await.unwrap();
+
+        let mut transmissions = 0;
+        loop {
+            futures::select_biased! {
+                context = coordinator.wait().fuse() => {
+                    if coordinator.process(context.unwrap()).await.unwrap().is_some() {
+                        transmissions += 1;
+                    }
+                }
+                _ = done_receiver.recv().fuse() => break,
+            }
+        }
+
+        transmissions_sender.send(transmissions).await.unwrap();
+    });
+
+    runner.attach_test_task(async move {
+        // The first notification fits in the limit
+        assert_eq!(disassociate(device).await, Status::NoAck);
+        // The second one doesn't. A reset doesn't make the mac forget what it has sent.
+        assert_eq!(disassociate(device).await, Status::LimitReached);
+
+        // Once the first notification has left the window, there's room again
+        simulation_time.delay(limit.window).await;
+        assert_eq!(disassociate(device).await, Status::NoAck);
+
+        done_sender.send(()).await.unwrap();
+        assert_eq!(transmissions_receiver.recv().await.unwrap(), 2);
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn beacon_requests_are_blocked_by_the_limit() {
+    // A beacon request is 8 octets, plus the FCS the phy adds
+    let frame_time =
+        Duration::from_ticks(10000) * PhyPib::unspecified_new().frame_duration(10) as i64;
+
+    // Not even one beacon request fits in the limit
+    let limit = DutyCycleLimit {
+        window: frame_time * 100,
+        max_permille: 1,
+    };
+
+    let (commanders, _, mut runner) =
+        lr_wpan_rs_tests::run::create_test_runner_with([EngineOptions {
+            duty_cycle_limit: Some(limit),
+            ..EngineOptions::new(0)
+        }]);
+
+    let device = commanders[0];
+
+    runner.attach_test_task(async move {
+        let mut scan_allocation = [None; 1];
+        let scan_confirm = device
+            .request_with_allocation(
+                ScanRequest {
+                    scan_type: ScanType::Active,
+                    scan_channels: Vec::from_slice(&[0]).unwrap(),
+                    pan_descriptor_list: Allocation::new(),
+                    scan_duration: 1,
+                    channel_page: ChannelPage::Uwb,
+                    scan_channel_pages: Vec::new(),
+                    security_info: SecurityInfo::new_none_security(),
+                    include_source_address: false,
+                },
+                &mut scan_allocation,
+            )
+            .await;
+
+        // The channel is skipped like it is on a channel access failure
+        assert_eq!(scan_confirm.status, Status::Success);
+        assert_eq!(&scan_confirm.unscanned_channels[..], &[0]);
+        assert_eq!(scan_confirm.result_list_size, 0);
+    });
+
+    runner.run();
+}
+
+/// Act like we're associated to the coordinator and send it a single disassociation notification
+async fn disassociate(device: &MacCommander) -> Status {
+    device
+        .initialize(&[
+            PibValue::MacPanId(PanId(0)),
+            PibValue::MacCoordShortAddress(ShortAddress(0)),
+            PibValue::MacMaxFrameRetries(0),
+        ])
+        .await
+        .unwrap();
+
+    device
+        .request(DisassociateRequest {
+            device_address: Address::Short(PanId(0), ShortAddress(0)),
+            disassociate_reason: DisassociationReason::DeviceLeave,
+            tx_indirect: false,
+            security_info: SecurityInfo::new_none_security(),
+        })
+        .await
+        .status
+}
@@ -0,0 +1,98 @@
+#![cfg(feature = "test-hooks")]
+
+use lr_wpan_rs::{
+    ChannelPage,
+    mac::{
+        MacCommander, PlanningHeadroom,
+        test_hooks::{BranchOrder, EngineBranch},
+    },
+    phy::Phy,
+    pib::PibValue,
+    sap::{SecurityInfo, Status, get::GetRequest, start::StartRequest},
+    time::Instant,
+    wire::{
+        PanId, ShortAddress,
+        beacon::{BeaconOrder, SuperframeOrder},
+    },
+};
+use lr_wpan_rs_tests::aether::AetherRadio;
+
+#[test_log::test]
+fn beacon_is_not_delayed_by_a_request_at_the_same_time() {
+    let (commanders, mut aether, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    let coordinator = commanders[0];
+    let simulation_time = runner.simulation_time;
+
+    runner.attach_test_task(async move {
+        let mut sniffer = aether.radio();
+        sniffer.start_receive().await.unwrap();
+
+        coordinator
+            .initialize(&[PibValue::MacShortAddress(ShortAddress(0))])
+            .await
+            .unwrap();
+
+        let start_response = coordinator
+            .request(StartRequest {
+                pan_id: PanId(1234),
+                channel_number: 5,
+                channel_page: ChannelPage::Uwb,
+                start_time: 0,
+                beacon_order: BeaconOrder::BeaconOrder(10),
+                superframe_order: SuperframeOrder::SuperframeOrder(8),
+                pan_coordinator: true,
+                battery_life_extension: false,
+                coord_realignment: false,
+                coord_realign_security_info: SecurityInfo::new_none_security(),
+                beacon_security_info: SecurityInfo::new_none_security(),
+            })
+            .await;
+        assert_eq!(start_response.status, Status::Success);
+
+        // The first beacon is sent right away, so the interval is measured between the ones after it
+        next_beacon_time(&mut sniffer).await;
+        let second_beacon = next_beacon_time(&mut sniffer).await;
+        let mut last_beacon = next_beacon_time(&mut sniffer).await;
+        let beacon_interval = last_beacon.duration_since(second_beacon);
+
+        // Whichever of the two the engine handles first, the beacon goes out on time
+        for branch_order in [
+            BranchOrder::DEFAULT,
+            BranchOrder::new(
+                EngineBranch::Request,
+                EngineBranch::RadioEvent,
+                EngineBranch::IndirectIndication,
+            ),
+        ] {
+            coordinator.set_branch_order(branch_order);
+
+            // The request comes in right when the mac starts planning the beacon
+            simulation_time
+                .delay_until(last_beacon + beacon_interval - PlanningHeadroom::default().beacon)
+                .await;
+            get_anything(coordinator).await;
+
+            let beacon = next_beacon_time(&mut sniffer).await;
+            assert_eq!(beacon, last_beacon + beacon_interval);
+            last_beacon = beacon;
+        }
+    });
+
+    runner.run();
+}
+
+async fn next_beacon_time(sniffer: &mut AetherRadio) -> Instant {
+    let context = sniffer.wait().await.unwrap();
+    sniffer.process(context).await.unwrap().unwrap().timestamp
+}
+
+async fn get_anything(device: &MacCommander) {
+    device
+        .request(GetRequest {
+            pib_attribute: PibValue::MAC_SHORT_ADDRESS,
+        })
+        .await
+        .status
+        .unwrap();
+}
@@ -0,0 +1,54 @@
+use std::error::Error;
+
+use lr_wpan_rs::{
+    mac::MacError,
+    pib::PibValue,
+    sap::{Status, get::GetRequest, reset::ResetRequest},
+};
+use lr_wpan_rs_tests::aether::AetherError;
+
+#[test_log::test]
+fn phy_error_detail_is_kept() {
+    let (commanders, mut aether, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    aether.set_radios_broken(true);
+
+    runner.attach_test_task(async {
+        let commander = commanders[0];
+
+        let (confirm, detail) = commander
+            .request_with_error_detail(ResetRequest {
+                set_default_pib: true,
+            })
+            .await;
+        assert_eq!(confirm.status, Status::PhyError);
+
+        let detail = detail.unwrap();
+        assert_eq!(detail.status, Status::PhyError);
+        assert_eq!(detail.description, "PhyError(RadioBroken)");
+
+        // Confirms without an error have no detail
+        let (confirm, detail) = commander
+            .request_with_error_detail(GetRequest {
+                pib_attribute: PibValue::MAC_PAN_ID,
+            })
+            .await;
+        assert_eq!(confirm.status, Status::Success);
+        assert!(detail.is_none());
+    });
+
+    runner.run();
+}
+
+#[test]
+fn mac_error_can_be_boxed() {
+    let error: Box<dyn Error> = Box::new(MacError::PhyError(AetherError::RadioBroken));
+    assert_eq!(error.to_string(), "PhyError(RadioBroken)");
+
+    // The phy error is the source
+    let source = error.source().unwrap();
+    assert_eq!(source.to_string(), "RadioBroken");
+
+    let error: Box<dyn Error> = Box::new(MacError::<AetherError>::UnknownChannelPage(42));
+    assert!(error.source().is_none());
+}
@@ -0,0 +1,114 @@
+use lr_wpan_rs::{
+    phy::FrameFilter,
+    pib::PibValue,
+    sap::{get::GetRequest, set::SetRequest},
+    time::Duration,
+    wire::{ExtendedAddress, PanId, ShortAddress},
+};
+
+#[test_log::test]
+fn promiscuous_mode_disables_the_frame_filter() {
+    let (commanders, aether, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    let device = commanders[0];
+    let simulation_time = runner.simulation_time;
+
+    runner.attach_test_task(async move {
+        device
+            .initialize(&[
+                PibValue::MacPanId(PanId(5)),
+                PibValue::MacShortAddress(ShortAddress(7)),
+            ])
+            .await
+            .unwrap();
+
+        let filter = FrameFilter {
+            pan_id: PanId(5),
+            short_address: ShortAddress(7),
+            extended_address: ExtendedAddress(0),
+            pan_coordinator: false,
+        };
+
+        // Give the mac engine the time to update its radio
+        simulation_time.delay(Duration::from_millis(1)).await;
+        assert_eq!(aether.frame_filters(), [Some(filter)]);
+
+        for promiscuous_mode in [true, false] {
+            device
+                .request(SetRequest {
+                    pib_attribute: PibValue::MAC_PROMISCUOUS_MODE,
+                    pib_attribute_value: PibValue::MacPromiscuousMode(promiscuous_mode),
+                })
+                .await
+                .status
+                .unwrap();
+
+            simulation_time.delay(Duration::from_millis(1)).await;
+            let expected_filter = if promiscuous_mode { None } else { Some(filter) };
+            assert_eq!(aether.frame_filters(), [expected_filter]);
+        }
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn failed_frame_filter_update_is_tried_again() {
+    let (commanders, mut aether, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    let device = commanders[0];
+    let simulation_time = runner.simulation_time;
+
+    runner.attach_test_task(async move {
+        device
+            .initialize(&[
+                PibValue::MacPanId(PanId(5)),
+                PibValue::MacShortAddress(ShortAddress(7)),
+            ])
+            .await
+            .unwrap();
+
+        let filter = FrameFilter {
+            pan_id: PanId(5),
+            short_address: ShortAddress(7),
+            extended_address: ExtendedAddress(0),
+            pan_coordinator: false,
+        };
+
+        simulation_time.delay(Duration::from_millis(1)).await;
+        assert_eq!(aether.frame_filters(), [Some(filter)]);
+
+        // The new address can't be given to the broken radio
+        aether.set_radios_broken(true);
+        device
+            .request(SetRequest {
+                pib_attribute: PibValue::MAC_SHORT_ADDRESS,
+                pib_attribute_value: PibValue::MacShortAddress(ShortAddress(8)),
+            })
+            .await
+            .status
+            .unwrap();
+
+        simulation_time.delay(Duration::from_millis(1)).await;
+        assert_eq!(aether.frame_filters(), [Some(filter)]);
+
+        // Once the radio works again, any request makes the mac engine try again
+        aether.set_radios_broken(false);
+        device
+            .request(GetRequest {
+                pib_attribute: PibValue::MAC_SHORT_ADDRESS,
+            })
+            .await;
+
+        simulation_time.delay(Duration::from_millis(1)).await;
+        assert_eq!(
+            aether.frame_filters(),
+            [Some(FrameFilter {
+                short_address: ShortAddress(8),
+                ..filter
+            })]
+        );
+    });
+
+    runner.run();
+}
@@ -0,0 +1,62 @@
+#![cfg(feature = "frame-tap")]
+
+use byte::TryRead;
+use lr_wpan_rs::{
+    mac::{
+        MacCommander,
+        frame_tap::{FrameDirection, TappedFrame},
+    },
+    pib::PibValue,
+    wire::{
+        FooterMode, Frame, FrameType, PanId,
+        beacon::{BeaconOrder, SuperframeOrder},
+    },
+};
+use lr_wpan_rs_tests::pan::spawn_coordinator;
+
+#[test_log::test]
+fn sent_and_received_beacons_are_tapped() {
+    let (commanders, _, mut runner) = lr_wpan_rs_tests::run::create_test_runner(2);
+
+    let coordinator = commanders[0];
+    let device = commanders[1];
+
+    let pan_started = spawn_coordinator(
+        &mut runner,
+        coordinator,
+        PanId(1),
+        5,
+        BeaconOrder::BeaconOrder(8),
+        SuperframeOrder::SuperframeOrder(4),
+    );
+
+    runner.attach_test_task(async move {
+        device
+            .initialize(&[PibValue::MacRxOnWhenIdle(true)])
+            .await
+            .unwrap();
+
+        let _ = pan_started.recv().await;
+
+        let sent = next_beacon(coordinator).await;
+        assert_eq!(sent.direction, FrameDirection::Sent);
+
+        let received = next_beacon(device).await;
+        assert_eq!(received.direction, FrameDirection::Received);
+        assert!(received.timestamp >= sent.timestamp);
+    });
+
+    runner.run();
+}
+
+/// Wait for the next beacon on the tap of the commander
+async fn next_beacon(commander: &MacCommander) -> TappedFrame {
+    loop {
+        let tapped_frame = commander.next_tapped_frame().await;
+        let (frame, _) = Frame::try_read(&tapped_frame.data, FooterMode::None).unwrap();
+
+        if frame.header.frame_type == FrameType::Beacon {
+            return tapped_frame;
+        }
+    }
+}
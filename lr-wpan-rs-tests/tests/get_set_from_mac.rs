@@ -1,7 +1,8 @@
+use heapless::Vec;
 use lr_wpan_rs::{
     mac::MacCommander,
     pib::PibValue,
-    sap::{Status, get::GetRequest, set::SetRequest},
+    sap::{Status, get::GetRequest, set::SetRequest, set_many::SetManyRequest},
 };
 
 #[test_log::test]
@@ -16,6 +17,156 @@ fn get_set() {
     runner.run();
 }
 
+#[test_log::test]
+fn read_only_phy_attribute_is_rejected() {
+    let (commanders, _, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    runner.attach_test_task(async {
+        let commander = commanders[0];
+
+        let PibValue::PhyMaxFrameDuration(max_frame_duration) = commander
+            .request(GetRequest {
+                pib_attribute: PibValue::PHY_MAX_FRAME_DURATION,
+            })
+            .await
+            .value
+        else {
+            panic!("Wrong pib value type");
+        };
+
+        let response = commander
+            .request(SetRequest {
+                pib_attribute: PibValue::PHY_MAX_FRAME_DURATION,
+                pib_attribute_value: PibValue::PhyMaxFrameDuration(max_frame_duration + 1),
+            })
+            .await;
+
+        assert_eq!(response.pib_attribute, PibValue::PHY_MAX_FRAME_DURATION);
+        assert_eq!(response.status, Status::ReadOnly);
+
+        // The value must not have changed
+        assert_eq!(
+            commander
+                .request(GetRequest {
+                    pib_attribute: PibValue::PHY_MAX_FRAME_DURATION,
+                })
+                .await
+                .value,
+            PibValue::PhyMaxFrameDuration(max_frame_duration)
+        );
+
+        // A writable phy attribute is set
+        let response = commander
+            .request(SetRequest {
+                pib_attribute: PibValue::PHY_CURRENT_CHANNEL,
+                pib_attribute_value: PibValue::PhyCurrentChannel(3),
+            })
+            .await;
+        assert_eq!(response.status, Status::Success);
+        assert_eq!(
+            commander
+                .request(GetRequest {
+                    pib_attribute: PibValue::PHY_CURRENT_CHANNEL,
+                })
+                .await
+                .value,
+            PibValue::PhyCurrentChannel(3)
+        );
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn set_many_checks_the_batch_as_a_whole() {
+    let (commanders, _, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    runner.attach_test_task(async {
+        let commander = commanders[0];
+        commander.initialize(&[]).await.unwrap();
+
+        // One at a time, macMinBE can't be raised above the current macMaxBE of 5
+        let response = commander
+            .request(SetRequest {
+                pib_attribute: PibValue::MAC_MIN_BE,
+                pib_attribute_value: PibValue::MacMinBe(7),
+            })
+            .await;
+        assert_eq!(response.status, Status::InvalidParameter);
+
+        // Together with the new macMaxBE it can, in any order
+        commander
+            .set_many(&[PibValue::MacMinBe(7), PibValue::MacMaxBe(8)])
+            .await
+            .unwrap();
+        assert_eq!(
+            get(commander, PibValue::MAC_MIN_BE).await,
+            PibValue::MacMinBe(7)
+        );
+        assert_eq!(
+            get(commander, PibValue::MAC_MAX_BE).await,
+            PibValue::MacMaxBe(8)
+        );
+
+        // The constraints are checked on the end result of the batch
+        let response = commander
+            .request(SetManyRequest {
+                pib_attribute_values: Vec::from_slice(&[
+                    PibValue::MacMaxBe(6),
+                    PibValue::PhyCurrentChannel(3),
+                ])
+                .unwrap(),
+            })
+            .await;
+        assert_eq!(response.status, Status::InvalidParameter);
+        assert_eq!(response.pib_attribute, Some(PibValue::MAC_MIN_BE));
+        assert_eq!(
+            get(commander, PibValue::MAC_MAX_BE).await,
+            PibValue::MacMaxBe(8)
+        );
+
+        // Lowering macMaxBE on its own is checked the same way
+        let response = commander
+            .request(SetRequest {
+                pib_attribute: PibValue::MAC_MAX_BE,
+                pib_attribute_value: PibValue::MacMaxBe(4),
+            })
+            .await;
+        assert_eq!(response.status, Status::InvalidParameter);
+
+        // When one value is rejected, none are written
+        let response = commander
+            .request(SetManyRequest {
+                pib_attribute_values: Vec::from_slice(&[
+                    PibValue::MacMaxBe(6),
+                    PibValue::PhyCurrentChannel(3),
+                    PibValue::MacMaxCsmaBackoffs(9), // Above allowed range
+                ])
+                .unwrap(),
+            })
+            .await;
+        assert_eq!(response.status, Status::InvalidParameter);
+        assert_eq!(
+            response.pib_attribute,
+            Some(PibValue::MAC_MAX_CSMA_BACKOFFS)
+        );
+        assert_eq!(
+            get(commander, PibValue::MAC_MAX_BE).await,
+            PibValue::MacMaxBe(8)
+        );
+        assert_ne!(
+            get(commander, PibValue::PHY_CURRENT_CHANNEL).await,
+            PibValue::PhyCurrentChannel(3)
+        );
+    });
+
+    runner.run();
+}
+
+async fn get(commander: &MacCommander, pib_attribute: &'static str) -> PibValue {
+    commander.request(GetRequest { pib_attribute }).await.value
+}
+
 async fn test_get(commander: &MacCommander) {
     let response = commander
         .request(GetRequest {
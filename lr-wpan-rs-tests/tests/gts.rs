@@ -0,0 +1,153 @@
+use byte::TryWrite;
+use lr_wpan_rs::{
+    ChannelPage,
+    consts::MAX_PHY_PACKET_SIZE,
+    phy::{Phy, SendContinuation},
+    pib::PibValue,
+    sap::{
+        SecurityInfo, Status, gts::GtsIndication, reset::ResetRequest, set::SetRequest,
+        start::StartRequest,
+    },
+    time::Duration,
+    wire::{
+        Address, FooterMode, Frame, FrameContent, FrameSerDesContext, FrameType, FrameVersion,
+        Header, PanId, ShortAddress,
+        beacon::{BeaconOrder, Direction, GuaranteedTimeSlotDescriptor, SuperframeOrder},
+        command::{Command, GuaranteedTimeSlotCharacteristics},
+    },
+};
+
+#[test_log::test]
+fn gts_request_is_allocated_in_the_beacon() {
+    const DEVICE_ADDRESS: ShortAddress = ShortAddress(1);
+    const CHARACTERISTICS: GuaranteedTimeSlotCharacteristics = GuaranteedTimeSlotCharacteristics {
+        count: 2,
+        receive_only: false,
+        allocation: true,
+    };
+
+    let (commanders, mut aether, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    let pan_coordinator = commanders[0];
+    let simulation_time = runner.simulation_time;
+
+    runner.attach_test_task(async move {
+        // The device is played by a raw radio
+        let mut device = aether.radio();
+        device
+            .update_phy_pib(|pib| pib.current_channel = 0)
+            .await
+            .unwrap();
+
+        start_pan_coordinator(pan_coordinator).await;
+
+        let gts_request = Frame {
+            header: Header {
+                frame_type: FrameType::MacCommand,
+                frame_pending: false,
+                ack_request: true,
+                pan_id_compress: true,
+                seq_no_suppress: false,
+                ie_present: false,
+                version: FrameVersion::Ieee802154_2003,
+                seq: 1,
+                destination: Some(Address::Short(PanId(0), ShortAddress(0))),
+                source: Some(Address::Short(PanId(0), DEVICE_ADDRESS)),
+                auxiliary_security_header: None,
+                time_correction: None,
+            },
+            content: FrameContent::Command(Command::GuaranteedTimeSlotRequest(CHARACTERISTICS)),
+            payload: &[],
+            footer: [0, 0],
+        };
+        let mut buffer = [0; MAX_PHY_PACKET_SIZE];
+        let length = gts_request
+            .try_write(
+                &mut buffer,
+                &mut FrameSerDesContext::no_security(FooterMode::None),
+            )
+            .unwrap();
+        device
+            .send(
+                &buffer[..length],
+                None,
+                false,
+                false,
+                SendContinuation::Idle,
+            )
+            .await
+            .unwrap();
+
+        let responder = pan_coordinator
+            .wait_for_indication()
+            .await
+            .into_concrete::<GtsIndication>();
+        assert_eq!(responder.indication.device_address, DEVICE_ADDRESS);
+        assert_eq!(responder.indication.gts_characteristics, CHARACTERISTICS);
+        responder.respond(());
+
+        // The new GTS is announced at the end of the superframe in the next beacons
+        aether.start_trace("gts_request");
+        simulation_time.delay(Duration::from_seconds(2)).await;
+        let trace = aether.stop_trace();
+
+        let mut beacons = 0;
+        for frame in aether.parse_trace(trace) {
+            let FrameContent::Beacon(beacon) = frame.content else {
+                continue;
+            };
+
+            assert_eq!(
+                beacon.guaranteed_time_slot_info.slots(),
+                &[GuaranteedTimeSlotDescriptor {
+                    short_address: DEVICE_ADDRESS,
+                    starting_slot: 14,
+                    length: 2,
+                    direction: Direction::Transmit,
+                }]
+            );
+            assert_eq!(beacon.superframe_spec.final_cap_slot, 14);
+            beacons += 1;
+        }
+        assert!(beacons > 0);
+    });
+
+    runner.run();
+}
+
+async fn start_pan_coordinator(pan_coordinator: &lr_wpan_rs::mac::MacCommander) {
+    pan_coordinator
+        .request(ResetRequest {
+            set_default_pib: true,
+        })
+        .await
+        .status
+        .unwrap();
+
+    pan_coordinator
+        .request(SetRequest {
+            pib_attribute: PibValue::MAC_SHORT_ADDRESS,
+            pib_attribute_value: PibValue::MacShortAddress(ShortAddress(0)),
+        })
+        .await
+        .status
+        .unwrap();
+
+    // A beacon every ~1 second, with the superframe taking up the whole beacon interval
+    let start_response = pan_coordinator
+        .request(StartRequest {
+            pan_id: PanId(0),
+            channel_number: 0,
+            channel_page: ChannelPage::Mhz868_915_2450,
+            start_time: 0,
+            beacon_order: BeaconOrder::BeaconOrder(6),
+            superframe_order: SuperframeOrder::SuperframeOrder(6),
+            pan_coordinator: true,
+            battery_life_extension: false,
+            coord_realignment: false,
+            coord_realign_security_info: SecurityInfo::new_none_security(),
+            beacon_security_info: SecurityInfo::new_none_security(),
+        })
+        .await;
+    assert_eq!(start_response.status, Status::Success);
+}
@@ -0,0 +1,172 @@
+use byte::TryWrite;
+use futures::FutureExt;
+use lr_wpan_rs::{
+    consts::MAX_PHY_PACKET_SIZE,
+    mac::{IndicationOverflowPolicy, MacCommander},
+    phy::{Phy, SendContinuation},
+    pib::PibValue,
+    sap::{
+        IndicationValue, Status, data::DataIndication, get::GetRequest, reset::ResetRequest,
+        set::SetRequest,
+    },
+    time::Duration,
+    wire::{
+        Address, ExtendedAddress, FooterMode, Frame, FrameContent, FrameSerDesContext, FrameType,
+        FrameVersion, Header, PanId, ShortAddress,
+    },
+};
+use lr_wpan_rs_tests::{run::EngineOptions, time::SimulationTime};
+
+/// The number of indications that fit in the channel of the commander
+const INDICATION_CHANNEL_SIZE: u8 = 4;
+/// More than fit in the indication channel
+const FLOOD_SIZE: u8 = 6;
+
+#[test_log::test]
+fn drop_newest_keeps_the_first_indications() {
+    let received = flood(IndicationOverflowPolicy::DropNewest);
+    assert_eq!(received, [0, 1, 2, 3]);
+}
+
+#[test_log::test]
+fn drop_oldest_keeps_the_last_indications() {
+    let received = flood(IndicationOverflowPolicy::DropOldest);
+    assert_eq!(received, [2, 3, 4, 5]);
+}
+
+/// Send more data frames than the device can indicate without the test taking them out of the channel.
+/// Returns the sequence numbers of the indications that made it.
+fn flood(policy: IndicationOverflowPolicy) -> Vec<u8> {
+    let (commanders, mut aether, mut runner) =
+        lr_wpan_rs_tests::run::create_test_runner_with([EngineOptions {
+            indication_overflow_policy: policy,
+            ..EngineOptions::new(0)
+        }]);
+
+    let device = commanders[0];
+    let simulation_time = runner.simulation_time;
+    let (result_sender, result_receiver) = async_channel::bounded(1);
+
+    runner.attach_test_task(async move {
+        let mut radio = aether.radio();
+
+        prepare_device(device).await;
+
+        // Give the mac engine the time to turn on its receiver
+        simulation_time.delay(Duration::from_millis(1)).await;
+
+        for seq in 0..FLOOD_SIZE {
+            let mut buffer = [0; MAX_PHY_PACKET_SIZE];
+            let length = write_broadcast_data_frame(&mut buffer, seq);
+            radio
+                .send(
+                    &buffer[..length],
+                    None,
+                    false,
+                    false,
+                    SendContinuation::Idle,
+                )
+                .await
+                .unwrap();
+
+            simulation_time.delay(Duration::from_millis(1)).await;
+        }
+
+        // The mac is not stalled by the full channel
+        let get_confirm = device
+            .request(GetRequest {
+                pib_attribute: PibValue::MAC_RX_ON_WHEN_IDLE,
+            })
+            .await;
+        assert_eq!(get_confirm.status, Status::Success);
+
+        assert_eq!(
+            device.dropped_indications(),
+            (FLOOD_SIZE - INDICATION_CHANNEL_SIZE) as u32
+        );
+
+        let mut received = Vec::new();
+        while let Some(seq) = next_indication(device, simulation_time).await {
+            received.push(seq);
+        }
+
+        result_sender.send(received).await.unwrap();
+    });
+
+    runner.run();
+
+    result_receiver.try_recv().unwrap()
+}
+
+/// Take the next data indication out of the channel, if there is one
+async fn next_indication(
+    device: &MacCommander,
+    simulation_time: &'static SimulationTime,
+) -> Option<u8> {
+    futures::select_biased! {
+        indication_responder = device.wait_for_indication().fuse() => {
+            let IndicationValue::Data(indication) = &indication_responder.indication else {
+                panic!(
+                    "Got an unexpected indication: {:?}",
+                    indication_responder.indication
+                );
+            };
+            let seq = indication.dsn;
+            indication_responder
+                .into_concrete::<DataIndication>()
+                .respond(());
+
+            Some(seq)
+        }
+        _ = simulation_time.delay(Duration::from_millis(10)).fuse() => None,
+    }
+}
+
+async fn prepare_device(device: &MacCommander) {
+    device
+        .request(ResetRequest {
+            set_default_pib: true,
+        })
+        .await
+        .status
+        .unwrap();
+
+    device
+        .request(SetRequest {
+            pib_attribute: PibValue::MAC_RX_ON_WHEN_IDLE,
+            pib_attribute_value: PibValue::MacRxOnWhenIdle(true),
+        })
+        .await
+        .status
+        .unwrap();
+}
+
+/// Write a broadcast data frame and return its length
+fn write_broadcast_data_frame(buffer: &mut [u8], seq: u8) -> usize {
+    let frame = Frame {
+        header: Header {
+            frame_type: FrameType::Data,
+            frame_pending: false,
+            ack_request: false,
+            pan_id_compress: false,
+            seq_no_suppress: false,
+            ie_present: false,
+            version: FrameVersion::Ieee802154_2003,
+            seq,
+            destination: Some(Address::Short(PanId::broadcast(), ShortAddress::BROADCAST)),
+            source: Some(Address::Extended(PanId(0x1234), ExtendedAddress(100))),
+            auxiliary_security_header: None,
+            time_correction: None,
+        },
+        content: FrameContent::Data,
+        payload: &[seq],
+        footer: [0, 0],
+    };
+
+    frame
+        .try_write(
+            buffer,
+            &mut FrameSerDesContext::no_security(FooterMode::None),
+        )
+        .unwrap()
+}
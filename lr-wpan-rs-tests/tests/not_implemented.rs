@@ -0,0 +1,125 @@
+use lr_wpan_rs::{
+    ChannelPage, DeviceAddress,
+    allocation::Allocation,
+    pib::PibValue,
+    sap::{
+        SecurityInfo, Status,
+        calibrate::CalibrateRequest,
+        data::{DataRequest, Ranging, UwbPreambleSymbolRepetitions, UwbPrf},
+        dps::DpsRequest,
+        get::GetRequest,
+        gts::GtsRequest,
+        poll::PollRequest,
+        purge::PurgeRequest,
+        rx_enable::RxEnableRequest,
+        scan::{ScanRequest, ScanType},
+    },
+    time::{Duration, Instant},
+    wire::{Address, AddressMode, PanId, ShortAddress, command::GuaranteedTimeSlotCharacteristics},
+};
+
+#[test_log::test]
+fn unimplemented_requests_are_confirmed() {
+    let (commanders, _, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    runner.attach_test_task(async {
+        let commander = commanders[0];
+        commander.initialize(&[]).await.unwrap();
+
+        let confirm = commander
+            .request(GtsRequest {
+                gts_characteristics: GuaranteedTimeSlotCharacteristics {
+                    count: 1,
+                    receive_only: false,
+                    allocation: true,
+                },
+                security_info: SecurityInfo::new_none_security(),
+            })
+            .await;
+        assert_eq!(confirm.status, Status::NotImplemented);
+
+        let confirm = commander
+            .request(RxEnableRequest {
+                defer_permit: false,
+                rx_on_time: Instant::from_ticks(0),
+                rx_on_duration: Duration::from_millis(1),
+                ranging_rx_control: false,
+            })
+            .await;
+        assert_eq!(confirm.status, Status::NotImplemented);
+
+        let confirm = commander
+            .request(PollRequest {
+                coord_address: Address::Short(PanId(1), ShortAddress(0)),
+                security_info: SecurityInfo::new_none_security(),
+            })
+            .await;
+        assert_eq!(confirm.status, Status::NotImplemented);
+
+        let confirm = commander
+            .request(DpsRequest {
+                tx_dps_index: 0,
+                rx_dps_index: 0,
+                dps_index_duration: Duration::from_millis(1),
+            })
+            .await;
+        assert_eq!(confirm.status, Status::NotImplemented);
+
+        let confirm = commander.request(CalibrateRequest {}).await;
+        assert_eq!(confirm.status, Status::NotImplemented);
+
+        let confirm = commander.request(PurgeRequest { msdu_handle: 7 }).await;
+        assert_eq!(confirm.status, Status::NotImplemented);
+        assert_eq!(confirm.msdu_handle, 7);
+
+        let confirm = commander
+            .request(DataRequest {
+                src_addr_mode: AddressMode::Short,
+                dst_pan_id: PanId(1),
+                dst_addr: Some(DeviceAddress::Short(ShortAddress(0))),
+                msdu: [1, 2, 3].into_iter().collect(),
+                msdu_handle: 42,
+                ack_tx: false,
+                gtstx: false,
+                indirect_tx: true,
+                security_info: SecurityInfo::new_none_security(),
+                uwbprf: UwbPrf::Off,
+                ranging: Ranging::NonRanging,
+                uwb_preamble_symbol_repetitions: UwbPreambleSymbolRepetitions::Reps0,
+                data_rate: 2,
+            })
+            .await;
+        assert_eq!(confirm.status, Status::NotImplemented);
+        assert_eq!(confirm.msdu_handle, 42);
+
+        for scan_type in [ScanType::Ed, ScanType::Orphan] {
+            let mut scan_allocation = [None; 1];
+            let confirm = commander
+                .request_with_allocation(
+                    ScanRequest {
+                        scan_type,
+                        scan_channels: [0].as_slice().try_into().unwrap(),
+                        pan_descriptor_list: Allocation::new(),
+                        scan_duration: 2,
+                        channel_page: ChannelPage::Uwb,
+                        scan_channel_pages: heapless::Vec::new(),
+                        security_info: SecurityInfo::new_none_security(),
+                        include_source_address: false,
+                    },
+                    &mut scan_allocation,
+                )
+                .await;
+            assert_eq!(confirm.status, Status::NotImplemented);
+        }
+
+        // The mac is still running
+        let confirm = commander
+            .request(GetRequest {
+                pib_attribute: PibValue::MAC_PAN_ID,
+            })
+            .await;
+        assert_eq!(confirm.status, Status::Success);
+    });
+
+    runner.run();
+}
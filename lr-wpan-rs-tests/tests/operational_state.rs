@@ -0,0 +1,132 @@
+use heapless::Vec;
+use lr_wpan_rs::{
+    ChannelPage,
+    allocation::Allocation,
+    pib::PibValue,
+    sap::{
+        SecurityInfo,
+        associate::AssociateRequest,
+        operational_state::MacOperationalState,
+        scan::{ScanRequest, ScanType},
+        sync::SyncRequest,
+    },
+    time::Duration,
+    wire::{
+        PanId, ShortAddress,
+        beacon::{BeaconOrder, SuperframeOrder},
+        command::{AssociationStatus, CapabilityInformation},
+    },
+};
+use lr_wpan_rs_tests::pan::spawn_coordinator;
+
+#[test_log::test]
+fn state_follows_start_scan_and_associate() {
+    let (commanders, _, mut runner) = lr_wpan_rs_tests::run::create_test_runner(2);
+
+    let coordinator = commanders[0];
+    let device = commanders[1];
+    let simulation_time = runner.simulation_time;
+
+    let pan_started = spawn_coordinator(
+        &mut runner,
+        coordinator,
+        PanId(1),
+        5,
+        BeaconOrder::BeaconOrder(10),
+        SuperframeOrder::SuperframeOrder(5),
+    );
+
+    runner.attach_test_task(async move {
+        device
+            .initialize(&[PibValue::MacAutoRequest(true)])
+            .await
+            .unwrap();
+        assert_eq!(device.state().await, MacOperationalState::Idle);
+
+        let _ = pan_started.recv().await;
+        assert_eq!(
+            coordinator.state().await,
+            MacOperationalState::PanCoordinator
+        );
+
+        // The state is asked while the scan is running
+        let mut scan_allocation = [None; 4];
+        let (scan_confirm, state_while_scanning) = futures::join!(
+            device.request_with_allocation(
+                ScanRequest {
+                    scan_type: ScanType::Active,
+                    scan_channels: Vec::from_slice(&[5]).unwrap(),
+                    pan_descriptor_list: Allocation::new(),
+                    scan_duration: 14,
+                    channel_page: ChannelPage::Uwb,
+                    scan_channel_pages: Vec::new(),
+                    security_info: SecurityInfo::new_none_security(),
+                    include_source_address: false,
+                },
+                &mut scan_allocation,
+            ),
+            async {
+                simulation_time.delay(Duration::from_millis(10)).await;
+                device.state().await
+            }
+        );
+        assert_eq!(state_while_scanning, MacOperationalState::Scanning);
+        assert_eq!(device.state().await, MacOperationalState::Idle);
+
+        let coord_address = scan_confirm
+            .pan_descriptor_list()
+            .next()
+            .expect("The PAN must have been found")
+            .coord_address;
+
+        let associate_confirm = device
+            .request(AssociateRequest {
+                channel_number: 5,
+                channel_page: ChannelPage::Uwb,
+                coord_address,
+                capability_information: CapabilityInformation {
+                    full_function_device: false,
+                    mains_power: false,
+                    idle_receive: false,
+                    frame_protection: false,
+                    allocate_address: true,
+                },
+                security_info: SecurityInfo::new_none_security(),
+            })
+            .await;
+        assert_eq!(associate_confirm.status, Ok(AssociationStatus::Successful));
+        assert_eq!(device.state().await, MacOperationalState::Associated);
+
+        device
+            .request(SyncRequest {
+                channel_number: 5,
+                channel_page: ChannelPage::Uwb as u8,
+                track_beacon: true,
+            })
+            .await;
+
+        // A couple of beacon intervals
+        simulation_time.delay(Duration::from_millis(500)).await;
+        assert_eq!(device.state().await, MacOperationalState::Tracking);
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn short_address_alone_is_not_associated() {
+    let (commanders, _, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    let device = commanders[0];
+
+    runner.attach_test_task(async move {
+        // A short address can be set by the higher layer without any association taking place
+        device
+            .initialize(&[PibValue::MacShortAddress(ShortAddress(5))])
+            .await
+            .unwrap();
+        assert_eq!(device.state().await, MacOperationalState::Idle);
+    });
+
+    runner.run();
+}
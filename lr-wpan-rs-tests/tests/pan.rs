@@ -0,0 +1,41 @@
+use lr_wpan_rs::{
+    time::Duration,
+    wire::{
+        PanId, ShortAddress,
+        beacon::{BeaconOrder, SuperframeOrder},
+        command::AssociationStatus,
+    },
+};
+use lr_wpan_rs_tests::pan::{spawn_coordinator, spawn_end_device};
+
+#[test_log::test]
+fn device_associates_to_beacon_enabled_pan() {
+    let (commanders, _, mut runner) = lr_wpan_rs_tests::run::create_test_runner(2);
+
+    let pan_started = spawn_coordinator(
+        &mut runner,
+        commanders[0],
+        PanId(1),
+        5,
+        BeaconOrder::BeaconOrder(10),
+        SuperframeOrder::SuperframeOrder(10),
+    );
+    let associate_confirm = spawn_end_device(&mut runner, commanders[1], PanId(1), 5, pan_started);
+
+    let coordinator = commanders[0];
+    let simulation_time = runner.simulation_time;
+    runner.attach_test_task(async move {
+        let associate_confirm = associate_confirm.recv().await.unwrap();
+        assert_eq!(associate_confirm.status, Ok(AssociationStatus::Successful));
+        assert_eq!(associate_confirm.assoc_short_address, ShortAddress(1));
+
+        // Give the coordinator the time to process the ack of its association response
+        simulation_time.delay(Duration::from_millis(10)).await;
+
+        let associated_devices = coordinator.associated_devices();
+        assert_eq!(associated_devices.len(), 1);
+        assert_eq!(associated_devices[0].short_address, ShortAddress(1));
+    });
+
+    runner.run();
+}
@@ -0,0 +1,97 @@
+use lr_wpan_rs::{
+    ChannelPage,
+    mac::{MacCommander, PlanningHeadroom},
+    phy::Phy,
+    pib::PibValue,
+    sap::{SecurityInfo, Status, get::GetRequest, start::StartRequest},
+    time::{Duration, Instant},
+    wire::{
+        PanId, ShortAddress,
+        beacon::{BeaconOrder, SuperframeOrder},
+    },
+};
+use lr_wpan_rs_tests::{aether::AetherRadio, run::EngineOptions};
+
+#[test_log::test]
+fn beacons_are_planned_with_the_configured_headroom() {
+    let headroom = PlanningHeadroom {
+        beacon: Duration::from_millis(10),
+        ..Default::default()
+    };
+
+    let (commanders, mut aether, mut runner) =
+        lr_wpan_rs_tests::run::create_test_runner_with([EngineOptions {
+            planning_headroom: headroom,
+            ..EngineOptions::new(0)
+        }]);
+
+    let coordinator = commanders[0];
+    let simulation_time = runner.simulation_time;
+
+    runner.attach_test_task(async move {
+        let mut sniffer = aether.radio();
+        sniffer.start_receive().await.unwrap();
+
+        coordinator
+            .initialize(&[PibValue::MacShortAddress(ShortAddress(0))])
+            .await
+            .unwrap();
+
+        // A beacon interval of about 150 millis
+        let start_response = coordinator
+            .request(StartRequest {
+                pan_id: PanId(1234),
+                channel_number: 5,
+                channel_page: ChannelPage::Uwb,
+                start_time: 0,
+                beacon_order: BeaconOrder::BeaconOrder(10),
+                superframe_order: SuperframeOrder::SuperframeOrder(8),
+                pan_coordinator: true,
+                battery_life_extension: false,
+                coord_realignment: false,
+                coord_realign_security_info: SecurityInfo::new_none_security(),
+                beacon_security_info: SecurityInfo::new_none_security(),
+            })
+            .await;
+        assert_eq!(start_response.status, Status::Success);
+
+        let first_beacon = next_beacon_time(&mut sniffer).await;
+        let second_beacon = next_beacon_time(&mut sniffer).await;
+        let beacon_interval = second_beacon.duration_since(first_beacon);
+
+        // Within the headroom, the mac is busy with the beacon and only answers after sending it
+        simulation_time
+            .delay_until(second_beacon + beacon_interval - Duration::from_millis(5))
+            .await;
+        get_anything(coordinator).await;
+        let answer_time = simulation_time.now();
+        let third_beacon = next_beacon_time(&mut sniffer).await;
+        assert!(answer_time >= third_beacon);
+
+        // Outside of the headroom (but within the default one) it answers right away
+        simulation_time
+            .delay_until(third_beacon + beacon_interval - Duration::from_millis(15))
+            .await;
+        get_anything(coordinator).await;
+        let answer_time = simulation_time.now();
+        let fourth_beacon = next_beacon_time(&mut sniffer).await;
+        assert!(answer_time < fourth_beacon);
+    });
+
+    runner.run();
+}
+
+async fn next_beacon_time(sniffer: &mut AetherRadio) -> Instant {
+    let context = sniffer.wait().await.unwrap();
+    sniffer.process(context).await.unwrap().unwrap().timestamp
+}
+
+async fn get_anything(device: &MacCommander) {
+    device
+        .request(GetRequest {
+            pib_attribute: PibValue::MAC_SHORT_ADDRESS,
+        })
+        .await
+        .status
+        .unwrap();
+}
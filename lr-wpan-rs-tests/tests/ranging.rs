@@ -0,0 +1,219 @@
+use std::sync::Arc;
+
+use byte::TryWrite;
+use lr_wpan_rs::{
+    consts::MAX_PHY_PACKET_SIZE,
+    mac::MacCommander,
+    phy::{Phy, SendContinuation},
+    pib::PibValue,
+    sap::{Status, data::DataIndication},
+    time::Duration,
+    wire::{
+        Address, ExtendedAddress, FooterMode, Frame, FrameContent, FrameSerDesContext, FrameType,
+        FrameVersion, Header, PanId, ShortAddress,
+    },
+};
+use lr_wpan_rs_tests::run::{EngineOptions, TestRunner};
+
+/// The mac engines of the test runner are placed 1 meter apart
+const DISTANCE: f32 = 1.0;
+
+#[test_log::test]
+fn range_to_a_device() {
+    let (commanders, _, mut runner) = lr_wpan_rs_tests::run::create_test_runner(2);
+
+    let distance = range(&mut runner, commanders);
+    runner.run();
+
+    let distance = distance.try_recv().unwrap().unwrap();
+    assert!((distance - DISTANCE).abs() < 0.05, "{distance}");
+}
+
+#[test_log::test]
+fn range_with_clock_drift() {
+    // The responder's clock runs fast, so it holds on to the poll a bit longer than it thinks.
+    // With the reply delay of 1000 symbols, that's less than a nanosecond.
+    let (commanders, _, mut runner) = lr_wpan_rs_tests::run::create_test_runner_with([
+        EngineOptions::new(0),
+        EngineOptions {
+            clock_drift_ppm: 10.0,
+            ..EngineOptions::new(1)
+        },
+    ]);
+
+    let distance = range(&mut runner, commanders);
+    runner.run();
+
+    let distance = distance.try_recv().unwrap().unwrap();
+    assert!((distance - DISTANCE).abs() < 0.5, "{distance}");
+}
+
+#[test_log::test]
+fn range_with_the_timing_limits_of_a_dw1000() {
+    // A delayed send of the DW1000 must be scheduled at least 10 ms ahead, and the low 9 bits of its time are ignored.
+    // The symbol of the aether is 10000 ticks.
+    let dw1000_like = |seed| EngineOptions {
+        send_setup_time_symbols: (Duration::from_millis(10).ticks() / 10000) as u32 + 1,
+        send_time_resolution_ticks: 512,
+        ..EngineOptions::new(seed)
+    };
+    let (commanders, _, mut runner) =
+        lr_wpan_rs_tests::run::create_test_runner_with((0..2).map(dw1000_like));
+
+    let distance = range(&mut runner, commanders);
+    runner.run();
+
+    // The rounding of the send time is in the response, so it doesn't end up in the distance
+    let distance = distance.try_recv().unwrap().unwrap();
+    assert!((distance - DISTANCE).abs() < 0.05, "{distance}");
+}
+
+#[test_log::test]
+fn ranging_needs_phy_support() {
+    let (commanders, _, mut runner) =
+        lr_wpan_rs_tests::run::create_test_runner_with((0..2).map(|seed| EngineOptions {
+            phy_ranging: false,
+            ..EngineOptions::new(seed)
+        }));
+
+    let distance = range(&mut runner, commanders);
+    runner.run();
+
+    assert_eq!(
+        distance.try_recv().unwrap(),
+        Err(Status::RangingNotSupported)
+    );
+}
+
+#[test_log::test]
+fn frames_received_while_ranging_are_processed_afterwards() {
+    let (commanders, mut aether, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    let initiator = commanders[0];
+    let (ready_sender, ready_receiver) = async_channel::bounded(1);
+
+    runner.attach_test_task(async move {
+        let mut radio = aether.radio();
+        radio.start_receive().await.unwrap();
+        ready_sender.send(()).await.unwrap();
+
+        // The initiator only has its receiver on while it waits on the response to its poll
+        let context = radio.wait().await.unwrap();
+        radio.process(context).await.unwrap().unwrap();
+
+        let frame = Frame {
+            header: Header {
+                frame_type: FrameType::Data,
+                frame_pending: false,
+                ack_request: false,
+                pan_id_compress: false,
+                seq_no_suppress: false,
+                ie_present: false,
+                version: FrameVersion::Ieee802154_2003,
+                seq: 7,
+                destination: Some(Address::Short(PanId::broadcast(), ShortAddress::BROADCAST)),
+                source: Some(Address::Extended(PanId(1), ExtendedAddress(100))),
+                auxiliary_security_header: None,
+                time_correction: None,
+            },
+            content: FrameContent::Data,
+            payload: &[1, 2, 3, 4],
+            footer: [0, 0],
+        };
+        let mut buffer = [0; MAX_PHY_PACKET_SIZE];
+        let length = frame
+            .try_write(
+                &mut buffer,
+                &mut FrameSerDesContext::no_security(FooterMode::None),
+            )
+            .unwrap();
+        radio
+            .send(
+                &buffer[..length],
+                None,
+                false,
+                false,
+                SendContinuation::Idle,
+            )
+            .await
+            .unwrap();
+    });
+
+    runner.attach_test_task(async move {
+        initiator
+            .initialize(&[
+                PibValue::MacPanId(PanId(1)),
+                PibValue::MacShortAddress(ShortAddress(1)),
+            ])
+            .await
+            .unwrap();
+
+        ready_receiver.recv().await.unwrap();
+
+        // Nobody answers the poll
+        assert_eq!(
+            initiator
+                .range_to(Address::Short(PanId(1), ShortAddress(2)))
+                .await
+                .map(|distance| distance.0),
+            Err(Status::NoAck)
+        );
+
+        // The data frame that came in while waiting wasn't lost
+        let responder = initiator
+            .wait_for_indication()
+            .await
+            .into_concrete::<DataIndication>();
+        assert_eq!(responder.indication.msdu, [1, 2, 3, 4]);
+        responder.respond(());
+    });
+
+    runner.run();
+}
+
+/// Let the first commander range to the second one
+fn range(
+    runner: &mut TestRunner<'_>,
+    commanders: Arc<[&'static MacCommander]>,
+) -> async_channel::Receiver<Result<f32, Status>> {
+    let initiator = commanders[0];
+    let responder = commanders[1];
+    let simulation_time = runner.simulation_time;
+    let (ready_sender, ready_receiver) = async_channel::bounded(1);
+    let (distance_sender, distance_receiver) = async_channel::bounded(1);
+
+    runner.attach_test_task(async move {
+        responder
+            .initialize(&[
+                PibValue::MacPanId(PanId(1)),
+                PibValue::MacShortAddress(ShortAddress(2)),
+                PibValue::MacRxOnWhenIdle(true),
+            ])
+            .await
+            .unwrap();
+
+        ready_sender.send(()).await.unwrap();
+    });
+
+    runner.attach_test_task(async move {
+        initiator
+            .initialize(&[
+                PibValue::MacPanId(PanId(1)),
+                PibValue::MacShortAddress(ShortAddress(1)),
+            ])
+            .await
+            .unwrap();
+
+        ready_receiver.recv().await.unwrap();
+        // Give the responder the time to turn on its receiver
+        simulation_time.delay(Duration::from_millis(1)).await;
+
+        let distance = initiator
+            .range_to(Address::Short(PanId(1), ShortAddress(2)))
+            .await
+            .map(|distance| distance.0);
+        distance_sender.send(distance).await.unwrap();
+    });
+
+    distance_receiver
+}
@@ -0,0 +1,246 @@
+use byte::TryWrite;
+use lr_wpan_rs::{
+    ChannelPage,
+    consts::MAX_PHY_PACKET_SIZE,
+    mac::MacCommander,
+    phy::{Phy, SendContinuation},
+    pib::PibValue,
+    sap::{
+        IndicationValue, SecurityInfo, Status,
+        get::GetRequest,
+        reset::ResetRequest,
+        set::SetRequest,
+        start::StartRequest,
+        sync::{LossReason, SyncLossIndication},
+    },
+    time::Duration,
+    wire::{
+        Address, ExtendedAddress, FooterMode, Frame, FrameContent, FrameSerDesContext, FrameType,
+        FrameVersion, Header, PanId, ShortAddress,
+        beacon::{BeaconOrder, SuperframeOrder},
+        command::{Command, CoordinatorRealignmentData},
+    },
+};
+
+const PAN_ID: PanId = PanId(0x1234);
+
+#[test_log::test]
+fn device_follows_channel_change() {
+    let (commanders, _, mut runner) = lr_wpan_rs_tests::run::create_test_runner(2);
+
+    let coordinator = commanders[0];
+    let device = commanders[1];
+
+    let (ready_sender, ready_receiver) = async_channel::bounded(1);
+
+    runner.attach_test_task(async move {
+        start_pan(coordinator, 1).await;
+
+        // Move away once the device is listening
+        ready_receiver.recv().await.unwrap();
+        coordinator
+            .change_channel(ChannelPage::Uwb, 2)
+            .await
+            .unwrap();
+
+        assert_eq!(current_channel(coordinator).await, 2);
+    });
+
+    runner.attach_test_task(async move {
+        join_pan(device, 1).await;
+        ready_sender.send(()).await.unwrap();
+
+        let indication_responder = device.wait_for_indication().await;
+        let IndicationValue::SyncLoss(_) = indication_responder.indication else {
+            panic!(
+                "Got an unexpected indication: {:?}",
+                indication_responder.indication
+            );
+        };
+        let responder = indication_responder.into_concrete::<SyncLossIndication>();
+
+        assert_eq!(responder.indication.loss_reason, LossReason::Realignment);
+        assert_eq!(responder.indication.pan_id, PAN_ID);
+        assert_eq!(responder.indication.channel_number, 2);
+        assert_eq!(responder.indication.channel_page, ChannelPage::Uwb as u8);
+        responder.respond(());
+
+        assert_eq!(current_channel(device).await, 2);
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn realignment_from_another_device_is_ignored() {
+    let (commanders, mut aether, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    let device = commanders[0];
+    let simulation_time = runner.simulation_time;
+
+    runner.attach_test_task(async move {
+        let mut radio = aether.radio();
+        radio
+            .update_phy_pib(|phy_pib| phy_pib.current_channel = 1)
+            .await
+            .unwrap();
+
+        join_pan(device, 1).await;
+
+        // Give the mac engine the time to turn on its receiver
+        simulation_time.delay(Duration::from_millis(1)).await;
+
+        // The realignment names the short address of our coordinator, but isn't sent by it
+        let frame = Frame {
+            header: Header {
+                frame_type: FrameType::MacCommand,
+                frame_pending: false,
+                ack_request: false,
+                pan_id_compress: false,
+                seq_no_suppress: false,
+                ie_present: false,
+                version: FrameVersion::Ieee802154_2006,
+                seq: 7,
+                destination: Some(Address::Short(PanId::broadcast(), ShortAddress::BROADCAST)),
+                source: Some(Address::Extended(PAN_ID, ExtendedAddress(100))),
+                auxiliary_security_header: None,
+                time_correction: None,
+            },
+            content: FrameContent::Command(Command::CoordinatorRealignment(
+                CoordinatorRealignmentData {
+                    pan_id: PAN_ID,
+                    coordinator_address: ShortAddress(0),
+                    channel: 2,
+                    device_address: ShortAddress::BROADCAST,
+                    channel_page: Some(ChannelPage::Uwb as u8),
+                },
+            )),
+            payload: &[],
+            footer: [0, 0],
+        };
+        let mut buffer = [0; MAX_PHY_PACKET_SIZE];
+        let length = frame
+            .try_write(
+                &mut buffer,
+                &mut FrameSerDesContext::no_security(FooterMode::None),
+            )
+            .unwrap();
+        radio
+            .send(
+                &buffer[..length],
+                None,
+                false,
+                false,
+                SendContinuation::Idle,
+            )
+            .await
+            .unwrap();
+
+        simulation_time.delay(Duration::from_millis(10)).await;
+        assert_eq!(current_channel(device).await, 1);
+    });
+
+    runner.run();
+}
+
+async fn start_pan(coordinator: &MacCommander, channel_number: u8) {
+    coordinator
+        .request(ResetRequest {
+            set_default_pib: true,
+        })
+        .await
+        .status
+        .unwrap();
+
+    set(
+        coordinator,
+        PibValue::MAC_SHORT_ADDRESS,
+        PibValue::MacShortAddress(ShortAddress(0)),
+    )
+    .await;
+
+    // The realignment command is sent along with a beacon, so the PAN must be beacon-enabled
+    let start_response = coordinator
+        .request(StartRequest {
+            pan_id: PAN_ID,
+            channel_number,
+            channel_page: ChannelPage::Uwb,
+            start_time: 0,
+            beacon_order: BeaconOrder::BeaconOrder(10),
+            superframe_order: SuperframeOrder::SuperframeOrder(10),
+            pan_coordinator: true,
+            battery_life_extension: false,
+            coord_realignment: false,
+            coord_realign_security_info: SecurityInfo::new_none_security(),
+            beacon_security_info: SecurityInfo::new_none_security(),
+        })
+        .await;
+    assert_eq!(start_response.status, Status::Success);
+}
+
+/// Set up the device as if it's associated with the coordinator
+async fn join_pan(device: &MacCommander, channel_number: u8) {
+    device
+        .request(ResetRequest {
+            set_default_pib: true,
+        })
+        .await
+        .status
+        .unwrap();
+
+    set(device, PibValue::MAC_PAN_ID, PibValue::MacPanId(PAN_ID)).await;
+    set(
+        device,
+        PibValue::MAC_SHORT_ADDRESS,
+        PibValue::MacShortAddress(ShortAddress(1)),
+    )
+    .await;
+    set(
+        device,
+        PibValue::MAC_COORD_SHORT_ADDRESS,
+        PibValue::MacCoordShortAddress(ShortAddress(0)),
+    )
+    .await;
+    set(
+        device,
+        PibValue::MAC_COORD_EXTENDED_ADDRESS,
+        PibValue::MacCoordExtendedAddress(ExtendedAddress(0)),
+    )
+    .await;
+    set(
+        device,
+        PibValue::PHY_CURRENT_CHANNEL,
+        PibValue::PhyCurrentChannel(channel_number),
+    )
+    .await;
+    set(
+        device,
+        PibValue::MAC_RX_ON_WHEN_IDLE,
+        PibValue::MacRxOnWhenIdle(true),
+    )
+    .await;
+}
+
+async fn set(commander: &MacCommander, pib_attribute: &'static str, pib_attribute_value: PibValue) {
+    let set_response = commander
+        .request(SetRequest {
+            pib_attribute,
+            pib_attribute_value,
+        })
+        .await;
+    assert_eq!(set_response.status, Status::Success);
+}
+
+async fn current_channel(commander: &MacCommander) -> u8 {
+    let PibValue::PhyCurrentChannel(channel) = commander
+        .request(GetRequest {
+            pib_attribute: PibValue::PHY_CURRENT_CHANNEL,
+        })
+        .await
+        .value
+    else {
+        panic!("Wrong pib value type");
+    };
+
+    channel
+}
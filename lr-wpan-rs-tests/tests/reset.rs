@@ -0,0 +1,236 @@
+use lr_wpan_rs::{
+    mac::MacCommander,
+    pib::PibValue,
+    sap::{
+        Status,
+        get::GetRequest,
+        reset::{ResetConfirm, ResetRequest},
+        set::SetRequest,
+    },
+    wire::PanId,
+};
+
+#[test_log::test]
+fn reset_restores_default_pib() {
+    let (commanders, _, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    runner.attach_test_task(async {
+        let commander = commanders[0];
+
+        set_non_default_values(commander).await;
+
+        // Without setting the defaults, the pib must be left alone
+        assert_eq!(
+            commander
+                .request(ResetRequest {
+                    set_default_pib: false
+                })
+                .await,
+            ResetConfirm {
+                status: Status::Success
+            }
+        );
+        assert_eq!(
+            get(commander, PibValue::MAC_MAX_BE).await,
+            PibValue::MacMaxBe(7)
+        );
+        assert_eq!(
+            get(commander, PibValue::MAC_RESPONSE_WAIT_TIME).await,
+            PibValue::MacResponseWaitTime(50)
+        );
+        assert_eq!(
+            get(commander, PibValue::MAC_ASSOCIATION_PERMIT).await,
+            PibValue::MacAssociationPermit(true)
+        );
+        assert_eq!(
+            get(commander, PibValue::MAC_RX_ON_WHEN_IDLE).await,
+            PibValue::MacRxOnWhenIdle(true)
+        );
+
+        assert_eq!(
+            commander
+                .request(ResetRequest {
+                    set_default_pib: true
+                })
+                .await,
+            ResetConfirm {
+                status: Status::Success
+            }
+        );
+        assert_eq!(
+            get(commander, PibValue::MAC_MAX_BE).await,
+            PibValue::MacMaxBe(5)
+        );
+        assert_eq!(
+            get(commander, PibValue::MAC_RESPONSE_WAIT_TIME).await,
+            PibValue::MacResponseWaitTime(32)
+        );
+        assert_eq!(
+            get(commander, PibValue::MAC_ASSOCIATION_PERMIT).await,
+            PibValue::MacAssociationPermit(false)
+        );
+        assert_eq!(
+            get(commander, PibValue::MAC_RX_ON_WHEN_IDLE).await,
+            PibValue::MacRxOnWhenIdle(false)
+        );
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn reset_without_default_pib_keeps_the_phy_config() {
+    let (commanders, _, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    runner.attach_test_task(async {
+        let commander = commanders[0];
+
+        for pib_attribute_value in [PibValue::PhyCurrentChannel(3), PibValue::PhyTxPower(-5)] {
+            let response = commander
+                .request(SetRequest {
+                    pib_attribute: pib_attribute_value.name(),
+                    pib_attribute_value,
+                })
+                .await;
+            assert_eq!(response.status, Status::Success);
+        }
+
+        commander
+            .request(ResetRequest {
+                set_default_pib: false,
+            })
+            .await;
+        assert_eq!(
+            get(commander, PibValue::PHY_CURRENT_CHANNEL).await,
+            PibValue::PhyCurrentChannel(3)
+        );
+        assert_eq!(
+            get(commander, PibValue::PHY_TX_POWER).await,
+            PibValue::PhyTxPower(-5)
+        );
+
+        // Setting the defaults does reset the phy
+        commander
+            .request(ResetRequest {
+                set_default_pib: true,
+            })
+            .await;
+        assert_eq!(
+            get(commander, PibValue::PHY_CURRENT_CHANNEL).await,
+            PibValue::PhyCurrentChannel(5)
+        );
+        assert_eq!(
+            get(commander, PibValue::PHY_TX_POWER).await,
+            PibValue::PhyTxPower(0)
+        );
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn initialize_resets_and_sets_the_pib() {
+    let (commanders, _, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    runner.attach_test_task(async {
+        let commander = commanders[0];
+
+        set_non_default_values(commander).await;
+
+        commander
+            .initialize(&[
+                PibValue::MacPanId(PanId(0x1234)),
+                PibValue::MacRxOnWhenIdle(true),
+            ])
+            .await
+            .unwrap();
+
+        // The values that weren't given are back to their defaults
+        assert_eq!(
+            get(commander, PibValue::MAC_MAX_BE).await,
+            PibValue::MacMaxBe(5)
+        );
+        assert_eq!(
+            get(commander, PibValue::MAC_ASSOCIATION_PERMIT).await,
+            PibValue::MacAssociationPermit(false)
+        );
+        assert_eq!(
+            get(commander, PibValue::MAC_PAN_ID).await,
+            PibValue::MacPanId(PanId(0x1234))
+        );
+        assert_eq!(
+            get(commander, PibValue::MAC_RX_ON_WHEN_IDLE).await,
+            PibValue::MacRxOnWhenIdle(true)
+        );
+
+        // The first value that can't be set stops the initialization
+        assert_eq!(
+            commander
+                .initialize(&[
+                    PibValue::MacBattLifeExtPeriods(0), // Below allowed range
+                    PibValue::MacRxOnWhenIdle(true),
+                ])
+                .await
+                .map_err(|detail| detail.status),
+            Err(Status::InvalidParameter)
+        );
+        assert_eq!(
+            get(commander, PibValue::MAC_RX_ON_WHEN_IDLE).await,
+            PibValue::MacRxOnWhenIdle(false)
+        );
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn initialize_reports_phy_errors() {
+    let (commanders, mut aether, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    aether.set_radios_broken(true);
+
+    runner.attach_test_task(async {
+        let commander = commanders[0];
+
+        let detail = commander
+            .initialize(&[PibValue::MacRxOnWhenIdle(true)])
+            .await
+            .unwrap_err();
+        assert_eq!(detail.status, Status::PhyError);
+        assert_eq!(detail.description, "PhyError(RadioBroken)");
+    });
+
+    runner.run();
+}
+
+async fn set_non_default_values(commander: &MacCommander) {
+    for (pib_attribute, pib_attribute_value) in [
+        (PibValue::MAC_MAX_BE, PibValue::MacMaxBe(7)),
+        (
+            PibValue::MAC_RESPONSE_WAIT_TIME,
+            PibValue::MacResponseWaitTime(50),
+        ),
+        (
+            PibValue::MAC_ASSOCIATION_PERMIT,
+            PibValue::MacAssociationPermit(true),
+        ),
+        (
+            PibValue::MAC_RX_ON_WHEN_IDLE,
+            PibValue::MacRxOnWhenIdle(true),
+        ),
+    ] {
+        let response = commander
+            .request(SetRequest {
+                pib_attribute,
+                pib_attribute_value,
+            })
+            .await;
+        assert_eq!(response.status, Status::Success);
+    }
+}
+
+async fn get(commander: &MacCommander, pib_attribute: &'static str) -> PibValue {
+    let response = commander.request(GetRequest { pib_attribute }).await;
+    assert_eq!(response.status, Status::Success);
+    response.value
+}
@@ -0,0 +1,121 @@
+use byte::TryRead;
+use futures::FutureExt;
+use lr_wpan_rs::{
+    phy::Phy,
+    pib::{PibValue, TxPolicy},
+    sap::{
+        SecurityInfo, Status, disassociate::DisassociateRequest, reset::ResetRequest,
+        set::SetRequest,
+    },
+    wire::{
+        Address, FooterMode, Frame, FrameContent, PanId, ShortAddress,
+        command::{Command, DisassociationReason},
+    },
+};
+use lr_wpan_rs_tests::run::EngineOptions;
+
+#[test_log::test]
+fn no_retransmissions() {
+    assert_eq!(count_transmissions(0), 1);
+}
+
+#[test_log::test]
+fn default_retransmissions() {
+    assert_eq!(
+        count_transmissions(TxPolicy::default().max_frame_retries),
+        4
+    );
+}
+
+#[test_log::test]
+fn max_retransmissions() {
+    assert_eq!(count_transmissions(7), 8);
+}
+
+/// Let a device send a disassociation notification to a coordinator that never acks it
+/// and count how often the notification is transmitted
+fn count_transmissions(max_frame_retries: u8) -> usize {
+    let (commanders, mut aether, mut runner) =
+        lr_wpan_rs_tests::run::create_test_runner_with([EngineOptions {
+            tx_policy: TxPolicy {
+                max_frame_retries,
+                ..Default::default()
+            },
+            ..EngineOptions::new(0)
+        }]);
+
+    let device = commanders[0];
+    let (transmissions_sender, transmissions_receiver) = async_channel::bounded(1);
+    let (done_sender, done_receiver) = async_channel::bounded(1);
+
+    // The coordinator is played by a raw radio that listens, but never acks
+    let mut coordinator = aether.radio();
+    runner.attach_test_task(async move {
+        coordinator.start_receive().await.unwrap();
+
+        let mut transmissions = 0;
+        loop {
+            futures::select_biased! {
+                context = coordinator.wait().fuse() => {
+                    let message = coordinator.process(context.unwrap()).await.unwrap().unwrap();
+                    let (frame, _) = Frame::try_read(&message.data, FooterMode::None).unwrap();
+
+                    assert_eq!(
+                        frame.content,
+                        FrameContent::Command(Command::DisassociationNotification(
+                            DisassociationReason::DeviceLeave
+                        ))
+                    );
+                    transmissions += 1;
+                }
+                _ = done_receiver.recv().fuse() => break,
+            }
+        }
+
+        transmissions_sender.send(transmissions).await.unwrap();
+    });
+
+    runner.attach_test_task(async move {
+        device
+            .request(ResetRequest {
+                set_default_pib: true,
+            })
+            .await
+            .status
+            .unwrap();
+
+        // Act like we're associated to the coordinator
+        for (pib_attribute, pib_attribute_value) in [
+            (PibValue::MAC_PAN_ID, PibValue::MacPanId(PanId(0))),
+            (
+                PibValue::MAC_COORD_SHORT_ADDRESS,
+                PibValue::MacCoordShortAddress(ShortAddress(0)),
+            ),
+        ] {
+            device
+                .request(SetRequest {
+                    pib_attribute,
+                    pib_attribute_value,
+                })
+                .await
+                .status
+                .unwrap();
+        }
+
+        let disassociate_confirm = device
+            .request(DisassociateRequest {
+                device_address: Address::Short(PanId(0), ShortAddress(0)),
+                disassociate_reason: DisassociationReason::DeviceLeave,
+                tx_indirect: false,
+                security_info: SecurityInfo::new_none_security(),
+            })
+            .await;
+        assert_eq!(disassociate_confirm.status, Status::NoAck);
+
+        done_sender.send(()).await.unwrap();
+    });
+
+    runner.run();
+
+    transmissions_receiver.try_recv().unwrap()
+}
@@ -1,9 +1,12 @@
+use byte::{BytesExt, TryWrite};
 use futures::FutureExt;
 use lr_wpan_rs::{
     ChannelPage,
     allocation::{Allocated, Allocation},
+    consts::{MAX_BEACON_PAYLOAD_LENGTH, MAX_PHY_PACKET_SIZE},
     mac::MacCommander,
-    pib::PibValue,
+    phy::{Phy, SendContinuation},
+    pib::{ChannelDescription, PibValue},
     sap::{
         IndicationValue, PanDescriptor, SecurityInfo, Status,
         beacon_notify::BeaconNotifyIndication,
@@ -12,13 +15,23 @@ use lr_wpan_rs::{
         set::SetRequest,
         start::StartRequest,
     },
-    time::Instant,
+    time::{Duration, Instant},
     wire::{
-        Address, Frame, FrameContent, PanId, ShortAddress,
-        beacon::{BeaconOrder, SuperframeOrder},
+        Address, ExtendedAddress, FooterMode, Frame, FrameContent, FrameSerDesContext, FrameType,
+        FrameVersion, Header, PanId, ShortAddress,
+        beacon::{
+            Beacon, BeaconOrder, Direction, GuaranteedTimeSlotDescriptor,
+            GuaranteedTimeSlotInformation, PendingAddress, SuperframeOrder,
+            SuperframeSpecification,
+        },
         command::Command,
+        security::{
+            AuxiliarySecurityHeader, SecurityContext, SecurityControl, SecurityError,
+            SecurityLevel, default::Unimplemented,
+        },
     },
 };
+use lr_wpan_rs_tests::run::EngineOptions;
 
 #[test_log::test]
 fn scan_passive() {
@@ -240,9 +253,473 @@ fn scan_passive_no_auto_request() {
     runner.run();
 }
 
-// // TODO: A test with auto request enabled and more PANs being scanned than can fit in the allocation
+#[test_log::test]
+fn scan_passive_auto_request_with_beacon_payload() {
+    // Even with auto request, beacons that carry a payload must be notified (6.2.4.1)
+
+    let (commanders, _, mut runner) = lr_wpan_rs_tests::run::create_test_runner(2);
+
+    const BEACON_PAYLOAD: &[u8] = b"hello";
+
+    runner.attach_test_task(async {
+        start_beacon(commanders[0], 0, true).await;
+
+        let mut beacon_payload = [0; MAX_BEACON_PAYLOAD_LENGTH];
+        beacon_payload[..BEACON_PAYLOAD.len()].copy_from_slice(BEACON_PAYLOAD);
+
+        for (pib_attribute, pib_attribute_value) in [
+            (
+                PibValue::MAC_BEACON_PAYLOAD,
+                PibValue::MacBeaconPayload(beacon_payload),
+            ),
+            (
+                PibValue::MAC_BEACON_PAYLOAD_LENGTH,
+                PibValue::MacBeaconPayloadLength(BEACON_PAYLOAD.len()),
+            ),
+        ] {
+            let set_response = commanders[0]
+                .request(SetRequest {
+                    pib_attribute,
+                    pib_attribute_value,
+                })
+                .await;
+            assert_eq!(set_response.status, Status::Success);
+        }
+    });
+
+    runner.attach_test_task(async {
+        let (scan_confirm, notifications) =
+            perform_scan(commanders[1], ScanType::Passive, &[0], true).await;
+
+        assert_eq!(scan_confirm.status, Status::Success);
+        // The beacon is still stored in the results because of auto request
+        assert_eq!(scan_confirm.result_list_size, 1);
+
+        // But it's notified too, because it has a payload
+        assert!(!notifications.is_empty());
+        for notification in notifications {
+            assert_eq!(&notification.sdu[..], BEACON_PAYLOAD);
+            assert_eq!(
+                notification.pan_descriptor.coord_address,
+                scan_confirm
+                    .pan_descriptor_list()
+                    .nth(0)
+                    .unwrap()
+                    .coord_address
+            );
+        }
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn scan_reports_gts_slots() {
+    let (commanders, mut aether, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    let slots = heapless::Vec::from_slice(&[
+        GuaranteedTimeSlotDescriptor {
+            short_address: ShortAddress(1),
+            starting_slot: 10,
+            length: 2,
+            direction: Direction::Transmit,
+        },
+        GuaranteedTimeSlotDescriptor {
+            short_address: ShortAddress(2),
+            starting_slot: 12,
+            length: 3,
+            direction: Direction::Receive,
+        },
+    ])
+    .unwrap();
+
+    // The coordinator is played by a raw radio that keeps sending beacons with GTSs allocated
+    let mut coordinator = aether.radio();
+    let simulation_time = runner.simulation_time;
+    let (done_sender, done_receiver) = async_channel::bounded(1);
+    let beacon_slots = slots.clone();
+    runner.attach_test_task(async move {
+        let beacon_frame = Frame {
+            header: Header {
+                frame_type: FrameType::Beacon,
+                frame_pending: false,
+                ack_request: false,
+                pan_id_compress: false,
+                seq_no_suppress: false,
+                ie_present: false,
+                version: FrameVersion::Ieee802154,
+                seq: 0,
+                destination: None,
+                source: Some(Address::Short(PanId(7), ShortAddress(0))),
+                auxiliary_security_header: None,
+                time_correction: None,
+            },
+            content: FrameContent::Beacon(Beacon {
+                superframe_spec: SuperframeSpecification {
+                    beacon_order: BeaconOrder::OnDemand,
+                    superframe_order: SuperframeOrder::Inactive,
+                    final_cap_slot: 9,
+                    battery_life_extension: false,
+                    pan_coordinator: true,
+                    association_permit: false,
+                },
+                guaranteed_time_slot_info: GuaranteedTimeSlotInformation {
+                    permit: true,
+                    slots: beacon_slots,
+                },
+                pending_address: PendingAddress::new(),
+            }),
+            payload: &[],
+            footer: Default::default(),
+        };
+
+        let mut buffer = [0; MAX_PHY_PACKET_SIZE];
+        let length = beacon_frame
+            .try_write(
+                &mut buffer,
+                &mut FrameSerDesContext::no_security(FooterMode::None),
+            )
+            .unwrap();
+
+        while done_receiver.is_empty() {
+            coordinator
+                .send(
+                    &buffer[..length],
+                    None,
+                    false,
+                    false,
+                    SendContinuation::Idle,
+                )
+                .await
+                .unwrap();
+            simulation_time.delay(Duration::from_millis(100)).await;
+        }
+    });
+
+    runner.attach_test_task(async move {
+        // Raw radios start on channel 5
+        // The slots are only notified, so they don't take up room in every descriptor of the confirm
+        let (scan_confirm, notifications) =
+            perform_scan(commanders[0], ScanType::Passive, &[5], false).await;
+        done_sender.send(()).await.unwrap();
+
+        assert_eq!(scan_confirm.status, Status::Success);
+
+        let notification = &notifications[0];
+        assert!(notification.pan_descriptor.gts_permit);
+        assert_eq!(notification.gts_slots, slots);
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn scan_reports_beacons_that_cannot_be_unsecured() {
+    let (commanders, mut aether, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    // The coordinator is played by a raw radio that keeps sending secured beacons
+    let mut coordinator = aether.radio();
+    let simulation_time = runner.simulation_time;
+    let (done_sender, done_receiver) = async_channel::bounded(1);
+    runner.attach_test_task(async move {
+        let header = Header {
+            frame_type: FrameType::Beacon,
+            frame_pending: false,
+            ack_request: false,
+            pan_id_compress: false,
+            seq_no_suppress: false,
+            ie_present: false,
+            version: FrameVersion::Ieee802154_2006,
+            seq: 0,
+            destination: None,
+            source: Some(Address::Short(PanId(7), ShortAddress(0))),
+            auxiliary_security_header: Some(AuxiliarySecurityHeader::new(
+                SecurityControl::new(SecurityLevel::MIC32),
+                None,
+            )),
+            time_correction: None,
+        };
+        let beacon = FrameContent::Beacon(Beacon {
+            superframe_spec: SuperframeSpecification {
+                beacon_order: BeaconOrder::OnDemand,
+                superframe_order: SuperframeOrder::Inactive,
+                final_cap_slot: 15,
+                battery_life_extension: false,
+                pan_coordinator: true,
+                association_permit: true,
+            },
+            guaranteed_time_slot_info: GuaranteedTimeSlotInformation::new(),
+            pending_address: PendingAddress::new(),
+        });
+
+        // Securing beacons isn't implemented, so the frame is put together by hand with a MIC
+        // that the scanning mac has no key for
+        let mut buffer = [0; MAX_PHY_PACKET_SIZE];
+        let mut length = 0;
+        let mut security_context =
+            SecurityContext::<Unimplemented, Unimplemented>::new(0, 0, Unimplemented);
+        buffer
+            .write_with(&mut length, header, &Some(&mut security_context))
+            .unwrap();
+        buffer.write(&mut length, beacon).unwrap();
+        buffer.write(&mut length, &[0xAB; 4][..]).unwrap();
+
+        while done_receiver.is_empty() {
+            coordinator
+                .send(
+                    &buffer[..length],
+                    None,
+                    false,
+                    false,
+                    SendContinuation::Idle,
+                )
+                .await
+                .unwrap();
+            simulation_time.delay(Duration::from_millis(100)).await;
+        }
+    });
+
+    runner.attach_test_task(async move {
+        // Raw radios start on channel 5
+        let (scan_confirm, _) = perform_scan(commanders[0], ScanType::Passive, &[5], true).await;
+        done_sender.send(()).await.unwrap();
+
+        // The PAN is found, but the mac tells it couldn't verify the beacon
+        assert_eq!(scan_confirm.status, Status::Success);
+
+        let pan_descriptor = scan_confirm.pan_descriptor_list().nth(0).unwrap();
+        assert_eq!(
+            pan_descriptor.coord_address,
+            Address::Short(PanId(7), ShortAddress(0))
+        );
+        // Secured beacons can't be unsecured yet
+        assert_eq!(
+            pan_descriptor.security_status,
+            Some(SecurityError::NotImplemented)
+        );
+        assert_eq!(
+            pan_descriptor.security_info.security_level,
+            SecurityLevel::MIC32
+        );
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn scan_ends_when_the_pan_descriptor_list_is_full() {
+    let (commanders, _, mut runner) = lr_wpan_rs_tests::run::create_test_runner(4);
+
+    // Three PANs on channel 0
+    runner.attach_test_task(start_beacon(commanders[0], 0, true));
+    runner.attach_test_task(start_beacon(commanders[1], 1, true));
+    runner.attach_test_task(start_beacon(commanders[2], 2, true));
+
+    runner.attach_test_task(async {
+        // There's only room for two of them
+        let (scan_confirm, _) = perform_scan_with_allocation(
+            commanders[3],
+            ScanType::Passive,
+            &[0, 1],
+            true,
+            vec![None; 2].leak(),
+        )
+        .await;
+
+        assert_eq!(scan_confirm.status, Status::LimitReached);
+        assert_eq!(scan_confirm.result_list_size, 2);
+        assert_eq!(scan_confirm.pan_descriptor_list().count(), 2);
+        // The scan stopped on channel 0
+        assert_eq!(&scan_confirm.unscanned_channels[..], &[1]);
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn scan_skips_unsupported_channels() {
+    let (commanders, _, mut runner) = lr_wpan_rs_tests::run::create_test_runner(3);
+
+    runner.attach_test_task(start_beacon(commanders[0], 0, true));
+    runner.attach_test_task(start_beacon(commanders[1], 1, true));
+
+    runner.attach_test_task(async {
+        // The UWB phy of the aether only has channels 0 up to 15
+        let (scan_confirm, _) =
+            perform_scan(commanders[2], ScanType::Passive, &[0, 16, 1, 200], true).await;
+
+        // The supported channels are still scanned
+        assert_eq!(scan_confirm.status, Status::Success);
+        assert_eq!(scan_confirm.result_list_size, 2);
+        // The unsupported ones are reported back
+        assert_eq!(&scan_confirm.unscanned_channels[..], &[16, 200]);
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn scan_over_two_pages() {
+    static CHANNELS_SUPPORTED: &[ChannelDescription] = &[
+        ChannelDescription {
+            page: ChannelPage::Uwb,
+            channel_numbers: &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+        },
+        ChannelDescription {
+            page: ChannelPage::Css,
+            channel_numbers: &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13],
+        },
+    ];
+
+    let (commanders, _, mut runner) =
+        lr_wpan_rs_tests::run::create_test_runner_with((0..3).map(|seed| EngineOptions {
+            channels_supported: CHANNELS_SUPPORTED,
+            ..EngineOptions::new(seed)
+        }));
+
+    runner.attach_test_task(start_beacon_on(commanders[0], 0, 1, ChannelPage::Uwb, true));
+    runner.attach_test_task(start_beacon_on(commanders[1], 1, 2, ChannelPage::Css, true));
+
+    let device = commanders[2];
+    runner.attach_test_task(async move {
+        device
+            .initialize(&[PibValue::MacAutoRequest(true)])
+            .await
+            .unwrap();
+
+        let mut scan_allocation = [None; 4];
+        let scan_confirm = device
+            .request_with_allocation(
+                ScanRequest {
+                    scan_type: ScanType::Passive,
+                    scan_channels: [1, 2].as_slice().try_into().unwrap(),
+                    pan_descriptor_list: Allocation::new(),
+                    scan_duration: 14,
+                    channel_page: ChannelPage::Uwb,
+                    scan_channel_pages: [ChannelPage::Uwb, ChannelPage::Css]
+                        .as_slice()
+                        .try_into()
+                        .unwrap(),
+                    security_info: SecurityInfo::new_none_security(),
+                    include_source_address: false,
+                },
+                &mut scan_allocation,
+            )
+            .await;
+
+        assert_eq!(scan_confirm.status, Status::Success);
+        assert!(scan_confirm.unscanned_channels.is_empty());
+        assert!(scan_confirm.unscanned_channel_pages.is_empty());
+
+        // Every PAN is reported on the page it was found on
+        let found = scan_confirm
+            .pan_descriptor_list()
+            .map(|pan_descriptor| {
+                (
+                    pan_descriptor.coord_address.pan_id(),
+                    pan_descriptor.channel_number,
+                    pan_descriptor.channel_page,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            [
+                (PanId(0), 1, ChannelPage::Uwb),
+                (PanId(1), 2, ChannelPage::Css)
+            ]
+        );
+
+        // There must be a page for every channel
+        let mut scan_allocation = [None; 4];
+        let scan_confirm = device
+            .request_with_allocation(
+                ScanRequest {
+                    scan_type: ScanType::Passive,
+                    scan_channels: [1, 2].as_slice().try_into().unwrap(),
+                    pan_descriptor_list: Allocation::new(),
+                    scan_duration: 14,
+                    channel_page: ChannelPage::Uwb,
+                    scan_channel_pages: [ChannelPage::Css].as_slice().try_into().unwrap(),
+                    security_info: SecurityInfo::new_none_security(),
+                    include_source_address: false,
+                },
+                &mut scan_allocation,
+            )
+            .await;
+        assert_eq!(scan_confirm.status, Status::InvalidParameter);
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn scan_active_with_source_address() {
+    let (commanders, mut aether, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    let device = commanders[0];
+
+    // Catch the beacon request the device sends out
+    let mut sniffer = aether.radio();
+    let (frame_sender, frame_receiver) = async_channel::bounded(1);
+    runner.attach_test_task(async move {
+        sniffer.start_receive().await.unwrap();
+
+        let message = loop {
+            let context = sniffer.wait().await.unwrap();
+            if let Some(message) = sniffer.process(context).await.unwrap() {
+                break message;
+            }
+        };
+
+        let (frame, _) = Frame::try_read(&message.data, FooterMode::None).unwrap();
+        frame_sender.send(frame).await.unwrap();
+    });
+
+    runner.attach_test_task(async move {
+        let mut scan_allocation = [None; 1];
+        let scan_confirm = device
+            .request_with_allocation(
+                ScanRequest {
+                    scan_type: ScanType::Active,
+                    scan_channels: [0].as_slice().try_into().unwrap(),
+                    pan_descriptor_list: Allocation::new(),
+                    scan_duration: 2,
+                    channel_page: ChannelPage::Uwb,
+                    scan_channel_pages: heapless::Vec::new(),
+                    security_info: SecurityInfo::new_none_security(),
+                    include_source_address: true,
+                },
+                &mut scan_allocation,
+            )
+            .await;
+        assert_eq!(scan_confirm.status, Status::NoBeacon);
+
+        let frame = frame_receiver.recv().await.unwrap();
+        assert_eq!(frame.content, FrameContent::Command(Command::BeaconRequest));
+        // The device has no short address, so it's identified by its extended address.
+        // During the scan its PAN id is the broadcast one.
+        assert_eq!(
+            frame.header.source,
+            Some(Address::Extended(PanId::broadcast(), ExtendedAddress(0)))
+        );
+    });
+
+    runner.run();
+}
 
 async fn start_beacon(commander: &MacCommander, id: u16, emit_beacons: bool) {
+    start_beacon_on(commander, id, 0, ChannelPage::Uwb, emit_beacons).await
+}
+
+/// Like [start_beacon], but on the given channel and page
+async fn start_beacon_on(
+    commander: &MacCommander,
+    id: u16,
+    channel_number: u8,
+    channel_page: ChannelPage,
+    emit_beacons: bool,
+) {
     let reset_response = commander
         .request(ResetRequest {
             set_default_pib: true,
@@ -261,8 +738,8 @@ async fn start_beacon(commander: &MacCommander, id: u16, emit_beacons: bool) {
     let start_response = commander
         .request(StartRequest {
             pan_id: PanId(id),
-            channel_number: 0,
-            channel_page: ChannelPage::Uwb,
+            channel_number,
+            channel_page,
             start_time: 0,
             beacon_order: if emit_beacons {
                 BeaconOrder::BeaconOrder(10)
@@ -289,6 +766,23 @@ async fn perform_scan(
     scan_type: ScanType,
     channels: &[u8],
     auto_request: bool,
+) -> (Allocated<'static, ScanConfirm>, Vec<BeaconNotifyIndication>) {
+    perform_scan_with_allocation(
+        commander,
+        scan_type,
+        channels,
+        auto_request,
+        vec![None; 16].leak(),
+    )
+    .await
+}
+
+async fn perform_scan_with_allocation(
+    commander: &MacCommander,
+    scan_type: ScanType,
+    channels: &[u8],
+    auto_request: bool,
+    pan_descriptor_allocation: &'static mut [Option<PanDescriptor>],
 ) -> (Allocated<'static, ScanConfirm>, Vec<BeaconNotifyIndication>) {
     let reset_response = commander
         .request(ResetRequest {
@@ -316,10 +810,12 @@ async fn perform_scan(
                     scan_channels: channels.try_into().unwrap(),
                     scan_duration: 14,
                     channel_page: ChannelPage::Uwb,
+                    scan_channel_pages: heapless::Vec::new(),
                     security_info: SecurityInfo::new_none_security(),
+                    include_source_address: false,
                     pan_descriptor_list: Allocation::new(),
                 },
-                vec![None; 16].leak()
+                pan_descriptor_allocation
             )
             .fuse()
     );
@@ -0,0 +1,79 @@
+use std::sync::{Arc, Mutex};
+
+use lr_wpan_rs::{
+    mac::MacCommander,
+    pib::PibValue,
+    sap::{get::GetRequest, reset::ResetRequest},
+};
+use lr_wpan_rs_tests::run::EngineOptions;
+
+#[test_log::test]
+fn same_seed_gives_same_behavior() {
+    let seeds = [7, 1234, 0xDEAD_BEEF];
+
+    let first_run = run_with_seeds(&seeds);
+    let second_run = run_with_seeds(&seeds);
+
+    assert_eq!(first_run, second_run);
+}
+
+/// Reset all mac engines and return the sequence numbers they got from their rng
+fn run_with_seeds(seeds: &[u64]) -> Vec<(u8, u8)> {
+    let (commanders, _, mut runner) = lr_wpan_rs_tests::run::create_test_runner_with(
+        seeds.iter().map(|seed| EngineOptions::new(*seed)),
+    );
+
+    let sequence_numbers = Arc::new(Mutex::new(Vec::new()));
+
+    runner.attach_test_task({
+        let sequence_numbers = sequence_numbers.clone();
+        async move {
+            for commander in commanders.iter() {
+                // Do it twice to see that the rng continues deterministically
+                for _ in 0..2 {
+                    let numbers = reset_and_get_sequence_numbers(commander).await;
+                    sequence_numbers.lock().unwrap().push(numbers);
+                }
+            }
+        }
+    });
+
+    runner.run();
+
+    Arc::into_inner(sequence_numbers)
+        .unwrap()
+        .into_inner()
+        .unwrap()
+}
+
+async fn reset_and_get_sequence_numbers(commander: &MacCommander) -> (u8, u8) {
+    commander
+        .request(ResetRequest {
+            set_default_pib: true,
+        })
+        .await
+        .status
+        .unwrap();
+
+    let PibValue::MacBsn(bsn) = commander
+        .request(GetRequest {
+            pib_attribute: PibValue::MAC_BSN,
+        })
+        .await
+        .value
+    else {
+        panic!("Wrong pib value type");
+    };
+
+    let PibValue::MacDsn(dsn) = commander
+        .request(GetRequest {
+            pib_attribute: PibValue::MAC_DSN,
+        })
+        .await
+        .value
+    else {
+        panic!("Wrong pib value type");
+    };
+
+    (bsn, dsn)
+}
@@ -0,0 +1,82 @@
+use lr_wpan_rs::{
+    phy::Phy,
+    pib::PibValue,
+    sap::{SecurityInfo, Status, disassociate::DisassociateRequest, get::GetRequest},
+    wire::{
+        Address, FooterMode, Frame, FrameContent, PanId, ShortAddress,
+        command::{Command, DisassociationReason},
+    },
+};
+
+#[test_log::test]
+fn set_dsn_is_used_by_the_next_frame() {
+    let (commanders, mut aether, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    let device = commanders[0];
+
+    // The coordinator is played by a raw radio that passes on the sequence numbers, but never acks
+    let mut coordinator = aether.radio();
+    let (seq_sender, seq_receiver) = async_channel::bounded(2);
+    runner.attach_test_task(async move {
+        coordinator.start_receive().await.unwrap();
+
+        for _ in 0..2 {
+            let message = loop {
+                let context = coordinator.wait().await.unwrap();
+                if let Some(message) = coordinator.process(context).await.unwrap() {
+                    break message;
+                }
+            };
+
+            let (frame, _) = Frame::try_read(&message.data, FooterMode::None).unwrap();
+            assert_eq!(
+                frame.content,
+                FrameContent::Command(Command::DisassociationNotification(
+                    DisassociationReason::DeviceLeave
+                ))
+            );
+            seq_sender.send(frame.header.seq).await.unwrap();
+        }
+    });
+
+    runner.attach_test_task(async move {
+        // Act like we're associated to the coordinator
+        device
+            .initialize(&[
+                PibValue::MacPanId(PanId(0)),
+                PibValue::MacCoordShortAddress(ShortAddress(0)),
+                PibValue::MacMaxFrameRetries(0),
+                PibValue::MacDsn(255),
+            ])
+            .await
+            .unwrap();
+
+        // The first frame gets exactly the value that was set, after which it wraps around
+        assert_eq!(disassociate(device).await, Status::NoAck);
+        assert_eq!(seq_receiver.recv().await.unwrap(), 255);
+        assert_eq!(disassociate(device).await, Status::NoAck);
+        assert_eq!(seq_receiver.recv().await.unwrap(), 0);
+
+        // The attribute reads the value the next frame gets
+        let get_confirm = device
+            .request(GetRequest {
+                pib_attribute: PibValue::MAC_DSN,
+            })
+            .await;
+        assert_eq!(get_confirm.value, PibValue::MacDsn(1));
+    });
+
+    runner.run();
+}
+
+async fn disassociate(device: &lr_wpan_rs::mac::MacCommander) -> Status {
+    device
+        .request(DisassociateRequest {
+            device_address: Address::Short(PanId(0), ShortAddress(0)),
+            disassociate_reason: DisassociationReason::DeviceLeave,
+            tx_indirect: false,
+            security_info: SecurityInfo::new_none_security(),
+        })
+        .await
+        .status
+}
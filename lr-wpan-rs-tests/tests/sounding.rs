@@ -0,0 +1,39 @@
+use lr_wpan_rs::{
+    allocation::Allocation,
+    sap::{
+        Status,
+        reset::ResetRequest,
+        sounding::{SoundingData, SoundingRequest},
+    },
+};
+
+#[test_log::test]
+fn sounding_uses_allocation() {
+    let (commanders, _, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    runner.attach_test_task(async {
+        let commander = commanders[0];
+
+        commander
+            .request(ResetRequest {
+                set_default_pib: true,
+            })
+            .await;
+
+        let mut buffer = [SoundingData::default(); 16];
+        let confirm = commander
+            .request_with_allocation(
+                SoundingRequest {
+                    sounding_list_allocation: Allocation::new(),
+                },
+                &mut buffer,
+            )
+            .await;
+
+        // The aether radio can't do channel sounding
+        assert_eq!(confirm.status, Status::UnsupportedAttribute);
+        assert!(confirm.sounding_list().is_empty());
+    });
+
+    runner.run();
+}
@@ -0,0 +1,45 @@
+use lr_wpan_rs::{
+    ChannelPage,
+    pib::PibValue,
+    sap::{Status, get::GetRequest},
+};
+
+#[test_log::test]
+fn survey_finds_interference() {
+    let (commanders, mut aether, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    aether.set_interference(3, 200);
+
+    runner.attach_test_task(async {
+        let energy_map = commanders[0].survey_spectrum().await.unwrap();
+
+        // All supported channels of the aether are surveyed
+        let expected_energy_map = (0..16)
+            .map(|channel| {
+                (
+                    ChannelPage::Uwb,
+                    channel,
+                    if channel == 3 { 200 } else { 0 },
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(&energy_map[..], &expected_energy_map[..]);
+
+        let quietest_channel = energy_map
+            .iter()
+            .min_by_key(|(_, _, energy)| *energy)
+            .map(|(_, channel, _)| *channel);
+        assert_ne!(quietest_channel, Some(3));
+
+        // The radio is back on the channel it was on before the survey
+        let response = commanders[0]
+            .request(GetRequest {
+                pib_attribute: PibValue::PHY_CURRENT_CHANNEL,
+            })
+            .await;
+        assert_eq!(response.status, Status::Success);
+        assert_eq!(response.value, PibValue::PhyCurrentChannel(5));
+    });
+
+    runner.run();
+}
@@ -0,0 +1,243 @@
+use futures::FutureExt;
+use lr_wpan_rs::{
+    ChannelPage,
+    consts::MAX_LOST_BEACONS,
+    mac::MacCommander,
+    pib::PibValue,
+    sap::{
+        IndicationValue, SecurityInfo, Status,
+        start::StartRequest,
+        sync::{LossReason, SyncLossIndication, SyncRequest},
+    },
+    time::Duration,
+    wire::{
+        Address, FrameType, PanId, ShortAddress,
+        beacon::{BeaconOrder, SuperframeOrder},
+    },
+};
+use lr_wpan_rs_tests::pan::spawn_coordinator;
+
+#[test_log::test]
+fn start_relative_to_a_beacon_that_is_not_tracked_is_refused() {
+    let (commanders, _, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    let device = commanders[0];
+
+    runner.attach_test_task(async move {
+        initialize_device(device).await;
+        assert_eq!(start_tracked_superframe(device).await, Status::TrackingOff);
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn sync_without_a_coordinator_loses_the_beacon() {
+    let (commanders, _, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    let device = commanders[0];
+
+    runner.attach_test_task(async move {
+        initialize_device(device).await;
+        sync(device).await;
+
+        let indication_responder = device.wait_for_indication().await;
+        let IndicationValue::SyncLoss(_) = indication_responder.indication else {
+            panic!(
+                "Got an unexpected indication: {:?}",
+                indication_responder.indication
+            );
+        };
+        let responder = indication_responder.into_concrete::<SyncLossIndication>();
+        assert_eq!(responder.indication.loss_reason, LossReason::BeaconLost);
+        assert_eq!(responder.indication.pan_id, PanId(1));
+        responder.respond(());
+
+        // Giving up on the sync turned tracking off
+        assert_eq!(start_tracked_superframe(device).await, Status::TrackingOff);
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn tracked_beacon_allows_a_start_relative_to_it() {
+    let (commanders, _, mut runner) = lr_wpan_rs_tests::run::create_test_runner(2);
+
+    let pan_started = spawn_coordinator(
+        &mut runner,
+        commanders[0],
+        PanId(1),
+        5,
+        BeaconOrder::BeaconOrder(10),
+        SuperframeOrder::SuperframeOrder(5),
+    );
+
+    let device = commanders[1];
+    let simulation_time = runner.simulation_time;
+
+    runner.attach_test_task(async move {
+        initialize_device(device).await;
+        let _ = pan_started.recv().await;
+
+        sync(device).await;
+
+        // A couple of beacon intervals
+        simulation_time.delay(Duration::from_millis(500)).await;
+        assert_eq!(start_tracked_superframe(device).await, Status::Success);
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn tracked_superframe_sends_a_beacon_after_every_tracked_beacon() {
+    let (commanders, mut aether, mut runner) = lr_wpan_rs_tests::run::create_test_runner(2);
+
+    let pan_started = spawn_coordinator(
+        &mut runner,
+        commanders[0],
+        PanId(1),
+        5,
+        BeaconOrder::BeaconOrder(10),
+        SuperframeOrder::SuperframeOrder(5),
+    );
+
+    let device = commanders[1];
+    let simulation_time = runner.simulation_time;
+
+    runner.attach_test_task(async move {
+        initialize_device(device).await;
+        let _ = pan_started.recv().await;
+
+        sync(device).await;
+        simulation_time.delay(Duration::from_millis(500)).await;
+        assert_eq!(start_tracked_superframe(device).await, Status::Success);
+
+        aether.start_trace("tracked_superframe_beacons");
+        simulation_time.delay(Duration::from_millis(1500)).await;
+        let trace = aether.stop_trace();
+
+        let device_address = Some(Address::Short(PanId(1), ShortAddress(5)));
+        let mut previous_source = None;
+        let mut device_beacons = 0;
+        for frame in aether.parse_trace(trace) {
+            if frame.header.frame_type != FrameType::Beacon {
+                continue;
+            }
+
+            if frame.header.source == device_address {
+                // Our beacon comes in the inactive portion, after the one of the coordinator
+                assert_ne!(previous_source, device_address);
+                device_beacons += 1;
+            }
+            previous_source = frame.header.source;
+        }
+
+        // The beacon interval is about 150 millis
+        assert!(
+            device_beacons >= 8,
+            "Only {device_beacons} beacons were sent"
+        );
+    });
+
+    runner.run();
+}
+
+#[test_log::test]
+fn sync_is_only_lost_after_too_many_missed_beacons() {
+    let (commanders, mut aether, mut runner) = lr_wpan_rs_tests::run::create_test_runner(2);
+
+    // The beacon interval is about 150 millis
+    let pan_started = spawn_coordinator(
+        &mut runner,
+        commanders[0],
+        PanId(1),
+        5,
+        BeaconOrder::BeaconOrder(10),
+        SuperframeOrder::SuperframeOrder(5),
+    );
+
+    let device = commanders[1];
+    let simulation_time = runner.simulation_time;
+
+    runner.attach_test_task(async move {
+        initialize_device(device).await;
+        let _ = pan_started.recv().await;
+
+        sync(device).await;
+        simulation_time.delay(Duration::from_millis(500)).await;
+
+        // The coordinator only sends beacons, so these are the beacons that get lost
+        aether.corrupt_next_frames(MAX_LOST_BEACONS as usize - 1);
+
+        futures::select_biased! {
+            indication_responder = device.wait_for_indication().fuse() => {
+                panic!("Got an unexpected indication: {:?}", indication_responder.indication);
+            }
+            _ = simulation_time.delay(Duration::from_millis(1500)).fuse() => {}
+        }
+
+        aether.corrupt_next_frames(MAX_LOST_BEACONS as usize);
+
+        let responder = device
+            .wait_for_indication()
+            .await
+            .into_concrete::<SyncLossIndication>();
+        assert_eq!(responder.indication.loss_reason, LossReason::BeaconLost);
+        responder.respond(());
+
+        // There's only one indication, because tracking is off now
+        futures::select_biased! {
+            indication_responder = device.wait_for_indication().fuse() => {
+                panic!("Got an unexpected indication: {:?}", indication_responder.indication);
+            }
+            _ = simulation_time.delay(Duration::from_millis(1500)).fuse() => {}
+        }
+        assert_eq!(start_tracked_superframe(device).await, Status::TrackingOff);
+    });
+
+    runner.run();
+}
+
+/// Act like the device is associated to the coordinator with short address 0 of PAN 1
+async fn initialize_device(device: &MacCommander) {
+    device
+        .initialize(&[
+            PibValue::MacPanId(PanId(1)),
+            PibValue::MacShortAddress(ShortAddress(5)),
+            PibValue::MacCoordShortAddress(ShortAddress(0)),
+        ])
+        .await
+        .unwrap();
+}
+
+async fn sync(device: &MacCommander) {
+    device
+        .request(SyncRequest {
+            channel_number: 5,
+            channel_page: ChannelPage::Uwb as u8,
+            track_beacon: true,
+        })
+        .await;
+}
+
+/// Start our own superframe in the inactive portion of the superframe of the coordinator
+async fn start_tracked_superframe(device: &MacCommander) -> Status {
+    device
+        .request(StartRequest {
+            pan_id: PanId(1),
+            channel_number: 5,
+            channel_page: ChannelPage::Uwb,
+            start_time: 2 * (lr_wpan_rs::consts::BASE_SUPERFRAME_DURATION << 5),
+            beacon_order: BeaconOrder::BeaconOrder(10),
+            superframe_order: SuperframeOrder::SuperframeOrder(5),
+            pan_coordinator: false,
+            battery_life_extension: false,
+            coord_realignment: false,
+            coord_realign_security_info: SecurityInfo::new_none_security(),
+            beacon_security_info: SecurityInfo::new_none_security(),
+        })
+        .await
+        .status
+}
@@ -0,0 +1,134 @@
+use byte::{TryRead, TryWrite};
+use lr_wpan_rs::{
+    consts::MAX_PHY_PACKET_SIZE,
+    phy::{Phy, SendContinuation},
+    pib::PibValue,
+    sap::{SecurityInfo, Status, disassociate::DisassociateRequest},
+    wire::{
+        Address, FooterMode, Frame, FrameContent, FrameSerDesContext, FrameType, FrameVersion,
+        Header, PanId, ShortAddress, command::DisassociationReason, fcs::FCS_LENGTH,
+    },
+};
+use lr_wpan_rs_tests::run::EngineOptions;
+
+/// The turnaround time of the radio of the mac, which is a lot slower than the standard one
+const TURNAROUND_TIME: u32 = 100;
+
+#[test_log::test]
+fn ack_before_the_turnaround_is_missed() {
+    assert_eq!(
+        disassociate_with_ack_after(TURNAROUND_TIME as i64 / 2),
+        Status::NoAck
+    );
+}
+
+#[test_log::test]
+fn ack_after_the_turnaround_is_received() {
+    assert_eq!(
+        disassociate_with_ack_after(TURNAROUND_TIME as i64 + 10),
+        Status::Success
+    );
+}
+
+/// Let a device send a disassociation notification to a coordinator that acks it the given amount
+/// of symbols after the end of the notification, and return the status of the disassociation
+fn disassociate_with_ack_after(ack_delay_symbols: i64) -> Status {
+    let (commanders, mut aether, mut runner) =
+        lr_wpan_rs_tests::run::create_test_runner_with([EngineOptions {
+            turnaround_time_symbols: TURNAROUND_TIME,
+            ..EngineOptions::new(0)
+        }]);
+
+    let device = commanders[0];
+    let (status_sender, status_receiver) = async_channel::bounded(1);
+
+    // The coordinator is played by a raw radio, so the ack can be sent at any time
+    let mut coordinator = aether.radio();
+    runner.attach_background_task(async move {
+        coordinator.start_receive().await.unwrap();
+
+        let context = coordinator.wait().await.unwrap();
+        let message = coordinator.process(context).await.unwrap().unwrap();
+        let (frame, _) = Frame::try_read(&message.data, FooterMode::None).unwrap();
+
+        // The turnaround of the mac starts when the frame, with the FCS the phy adds, has been sent
+        let frame_end_time = message.timestamp
+            + coordinator.symbol_period()
+                * coordinator
+                    .get_phy_pib()
+                    .frame_duration(message.data.len() + FCS_LENGTH) as i64;
+
+        let mut buffer = [0; MAX_PHY_PACKET_SIZE];
+        let length = write_ack(&mut buffer, frame.header.seq);
+        coordinator
+            .send(
+                &buffer[..length],
+                Some(frame_end_time + coordinator.symbol_period() * ack_delay_symbols),
+                false,
+                false,
+                SendContinuation::Idle,
+            )
+            .await
+            .unwrap();
+    });
+
+    runner.attach_test_task(async move {
+        // Act like we're associated to the coordinator, with only a single try to get the ack
+        device
+            .initialize(&[
+                PibValue::MacPanId(PanId(0)),
+                PibValue::MacCoordShortAddress(ShortAddress(0)),
+                PibValue::MacMaxFrameRetries(0),
+            ])
+            .await
+            .unwrap();
+
+        let disassociate_confirm = device
+            .request(DisassociateRequest {
+                device_address: Address::Short(PanId(0), ShortAddress(0)),
+                disassociate_reason: DisassociationReason::DeviceLeave,
+                tx_indirect: false,
+                security_info: SecurityInfo::new_none_security(),
+            })
+            .await;
+
+        status_sender
+            .send(disassociate_confirm.status)
+            .await
+            .unwrap();
+    });
+
+    runner.run();
+
+    status_receiver.try_recv().unwrap()
+}
+
+/// Write an ack and return its length
+fn write_ack(buffer: &mut [u8], seq: u8) -> usize {
+    let frame = Frame {
+        header: Header {
+            frame_type: FrameType::Acknowledgement,
+            frame_pending: false,
+            ack_request: false,
+            pan_id_compress: false,
+            seq_no_suppress: false,
+            ie_present: false,
+            version: FrameVersion::Ieee802154_2003,
+            seq,
+            destination: None,
+            source: None,
+            auxiliary_security_header: None,
+            time_correction: None,
+        },
+        content: FrameContent::Acknowledgement,
+        payload: &[],
+        footer: [0, 0],
+    };
+
+    frame
+        .try_write(
+            buffer,
+            &mut FrameSerDesContext::no_security(FooterMode::None),
+        )
+        .unwrap()
+}
@@ -0,0 +1,96 @@
+use futures::FutureExt;
+use lr_wpan_rs::{
+    mac::MacCommander,
+    phy::Phy,
+    pib::{PhyPib, PibValue},
+    sap::{
+        SecurityInfo, Status, disassociate::DisassociateRequest, get::GetRequest, set::SetRequest,
+    },
+    wire::{Address, PanId, ShortAddress, command::DisassociationReason},
+};
+
+#[test_log::test]
+fn transmissions_are_accumulated_and_cleared() {
+    let (commanders, mut aether, mut runner) = lr_wpan_rs_tests::run::create_test_runner(1);
+
+    let device = commanders[0];
+    let (duration_sender, duration_receiver) = async_channel::bounded(1);
+    let (done_sender, done_receiver) = async_channel::bounded(1);
+
+    // A sniffer adds up the durations of all frames it sees, including the FCS the phy adds to them
+    let mut sniffer = aether.radio();
+    runner.attach_test_task(async move {
+        sniffer.start_receive().await.unwrap();
+        let phy_pib = PhyPib::unspecified_new();
+
+        let mut duration = 0;
+        loop {
+            futures::select_biased! {
+                context = sniffer.wait().fuse() => {
+                    if let Some(message) = sniffer.process(context.unwrap()).await.unwrap() {
+                        duration += phy_pib.frame_duration(message.data.len() + 2);
+                    }
+                }
+                _ = done_receiver.recv().fuse() => break,
+            }
+        }
+
+        duration_sender.send(duration).await.unwrap();
+    });
+
+    runner.attach_test_task(async move {
+        // Act like we're associated to a coordinator
+        device
+            .initialize(&[
+                PibValue::MacPanId(PanId(0)),
+                PibValue::MacCoordShortAddress(ShortAddress(0)),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(tx_total_duration(device).await, 0);
+
+        // The coordinator doesn't exist, so the notification is sent multiple times.
+        // All of those transmissions count.
+        let disassociate_confirm = device
+            .request(DisassociateRequest {
+                device_address: Address::Short(PanId(0), ShortAddress(0)),
+                disassociate_reason: DisassociationReason::DeviceLeave,
+                tx_indirect: false,
+                security_info: SecurityInfo::new_none_security(),
+            })
+            .await;
+        assert_eq!(disassociate_confirm.status, Status::NoAck);
+
+        done_sender.send(()).await.unwrap();
+        let sniffed_duration = duration_receiver.recv().await.unwrap();
+        assert!(sniffed_duration > 0);
+        assert_eq!(tx_total_duration(device).await, sniffed_duration);
+
+        // The higher layer clears the counter by setting it
+        device
+            .request(SetRequest {
+                pib_attribute: PibValue::MAC_TX_TOTAL_DURATION,
+                pib_attribute_value: PibValue::MacTxTotalDuration(0),
+            })
+            .await
+            .status
+            .unwrap();
+        assert_eq!(tx_total_duration(device).await, 0);
+    });
+
+    runner.run();
+}
+
+async fn tx_total_duration(device: &MacCommander) -> u32 {
+    let PibValue::MacTxTotalDuration(duration) = device
+        .request(GetRequest {
+            pib_attribute: PibValue::MAC_TX_TOTAL_DURATION,
+        })
+        .await
+        .value
+    else {
+        panic!("Wrong pib value type");
+    };
+
+    duration
+}
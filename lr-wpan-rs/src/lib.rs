@@ -19,6 +19,7 @@ pub mod time;
 pub mod wire;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
 pub enum DeviceAddress {
     Short(ShortAddress),
     Extended(ExtendedAddress),
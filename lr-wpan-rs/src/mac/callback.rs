@@ -5,7 +5,7 @@ use crate::{
     sap::{
         Status,
         associate::{AssociateConfirm, AssociateRequest},
-        start::StartRequest,
+        start::{StartConfirm, StartRequest},
     },
     wire::command::AssociationStatus,
 };
@@ -36,10 +36,21 @@ impl<'a> SendCallback<'a> {
             }
         }
     }
+
+    /// Abort the procedure waiting on the send without the message being sent
+    pub fn abort(self, status: Status) {
+        match self {
+            SendCallback::StartProcedure(responder) => {
+                responder.respond(StartConfirm { status });
+            }
+        }
+    }
 }
 
 pub enum DataRequestCallback<'a> {
     AssociationProcedure(RequestResponder<'a, AssociateRequest>),
+    /// Nobody waits on the data request, what we receive is processed like any other frame
+    FramePending,
 }
 
 impl DataRequestCallback<'_> {
@@ -51,11 +62,12 @@ impl DataRequestCallback<'_> {
         }
     }
 
+    /// Returns true if the device is now associated
     pub async fn run_associate(
         self,
         associate_confirm: Result<AssociateConfirm, Result<AssociationStatus, Status>>,
         mac_pib: &mut MacPib,
-    ) {
+    ) -> bool {
         match self {
             DataRequestCallback::AssociationProcedure(request_responder) => {
                 super::mlme_associate::association_data_request_callback(
@@ -63,8 +75,14 @@ impl DataRequestCallback<'_> {
                     associate_confirm,
                     mac_pib,
                 )
-                .await;
+                .await
             }
+            DataRequestCallback::FramePending => false,
         }
     }
+
+    /// Abort the procedure waiting on the data request without the data request being sent
+    pub async fn abort(self, status: Status, mac_pib: &mut MacPib) {
+        self.run_associate(Err(Err(status)), mac_pib).await;
+    }
 }
@@ -1,25 +1,128 @@
+#[cfg(feature = "test-hooks")]
+use core::cell::Cell;
 use core::{
+    cell::RefCell,
+    fmt::{Debug, Write},
     future::Future,
     pin::Pin,
+    sync::atomic::{AtomicU32, Ordering},
     task::{Context, Poll},
 };
 
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use heapless::{String, Vec};
+
+#[cfg(feature = "frame-tap")]
+use super::frame_tap::{FrameTap, TappedFrame};
+#[cfg(feature = "test-hooks")]
+use super::test_hooks::BranchOrder;
+use super::{
+    MacError,
+    device_table::{AssociatedDevice, MAX_ASSOCIATED_DEVICES},
+    state::{MAX_PENDING_TRANSACTIONS, PendingTransactionInfo},
+};
+
 use crate::{
+    ChannelPage,
     allocation::{Allocated, Allocation},
+    pib::PibValue,
     reqresp::{ReqResp, RequestFuture},
     sap::{
         ConfirmValue, DynamicRequest, Indication, IndicationValue, Request, RequestValue,
-        ResponseValue,
+        ResponseValue, SecurityInfo, Status,
+        get::{GetConfirm, GetRequest},
+        operational_state::{MacOperationalState, OperationalStateRequest},
+        ranging::{Meters, RangingConfirm, RangingRequest},
+        reset::ResetRequest,
+        set::SetRequest,
+        set_many::{SetManyConfirm, SetManyRequest},
+        spectrum_survey::{MAX_SURVEY_CHANNELS, SpectrumSurveyConfirm, SpectrumSurveyRequest},
+        start::StartRequest,
     },
     time::Instant,
+    wire::Address,
 };
 
 pub const CHANNEL_SIZE: usize = 4;
 
+/// The maximum length of the description of an [ErrorDetail]. Longer descriptions are truncated.
+pub const MAX_ERROR_DESCRIPTION_LENGTH: usize = 64;
+
+/// The detail of an error that caused the MAC to confirm a request with a non-success status.
+///
+/// The status codes of the standard are quite generic. For example, any error of the phy becomes a
+/// [Status::PhyError]. This contains the description of the original error to help with debugging.
+/// It's returned along with the confirm by [MacCommander::request_with_error_detail].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct ErrorDetail {
+    /// The status the request was confirmed with
+    pub status: Status,
+    /// The description of the error that caused the status
+    pub description: String<MAX_ERROR_DESCRIPTION_LENGTH>,
+}
+
+impl From<Status> for ErrorDetail {
+    /// A detail without a description, for when the status is all there is to know
+    fn from(status: Status) -> Self {
+        Self {
+            status,
+            description: String::new(),
+        }
+    }
+}
+
+impl<PE: Debug> From<MacError<PE>> for ErrorDetail {
+    fn from(error: MacError<PE>) -> Self {
+        let mut description = String::new();
+        // An error only means the description didn't fit, which is fine
+        let _ = write!(description, "{error}");
+
+        Self {
+            status: error.into(),
+            description,
+        }
+    }
+}
+
+/// What the MAC does with an indication when the higher layer isn't taking them out of the channel fast enough.
+///
+/// This only applies to indications that don't need a response, like the [DataIndication](crate::sap::data::DataIndication).
+/// The MAC always waits for the higher layer on indications that do need a response.
+/// Dropped indications are counted, see [MacCommander::dropped_indications].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum IndicationOverflowPolicy {
+    /// Wait until the higher layer has responded to the indication.
+    /// Nothing is lost, but a slow higher layer stalls the whole MAC.
+    #[default]
+    Block,
+    /// Drop the new indication when the channel is full
+    DropNewest,
+    /// Drop the oldest indication that's still in the channel to make room for the new one.
+    ///
+    /// Beware that the dropped indication could be one that needs a response.
+    /// The MAC then treats it like the higher layer didn't respond in time.
+    DropOldest,
+}
+
 /// The main interface to the MAC layer. It can be used to make requests and receive indications
 pub struct MacCommander {
-    request_confirm_channel: ReqResp<RequestValue, ConfirmValue, CHANNEL_SIZE>,
+    request_confirm_channel:
+        ReqResp<RequestValue, (ConfirmValue, Option<ErrorDetail>), CHANNEL_SIZE>,
     indication_response_channel: ReqResp<IndicationValue, ResponseValue, CHANNEL_SIZE>,
+    dropped_indications: AtomicU32,
+    discarded_frames: AtomicU32,
+    associated_devices:
+        Mutex<CriticalSectionRawMutex, RefCell<Vec<AssociatedDevice, MAX_ASSOCIATED_DEVICES>>>,
+    pending_transactions: Mutex<
+        CriticalSectionRawMutex,
+        RefCell<Vec<PendingTransactionInfo, MAX_PENDING_TRANSACTIONS>>,
+    >,
+    #[cfg(feature = "frame-tap")]
+    frame_tap: FrameTap,
+    #[cfg(feature = "test-hooks")]
+    branch_order: Mutex<CriticalSectionRawMutex, Cell<BranchOrder>>,
 }
 
 impl MacCommander {
@@ -28,6 +131,14 @@ impl MacCommander {
         Self {
             request_confirm_channel: ReqResp::new(),
             indication_response_channel: ReqResp::new(),
+            dropped_indications: AtomicU32::new(0),
+            discarded_frames: AtomicU32::new(0),
+            associated_devices: Mutex::new(RefCell::new(Vec::new())),
+            pending_transactions: Mutex::new(RefCell::new(Vec::new())),
+            #[cfg(feature = "frame-tap")]
+            frame_tap: FrameTap::new(),
+            #[cfg(feature = "test-hooks")]
+            branch_order: Mutex::new(Cell::new(BranchOrder::DEFAULT)),
         }
     }
 
@@ -35,10 +146,19 @@ impl MacCommander {
     /// This API is cancel-safe, though the request may not have been sent at the point of cancellation.
     #[must_use]
     pub async fn request<R: Request>(&self, request: R) -> R::Confirm {
-        self.request_confirm_channel
-            .request(request.into())
-            .await
-            .into()
+        self.request_with_error_detail(request).await.0
+    }
+
+    /// Make a request to the MAC layer. The typed confirm response is returned along with the
+    /// detail of the error when the MAC has one for the status of the confirm, e.g. for a [Status::PhyError].
+    /// This API is cancel-safe, though the request may not have been sent at the point of cancellation.
+    #[must_use]
+    pub async fn request_with_error_detail<R: Request>(
+        &self,
+        request: R,
+    ) -> (R::Confirm, Option<ErrorDetail>) {
+        let (confirm, error_detail) = self.request_confirm_channel.request(request.into()).await;
+        (confirm.into(), error_detail)
     }
 
     /// Make a request to the MAC layer. The typed confirm response is returned.
@@ -62,13 +182,247 @@ impl MacCommander {
         #[expect(unused)]
         let allocation = ();
 
-        let confirm = self
-            .request_confirm_channel
-            .request(request.into())
+        let (confirm, _) = self.request_confirm_channel.request(request.into()).await;
+
+        Allocated::new(confirm.into())
+    }
+
+    /// Bring the whole stack into a known state, e.g. when bringing up a board.
+    ///
+    /// The phy is reset (which loads its capabilities), the MAC pib is set to its defaults and then all given
+    /// pib values are set in order. This returns once the stack is ready to be used.
+    ///
+    /// This is a convenience function for a [ResetRequest] with `set_default_pib` followed by a [SetRequest]
+    /// for every value. The status of the first request that fails is returned, along with the detail of
+    /// what went wrong if the MAC has one, e.g. for a [Status::PhyError].
+    ///
+    /// ```
+    /// # use lr_wpan_rs::{mac::{ErrorDetail, MacCommander}, pib::PibValue, wire::{PanId, ShortAddress}};
+    /// # async fn bring_up(commander: &MacCommander) -> Result<(), ErrorDetail> {
+    /// commander
+    ///     .initialize(&[
+    ///         PibValue::MacPanId(PanId(0x1234)),
+    ///         PibValue::MacShortAddress(ShortAddress(0x0001)),
+    ///         PibValue::MacRxOnWhenIdle(true),
+    ///     ])
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn initialize(&self, pib_values: &[PibValue]) -> Result<(), ErrorDetail> {
+        let (reset_confirm, error_detail) = self
+            .request_with_error_detail(ResetRequest {
+                set_default_pib: true,
+            })
+            .await;
+
+        if reset_confirm.status != Status::Success {
+            return Err(error_detail.unwrap_or_else(|| reset_confirm.status.into()));
+        }
+
+        for pib_value in pib_values {
+            let (set_confirm, error_detail) = self
+                .request_with_error_detail(SetRequest {
+                    pib_attribute: pib_value.name(),
+                    pib_attribute_value: pib_value.clone(),
+                })
+                .await;
+
+            if set_confirm.status != Status::Success {
+                return Err(error_detail.unwrap_or_else(|| set_confirm.status.into()));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write all given PIB values at once, or none of them if one is rejected.
+    ///
+    /// This is a convenience function for the [SetManyRequest], which explains how related attributes
+    /// are checked. It returns the status of the rejected value. Giving more than
+    /// [MAX_SET_MANY_VALUES](crate::sap::set_many::MAX_SET_MANY_VALUES) values is an INVALID_PARAMETER.
+    pub async fn set_many(&self, pib_values: &[PibValue]) -> Result<(), Status> {
+        let Ok(pib_attribute_values) = Vec::from_slice(pib_values) else {
+            return Err(Status::InvalidParameter);
+        };
+
+        match self
+            .request(SetManyRequest {
+                pib_attribute_values,
+            })
+            .await
+        {
+            SetManyConfirm {
+                status: Status::Success,
+                ..
+            } => Ok(()),
+            SetManyConfirm { status, .. } => Err(status),
+        }
+    }
+
+    /// Measure the energy on all channels the phy supports and return them as `(page, channel, energy)`.
+    ///
+    /// This is a convenience function for the [SpectrumSurveyRequest].
+    pub async fn survey_spectrum(
+        &self,
+    ) -> Result<Vec<(ChannelPage, u8, u8), MAX_SURVEY_CHANNELS>, Status> {
+        match self.request(SpectrumSurveyRequest).await {
+            SpectrumSurveyConfirm {
+                status: Status::Success,
+                energy_map,
+            } => Ok(energy_map),
+            SpectrumSurveyConfirm { status, .. } => Err(status),
+        }
+    }
+
+    /// Get what the MAC is doing at the moment, like scanning or being a PAN coordinator.
+    ///
+    /// This is a convenience function for the [OperationalStateRequest]. The state is a snapshot that
+    /// can already be outdated when it's returned, e.g. when a scan finishes right after.
+    pub async fn state(&self) -> MacOperationalState {
+        self.request(OperationalStateRequest).await.state
+    }
+
+    /// Measure the distance to the device at the address with single-sided two-way ranging.
+    ///
+    /// This is a convenience function for the [RangingRequest]. Both we and the other device must
+    /// have a phy that supports ranging, and the other device must have its receiver on.
+    pub async fn range_to(&self, address: Address) -> Result<Meters, Status> {
+        match self
+            .request(RangingRequest {
+                destination: address,
+            })
             .await
-            .into();
+        {
+            RangingConfirm {
+                status: Status::Success,
+                distance,
+                ..
+            } => Ok(distance),
+            RangingConfirm { status, .. } => Err(status),
+        }
+    }
+
+    /// Move the PAN we're coordinating to another channel.
+    ///
+    /// A coordinator realignment command is broadcast on the current channel first so the devices
+    /// in the PAN can follow, after which the coordinator switches over. This can e.g. be used to get away
+    /// from interference found with [Self::survey_spectrum].
+    ///
+    /// This is a convenience function for a [StartRequest] with `coord_realignment` set that keeps all other
+    /// settings of the running PAN. It must only be used by a PAN coordinator that has started a beacon-enabled PAN,
+    /// since the realignment command is sent along with the next beacon.
+    pub async fn change_channel(
+        &self,
+        channel_page: ChannelPage,
+        channel_number: u8,
+    ) -> Result<(), Status> {
+        let PibValue::MacPanId(pan_id) = self.get(PibValue::MAC_PAN_ID).await? else {
+            unreachable!()
+        };
+        let PibValue::MacBeaconOrder(beacon_order) = self.get(PibValue::MAC_BEACON_ORDER).await?
+        else {
+            unreachable!()
+        };
+        let PibValue::MacSuperframeOrder(superframe_order) =
+            self.get(PibValue::MAC_SUPERFRAME_ORDER).await?
+        else {
+            unreachable!()
+        };
+        let PibValue::MacBattLifeExt(battery_life_extension) =
+            self.get(PibValue::MAC_BATT_LIFE_EXT).await?
+        else {
+            unreachable!()
+        };
+
+        let start_confirm = self
+            .request(StartRequest {
+                pan_id,
+                channel_number,
+                channel_page,
+                start_time: 0,
+                beacon_order,
+                superframe_order,
+                pan_coordinator: true,
+                battery_life_extension,
+                coord_realignment: true,
+                coord_realign_security_info: SecurityInfo::new_none_security(),
+                beacon_security_info: SecurityInfo::new_none_security(),
+            })
+            .await;
+
+        match start_confirm.status {
+            Status::Success => Ok(()),
+            status => Err(status),
+        }
+    }
+
+    async fn get(&self, pib_attribute: &'static str) -> Result<PibValue, Status> {
+        match self.request(GetRequest { pib_attribute }).await {
+            GetConfirm {
+                status: Status::Success,
+                value,
+                ..
+            } => Ok(value),
+            GetConfirm { status, .. } => Err(status),
+        }
+    }
+
+    /// The number of indications the MAC dropped because the higher layer didn't take them out of the channel in time.
+    ///
+    /// Indications are only dropped when the [IndicationOverflowPolicy] allows it.
+    pub fn dropped_indications(&self) -> u32 {
+        self.dropped_indications.load(Ordering::Relaxed)
+    }
+
+    /// The number of received frames the MAC discarded because of their frame type.
+    ///
+    /// These are frames with a reserved frame type, which must be discarded (5.1.6.2),
+    /// and the frame types we don't support yet, like multipurpose frames.
+    pub fn discarded_frames(&self) -> u32 {
+        self.discarded_frames.load(Ordering::Relaxed)
+    }
+
+    /// The devices that are associated to us, when we're a coordinator.
+    ///
+    /// A device is added once it has received the successful association response and is removed
+    /// when it's disassociated. The MAC keeps track of at most [MAX_ASSOCIATED_DEVICES] devices.
+    pub fn associated_devices(&self) -> Vec<AssociatedDevice, MAX_ASSOCIATED_DEVICES> {
+        self.associated_devices
+            .lock(|associated_devices| associated_devices.borrow().clone())
+    }
 
-        Allocated::new(confirm)
+    /// The indirect transactions that wait for a device to pick them up with a data request, when we're a coordinator.
+    ///
+    /// This is a snapshot that's taken after the MAC has handled a request or event,
+    /// in the order the transactions were queued.
+    /// Use [PendingTransactionInfo::age] to see how long a transaction has been waiting.
+    pub fn pending_transactions(&self) -> impl Iterator<Item = PendingTransactionInfo> {
+        self.pending_transactions
+            .lock(|pending_transactions| pending_transactions.borrow().clone())
+            .into_iter()
+    }
+
+    /// Wait for the next frame the MAC has sent or received, see [frame_tap](super::frame_tap).
+    ///
+    /// Call this in a loop to get all frames. This API is cancel-safe.
+    #[cfg(feature = "frame-tap")]
+    pub async fn next_tapped_frame(&self) -> TappedFrame {
+        self.frame_tap.receive().await
+    }
+
+    /// Set the order in which the mac engine handles its branches when more than one is ready.
+    ///
+    /// Only meant for tests, see [test_hooks](super::test_hooks) for how to use it.
+    #[cfg(feature = "test-hooks")]
+    pub fn set_branch_order(&self, order: BranchOrder) {
+        self.branch_order
+            .lock(|branch_order| branch_order.set(order));
+    }
+
+    #[cfg(feature = "test-hooks")]
+    pub(crate) fn branch_order(&self) -> BranchOrder {
+        self.branch_order.lock(|branch_order| branch_order.get())
     }
 
     /// Wait until an indication is received. The indication must be responded to using the returned [IndicationResponder].
@@ -83,8 +437,14 @@ impl MacCommander {
     }
 
     /// Get the inverse of the commander where you can receive requests and send indications.
-    pub(crate) fn get_handler(&self) -> MacHandler<'_> {
-        MacHandler { commander: self }
+    pub(crate) fn get_handler(
+        &self,
+        indication_overflow_policy: IndicationOverflowPolicy,
+    ) -> MacHandler<'_> {
+        MacHandler {
+            commander: self,
+            indication_overflow_policy,
+        }
     }
 }
 
@@ -99,6 +459,7 @@ pub type IndicateIndirectFuture<'a> =
 
 pub(crate) struct MacHandler<'a> {
     commander: &'a MacCommander,
+    indication_overflow_policy: IndicationOverflowPolicy,
 }
 
 impl<'a> MacHandler<'a> {
@@ -111,6 +472,84 @@ impl<'a> MacHandler<'a> {
             .into()
     }
 
+    /// Send an indication that doesn't need a response, following the [IndicationOverflowPolicy].
+    pub async fn indicate_without_response<I: Indication<Response = ()>>(&self, indication: I) {
+        let channel = &self.commander.indication_response_channel;
+
+        let indication = match self.indication_overflow_policy {
+            IndicationOverflowPolicy::Block => return self.indicate(indication).await,
+            IndicationOverflowPolicy::DropNewest | IndicationOverflowPolicy::DropOldest => {
+                match channel.try_send_request(indication.into()) {
+                    Ok(()) => return,
+                    Err(indication) => indication,
+                }
+            }
+        };
+
+        if self.indication_overflow_policy == IndicationOverflowPolicy::DropOldest {
+            warn!("The indication channel is full, dropping the oldest indication");
+            channel.remove_oldest_request();
+
+            if channel.try_send_request(indication).is_err() {
+                // Can only happen when another indication was sent in the meantime
+                warn!("The indication channel is still full, dropping the new indication too");
+                self.commander
+                    .dropped_indications
+                    .fetch_add(1, Ordering::Relaxed);
+            }
+        } else {
+            warn!("The indication channel is full, dropping the new indication");
+        }
+
+        self.commander
+            .dropped_indications
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a received frame that was discarded, see [MacCommander::discarded_frames]
+    pub fn count_discarded_frame(&self) {
+        self.commander
+            .discarded_frames
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The tap the sent and received frames are copied to, see [MacCommander::next_tapped_frame]
+    #[cfg(feature = "frame-tap")]
+    pub fn frame_tap(&self) -> &'a FrameTap {
+        &self.commander.frame_tap
+    }
+
+    /// Make the devices of the device table of the mac available to [MacCommander::associated_devices]
+    pub fn publish_device_table(&self, devices: &[AssociatedDevice]) {
+        self.commander
+            .associated_devices
+            .lock(|associated_devices| {
+                let mut associated_devices = associated_devices.borrow_mut();
+                associated_devices.clear();
+                associated_devices
+                    .extend_from_slice(devices)
+                    .expect("The device table has the same capacity");
+            });
+    }
+
+    /// Make the pending data of the mac available to [MacCommander::pending_transactions]
+    pub fn publish_pending_transactions(
+        &self,
+        transactions: impl Iterator<Item = PendingTransactionInfo>,
+    ) {
+        self.commander
+            .pending_transactions
+            .lock(|pending_transactions| {
+                let mut pending_transactions = pending_transactions.borrow_mut();
+                pending_transactions.clear();
+                for transaction in transactions {
+                    pending_transactions
+                        .push(transaction)
+                        .expect("The pending data has the same capacity");
+                }
+            });
+    }
+
     /// Send an indication, but don't immediately wait on it.
     /// Instead the response wait is put in a buffer so it can be dealt with later.
     pub fn indicate_indirect<I: Indication>(&self, indication: I) -> IndicateIndirectFuture<'a> {
@@ -189,14 +628,32 @@ impl<T: DynamicRequest> RequestResponder<'_, T> {
     pub fn respond(self, response: T::Confirm) {
         self.commander
             .request_confirm_channel
-            .respond(self.id, response.into());
+            .respond(self.id, (response.into(), None));
+    }
+
+    /// Respond with the confirm created from the status of the error.
+    /// The detail of the error is sent along, see [MacCommander::request_with_error_detail].
+    pub fn respond_with_error<PE: Debug>(
+        self,
+        error: MacError<PE>,
+        response: impl FnOnce(Status) -> T::Confirm,
+    ) {
+        let detail = ErrorDetail::from(error);
+        let confirm = response(detail.status);
+
+        self.commander
+            .request_confirm_channel
+            .respond(self.id, (confirm.into(), Some(detail)));
     }
 }
 
-const INDIRECT_INDICATION_COLLECTION_SIZE: usize = 4;
+/// The maximum number of indirect indications (like association requests) the mac can have
+/// outstanding at the same time. See [MacConfig::max_indirect_indications](super::MacConfig::max_indirect_indications).
+pub const MAX_INDIRECT_INDICATIONS: usize = 16;
 
 pub struct IndirectIndicationCollection<'a> {
-    futures: [IndirectIndicationCollectionSlot<'a>; INDIRECT_INDICATION_COLLECTION_SIZE],
+    futures: [IndirectIndicationCollectionSlot<'a>; MAX_INDIRECT_INDICATIONS],
+    capacity: usize,
 }
 
 struct IndirectIndicationCollectionSlot<'a> {
@@ -261,14 +718,24 @@ impl<'a> IndirectIndicationCollectionSlot<'a> {
 }
 
 impl<'a> IndirectIndicationCollection<'a> {
-    pub fn new() -> Self {
+    /// Create a new collection that holds at most `capacity` indications.
+    /// The capacity is limited to [MAX_INDIRECT_INDICATIONS].
+    pub fn new(capacity: usize) -> Self {
+        if capacity > MAX_INDIRECT_INDICATIONS {
+            warn!(
+                "An indirect indication capacity of {} is not supported, using {} instead",
+                capacity, MAX_INDIRECT_INDICATIONS
+            );
+        }
+
         Self {
             futures: [const {
                 IndirectIndicationCollectionSlot {
                     future: None,
                     expire_time: Instant::from_ticks(0),
                 }
-            }; INDIRECT_INDICATION_COLLECTION_SIZE],
+            }; MAX_INDIRECT_INDICATIONS],
+            capacity: capacity.min(MAX_INDIRECT_INDICATIONS),
         }
     }
 
@@ -281,21 +748,21 @@ impl<'a> IndirectIndicationCollection<'a> {
     }
 
     /// Push an [IndicateIndirectFuture] onto the collection.
-    /// If the collection is full, the function panics.
+    /// If the collection is full, the future is dropped and [Status::TransactionOverflow] is returned.
     pub fn push(
         mut self: Pin<&mut Self>,
         future: IndicateIndirectFuture<'a>,
         expire_time: Instant,
-    ) {
-        for index in 0..INDIRECT_INDICATION_COLLECTION_SIZE {
+    ) -> Result<(), Status> {
+        for index in 0..self.capacity {
             let mut future_slot = self.as_mut().project_future(index);
             if future_slot.as_mut().is_empty() {
                 future_slot.fill(future, expire_time);
-                return;
+                return Ok(());
             }
         }
 
-        panic!("`push` called on IndirectIndicationCollection while it's at capacity");
+        Err(Status::TransactionOverflow)
     }
 
     /// Wait on an outstanding indication to be answered.
@@ -303,13 +770,13 @@ impl<'a> IndirectIndicationCollection<'a> {
     /// This function is cancel-safe.
     pub async fn wait(mut self: Pin<&mut Self>, current_time: Instant) -> ResponseValue {
         // Check for expiry. If this future is long lived it's not super accurate, but that should be fine
-        for index in 0..INDIRECT_INDICATION_COLLECTION_SIZE {
+        for index in 0..self.capacity {
             let future_slot = self.as_mut().project_future(index);
             future_slot.check_expired(current_time);
         }
 
         core::future::poll_fn(|cx| {
-            for index in 0..INDIRECT_INDICATION_COLLECTION_SIZE {
+            for index in 0..self.capacity {
                 let future_slot = self.as_mut().project_future(index);
                 match future_slot.poll(cx) {
                     Poll::Ready(response) => return Poll::Ready(response),
@@ -322,3 +789,193 @@ impl<'a> IndirectIndicationCollection<'a> {
         .await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::join::join;
+
+    use super::*;
+    use crate::{
+        sap::{
+            associate::{AssociateConfirm, AssociateRequest},
+            reset::{ResetConfirm, ResetRequest},
+            scan::{ScanConfirm, ScanRequest, ScanType},
+            set::{SetConfirm, SetRequest},
+            start::StartConfirm,
+        },
+        wire::{
+            Address, PanId, ShortAddress,
+            command::{AssociationStatus, CapabilityInformation},
+        },
+    };
+
+    /// Answer the next request like the mac engine would, with the confirm created by `confirm`.
+    /// Panics if the request doesn't arrive as an `R`.
+    async fn respond_as<R: DynamicRequest>(
+        handler: &MacHandler<'_>,
+        confirm: impl FnOnce(&mut R) -> R::Confirm,
+    ) {
+        let mut responder = handler.wait_for_request().await.into_concrete::<R>();
+        let confirm = confirm(&mut responder.request);
+        responder.respond(confirm);
+    }
+
+    /// Send the request through the commander and check that the request and its confirm
+    /// arrive unchanged on the other side. Mismatched variant wiring panics with a bad cast.
+    async fn assert_round_trip<R>(request: R, confirm: R::Confirm)
+    where
+        R: Request + Clone + PartialEq + Debug,
+        R::Confirm: Clone + PartialEq + Debug,
+    {
+        let commander = MacCommander::new();
+        let handler = commander.get_handler(IndicationOverflowPolicy::Block);
+
+        let (received_confirm, ()) = join(
+            commander.request(request.clone()),
+            respond_as(&handler, |received: &mut R| {
+                assert_eq!(*received, request);
+                confirm.clone()
+            }),
+        )
+        .await;
+
+        assert_eq!(received_confirm, confirm);
+    }
+
+    #[futures_test::test]
+    async fn reset_round_trips() {
+        assert_round_trip(
+            ResetRequest {
+                set_default_pib: true,
+            },
+            ResetConfirm {
+                status: Status::Success,
+            },
+        )
+        .await;
+    }
+
+    #[futures_test::test]
+    async fn get_round_trips() {
+        assert_round_trip(
+            GetRequest {
+                pib_attribute: PibValue::MAC_PAN_ID,
+            },
+            GetConfirm {
+                status: Status::Success,
+                pib_attribute: PibValue::MAC_PAN_ID,
+                value: PibValue::MacPanId(PanId(0x1234)),
+            },
+        )
+        .await;
+    }
+
+    #[futures_test::test]
+    async fn set_round_trips() {
+        assert_round_trip(
+            SetRequest {
+                pib_attribute: PibValue::MAC_PAN_ID,
+                pib_attribute_value: PibValue::MacPanId(PanId(0x1234)),
+            },
+            SetConfirm {
+                status: Status::ReadOnly,
+                pib_attribute: PibValue::MAC_PAN_ID,
+            },
+        )
+        .await;
+    }
+
+    #[futures_test::test]
+    async fn start_round_trips() {
+        assert_round_trip(
+            StartRequest {
+                pan_id: PanId(0x1234),
+                channel_number: 5,
+                channel_page: ChannelPage::Uwb,
+                start_time: 0,
+                beacon_order: 6.into(),
+                superframe_order: 4.into(),
+                pan_coordinator: true,
+                battery_life_extension: false,
+                coord_realignment: false,
+                coord_realign_security_info: SecurityInfo::new_none_security(),
+                beacon_security_info: SecurityInfo::new_none_security(),
+            },
+            StartConfirm {
+                status: Status::NoShortAddress,
+            },
+        )
+        .await;
+    }
+
+    #[futures_test::test]
+    async fn associate_round_trips() {
+        assert_round_trip(
+            AssociateRequest {
+                channel_number: 5,
+                channel_page: ChannelPage::Uwb,
+                coord_address: Address::Short(PanId(0x1234), ShortAddress(0)),
+                capability_information: CapabilityInformation {
+                    full_function_device: false,
+                    mains_power: false,
+                    idle_receive: true,
+                    frame_protection: false,
+                    allocate_address: true,
+                },
+                security_info: SecurityInfo::new_none_security(),
+            },
+            AssociateConfirm {
+                assoc_short_address: ShortAddress(7),
+                status: Ok(AssociationStatus::Successful),
+                security_info: SecurityInfo::new_none_security(),
+            },
+        )
+        .await;
+    }
+
+    #[futures_test::test]
+    async fn scan_round_trips() {
+        let commander = MacCommander::new();
+        let handler = commander.get_handler(IndicationOverflowPolicy::Block);
+        let mut pan_descriptor_list = [const { None }; 4];
+
+        let (confirm, ()) = join(
+            commander.request_with_allocation(
+                ScanRequest {
+                    scan_type: ScanType::Passive,
+                    scan_channels: [1, 2].into_iter().collect(),
+                    pan_descriptor_list: Allocation::new(),
+                    scan_duration: 3,
+                    channel_page: ChannelPage::Uwb,
+                    scan_channel_pages: Vec::new(),
+                    security_info: SecurityInfo::new_none_security(),
+                    include_source_address: false,
+                },
+                &mut pan_descriptor_list,
+            ),
+            respond_as(&handler, |request: &mut ScanRequest| {
+                assert_eq!(request.scan_type, ScanType::Passive);
+                assert_eq!(request.scan_channels, [1, 2]);
+                assert_eq!(request.scan_duration, 3);
+                assert_eq!(request.pan_descriptor_list.as_slice().len(), 4);
+
+                ScanConfirm {
+                    status: Status::NoBeacon,
+                    scan_type: request.scan_type,
+                    channel_page: request.channel_page,
+                    unscanned_channels: [2].into_iter().collect(),
+                    pan_descriptor_list_allocation: core::mem::take(
+                        &mut request.pan_descriptor_list,
+                    ),
+                    ..Default::default()
+                }
+            }),
+        )
+        .await;
+
+        assert_eq!(confirm.status, Status::NoBeacon);
+        assert_eq!(confirm.scan_type, ScanType::Passive);
+        assert_eq!(confirm.unscanned_channels, [2]);
+        assert_eq!(confirm.pan_descriptor_list().count(), 0);
+    }
+}
@@ -0,0 +1,88 @@
+use super::{MacError, commander::MacHandler};
+use crate::{
+    ChannelPage, DeviceAddress,
+    phy::Phy,
+    pib::MacPib,
+    sap::{
+        SecurityInfo,
+        sync::{LossReason, SyncLossIndication},
+    },
+    wire::{Address, ShortAddress, command::CoordinatorRealignmentData},
+};
+
+/// Check if a received coordinator realignment command is meant for us (5.1.2.3.3).
+///
+/// It must come from the coordinator we're associated with and be either broadcast or addressed to us.
+pub fn is_for_us(
+    mac_pib: &MacPib,
+    source: Address,
+    realignment: &CoordinatorRealignmentData,
+) -> bool {
+    if source.pan_id() != mac_pib.pan_id {
+        return false;
+    }
+
+    let from_coordinator = match DeviceAddress::from(source) {
+        DeviceAddress::Short(short_address) => short_address == mac_pib.coord_short_address,
+        DeviceAddress::Extended(extended_address) => {
+            extended_address == mac_pib.coord_extended_address
+        }
+    };
+
+    from_coordinator
+        && (realignment.device_address == ShortAddress::BROADCAST
+            || realignment.device_address == mac_pib.short_address)
+}
+
+/// Follow the coordinator to its new PAN settings and let the higher layer know
+pub async fn process_received_coordinator_realignment<P: Phy>(
+    phy: &mut P,
+    mac_pib: &mut MacPib,
+    mac_handler: &MacHandler<'_>,
+    realignment: CoordinatorRealignmentData,
+    security_info: SecurityInfo,
+) {
+    let result: Result<ChannelPage, MacError<P::Error>> = async {
+        let channel_page = match realignment.channel_page {
+            Some(channel_page) => {
+                ChannelPage::try_from(channel_page).map_err(MacError::UnknownChannelPage)?
+            }
+            None => phy.get_phy_pib().current_page,
+        };
+
+        phy.update_phy_pib(|phy_pib| {
+            phy_pib.current_page = channel_page;
+            phy_pib.current_channel = realignment.channel;
+        })
+        .await?;
+
+        Ok(channel_page)
+    }
+    .await;
+
+    let channel_page = match result {
+        Ok(channel_page) => channel_page,
+        Err(e) => {
+            error!("Could not follow the coordinator realignment: {}", e);
+            return;
+        }
+    };
+
+    mac_pib.pan_id = realignment.pan_id;
+    mac_pib.coord_short_address = realignment.coordinator_address;
+
+    debug!(
+        "Realigned to channel {} of page {:?}",
+        realignment.channel, channel_page
+    );
+
+    mac_handler
+        .indicate_without_response(SyncLossIndication {
+            loss_reason: LossReason::Realignment,
+            pan_id: realignment.pan_id,
+            channel_number: realignment.channel,
+            channel_page: channel_page as u8,
+            security_info,
+        })
+        .await;
+}
@@ -0,0 +1,197 @@
+//! Helpers for the CSMA-CA algorithm as described in 5.1.1.4.
+//!
+//! All randomness used by the algorithm must be taken from the rng in the [MacConfig](super::MacConfig)
+//! so that the behavior of the MAC is reproducible when the rng is seeded deterministically.
+
+use rand_core::RngCore;
+
+use super::state::{BeaconMode, MacState};
+use crate::{
+    consts::UNIT_BACKOFF_PERIOD,
+    pib::MacPib,
+    time::{DelayNsExt, Duration},
+};
+
+/// One run of the unslotted CSMA-CA algorithm for a single transmission.
+///
+/// The phy does the CCA when it's asked to send with `use_csma`. Before every try, [Self::backoff]
+/// waits the random number of backoff periods. When the phy found the channel busy, [Self::channel_busy]
+/// tells whether to try again.
+pub struct CsmaCa {
+    battery_life_extension: bool,
+    number_of_backoffs: u8,
+}
+
+impl CsmaCa {
+    pub fn new(mac_state: &MacState, mac_pib: &MacPib) -> Self {
+        Self {
+            battery_life_extension: battery_life_extension(mac_state, mac_pib),
+            number_of_backoffs: 0,
+        }
+    }
+
+    /// Wait a random number of unit backoff periods before the next CCA
+    pub async fn backoff(
+        &self,
+        mac_pib: &MacPib,
+        symbol_period: Duration,
+        rng: &mut impl RngCore,
+        delay: &mut impl DelayNsExt,
+    ) {
+        let backoff_exponent = backoff_exponent(
+            mac_pib,
+            self.battery_life_extension,
+            self.number_of_backoffs,
+        );
+        let backoff_periods = random_backoff_periods(rng, backoff_exponent);
+
+        if backoff_periods > 0 {
+            delay
+                .delay_duration(symbol_period * (backoff_periods * UNIT_BACKOFF_PERIOD) as i64)
+                .await;
+        }
+    }
+
+    /// Register that the CCA found the channel busy.
+    ///
+    /// Returns false when macMaxCSMABackoffs is exceeded, which means the channel access failed.
+    pub fn channel_busy(&mut self, mac_pib: &MacPib) -> bool {
+        self.number_of_backoffs = self.number_of_backoffs.saturating_add(1);
+        self.number_of_backoffs <= mac_pib.max_csma_backoffs
+    }
+}
+
+/// Is battery life extension (BLE) in effect for the CSMA-CA?
+///
+/// A coordinator that sends beacons uses its own macBattLifeExt.
+/// Other devices follow the superframe specification of the beacon they are tracking.
+pub fn battery_life_extension(mac_state: &MacState, mac_pib: &MacPib) -> bool {
+    match (mac_state.beacon_mode, mac_state.tracked_superframe) {
+        (BeaconMode::OnAutonomous | BeaconMode::OnTracking { .. }, _) => mac_pib.batt_life_ext,
+        (BeaconMode::Off, Some(tracked_superframe)) => tracked_superframe.battery_life_extension,
+        (BeaconMode::Off, None) => false,
+    }
+}
+
+/// Get the backoff exponent (BE) to use after the given number of backoffs (NB).
+///
+/// With battery life extension, the BE starts at the lesser of 2 and macMinBE.
+pub fn backoff_exponent(
+    mac_pib: &MacPib,
+    battery_life_extension: bool,
+    number_of_backoffs: u8,
+) -> u8 {
+    let initial_be = if battery_life_extension {
+        mac_pib.min_be.min(2)
+    } else {
+        mac_pib.min_be
+    };
+
+    initial_be
+        .saturating_add(number_of_backoffs)
+        .min(mac_pib.max_be)
+}
+
+/// Pick the random number of unit backoff periods to delay, in the range `0..=2^BE - 1`
+pub fn random_backoff_periods(rng: &mut impl RngCore, backoff_exponent: u8) -> u32 {
+    let max_periods = (1u32 << backoff_exponent.min(31)) - 1;
+    rng.next_u32() & max_periods
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{SeedableRng, rngs::StdRng};
+
+    use super::*;
+
+    fn backoff_sequence(seed: u64) -> [u32; 32] {
+        let mac_pib = MacPib::dummy_new();
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        core::array::from_fn(|i| {
+            let be = backoff_exponent(&mac_pib, false, (i % 4) as u8);
+            random_backoff_periods(&mut rng, be)
+        })
+    }
+
+    #[test]
+    fn same_seed_gives_same_backoffs() {
+        assert_eq!(backoff_sequence(1234), backoff_sequence(1234));
+        assert_eq!(backoff_sequence(0), backoff_sequence(0));
+    }
+
+    #[test]
+    fn backoff_periods_in_range() {
+        let mut rng = StdRng::seed_from_u64(0);
+
+        for be in 0..=8 {
+            for _ in 0..100 {
+                assert!(random_backoff_periods(&mut rng, be) < (1 << be));
+            }
+        }
+    }
+
+    #[test]
+    fn backoff_exponent_is_clamped() {
+        let mac_pib = MacPib {
+            pib_write: crate::pib::MacPibWrite {
+                min_be: 3,
+                max_be: 5,
+                batt_life_ext: false,
+                ..MacPib::dummy_new().pib_write
+            },
+            ..MacPib::dummy_new()
+        };
+
+        assert_eq!(backoff_exponent(&mac_pib, false, 0), 3);
+        assert_eq!(backoff_exponent(&mac_pib, false, 1), 4);
+        assert_eq!(backoff_exponent(&mac_pib, false, 2), 5);
+        assert_eq!(backoff_exponent(&mac_pib, false, 10), 5);
+    }
+
+    #[test]
+    fn channel_access_fails_after_max_csma_backoffs() {
+        let mac_pib = MacPib {
+            pib_write: crate::pib::MacPibWrite {
+                max_csma_backoffs: 2,
+                ..MacPib::dummy_new().pib_write
+            },
+            ..MacPib::dummy_new()
+        };
+        let mut csma = CsmaCa {
+            battery_life_extension: false,
+            number_of_backoffs: 0,
+        };
+
+        // The first try and two backoffs
+        assert!(csma.channel_busy(&mac_pib));
+        assert!(csma.channel_busy(&mac_pib));
+        assert!(!csma.channel_busy(&mac_pib));
+    }
+
+    #[test]
+    fn battery_life_extension_shortens_the_backoff_exponent() {
+        let mac_pib = MacPib {
+            pib_write: crate::pib::MacPibWrite {
+                min_be: 4,
+                max_be: 5,
+                ..MacPib::dummy_new().pib_write
+            },
+            ..MacPib::dummy_new()
+        };
+
+        assert_eq!(backoff_exponent(&mac_pib, true, 0), 2);
+        assert_eq!(backoff_exponent(&mac_pib, true, 1), 3);
+        assert_eq!(backoff_exponent(&mac_pib, true, 10), 5);
+
+        // A macMinBE that's already lower stays in effect
+        let mac_pib = MacPib {
+            pib_write: crate::pib::MacPibWrite {
+                min_be: 1,
+                ..mac_pib.pib_write
+            },
+            ..mac_pib
+        };
+        assert_eq!(backoff_exponent(&mac_pib, true, 0), 1);
+    }
+}
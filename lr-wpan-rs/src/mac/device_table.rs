@@ -0,0 +1,208 @@
+use heapless::Vec;
+
+use crate::{
+    DeviceAddress,
+    sap::Status,
+    time::Instant,
+    wire::{ExtendedAddress, ShortAddress},
+};
+
+/// The maximum number of devices a coordinator keeps track of in its [DeviceTable]
+pub const MAX_ASSOCIATED_DEVICES: usize = 32;
+
+/// A device that's associated to us as its coordinator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct AssociatedDevice {
+    /// The extended address of the device
+    pub extended_address: ExtendedAddress,
+    /// The short address that was allocated to the device.
+    /// This is 0xfffe if the device only uses its extended address.
+    pub short_address: ShortAddress,
+    /// Whether the device keeps its receiver on when idle, as it reported in its association request.
+    ///
+    /// Frames can be sent directly to a device that does, while the others have to poll for them (indirect transmission).
+    pub rx_on_when_idle: bool,
+    /// The last time a frame was received from the device
+    pub last_seen: Instant,
+}
+
+impl AssociatedDevice {
+    /// Is the address one of the addresses of this device?
+    pub fn has_address(&self, address: DeviceAddress) -> bool {
+        match address {
+            DeviceAddress::Short(short_address) => {
+                is_allocated(short_address) && short_address == self.short_address
+            }
+            DeviceAddress::Extended(extended_address) => extended_address == self.extended_address,
+        }
+    }
+}
+
+/// Returns false for the addresses that mean there's no short address (0xfffe and 0xffff)
+pub(super) fn is_allocated(short_address: ShortAddress) -> bool {
+    short_address.0 < 0xfffe
+}
+
+/// The devices that are associated to us, maintained when we're a coordinator.
+///
+/// It maps the extended addresses of the devices to the short addresses they were allocated.
+pub struct DeviceTable {
+    devices: Vec<AssociatedDevice, MAX_ASSOCIATED_DEVICES>,
+    changed: bool,
+}
+
+impl DeviceTable {
+    pub fn new() -> Self {
+        Self {
+            devices: Vec::new(),
+            // A new table replaces any old one, which must be known outside the mac too
+            changed: true,
+        }
+    }
+
+    /// Add a device that just associated.
+    ///
+    /// Any device with the same extended or short address is replaced, since that information is outdated.
+    pub fn insert(&mut self, device: AssociatedDevice) -> Result<(), Status> {
+        self.devices.retain(|existing| {
+            existing.extended_address != device.extended_address
+                && !existing.has_address(DeviceAddress::Short(device.short_address))
+        });
+        self.changed = true;
+
+        self.devices
+            .push(device)
+            .map_err(|_| Status::TransactionOverflow)
+    }
+
+    /// Remove the device with the address, returning it if it was in the table
+    pub fn remove(&mut self, address: DeviceAddress) -> Option<AssociatedDevice> {
+        let position = self
+            .devices
+            .iter()
+            .position(|device| device.has_address(address))?;
+        self.changed = true;
+
+        Some(self.devices.swap_remove(position))
+    }
+
+    /// Get the device with the address
+    pub fn get(&self, address: DeviceAddress) -> Option<&AssociatedDevice> {
+        self.devices
+            .iter()
+            .find(|device| device.has_address(address))
+    }
+
+    /// Register that a frame was received from the address at the given time.
+    /// Addresses that aren't in the table are ignored.
+    pub fn mark_seen(&mut self, address: DeviceAddress, time: Instant) {
+        if let Some(device) = self
+            .devices
+            .iter_mut()
+            .find(|device| device.has_address(address))
+        {
+            device.last_seen = time;
+            self.changed = true;
+        }
+    }
+
+    /// Do the two addresses belong to the same device?
+    ///
+    /// Addresses of devices we don't know about are only the same if they're equal.
+    pub fn is_same_device(&self, a: DeviceAddress, b: DeviceAddress) -> bool {
+        a == b || self.get(a).is_some_and(|device| device.has_address(b))
+    }
+
+    pub fn devices(&self) -> &[AssociatedDevice] {
+        &self.devices
+    }
+
+    /// Returns true if the table has changed since the last call
+    pub fn take_changed(&mut self) -> bool {
+        core::mem::take(&mut self.changed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(extended_address: u64, short_address: u16) -> AssociatedDevice {
+        AssociatedDevice {
+            extended_address: ExtendedAddress(extended_address),
+            short_address: ShortAddress(short_address),
+            rx_on_when_idle: false,
+            last_seen: Instant::from_ticks(0),
+        }
+    }
+
+    #[test]
+    fn devices_are_found_by_both_addresses() {
+        let mut table = DeviceTable::new();
+        table.insert(device(1, 10)).unwrap();
+        table.insert(device(2, 0xfffe)).unwrap();
+
+        let short = DeviceAddress::Short(ShortAddress(10));
+        let extended = DeviceAddress::Extended(ExtendedAddress(1));
+
+        assert_eq!(table.get(short), Some(&device(1, 10)));
+        assert_eq!(table.get(extended), Some(&device(1, 10)));
+        assert!(table.is_same_device(short, extended));
+        assert!(!table.is_same_device(short, DeviceAddress::Extended(ExtendedAddress(2))));
+
+        // A device without a short address can't be found by 0xfffe
+        assert_eq!(table.get(DeviceAddress::Short(ShortAddress(0xfffe))), None);
+    }
+
+    #[test]
+    fn reassociation_replaces_outdated_devices() {
+        let mut table = DeviceTable::new();
+        table.insert(device(1, 10)).unwrap();
+        table.insert(device(2, 11)).unwrap();
+
+        // Device 1 gets a new short address and device 3 takes over the one of device 2
+        table.insert(device(1, 12)).unwrap();
+        table.insert(device(3, 11)).unwrap();
+
+        let mut devices = table.devices().to_vec();
+        devices.sort_by_key(|device| device.extended_address.0);
+        assert_eq!(devices, [device(1, 12), device(3, 11)]);
+    }
+
+    #[test]
+    fn changes_are_tracked() {
+        let mut table = DeviceTable::new();
+        assert!(table.take_changed());
+        assert!(!table.take_changed());
+
+        table.mark_seen(
+            DeviceAddress::Extended(ExtendedAddress(1)),
+            Instant::from_ticks(5),
+        );
+        assert!(!table.take_changed());
+
+        table.insert(device(1, 10)).unwrap();
+        assert!(table.take_changed());
+
+        table.mark_seen(
+            DeviceAddress::Short(ShortAddress(10)),
+            Instant::from_ticks(5),
+        );
+        assert!(table.take_changed());
+        assert_eq!(table.devices()[0].last_seen, Instant::from_ticks(5));
+
+        assert!(
+            table
+                .remove(DeviceAddress::Short(ShortAddress(10)))
+                .is_some()
+        );
+        assert!(table.take_changed());
+        assert!(
+            table
+                .remove(DeviceAddress::Short(ShortAddress(10)))
+                .is_none()
+        );
+        assert!(!table.take_changed());
+    }
+}
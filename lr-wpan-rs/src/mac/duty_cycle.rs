@@ -0,0 +1,150 @@
+//! Limiting the share of time the mac spends transmitting, as required in some regulatory regimes
+//! (like for parts of the 868 MHz band).
+
+use arraydeque::ArrayDeque;
+
+use crate::time::{Duration, Instant};
+
+/// The number of transmissions that are tracked individually.
+/// When more are done within the window, the oldest ones are merged, which only makes the limit stricter.
+const MAX_TRACKED_TRANSMISSIONS: usize = 32;
+
+/// The maximum share of time the mac may spend transmitting within a sliding window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct DutyCycleLimit {
+    /// The length of the sliding window, e.g. an hour
+    pub window: Duration,
+    /// The maximum share of the window spent transmitting, in parts per thousand.
+    /// E.g. 10 is a duty cycle of 1%.
+    pub max_permille: u16,
+}
+
+impl DutyCycleLimit {
+    /// The total transmit time allowed within a window
+    fn budget(&self) -> Duration {
+        self.window * self.max_permille as i64 / 1000
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Transmission {
+    start: Instant,
+    duration: Duration,
+}
+
+impl Transmission {
+    fn end(&self) -> Instant {
+        self.start + self.duration
+    }
+}
+
+/// Keeps track of the transmit time within the sliding window of a [DutyCycleLimit]
+pub struct DutyCycleGovernor {
+    limit: Option<DutyCycleLimit>,
+    transmissions: ArrayDeque<Transmission, MAX_TRACKED_TRANSMISSIONS>,
+}
+
+impl DutyCycleGovernor {
+    /// Create a governor for the limit. Without a limit everything is allowed.
+    pub fn new(limit: Option<DutyCycleLimit>) -> Self {
+        Self {
+            limit,
+            transmissions: ArrayDeque::new(),
+        }
+    }
+
+    /// Can a transmission of the given duration be started now without going over the limit?
+    pub fn allows(&mut self, now: Instant, duration: Duration) -> bool {
+        let Some(limit) = self.limit else {
+            return true;
+        };
+
+        // Forget the transmissions that have slid out of the window
+        if let Some(window_start) = now.checked_sub_duration(limit.window) {
+            while self
+                .transmissions
+                .front()
+                .is_some_and(|transmission| transmission.end() <= window_start)
+            {
+                self.transmissions.pop_front();
+            }
+        }
+
+        let used = self
+            .transmissions
+            .iter()
+            .fold(Duration::from_ticks(0), |used, transmission| {
+                used + transmission.duration
+            });
+
+        used + duration <= limit.budget()
+    }
+
+    /// Register a transmission that was done
+    pub fn register(&mut self, start: Instant, duration: Duration) {
+        if self.limit.is_none() {
+            return;
+        }
+
+        if self.transmissions.is_full() {
+            // Merge the oldest transmission into the one after it.
+            // That one ends later, so the merged time stays in the window for longer than it should.
+            let oldest = self.transmissions.pop_front().unwrap();
+            self.transmissions.front_mut().unwrap().duration += oldest.duration;
+        }
+
+        self.transmissions
+            .push_back(Transmission { start, duration })
+            .unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMIT: DutyCycleLimit = DutyCycleLimit {
+        window: Duration::from_seconds(100),
+        max_permille: 10,
+    };
+
+    #[test]
+    fn no_limit_allows_everything() {
+        let mut governor = DutyCycleGovernor::new(None);
+        governor.register(Instant::from_seconds(0), Duration::from_seconds(1000));
+        assert!(governor.allows(Instant::from_seconds(1), Duration::from_seconds(1000)));
+    }
+
+    #[test]
+    fn transmissions_are_blocked_until_the_window_slides() {
+        let mut governor = DutyCycleGovernor::new(Some(LIMIT));
+
+        // The budget is 1 second per 100 seconds
+        assert!(governor.allows(Instant::from_seconds(10), Duration::from_millis(600)));
+        governor.register(Instant::from_seconds(10), Duration::from_millis(600));
+        assert!(governor.allows(Instant::from_seconds(20), Duration::from_millis(400)));
+        assert!(!governor.allows(Instant::from_seconds(20), Duration::from_millis(401)));
+        governor.register(Instant::from_seconds(20), Duration::from_millis(400));
+        assert!(!governor.allows(Instant::from_seconds(30), Duration::from_millis(1)));
+
+        // The first transmission has left the window
+        assert!(!governor.allows(Instant::from_seconds(110), Duration::from_millis(1)));
+        assert!(governor.allows(Instant::from_seconds(111), Duration::from_millis(600)));
+        assert!(!governor.allows(Instant::from_seconds(111), Duration::from_millis(601)));
+    }
+
+    #[test]
+    fn merged_transmissions_still_count() {
+        let mut governor = DutyCycleGovernor::new(Some(LIMIT));
+
+        for i in 0..MAX_TRACKED_TRANSMISSIONS as u64 * 2 {
+            governor.register(Instant::from_seconds(i), Duration::from_millis(10));
+        }
+
+        // 640 milliseconds are used, so 360 are left
+        let now = Instant::from_seconds(MAX_TRACKED_TRANSMISSIONS as u64 * 2);
+        assert!(governor.allows(now, Duration::from_millis(360)));
+        assert!(!governor.allows(now, Duration::from_millis(361)));
+    }
+}
@@ -0,0 +1,45 @@
+//! Hex dumps of frames that can't be serialized or deserialized.
+//!
+//! Only available with the `frame-dump` feature. The dumps are logged as a plain hex string,
+//! so they can be pasted into a decoder (like Wireshark's) when debugging interop with other stacks.
+
+/// Formats the bytes as a hex string without separators, e.g. `4188a1`
+pub struct HexDump<'a>(pub &'a [u8]);
+
+impl core::fmt::Display for HexDump<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for byte in self.0 {
+            write!(f, "{byte:02x}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "defmt-03")]
+impl defmt::Format for HexDump<'_> {
+    fn format(&self, f: defmt::Formatter) {
+        for byte in self.0 {
+            defmt::write!(f, "{=u8:02x}", byte);
+        }
+    }
+}
+
+/// Log the raw bytes of a frame that failed to be serialized or deserialized
+pub fn dump_frame(description: &str, data: &[u8]) {
+    warn!("{} ({} bytes): {}", description, data.len(), HexDump(data));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_are_formatted_as_hex() {
+        assert_eq!(
+            HexDump(&[0x41, 0x88, 0x0a, 0xff, 0x00]).to_string(),
+            "41880aff00"
+        );
+        assert_eq!(HexDump(&[]).to_string(), "");
+    }
+}
@@ -0,0 +1,72 @@
+//! A tap on the raw frames the MAC sends and receives.
+//!
+//! Only available with the `frame-tap` feature. Every frame the MAC gives to the phy and every frame
+//! the phy gives to the MAC is copied into a channel that can be read with
+//! [MacCommander::next_tapped_frame](super::MacCommander::next_tapped_frame).
+//! This works like the pcap trace of the test aether, but on the target itself, e.g. for a gateway
+//! that bridges the frames to IP.
+//!
+//! The MAC never waits on the tap. When the channel is full, new frames are dropped.
+
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel};
+use heapless::Vec;
+
+use crate::{consts::MAX_PHY_PACKET_SIZE, time::Instant};
+
+/// The amount of frames the tap can hold before new frames are dropped
+pub const FRAME_TAP_SIZE: usize = 8;
+
+/// Whether a [TappedFrame] was sent or received
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum FrameDirection {
+    Sent,
+    Received,
+}
+
+/// A copy of a frame that was sent or received by the MAC
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct TappedFrame {
+    pub direction: FrameDirection,
+    /// The time the frame was sent or received, as reported by the phy
+    pub timestamp: Instant,
+    /// The frame as it went to or came from the phy.
+    ///
+    /// Whether this includes the FCS depends on the phy and the `mac_fcs` setting of the
+    /// [MacConfig](super::MacConfig). Received frames that failed the CRC check of the phy are also tapped.
+    pub data: Vec<u8, MAX_PHY_PACKET_SIZE>,
+}
+
+pub(crate) struct FrameTap {
+    channel: Channel<CriticalSectionRawMutex, TappedFrame, FRAME_TAP_SIZE>,
+}
+
+impl FrameTap {
+    pub const fn new() -> Self {
+        Self {
+            channel: Channel::new(),
+        }
+    }
+
+    /// Put a copy of the frame in the tap, unless it's full
+    pub fn tap(&self, direction: FrameDirection, timestamp: Instant, data: &[u8]) {
+        let Ok(data) = Vec::from_slice(data) else {
+            warn!("Not tapping a frame that's too long");
+            return;
+        };
+
+        let result = self.channel.try_send(TappedFrame {
+            direction,
+            timestamp,
+            data,
+        });
+        if result.is_err() {
+            trace!("The frame tap is full, dropping the frame");
+        }
+    }
+
+    pub async fn receive(&self) -> TappedFrame {
+        self.channel.receive().await
+    }
+}
@@ -0,0 +1,199 @@
+//! Allocation of guaranteed time slots (GTSs) by the PAN coordinator as described in 5.1.7.2.
+//!
+//! The GTSs are placed at the end of the superframe, shrinking the contention access period (CAP).
+//! The CAP must always remain at least [MIN_CAP_LENGTH] symbols long.
+
+use core::pin::Pin;
+
+use super::{
+    commander::{IndirectIndicationCollection, MacHandler},
+    state::MacState,
+};
+use crate::{
+    consts::{BASE_SLOT_DURATION, MIN_CAP_LENGTH, NUM_SUPERFRAME_SLOTS},
+    pib::MacPib,
+    sap::{SecurityInfo, Status, gts::GtsIndication},
+    time::{Duration, Instant},
+    wire::{
+        ShortAddress,
+        beacon::{
+            Direction, GuaranteedTimeSlotDescriptor, GuaranteedTimeSlotInformation, SuperframeOrder,
+        },
+        command::GuaranteedTimeSlotCharacteristics,
+    },
+};
+
+/// Allocate a new GTS for the device at the end of the CFP.
+///
+/// Returns [Status::InvalidGts] when the CAP would become shorter than [MIN_CAP_LENGTH]
+/// or when there's no active superframe to put the GTS in.
+pub fn allocate_gts(
+    gts: &mut GuaranteedTimeSlotInformation,
+    device_address: ShortAddress,
+    characteristics: GuaranteedTimeSlotCharacteristics,
+    superframe_order: SuperframeOrder,
+) -> Result<GuaranteedTimeSlotDescriptor, Status> {
+    if !characteristics.allocation || characteristics.count == 0 {
+        return Err(Status::InvalidParameter);
+    }
+
+    let SuperframeOrder::SuperframeOrder(superframe_order) = superframe_order else {
+        return Err(Status::InvalidGts);
+    };
+
+    let allocated_slots = gts
+        .slots()
+        .iter()
+        .map(|slot| slot.length as u32)
+        .sum::<u32>();
+    let cap_slots = NUM_SUPERFRAME_SLOTS
+        .checked_sub(allocated_slots + characteristics.count as u32)
+        .ok_or(Status::InvalidGts)?;
+
+    let slot_duration = BASE_SLOT_DURATION << superframe_order;
+    if cap_slots * slot_duration < MIN_CAP_LENGTH {
+        return Err(Status::InvalidGts);
+    }
+
+    let descriptor = GuaranteedTimeSlotDescriptor {
+        short_address: device_address,
+        starting_slot: cap_slots as u8,
+        length: characteristics.count,
+        direction: if characteristics.receive_only {
+            Direction::Receive
+        } else {
+            Direction::Transmit
+        },
+    };
+
+    // There's only room for 7 descriptors in the beacon
+    gts.slots.push(descriptor).map_err(|_| Status::Denied)?;
+
+    Ok(descriptor)
+}
+
+/// Handle a GTS request command of one of our devices.
+///
+/// Only the PAN coordinator hands out GTSs. A new GTS ends up in the next beacons
+/// and the higher layer is told about it with an MLME-GTS.indication.
+#[allow(clippy::too_many_arguments)]
+pub fn process_received_gts_request<'a>(
+    mac_handler: &MacHandler<'a>,
+    mac_pib: &MacPib,
+    mac_state: &mut MacState<'_>,
+    indirect_indications: Pin<&mut IndirectIndicationCollection<'a>>,
+    device_address: ShortAddress,
+    characteristics: GuaranteedTimeSlotCharacteristics,
+    security_info: SecurityInfo,
+    message_timestamp: Instant,
+    symbol_period: Duration,
+) {
+    if !mac_state.is_pan_coordinator {
+        warn!("Ignoring a GTS request, we're not the PAN coordinator");
+        return;
+    }
+
+    if let Err(status) = allocate_gts(
+        &mut mac_state.current_gts,
+        device_address,
+        characteristics,
+        mac_pib.superframe_order,
+    ) {
+        warn!(
+            "Could not allocate a GTS for {:?}: {}",
+            device_address, status
+        );
+        return;
+    }
+
+    // The indication is sent indirectly so we're not holding up the ack
+    let indirect_response = mac_handler.indicate_indirect(GtsIndication {
+        device_address,
+        gts_characteristics: characteristics,
+        security_info,
+    });
+
+    if let Err(status) = indirect_indications.push(
+        indirect_response,
+        message_timestamp + symbol_period * mac_pib.response_wait_duration() as i64,
+    ) {
+        warn!(
+            "Could not indicate the GTS of {:?}, too many indications are waiting on a response: {}",
+            device_address, status
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(count: u8) -> GuaranteedTimeSlotCharacteristics {
+        GuaranteedTimeSlotCharacteristics {
+            count,
+            receive_only: false,
+            allocation: true,
+        }
+    }
+
+    #[test]
+    fn cap_minimum_is_enforced() {
+        let mut gts = GuaranteedTimeSlotInformation::new();
+        let superframe_order = SuperframeOrder::SuperframeOrder(0);
+
+        // With superframe order 0, a slot is 60 symbols long, so 8 slots must be left for the CAP
+        let first = allocate_gts(&mut gts, ShortAddress(1), request(5), superframe_order).unwrap();
+        assert_eq!(first.starting_slot, 11);
+        assert_eq!(first.length, 5);
+
+        let second = allocate_gts(&mut gts, ShortAddress(2), request(3), superframe_order).unwrap();
+        assert_eq!(second.starting_slot, 8);
+        assert_eq!(second.length, 3);
+
+        assert_eq!(
+            allocate_gts(&mut gts, ShortAddress(3), request(1), superframe_order),
+            Err(Status::InvalidGts)
+        );
+        assert_eq!(gts.slots(), &[first, second]);
+    }
+
+    #[test]
+    fn longer_slots_leave_more_room() {
+        let mut gts = GuaranteedTimeSlotInformation::new();
+
+        // With superframe order 3, a single slot of 480 symbols is enough for the CAP
+        assert!(
+            allocate_gts(
+                &mut gts,
+                ShortAddress(1),
+                request(15),
+                SuperframeOrder::SuperframeOrder(3)
+            )
+            .is_ok()
+        );
+        assert_eq!(
+            allocate_gts(
+                &mut gts,
+                ShortAddress(2),
+                request(1),
+                SuperframeOrder::SuperframeOrder(3)
+            ),
+            Err(Status::InvalidGts)
+        );
+    }
+
+    #[test]
+    fn no_gts_without_superframe() {
+        let mut gts = GuaranteedTimeSlotInformation::new();
+
+        assert_eq!(
+            allocate_gts(
+                &mut gts,
+                ShortAddress(1),
+                request(1),
+                SuperframeOrder::Inactive
+            ),
+            Err(Status::InvalidGts)
+        );
+    }
+}
@@ -0,0 +1,411 @@
+use heapless::Vec;
+
+use rand_core::RngCore;
+
+use super::{
+    AckedSendResult, MacError, commander::RequestResponder, send_with_ack, send_with_csma,
+    state::MacState,
+};
+use crate::{
+    DeviceAddress,
+    consts::MAX_PHY_PACKET_SIZE,
+    phy::{Phy, SendContinuation, SendResult},
+    pib::MacPib,
+    sap::{
+        Status,
+        data::{
+            DataConfirm, DataIndication, DataRequest, Ranging, ReceivedRanging,
+            UwbPreambleSymbolRepetitions, UwbPrf,
+        },
+    },
+    time::{DelayNsExt, Duration, Instant},
+    wire::{Address, AddressMode, Frame, FrameContent, FrameType, FrameVersion, Header},
+};
+
+pub async fn process_data_request<P: Phy>(
+    phy: &mut P,
+    mac_pib: &mut MacPib,
+    mac_state: &mut MacState<'_>,
+    rng: &mut impl RngCore,
+    delay: &mut impl DelayNsExt,
+    responder: RequestResponder<'_, DataRequest>,
+) {
+    let request = &responder.request;
+    let msdu_handle = request.msdu_handle;
+
+    // The MSDU is never truncated or fragmented, so it must fit in a single frame
+    if mpdu_length(&data_header(request, mac_pib), request.msdu.len()) > MAX_PHY_PACKET_SIZE {
+        warn!(
+            "Data request with an MSDU of {} bytes doesn't fit in a frame",
+            request.msdu.len()
+        );
+
+        responder.respond(failed_confirm(msdu_handle, Status::FrameTooLong));
+        return;
+    }
+
+    // This is the rate the frame is sent with by `Phy::send_with_data_rate`
+    if !phy.get_phy_pib().supports_data_rate(request.data_rate) {
+        warn!(
+            "Data request with data rate {}, which the phy doesn't support",
+            request.data_rate
+        );
+
+        responder.respond(failed_confirm(msdu_handle, Status::InvalidParameter));
+        return;
+    }
+
+    if request.src_addr_mode == AddressMode::None && request.dst_addr.is_none() {
+        responder.respond(failed_confirm(msdu_handle, Status::InvalidAddress));
+        return;
+    }
+
+    if request.gtstx || request.indirect_tx || request.ranging != Ranging::NonRanging {
+        warn!("Only direct data transmissions without ranging are implemented");
+        responder.respond(failed_confirm(msdu_handle, Status::NotImplemented));
+        return;
+    }
+
+    let header = Header {
+        seq: mac_pib.dsn.take_next(),
+        ..data_header(request, mac_pib)
+    };
+
+    // The mac can't secure frames yet
+    if header.auxiliary_security_header.is_some() {
+        warn!("Data request with security, which is not supported");
+        responder.respond(failed_confirm(msdu_handle, Status::UnsupportedSecurity));
+        return;
+    }
+
+    let data = mac_state.serialize_frame(Frame {
+        header,
+        content: FrameContent::Data,
+        payload: &request.msdu,
+        footer: [0; 2],
+    });
+
+    let send_result = if request.ack_tx {
+        send_with_ack(
+            phy,
+            mac_pib,
+            mac_state,
+            rng,
+            delay,
+            &data,
+            header.seq,
+            None,
+            Some(request.data_rate),
+        )
+        .await
+        .map(|result| match result {
+            AckedSendResult::Acked { send_time, .. } => Ok(send_time),
+            AckedSendResult::NoAck => Err(Status::NoAck),
+            AckedSendResult::ChannelAccessFailure => Err(Status::ChannelAccessFailure),
+            AckedSendResult::LimitReached => Err(Status::LimitReached),
+        })
+    } else {
+        send_without_ack(
+            phy,
+            mac_pib,
+            mac_state,
+            rng,
+            delay,
+            &data,
+            request.data_rate,
+        )
+        .await
+    };
+
+    match send_result {
+        Ok(Ok(send_time)) => responder.respond(DataConfirm {
+            timestamp: mac_pib.reported_timestamp(send_time, phy.symbol_period()),
+            status: Status::Success,
+            ..failed_confirm(msdu_handle, Status::Success)
+        }),
+        Ok(Err(status)) => {
+            warn!("Could not send the data frame: {}", status);
+            responder.respond(failed_confirm(msdu_handle, status));
+        }
+        Err(e) => {
+            error!("Could not send the data frame: {}", e);
+            responder.respond_with_error(MacError::PhyError(e), |status| {
+                failed_confirm(msdu_handle, status)
+            });
+        }
+    }
+}
+
+/// Send a frame using CSMA-CA that doesn't request an ack, returning the time it was sent
+async fn send_without_ack<P: Phy>(
+    phy: &mut P,
+    mac_pib: &mut MacPib,
+    mac_state: &mut MacState<'_>,
+    rng: &mut impl RngCore,
+    delay: &mut impl DelayNsExt,
+    data: &[u8],
+    data_rate: u8,
+) -> Result<Result<Instant, Status>, P::Error> {
+    let now = phy.get_instant().await?;
+    if !mac_state.duty_cycle_allows(phy, now, data) {
+        warn!("Not sending a frame, because it would go over the duty cycle limit");
+        return Ok(Err(Status::LimitReached));
+    }
+
+    match send_with_csma(
+        phy,
+        mac_pib,
+        mac_state,
+        rng,
+        delay,
+        data,
+        Some(data_rate),
+        SendContinuation::Idle,
+    )
+    .await?
+    {
+        SendResult::Success(send_time, _) => {
+            mac_state.register_transmission(phy, mac_pib, send_time, data);
+            Ok(Ok(send_time))
+        }
+        SendResult::ChannelAccessFailure => Ok(Err(Status::ChannelAccessFailure)),
+    }
+}
+
+/// Create the header of the data frame that would carry the MSDU of the request
+fn data_header(request: &DataRequest, mac_pib: &MacPib) -> Header {
+    let destination = request.dst_addr.map(|dst_addr| match dst_addr {
+        DeviceAddress::Short(address) => Address::Short(request.dst_pan_id, address),
+        DeviceAddress::Extended(address) => Address::Extended(request.dst_pan_id, address),
+    });
+
+    let source = match request.src_addr_mode {
+        AddressMode::None => None,
+        AddressMode::Short => Some(Address::Short(mac_pib.pan_id, mac_pib.short_address)),
+        AddressMode::Extended => Some(Address::Extended(mac_pib.pan_id, mac_pib.extended_address)),
+    };
+
+    Header {
+        frame_type: FrameType::Data,
+        frame_pending: false,
+        ack_request: request.ack_tx,
+        pan_id_compress: Header::pan_id_compression(destination, source),
+        seq_no_suppress: false,
+        ie_present: false,
+        version: FrameVersion::Ieee802154_2003,
+        seq: 0,
+        destination,
+        source,
+        auxiliary_security_header: request.security_info.into(),
+        time_correction: None,
+    }
+}
+
+/// The length of the MPDU with the given header and payload length, including the MIC and the FCS
+fn mpdu_length(header: &Header, payload_length: usize) -> usize {
+    let security_length = header
+        .auxiliary_security_header
+        .map(|aux_sec_header| {
+            aux_sec_header.get_octet_size()
+                + aux_sec_header.control.security_level().get_mic_octet_size()
+        })
+        .unwrap_or(0);
+
+    header.get_octet_size() + security_length + payload_length + 2
+}
+
+fn failed_confirm(msdu_handle: u8, status: Status) -> DataConfirm {
+    DataConfirm {
+        msdu_handle,
+        timestamp: Instant::from_ticks(0),
+        ranging_received: false,
+        ranging_counter_start: Instant::from_ticks(0),
+        ranging_counter_stop: Instant::from_ticks(0),
+        ranging_tracking_interval: Duration::from_ticks(0),
+        ranging_offset: Duration::from_ticks(0),
+        ranging_fom: 0,
+        status,
+    }
+}
+
+/// Create the indication for a received data frame.
+/// The `ranging` is the ranging bit of the PHY header and the `data_rate` is the one the frame was received with.
+///
+/// Returns [None] if the payload is too big to be an MSDU.
+pub fn data_indication(
+    frame: &Frame<'_>,
+    timestamp: Instant,
+    lqi: u8,
+    ranging: bool,
+    data_rate: u8,
+    mac_pib: &MacPib,
+) -> Option<DataIndication> {
+    let Ok(msdu) = Vec::from_slice(frame.payload) else {
+        warn!(
+            "Received a data frame with a payload of {} bytes, which is too big for an MSDU",
+            frame.payload.len()
+        );
+        return None;
+    };
+
+    let dst_pan_id = frame
+        .header
+        .destination
+        .map(|destination| destination.pan_id())
+        .unwrap_or(mac_pib.pan_id);
+
+    Some(DataIndication {
+        src_pan_id: frame
+            .header
+            .source
+            .map(|source| source.pan_id())
+            .unwrap_or(dst_pan_id),
+        src_addr: frame.header.source.map(DeviceAddress::from),
+        dst_pan_id,
+        dst_addr: frame.header.destination.map(DeviceAddress::from),
+        msdu,
+        mpdu_link_quality: lqi,
+        dsn: frame.header.seq,
+        timestamp,
+        security_info: frame.header.auxiliary_security_header.into(),
+        uwbprf: UwbPrf::Off,
+        uwb_preamble_symbol_repetitions: UwbPreambleSymbolRepetitions::Reps0,
+        data_rate,
+        ranging_received: match (ranging, mac_pib.ranging_supported) {
+            (false, _) => ReceivedRanging::NoRangingRequested,
+            (true, true) => ReceivedRanging::RangingActive,
+            (true, false) => ReceivedRanging::RangingRequestedButNotSupported,
+        },
+        ranging_counter_start: Instant::from_ticks(0),
+        ranging_counter_stop: Instant::from_ticks(0),
+        ranging_tracking_interval: Duration::from_ticks(0),
+        ranging_offset: Duration::from_ticks(0),
+        ranging_fom: 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use aes::Aes128;
+    use byte::TryWrite;
+    use ccm::aead::generic_array::GenericArray;
+
+    use super::*;
+    use crate::wire::{
+        ExtendedAddress, FooterMode, FrameContent, FrameSerDesContext, PanId,
+        security::{
+            AddressingMode, AuxiliarySecurityHeader, DeviceDescriptor, DeviceDescriptorLookup,
+            KeyDescriptorLookup, KeyIdentifier, KeyIdentifierMode, SecurityContext,
+            SecurityControl, SecurityLevel, U16,
+        },
+    };
+
+    const SOURCE: ExtendedAddress = ExtendedAddress(0x0102030405060708);
+
+    /// Every device uses the same all-zero key
+    struct ZeroKeyLookup;
+
+    impl KeyDescriptorLookup<U16> for ZeroKeyLookup {
+        fn lookup_key_descriptor(
+            &self,
+            _address_mode: AddressingMode,
+            _key_identifier: Option<KeyIdentifier>,
+            device_address: Option<Address>,
+        ) -> Option<(u64, GenericArray<u8, U16>)> {
+            match device_address {
+                Some(Address::Extended(_, ExtendedAddress(address))) => {
+                    Some((address, GenericArray::default()))
+                }
+                _ => None,
+            }
+        }
+    }
+
+    struct SingleDeviceLookup(DeviceDescriptor);
+
+    impl DeviceDescriptorLookup for SingleDeviceLookup {
+        fn lookup_device(
+            &mut self,
+            _addressing_mode: AddressingMode,
+            _address: Address,
+        ) -> Option<&mut DeviceDescriptor> {
+            Some(&mut self.0)
+        }
+    }
+
+    #[test]
+    fn security_of_received_data_is_indicated() {
+        let frame = Frame {
+            header: Header {
+                ie_present: false,
+                seq_no_suppress: false,
+                frame_type: FrameType::Data,
+                frame_pending: false,
+                ack_request: false,
+                pan_id_compress: false,
+                version: FrameVersion::Ieee802154_2006,
+                seq: 12,
+                destination: Some(Address::Extended(PanId(1), ExtendedAddress(0xAA))),
+                source: Some(Address::Extended(PanId(1), SOURCE)),
+                auxiliary_security_header: Some(AuxiliarySecurityHeader::new(
+                    SecurityControl::new(SecurityLevel::ENCMIC64),
+                    Some(KeyIdentifier {
+                        key_source: None,
+                        key_index: 3,
+                    }),
+                )),
+                time_correction: None,
+            },
+            content: FrameContent::Data,
+            payload: &[1, 2, 3, 4],
+            footer: [0, 0],
+        };
+
+        let mut sender_context = SecurityContext::<Aes128, _>::new(SOURCE.0, 0, ZeroKeyLookup);
+        let mut buffer = [0; 128];
+        let len = frame
+            .try_write(
+                &mut buffer,
+                &mut FrameSerDesContext::new(FooterMode::None, Some(&mut sender_context)),
+            )
+            .unwrap();
+
+        let mut receiver_context = SecurityContext::<Aes128, _>::new(0xAA, 0, ZeroKeyLookup);
+        let (received_frame, _) = Frame::try_read_and_unsecure(
+            &mut buffer[..len],
+            &mut FrameSerDesContext::new(FooterMode::None, Some(&mut receiver_context)),
+            &mut SingleDeviceLookup(DeviceDescriptor {
+                frame_counter: 0,
+                exempt: false,
+            }),
+        )
+        .unwrap();
+
+        let indication = data_indication(
+            &received_frame,
+            Instant::from_ticks(0),
+            255,
+            false,
+            2,
+            &MacPib::dummy_new(),
+        )
+        .unwrap();
+
+        assert_eq!(indication.msdu, [1, 2, 3, 4]);
+        assert_eq!(
+            indication.security_info.security_level,
+            SecurityLevel::ENCMIC64
+        );
+        assert_eq!(
+            indication.security_info.key_id_mode,
+            KeyIdentifierMode::KeyIndex
+        );
+        assert_eq!(
+            indication.security_info.key_identifier,
+            Some(KeyIdentifier {
+                key_source: None,
+                key_index: 3,
+            })
+        );
+    }
+}
@@ -1,19 +1,23 @@
 use core::pin::Pin;
 
+use rand_core::RngCore;
+
 use super::{
+    AckedSendResult,
     callback::DataRequestCallback,
     commander::{IndirectIndicationCollection, MacHandler, RequestResponder},
+    device_table, send_with_ack,
     state::{DataRequestMode, MacState, PendingData, ScheduledDataRequest},
 };
 use crate::{
     mac::state::DataRequestTrigger,
-    phy::{Phy, SendContinuation, SendResult},
+    phy::Phy,
     pib::MacPib,
     sap::{
         SecurityInfo, Status,
         associate::{AssociateConfirm, AssociateIndication, AssociateRequest, AssociateResponse},
     },
-    time::{Duration, Instant},
+    time::{DelayNsExt, Duration, Instant},
     wire::{
         Address, ExtendedAddress, Frame, FrameContent, FrameType, FrameVersion, Header, PanId,
         ShortAddress,
@@ -21,10 +25,21 @@ use crate::{
     },
 };
 
+/// The short address that tells a device to use its extended address
+const NO_SHORT_ADDRESS: ShortAddress = ShortAddress(0xfffe);
+
+/// Picks the short address of a device that associates to us, see [MacConfig::short_address_assignment](super::MacConfig::short_address_assignment)
+pub type ShortAddressAssignment = fn(
+    extended_address: ExtendedAddress,
+    capabilities: CapabilityInformation,
+) -> Option<ShortAddress>;
+
 pub async fn process_associate_request<'a>(
     phy: &mut impl Phy,
     mac_pib: &mut MacPib,
     mac_state: &mut MacState<'a>,
+    rng: &mut impl RngCore,
+    delay: &mut impl DelayNsExt,
     responder: RequestResponder<'a, AssociateRequest>,
 ) {
     if mac_pib.pan_id != PanId::broadcast() {
@@ -59,19 +74,16 @@ pub async fn process_associate_request<'a>(
         return;
     }
 
-    mac_pib.pan_id = responder.request.coord_address.pan_id();
-    match responder.request.coord_address {
-        Address::Short(_, short_address) => mac_pib.coord_short_address = short_address,
-        Address::Extended(_, extended_address) => mac_pib.coord_extended_address = extended_address,
-    }
+    set_coordinator(mac_pib, responder.request.coord_address);
 
     // Generate the associate request and send it
-    let dsn = mac_pib.dsn.increment();
+    let dsn = mac_pib.dsn.take_next();
+    let command = Command::AssociationRequest(responder.request.capability_information);
     let associate_request_frame = Frame {
         header: Header {
             frame_type: FrameType::MacCommand,
             frame_pending: false,
-            ack_request: true,
+            ack_request: command.requires_ack(),
             pan_id_compress: false,
             seq_no_suppress: false,
             ie_present: false,
@@ -83,10 +95,9 @@ pub async fn process_associate_request<'a>(
                 mac_pib.extended_address,
             )),
             auxiliary_security_header: responder.request.security_info.into(),
+            time_correction: None,
         },
-        content: FrameContent::Command(Command::AssociationRequest(
-            responder.request.capability_information,
-        )),
+        content: FrameContent::Command(command),
         payload: &[],
         footer: [0, 0],
     };
@@ -94,68 +105,42 @@ pub async fn process_associate_request<'a>(
 
     debug!("Sending association request");
 
-    let ack_wait_duration = mac_pib.ack_wait_duration(phy.get_phy_pib()) as i64;
-    // We send with ack request, but we won't retry if the ack is not received
-    let send_result = phy
-        .send(
-            &associate_request_frame_data,
-            None,
-            false,
-            true,
-            SendContinuation::WaitForResponse {
-                turnaround_time: phy.symbol_period() * crate::consts::TURNAROUND_TIME as i64,
-                timeout: phy.symbol_period() * ack_wait_duration,
-            },
-        )
-        .await;
+    let send_result = send_with_ack(
+        phy,
+        mac_pib,
+        mac_state,
+        rng,
+        delay,
+        &associate_request_frame_data,
+        dsn,
+        None,
+        None,
+    )
+    .await;
 
-    let ack_timestamp = match send_result {
-        Ok(SendResult::Success(_, None)) => None,
-        Ok(SendResult::Success(_, Some(mut response))) => {
-            // See if what we received was an Ack for us
-
-            match mac_state.deserialize_frame(&mut response.data) {
-                Some(frame) => {
-                    if matches!(frame.header.frame_type, FrameType::Acknowledgement)
-                        && frame.header.seq == dsn
-                    {
-                        Some(response.timestamp)
-                    } else {
-                        None
-                    }
-                }
-                None => None,
-            }
-        }
-        Ok(SendResult::ChannelAccessFailure) => {
-            responder.respond(AssociateConfirm {
-                assoc_short_address: ShortAddress::BROADCAST,
-                status: Err(Status::ChannelAccessFailure),
-                security_info: SecurityInfo::new_none_security(),
-            });
-            return;
-        }
+    let status = match send_result {
+        Ok(AckedSendResult::Acked { timestamp, .. }) => Ok(timestamp),
+        Ok(AckedSendResult::NoAck) => Err(Status::NoAck),
+        Ok(AckedSendResult::ChannelAccessFailure) => Err(Status::ChannelAccessFailure),
+        Ok(AckedSendResult::LimitReached) => Err(Status::LimitReached),
         Err(e) => {
             error!("Could not send the association request: {}", e);
+            Err(Status::PhyError)
+        }
+    };
+
+    let ack_timestamp = match status {
+        Ok(ack_timestamp) => ack_timestamp,
+        Err(status) => {
             responder.respond(AssociateConfirm {
                 assoc_short_address: ShortAddress::BROADCAST,
-                status: Err(Status::PhyError),
+                status: Err(status),
                 security_info: SecurityInfo::new_none_security(),
             });
             return;
         }
     };
 
-    // We did not get an ack, so let the higher level layer know
-    let Some(ack_timestamp) = ack_timestamp else {
-        responder.respond(AssociateConfirm {
-            assoc_short_address: ShortAddress::BROADCAST,
-            status: Err(Status::NoAck),
-            security_info: SecurityInfo::new_none_security(),
-        });
-        return;
-    };
-
     debug!("Association procedure now waiting until the response can be requested");
 
     // We have received the ack to our association request.
@@ -168,37 +153,43 @@ pub async fn process_associate_request<'a>(
             // TODO: If after macResponseWaitTime that still hasn't happened, we need to give up
             mode: DataRequestMode::Independent {
                 timestamp: Some(
-                    ack_timestamp
-                        + phy.symbol_period()
-                            * crate::consts::BASE_SUPERFRAME_DURATION as i64
-                            * mac_pib.response_wait_time as i64,
+                    ack_timestamp + phy.symbol_period() * mac_pib.response_wait_duration() as i64,
                 ),
             },
-            trigger: DataRequestTrigger::Association,
+            trigger: DataRequestTrigger::Association {
+                coord_address: responder.request.coord_address,
+            },
             used_security_info: responder.request.security_info,
             callback: DataRequestCallback::AssociationProcedure(responder),
+            follow_ups: 0,
         });
 }
 
+/// Returns true if the association was successful
 pub async fn association_data_request_callback(
     responder: RequestResponder<'_, AssociateRequest>,
     associate_confirm: Result<AssociateConfirm, Result<AssociationStatus, Status>>,
     mac_pib: &mut MacPib,
-) {
-    match associate_confirm {
+) -> bool {
+    let associated = match associate_confirm {
         Ok(AssociateConfirm {
             assoc_short_address,
             status: Ok(AssociationStatus::Successful),
             security_info: _,
         }) => {
-            // Association was sucessful
+            // Association was sucessful.
+            // The coordinator is set again, because a disassociation notification that was pending
+            // before the response has cleared it.
+            set_coordinator(mac_pib, responder.request.coord_address);
             mac_pib.short_address = assoc_short_address;
+            true
         }
         _ => {
             // Association failed
             mac_pib.pan_id = PanId::broadcast();
+            false
         }
-    }
+    };
 
     responder.respond(match associate_confirm {
         Ok(confirm) => confirm,
@@ -208,12 +199,40 @@ pub async fn association_data_request_callback(
             security_info: SecurityInfo::new_none_security(),
         },
     });
+
+    associated
+}
+
+fn set_coordinator(mac_pib: &mut MacPib, coord_address: Address) {
+    mac_pib.pan_id = coord_address.pan_id();
+    match coord_address {
+        Address::Short(_, short_address) => mac_pib.coord_short_address = short_address,
+        Address::Extended(_, extended_address) => mac_pib.coord_extended_address = extended_address,
+    }
+}
+
+/// Translate the association status received from the coordinator into the status of the confirm.
+///
+/// A denial by the coordinator is reported as a failure [Status] so the higher layer doesn't
+/// receive what looks like a successful confirm with the denial hidden inside.
+pub fn association_status_to_confirm_status(
+    association_status: AssociationStatus,
+) -> Result<AssociationStatus, Status> {
+    match association_status {
+        AssociationStatus::Successful | AssociationStatus::FastAssociationSuccesful => {
+            Ok(association_status)
+        }
+        AssociationStatus::NetworkAtCapacity => Err(Status::NetworkAtCapacity),
+        AssociationStatus::AccessDenied => Err(Status::AccessDenied),
+        AssociationStatus::HoppingSequenceOffsetDuplication => Err(Status::Denied),
+    }
 }
 
 // Received from the radio, not as an MLME request
 pub async fn process_received_associate_request<'a>(
     mac_handler: &MacHandler<'a>,
     mac_pib: &MacPib,
+    mac_state: &mut MacState<'a>,
     indirect_indications: Pin<&mut IndirectIndicationCollection<'a>>,
     device_address: ExtendedAddress,
     capability_information: CapabilityInformation,
@@ -227,19 +246,106 @@ pub async fn process_received_associate_request<'a>(
         return;
     }
 
+    let assigned_short_address =
+        assign_short_address(mac_pib, mac_state, device_address, capability_information);
+
     let indirect_response = mac_handler.indicate_indirect(AssociateIndication {
         device_address,
         capability_information,
         security_info: SecurityInfo::new_none_security(),
+        assigned_short_address,
     });
 
-    indirect_indications.push(
+    if let Err(status) = indirect_indications.push(
         indirect_response,
-        message_timestamp
-            + symbol_period
-                * crate::consts::BASE_SUPERFRAME_DURATION as i64
-                * mac_pib.response_wait_time as i64,
-    );
+        message_timestamp + symbol_period * mac_pib.response_wait_duration() as i64,
+    ) {
+        warn!(
+            "Could not indicate the associate request of {:?}, too many indications are waiting on a response: {}",
+            device_address, status
+        );
+
+        // Tell the device we can't take it on right now, so it doesn't wait for a response that never comes
+        let push_result = mac_state.message_scheduler.push_pending_data(PendingData {
+            device: crate::DeviceAddress::Extended(device_address),
+            data_value: super::state::PendingDataValue::AssociationResponse {
+                short_address: ShortAddress::BROADCAST,
+                association_status: AssociationStatus::NetworkAtCapacity,
+                rx_on_when_idle: capability_information.idle_receive,
+            },
+            registration_time: message_timestamp,
+        });
+        if let Err(status) = push_result {
+            error!(
+                "Could not push associate response to pending data: {}",
+                status
+            );
+        }
+        return;
+    }
+
+    // Remember the capabilities and the assigned address for when the higher layer responds.
+    // A repeated request replaces the old one and when there's no room, the oldest request is forgotten.
+    let association_requests = &mut mac_state.association_requests;
+    association_requests.retain(|(address, _, _)| *address != device_address);
+    if !association_requests.is_empty()
+        && association_requests.len() >= mac_state.max_association_requests
+    {
+        association_requests.remove(0);
+    }
+    association_requests
+        .push((
+            device_address,
+            capability_information,
+            assigned_short_address,
+        ))
+        .unwrap();
+}
+
+/// Ask the [MacConfig::short_address_assignment](super::MacConfig::short_address_assignment)
+/// for the short address of a device that wants one.
+///
+/// An address that can't be given to a device, like the broadcast address, or that's already used
+/// by us or another device is refused.
+fn assign_short_address(
+    mac_pib: &MacPib,
+    mac_state: &MacState<'_>,
+    device_address: ExtendedAddress,
+    capability_information: CapabilityInformation,
+) -> Option<ShortAddress> {
+    if !capability_information.allocate_address {
+        return None;
+    }
+
+    let assign = mac_state.short_address_assignment?;
+    let short_address = assign(device_address, capability_information)?;
+
+    let in_use = short_address == mac_pib.short_address
+        || mac_state.device_table.devices().iter().any(|device| {
+            device.short_address == short_address && device.extended_address != device_address
+        })
+        || mac_state
+            .association_requests
+            .iter()
+            .any(|(address, _, assigned_short_address)| {
+                *address != device_address && *assigned_short_address == Some(short_address)
+            });
+
+    if !device_table::is_allocated(short_address) {
+        warn!(
+            "The short address assignment gave {:?} to {:?}, which can't be given to a device",
+            short_address, device_address
+        );
+        None
+    } else if in_use {
+        warn!(
+            "The short address assignment gave {:?} to {:?}, which is already in use",
+            short_address, device_address
+        );
+        None
+    } else {
+        Some(short_address)
+    }
 }
 
 /// Process the response to an indication
@@ -248,11 +354,40 @@ pub async fn process_associate_response(
     current_time: Instant,
     mac_state: &mut MacState<'_>,
 ) {
+    let capability_information = mac_state
+        .association_requests
+        .iter()
+        .position(|(address, _, _)| *address == response.device_address)
+        .map(|index| mac_state.association_requests.remove(index).1);
+
+    let successful = matches!(
+        response.status,
+        AssociationStatus::Successful | AssociationStatus::FastAssociationSuccesful
+    );
+
+    let short_address = match capability_information {
+        // The device wants to use its extended address, so it doesn't get a short one (5.1.3.1)
+        Some(CapabilityInformation {
+            allocate_address: false,
+            ..
+        }) if successful && response.assoc_short_address != NO_SHORT_ADDRESS => {
+            warn!(
+                "{:?} didn't ask for a short address, so it's not given {:?}",
+                response.device_address, response.assoc_short_address
+            );
+            NO_SHORT_ADDRESS
+        }
+        _ => response.assoc_short_address,
+    };
+
     let push_result = mac_state.message_scheduler.push_pending_data(PendingData {
         device: crate::DeviceAddress::Extended(response.device_address),
         data_value: super::state::PendingDataValue::AssociationResponse {
-            short_address: response.assoc_short_address,
+            short_address,
             association_status: response.status,
+            // Without the request we can't know, so assume the device must poll for its frames
+            rx_on_when_idle: capability_information
+                .is_some_and(|capability_information| capability_information.idle_receive),
         },
         registration_time: current_time,
     });
@@ -0,0 +1,214 @@
+use core::pin::Pin;
+
+use rand_core::RngCore;
+
+use super::{
+    AckedSendResult,
+    commander::{IndirectIndicationCollection, MacHandler, RequestResponder},
+    send_with_ack,
+    state::{MacState, PendingData, PendingDataValue},
+};
+use crate::{
+    DeviceAddress,
+    phy::Phy,
+    pib::MacPib,
+    sap::{
+        SecurityInfo, Status,
+        disassociate::{DisassociateConfirm, DisassociateIndication, DisassociateRequest},
+    },
+    time::{DelayNsExt, Duration, Instant},
+    wire::{
+        Address, ExtendedAddress, Frame, FrameContent, FrameType, Header, PanId, ShortAddress,
+        command::{Command, DisassociationReason},
+    },
+};
+
+pub async fn process_disassociate_request<'a>(
+    phy: &mut impl Phy,
+    mac_pib: &mut MacPib,
+    mac_state: &mut MacState<'a>,
+    rng: &mut impl RngCore,
+    delay: &mut impl DelayNsExt,
+    responder: RequestResponder<'a, DisassociateRequest>,
+) {
+    let device_address = responder.request.device_address;
+
+    if device_address.pan_id() != mac_pib.pan_id {
+        responder.respond(DisassociateConfirm {
+            status: Status::InvalidParameter,
+            device_address,
+        });
+        return;
+    }
+
+    let to_coordinator = is_coordinator_address(mac_pib, device_address);
+
+    // The coordinator can leave the notification for the device to pick up with a data request.
+    // When we're sending to our own coordinator, the indirect flag is ignored.
+    // For devices in our device table, what they told us in their association request decides:
+    // only a device that turns its receiver off when idle has to poll for the notification.
+    let tx_indirect = match mac_state.device_table.get(device_address.into()) {
+        Some(device) => !device.rx_on_when_idle,
+        None => responder.request.tx_indirect,
+    };
+    if !to_coordinator && tx_indirect {
+        let current_time = match phy.get_instant().await {
+            Ok(current_time) => current_time,
+            Err(e) => {
+                error!("Could not get the current time: {}", e);
+                responder.respond(DisassociateConfirm {
+                    status: Status::PhyError,
+                    device_address,
+                });
+                return;
+            }
+        };
+
+        // TODO: The confirm should only be sent once the device has picked up the notification
+        // or when the transaction has expired
+        let push_result = mac_state.message_scheduler.push_pending_data(PendingData {
+            device: device_address.into(),
+            data_value: PendingDataValue::DisassociationNotification {
+                reason: responder.request.disassociate_reason,
+            },
+            registration_time: current_time,
+        });
+
+        responder.respond(DisassociateConfirm {
+            status: match push_result {
+                Ok(()) => Status::Success,
+                Err(status) => status,
+            },
+            device_address,
+        });
+        return;
+    }
+
+    let dsn = mac_pib.dsn.take_next();
+    let command = Command::DisassociationNotification(responder.request.disassociate_reason);
+    let disassociation_frame = Frame {
+        header: Header {
+            frame_type: FrameType::MacCommand,
+            frame_pending: false,
+            ack_request: command.requires_ack(),
+            pan_id_compress: true,
+            seq_no_suppress: false,
+            ie_present: false,
+            version: responder.request.security_info.get_frame_version(),
+            seq: dsn,
+            destination: Some(device_address),
+            source: Some(Address::Extended(mac_pib.pan_id, mac_pib.extended_address)),
+            auxiliary_security_header: responder.request.security_info.into(),
+            time_correction: None,
+        },
+        content: FrameContent::Command(command),
+        payload: &[],
+        footer: [0, 0],
+    };
+    let disassociation_frame_data = mac_state.serialize_frame(disassociation_frame);
+
+    debug!("Sending disassociation notification");
+
+    let send_result = send_with_ack(
+        phy,
+        mac_pib,
+        mac_state,
+        rng,
+        delay,
+        &disassociation_frame_data,
+        dsn,
+        None,
+        None,
+    )
+    .await;
+
+    let status = match send_result {
+        Ok(AckedSendResult::Acked { .. }) => Status::Success,
+        Ok(AckedSendResult::NoAck) => Status::NoAck,
+        Ok(AckedSendResult::ChannelAccessFailure) => Status::ChannelAccessFailure,
+        Ok(AckedSendResult::LimitReached) => Status::LimitReached,
+        Err(e) => {
+            error!("Could not send the disassociation notification: {}", e);
+            Status::PhyError
+        }
+    };
+
+    // Even without an ack, we must consider ourselves disassociated
+    if matches!(status, Status::Success | Status::NoAck) {
+        if to_coordinator {
+            remove_association(mac_pib, mac_state);
+        } else {
+            mac_state.device_table.remove(device_address.into());
+        }
+    }
+
+    responder.respond(DisassociateConfirm {
+        status,
+        device_address,
+    });
+}
+
+// Received from the radio, not as an MLME request
+#[allow(clippy::too_many_arguments)]
+pub fn process_received_disassociation_notification<'a>(
+    mac_handler: &MacHandler<'a>,
+    mac_pib: &mut MacPib,
+    mac_state: &mut MacState<'_>,
+    indirect_indications: Pin<&mut IndirectIndicationCollection<'a>>,
+    device_address: ExtendedAddress,
+    disassociate_reason: DisassociationReason,
+    security_info: SecurityInfo,
+    message_timestamp: Instant,
+    symbol_period: Duration,
+) {
+    // If we're not the coordinator of the PAN, the notification must have come from our coordinator
+    // and we've been told to leave. Otherwise one of our devices has left.
+    if mac_state.is_pan_coordinator {
+        mac_state
+            .device_table
+            .remove(DeviceAddress::Extended(device_address));
+    } else if device_address == mac_pib.coord_extended_address {
+        remove_association(mac_pib, mac_state);
+    } else {
+        warn!(
+            "Ignoring a disassociation notification of {:?}, which is not our coordinator",
+            device_address
+        );
+        return;
+    }
+
+    // The indication is sent indirectly so we're not holding up the ack
+    let indirect_response = mac_handler.indicate_indirect(DisassociateIndication {
+        device_address,
+        disassociate_reason,
+        security_info,
+    });
+
+    if let Err(status) = indirect_indications.push(
+        indirect_response,
+        message_timestamp + symbol_period * mac_pib.response_wait_duration() as i64,
+    ) {
+        warn!(
+            "Could not indicate the disassociation notification of {:?}, too many indications are waiting on a response: {}",
+            device_address, status
+        );
+    }
+}
+
+fn is_coordinator_address(mac_pib: &MacPib, address: Address) -> bool {
+    match DeviceAddress::from(address) {
+        DeviceAddress::Short(short_address) => short_address == mac_pib.coord_short_address,
+        DeviceAddress::Extended(extended_address) => {
+            extended_address == mac_pib.coord_extended_address
+        }
+    }
+}
+
+fn remove_association(mac_pib: &mut MacPib, mac_state: &mut MacState<'_>) {
+    mac_state.is_associated = false;
+    mac_pib.pan_id = PanId::broadcast();
+    mac_pib.short_address = ShortAddress::BROADCAST;
+    mac_pib.associated_pan_coord = false;
+    mac_pib.coord_short_address = ShortAddress::BROADCAST;
+    mac_pib.coord_extended_address = ExtendedAddress::BROADCAST;
+}
@@ -22,9 +22,9 @@ pub async fn process_get_request(
             status: Status::Success,
             value,
         }),
-        Err(e) => responder.respond(GetConfirm {
+        Err(e) => responder.respond_with_error(e, |status| GetConfirm {
             pib_attribute,
-            status: e.into(),
+            status,
             value: PibValue::None,
         }),
     }
@@ -41,7 +41,7 @@ async fn get_pib_value<P: Phy>(
         return Ok(val);
     }
 
-    if let Some(val) = mac_pib.get(pib_attribute, phy_pib) {
+    if let Some(val) = mac_pib.get(pib_attribute, phy_pib, phy.turnaround_time_symbols()) {
         return Ok(val);
     }
 
@@ -2,15 +2,13 @@ use rand_core::RngCore;
 
 use super::{MacConfig, MacError, commander::RequestResponder, state::MacState};
 use crate::{
-    consts::MAX_BEACON_PAYLOAD_LENGTH,
     phy::Phy,
-    pib::{MacPib, MacPibWrite, SequenceNumber},
-    sap::reset::{ResetConfirm, ResetRequest},
-    time::DelayNsExt,
-    wire::{
-        ExtendedAddress, PanId, ShortAddress,
-        beacon::{BeaconOrder, SuperframeOrder},
+    pib::MacPib,
+    sap::{
+        Status,
+        reset::{ResetConfirm, ResetRequest},
     },
+    time::DelayNsExt,
 };
 
 pub async fn process_reset_request<P: Phy, Rng: RngCore, Delay: DelayNsExt>(
@@ -20,60 +18,58 @@ pub async fn process_reset_request<P: Phy, Rng: RngCore, Delay: DelayNsExt>(
     config: &mut MacConfig<Rng, Delay>,
     responder: RequestResponder<'_, ResetRequest>,
 ) {
+    // Whether or not the pib is reset, everything that's going on is stopped
+    abort_ongoing_operations(phy, mac_pib, mac_state).await;
+
     let result: Result<(), MacError<P::Error>> = async {
         if responder.request.set_default_pib {
             phy.reset().await?;
 
-            *mac_pib = MacPib {
-                pib_write: MacPibWrite {
-                    associated_pan_coord: false,
-                    association_permit: false,
-                    auto_request: true,
-                    batt_life_ext: false,
-                    beacon_payload: [0; MAX_BEACON_PAYLOAD_LENGTH],
-                    beacon_payload_length: 0,
-                    beacon_order: BeaconOrder::OnDemand,
-                    bsn: SequenceNumber::new(config.rng.next_u32() as u8),
-                    coord_extended_address: ExtendedAddress::BROADCAST,
-                    coord_short_address: ShortAddress::BROADCAST,
-                    dsn: SequenceNumber::new(config.rng.next_u32() as u8),
-                    gts_permit: true,
-                    max_be: 5,
-                    max_csma_backoffs: 4,
-                    max_frame_retries: 3,
-                    min_be: 3,
-                    pan_id: PanId::broadcast(),
-                    promiscuous_mode: false,
-                    response_wait_time: 32,
-                    rx_on_when_idle: false,
-                    security_enabled: false,
-                    short_address: ShortAddress::BROADCAST,
-                    transaction_persistence_time: 0x01F4,
-                    tx_control_active_duration: P::MODULATION.tx_control_active_duration(),
-                    tx_control_pause_duration: P::MODULATION.tx_control_pause_duration(),
-                    tx_total_duration: 0,
-                },
-                extended_address: config.extended_address,
-                beacon_tx_time: 0,
-                lifs_period: 40,
-                sifs_period: 12,
-                ranging_supported: true,
-                superframe_order: SuperframeOrder::Inactive,
-                sync_symbol_offset: 0,
-                timestamp_supported: true,
-            };
+            *mac_pib =
+                MacPib::new_default(&P::MODULATION, config.extended_address, &mut config.rng);
+            mac_pib.sync_symbol_offset = config.sync_symbol_offset;
+            mac_pib.apply_tx_policy(&config.tx_policy);
         }
 
-        *mac_state = MacState::new(config);
+        let old_state = core::mem::replace(mac_state, MacState::new(config));
+        // What was sent before the reset still counts towards the duty cycle limit
+        mac_state.duty_cycle = old_state.duty_cycle;
+        // The association is stored in the pib, so it's only forgotten when the pib is reset
+        if !responder.request.set_default_pib {
+            mac_state.is_associated = old_state.is_associated;
+        }
+        #[cfg(feature = "frame-tap")]
+        {
+            mac_state.frame_tap = old_state.frame_tap;
+        }
 
         Ok(())
     }
     .await;
 
-    responder.respond(ResetConfirm {
-        status: match result {
-            Ok(()) => crate::sap::Status::Success,
-            Err(e) => e.into(),
-        },
-    });
+    match result {
+        Ok(()) => responder.respond(ResetConfirm {
+            status: Status::Success,
+        }),
+        Err(e) => responder.respond_with_error(e, |status| ResetConfirm { status }),
+    }
+}
+
+/// Confirm all requests that are still in progress so nobody is left waiting on them
+async fn abort_ongoing_operations(
+    phy: &mut impl Phy,
+    mac_pib: &mut MacPib,
+    mac_state: &mut MacState<'_>,
+) {
+    if let Some(scan_process) = mac_state.current_scan_process.take() {
+        scan_process.abort_scan(mac_pib, Status::Denied, phy).await;
+    }
+
+    while let Some(broadcast) = mac_state.message_scheduler.take_scheduled_broadcast() {
+        broadcast.callback.abort(Status::Denied);
+    }
+
+    while let Some(data_request) = mac_state.message_scheduler.take_scheduled_data_request() {
+        data_request.callback.abort(Status::Denied, mac_pib).await;
+    }
 }
@@ -10,7 +10,7 @@ use crate::{
         scan::{ScanConfirm, ScanRequest, ScanType},
     },
     time::{DelayNsExt, Duration, Instant},
-    wire::{Frame, FrameContent, PanId},
+    wire::{Frame, FrameContent, PanId, security::SecurityError},
 };
 
 pub async fn process_scan_request<'a>(
@@ -28,6 +28,7 @@ pub async fn process_scan_request<'a>(
         channel_page: request.channel_page,
         pan_descriptor_list_allocation: pan_descriptor_list,
         unscanned_channels: request.scan_channels.clone(),
+        unscanned_channel_pages: request.scan_channel_pages.clone(),
         ..Default::default()
     };
 
@@ -43,6 +44,26 @@ pub async fn process_scan_request<'a>(
         }
     };
 
+    if let ScanType::Ed | ScanType::Orphan = request.scan_type {
+        warn!("The {:?} scan is not implemented yet", request.scan_type);
+        responder.respond(ScanConfirm {
+            status: Status::NotImplemented,
+            ..default_confirm
+        });
+        return;
+    }
+
+    if !request.scan_channel_pages.is_empty()
+        && request.scan_channel_pages.len() != request.scan_channels.len()
+    {
+        warn!("The scan has a channel page for some of its channels, but not all");
+        responder.respond(ScanConfirm {
+            status: Status::InvalidParameter,
+            ..default_confirm
+        });
+        return;
+    }
+
     // Only one scan can be in progress at a time
     if mac_state.current_scan_process.is_some() {
         responder.respond(ScanConfirm {
@@ -118,9 +139,15 @@ impl ScanProcess<'_> {
         if let Some(channel) = self.results.unscanned_channels.get(self.skipped_channels) {
             ScanAction::StartScan {
                 channel: *channel,
-                page: self.results.channel_page,
+                page: self
+                    .results
+                    .unscanned_channel_pages
+                    .get(self.skipped_channels)
+                    .copied()
+                    .unwrap_or(self.results.channel_page),
                 scan_type: self.results.scan_type,
                 current_code: (),
+                include_source_address: self.responder.request.include_source_address,
             }
         } else {
             ScanAction::Finish
@@ -135,6 +162,7 @@ impl ScanProcess<'_> {
         channel: u8,
         page: ChannelPage,
         frame: Frame<'_>,
+        security_status: Option<SecurityError>,
         mac_pib: &MacPib,
         mac_handler: &MacHandler<'_>,
     ) {
@@ -157,7 +185,7 @@ impl ScanProcess<'_> {
             gts_permit: beacon_data.guaranteed_time_slot_info.permit,
             link_quality: lqi,
             timestamp: receive_time,
-            security_status: None, // TODO: What's the expected behaviour here?
+            security_status,
             security_info: frame
                 .header
                 .auxiliary_security_header
@@ -170,51 +198,75 @@ impl ScanProcess<'_> {
             code_list: (),
         };
 
-        // We either need to store the beacon in the results or send out a beacon notification
-        if mac_pib.auto_request {
-            // Store them
+        // Without auto request every beacon is notified. With auto request only the ones with a payload are (6.2.4.1)
+        if !mac_pib.auto_request || !frame.payload.is_empty() {
+            mac_handler
+                .indicate_without_response(BeaconNotifyIndication {
+                    beacon_sequence_number: frame.header.seq,
+                    pan_descriptor: pan_descriptor.clone(),
+                    address_list: beacon_data.pending_address,
+                    gts_slots: beacon_data.guaranteed_time_slot_info.slots,
+                    // The payload of a beacon that couldn't be unsecured can still be encrypted
+                    sdu: match security_status {
+                        None => frame
+                            .payload
+                            .try_into()
+                            .expect("Payload is never bigger than SDU"),
+                        Some(_) => Default::default(),
+                    },
+                })
+                .await;
+        }
 
+        // With auto request the beacon is also stored in the results
+        if mac_pib.auto_request {
             // Ignore duplicates (5.1.2.1.2)
             let duplicate = self.results.pan_descriptor_list().any(|descr| {
-                descr.coord_address == beacon_source && descr.channel_number == channel
+                descr.coord_address == beacon_source
+                    && descr.channel_number == channel
+                    && descr.channel_page == page
             });
 
             if duplicate {
                 return;
             }
 
+            // The list can already be full when the allocation is empty,
+            // or when more beacons come in before the scan has been finished
+            let Some(slot) = self
+                .results
+                .pan_descriptor_list_allocation
+                .as_slice_mut()
+                .get_mut(self.results.result_list_size)
+            else {
+                warn!("Dropping a PAN descriptor because the pan_descriptor_list is full");
+                self.end_because_list_is_full();
+                return;
+            };
+
             // Push the descriptor
-            self.results.pan_descriptor_list_allocation.as_slice_mut()
-                [self.results.result_list_size] = Some(pan_descriptor);
+            *slot = Some(pan_descriptor);
             self.results.result_list_size += 1;
 
             // End the scan if full
             if self.results.result_list_size
                 == self.results.pan_descriptor_list_allocation.as_slice().len()
             {
-                // Next wait for action will return the Finish action
-                self.skipped_channels = self.results.unscanned_channels.len();
-                self.end_time = Instant::from_ticks(0);
-                self.results.status = Status::LimitReached;
-
-                trace!("Scan is done because no more space in pan_descriptor_list");
+                self.end_because_list_is_full();
             }
-        } else {
-            // Output a beacon notification as per the spec
-            mac_handler
-                .indicate(BeaconNotifyIndication {
-                    beacon_sequence_number: frame.header.seq,
-                    pan_descriptor,
-                    address_list: beacon_data.pending_address,
-                    sdu: frame
-                        .payload
-                        .try_into()
-                        .expect("Payload is never bigger than SDU"),
-                })
-                .await;
         }
     }
 
+    /// End the scan early with [Status::LimitReached] (5.1.2.1.2)
+    fn end_because_list_is_full(&mut self) {
+        // Next wait for action will return the Finish action
+        self.skipped_channels = self.results.unscanned_channels.len();
+        self.end_time = Instant::from_ticks(0);
+        self.results.status = Status::LimitReached;
+
+        trace!("Scan is done because no more space in pan_descriptor_list");
+    }
+
     pub fn register_action_as_executed(&mut self, action: ScanAction) {
         let scan_duration = self.symbol_period
             * (BASE_SUPERFRAME_DURATION
@@ -227,6 +279,11 @@ impl ScanProcess<'_> {
                 self.results
                     .unscanned_channels
                     .remove(self.skipped_channels);
+                if !self.results.unscanned_channel_pages.is_empty() {
+                    self.results
+                        .unscanned_channel_pages
+                        .remove(self.skipped_channels);
+                }
             }
             ScanAction::Finish => {
                 debug!("Scan has been finished!")
@@ -272,7 +329,9 @@ impl ScanProcess<'_> {
     }
 
     pub async fn finish_scan(self, mac_pib: &mut MacPib, phy: &mut impl Phy) {
-        self.abort_scan(mac_pib, Status::Success, phy).await
+        // Keep the status the scan ended with, like LimitReached
+        let status = self.results.status;
+        self.abort_scan(mac_pib, status, phy).await
     }
 }
 
@@ -286,6 +345,8 @@ pub enum ScanAction {
         /// for UWB and CSS. But this has not been implemented in the radio driver yet and we don't *really*
         /// need it. So ignore for now.
         current_code: (),
+        /// Send the beacon requests of an active scan with our own address as the source
+        include_source_address: bool,
     },
     Finish,
 }
@@ -5,6 +5,7 @@ use crate::{
     sap::{
         Status,
         set::{SetConfirm, SetRequest},
+        set_many::{SetManyConfirm, SetManyRequest},
     },
 };
 
@@ -27,8 +28,8 @@ pub async fn process_set_request(
             status,
             pib_attribute,
         }),
-        Err(e) => responder.respond(SetConfirm {
-            status: e.into(),
+        Err(e) => responder.respond_with_error(e, |status| SetConfirm {
+            status,
             pib_attribute,
         }),
     }
@@ -40,16 +41,95 @@ async fn set_pib_value<P: Phy>(
     pib_attribute: &str,
     pib_value: PibValue,
 ) -> Result<Status, MacError<P::Error>> {
-    if let Some(status) = phy
-        .update_phy_pib(|phy_pib| phy_pib.try_set(pib_attribute, &pib_value))
-        .await?
-    {
+    // Try the phy attributes on a copy first, so the phy is only updated when the value is accepted.
+    // That way, read-only attributes and invalid values are rejected without touching the radio.
+    let mut phy_pib_write = phy.get_phy_pib().pib_write.clone();
+    if let Some(status) = phy_pib_write.try_set(pib_attribute, &pib_value) {
+        if status == Status::Success {
+            phy.update_phy_pib(|phy_pib| *phy_pib = phy_pib_write)
+                .await?;
+        }
+
         return Ok(status);
     }
 
-    if let Some(status) = mac_pib_write.try_set(pib_attribute, &pib_value) {
+    // The mac attributes also go to a copy first, so the constraints between them can be checked
+    let mut new_mac_pib_write = mac_pib_write.clone();
+    if let Some(status) = new_mac_pib_write.try_set(pib_attribute, &pib_value) {
+        if status != Status::Success {
+            return Ok(status);
+        }
+
+        if new_mac_pib_write.check_consistency().is_err() {
+            return Ok(Status::InvalidParameter);
+        }
+
+        *mac_pib_write = new_mac_pib_write;
         return Ok(status);
     }
 
     Err(MacError::UnsupportedAttribute)
 }
+
+pub async fn process_set_many_request(
+    phy: &mut impl Phy,
+    mac_pib_write: &mut MacPibWrite,
+    responder: RequestResponder<'_, SetManyRequest>,
+) {
+    // Everything is written to copies first, so nothing changes when one of the values is rejected
+    let mut new_phy_pib_write = phy.get_phy_pib().pib_write.clone();
+    let mut new_mac_pib_write = mac_pib_write.clone();
+    let mut phy_changed = false;
+
+    for pib_value in &responder.request.pib_attribute_values {
+        let pib_attribute = pib_value.name();
+
+        let status = match new_phy_pib_write.try_set(pib_attribute, pib_value) {
+            Some(status) => {
+                phy_changed |= status == Status::Success;
+                status
+            }
+            None => new_mac_pib_write
+                .try_set(pib_attribute, pib_value)
+                .unwrap_or(Status::UnsupportedAttribute),
+        };
+
+        if status != Status::Success {
+            responder.respond(SetManyConfirm {
+                status,
+                pib_attribute: Some(pib_attribute),
+            });
+            return;
+        }
+    }
+
+    // The constraints between attributes are checked on the end result, so the order of the values doesn't matter
+    if let Err(pib_attribute) = new_mac_pib_write.check_consistency() {
+        responder.respond(SetManyConfirm {
+            status: Status::InvalidParameter,
+            pib_attribute: Some(pib_attribute),
+        });
+        return;
+    }
+
+    if phy_changed {
+        let result = phy
+            .update_phy_pib(|phy_pib| *phy_pib = new_phy_pib_write)
+            .await;
+
+        if let Err(e) = result {
+            responder.respond_with_error(MacError::from(e), |status| SetManyConfirm {
+                status,
+                pib_attribute: None,
+            });
+            return;
+        }
+    }
+
+    *mac_pib_write = new_mac_pib_write;
+
+    responder.respond(SetManyConfirm {
+        status: Status::Success,
+        pib_attribute: None,
+    });
+}
@@ -0,0 +1,16 @@
+use super::commander::RequestResponder;
+use crate::sap::{
+    Status,
+    sounding::{SoundingConfirm, SoundingRequest},
+};
+
+pub fn process_sounding_request(mut responder: RequestResponder<'_, SoundingRequest>) {
+    let sounding_list_allocation = core::mem::take(&mut responder.request.sounding_list_allocation);
+
+    // None of the phys have channel sounding capabilities (yet)
+    responder.respond(SoundingConfirm {
+        sounding_list_allocation,
+        sounding_list_size: 0,
+        status: Status::UnsupportedAttribute,
+    });
+}
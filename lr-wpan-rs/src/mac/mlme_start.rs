@@ -1,7 +1,7 @@
 use super::{
     MacError,
     commander::RequestResponder,
-    state::{BeaconMode, MacState},
+    state::{BeaconMode, MacState, TrackedSuperframe},
 };
 use crate::{
     consts,
@@ -50,30 +50,30 @@ pub async fn process_start_request<'a>(
         };
         // We need to send a realignment message and only after that change apply the changes.
         // This happens in the callback
+        let command = Command::CoordinatorRealignment(CoordinatorRealignmentData {
+            pan_id: responder.request.pan_id,
+            coordinator_address: mac_pib.short_address,
+            channel: responder.request.channel_number,
+            device_address: ShortAddress::BROADCAST,
+            channel_page: Some(responder.request.channel_page as u8),
+        });
         let coord_realignment_message = Frame {
             header: Header {
                 ie_present: false,
                 seq_no_suppress: false,
                 frame_type: FrameType::MacCommand,
                 frame_pending: false,
-                ack_request: false,
+                ack_request: command.requires_ack(),
                 pan_id_compress: false,
                 version: FrameVersion::Ieee802154_2006, // Realignment command with channel page present
 
-                seq: mac_pib.dsn.increment(),
+                seq: mac_pib.dsn.take_next(),
                 destination: Some(Address::Short(PanId::broadcast(), ShortAddress::BROADCAST)),
                 source: Some(Address::Extended(mac_pib.pan_id, mac_pib.extended_address)),
                 auxiliary_security_header: responder.request.coord_realign_security_info.into(),
+                time_correction: None,
             },
-            content: FrameContent::Command(Command::CoordinatorRealignment(
-                CoordinatorRealignmentData {
-                    pan_id: responder.request.pan_id,
-                    coordinator_address: mac_pib.short_address,
-                    channel: responder.request.channel_number,
-                    device_address: ShortAddress::BROADCAST,
-                    channel_page: Some(responder.request.channel_page as u8),
-                },
-            )),
+            content: FrameContent::Command(command),
             payload: &[],
             footer: [0, 0],
         };
@@ -123,7 +123,7 @@ async fn apply_changes<'a>(
 
         if let Err(e) = update_superframe_config(phy, mac_pib, request).await {
             error!("Updating superframe config returned an error: {}", e);
-            responder.respond(StartConfirm { status: e.into() });
+            responder.respond_with_error(e, |status| StartConfirm { status });
             return;
         }
 
@@ -138,13 +138,14 @@ async fn apply_changes<'a>(
         responder.respond(StartConfirm {
             status: Status::Success,
         });
-    } else if request.start_time > 0 && mac_state.coordinator_beacon_tracked {
+    } else if let Some(tracked_superframe) = mac_state.tracked_superframe {
         // We are going to run our beacon at an offset to the tracked beacon
 
-        let beacon_super_frame_symbols =
-            consts::BASE_SUPERFRAME_DURATION << u8::from(request.superframe_order);
-
-        if request.start_time < beacon_super_frame_symbols {
+        if superframe_overlaps(
+            tracked_superframe,
+            request.start_time,
+            request.superframe_order,
+        ) {
             responder.respond(StartConfirm {
                 status: Status::SuperframeOverlap,
             });
@@ -153,7 +154,7 @@ async fn apply_changes<'a>(
 
         if let Err(e) = update_superframe_config(phy, mac_pib, request).await {
             error!("Updating superframe config returned an error: {}", e);
-            responder.respond(StartConfirm { status: e.into() });
+            responder.respond_with_error(e, |status| StartConfirm { status });
             return;
         }
 
@@ -174,6 +175,34 @@ async fn apply_changes<'a>(
     }
 }
 
+/// Check if our superframe, starting `start_time` symbols after the tracked beacon, would overlap
+/// the superframe of the tracked coordinator.
+///
+/// Our superframe must fit in the inactive portion of the tracked superframe, before its next beacon.
+fn superframe_overlaps(
+    tracked_superframe: TrackedSuperframe,
+    start_time: u32,
+    superframe_order: SuperframeOrder,
+) -> bool {
+    let superframe_duration = |superframe_order: SuperframeOrder| match superframe_order {
+        SuperframeOrder::Inactive => 0,
+        SuperframeOrder::SuperframeOrder(so) => consts::BASE_SUPERFRAME_DURATION << so,
+    };
+
+    let incoming_duration = superframe_duration(tracked_superframe.superframe_order);
+    let outgoing_duration = superframe_duration(superframe_order);
+
+    let incoming_interval = match tracked_superframe.beacon_order {
+        // Without beacons there is nothing to track, so there's no next superframe to overlap with
+        BeaconOrder::OnDemand => return start_time < incoming_duration,
+        BeaconOrder::BeaconOrder(bo) => consts::BASE_SUPERFRAME_DURATION << bo,
+    };
+
+    start_time < incoming_duration
+        || start_time >= incoming_interval
+        || start_time + outgoing_duration > incoming_interval
+}
+
 async fn update_superframe_config<P: Phy>(
     phy: &mut P,
     mac_pib: &mut MacPib,
@@ -201,3 +230,68 @@ async fn update_superframe_config<P: Phy>(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: u32 = consts::BASE_SUPERFRAME_DURATION;
+
+    fn tracked(beacon_order: u8, superframe_order: u8) -> TrackedSuperframe {
+        TrackedSuperframe {
+            beacon_order: BeaconOrder::BeaconOrder(beacon_order),
+            superframe_order: SuperframeOrder::SuperframeOrder(superframe_order),
+            battery_life_extension: false,
+        }
+    }
+
+    #[test]
+    fn superframe_in_inactive_portion_does_not_overlap() {
+        // Incoming active for 1 base duration out of 4
+        let tracked = tracked(2, 0);
+
+        assert!(!superframe_overlaps(
+            tracked,
+            BASE,
+            SuperframeOrder::SuperframeOrder(0)
+        ));
+        assert!(!superframe_overlaps(
+            tracked,
+            2 * BASE,
+            SuperframeOrder::SuperframeOrder(1)
+        ));
+        assert!(!superframe_overlaps(
+            tracked,
+            3 * BASE,
+            SuperframeOrder::Inactive
+        ));
+    }
+
+    #[test]
+    fn superframe_starting_in_active_portion_overlaps() {
+        let tracked = tracked(2, 1);
+
+        assert!(superframe_overlaps(tracked, 0, SuperframeOrder::Inactive));
+        assert!(superframe_overlaps(
+            tracked,
+            2 * BASE - 1,
+            SuperframeOrder::SuperframeOrder(0)
+        ));
+    }
+
+    #[test]
+    fn superframe_running_into_next_beacon_overlaps() {
+        let tracked = tracked(2, 0);
+
+        assert!(superframe_overlaps(
+            tracked,
+            2 * BASE,
+            SuperframeOrder::SuperframeOrder(2)
+        ));
+        assert!(superframe_overlaps(
+            tracked,
+            4 * BASE,
+            SuperframeOrder::Inactive
+        ));
+    }
+}
@@ -0,0 +1,276 @@
+//! Synchronizing with the beacons of the coordinator we're associated with (5.1.4.1)
+
+use super::{
+    MacError,
+    commander::{MacHandler, RequestResponder},
+    state::{MacState, TrackedSuperframe},
+};
+use crate::{
+    ChannelPage, DeviceAddress,
+    consts::{BASE_SUPERFRAME_DURATION, MAX_LOST_BEACONS},
+    phy::Phy,
+    pib::MacPib,
+    sap::{
+        SecurityInfo,
+        sync::{LossReason, SyncLossIndication, SyncRequest},
+    },
+    time::{Duration, Instant},
+    wire::{
+        Address,
+        beacon::{BeaconOrder, SuperframeSpecification},
+    },
+};
+
+/// The state of a synchronization started by an MLME-SYNC.request
+pub struct BeaconSync {
+    /// Keep tracking the beacons after the first one has been found
+    track_beacon: bool,
+    /// The interval of the tracked beacon. None while still searching for the first one.
+    beacon_interval: Option<Duration>,
+    /// The number of tracked beacons that were missed in a row
+    lost_beacons: u32,
+    /// When no beacon of the coordinator has been received by this time, a beacon is missed
+    pub deadline: Instant,
+    /// The time the last tracked beacon was received
+    pub last_beacon: Option<Instant>,
+}
+
+impl BeaconSync {
+    fn new(track_beacon: bool, search_end: Instant) -> Self {
+        Self {
+            track_beacon,
+            beacon_interval: None,
+            lost_beacons: 0,
+            deadline: search_end,
+            last_beacon: None,
+        }
+    }
+
+    /// Register a received beacon of the coordinator we're tracking
+    fn register_beacon(&mut self, timestamp: Instant, beacon_interval: Duration) {
+        self.beacon_interval = Some(beacon_interval);
+        self.lost_beacons = 0;
+        self.last_beacon = Some(timestamp);
+        // Beacons can come in a bit early or late, so one is only missed half an interval after it was expected
+        self.deadline = timestamp + beacon_interval + beacon_interval / 2;
+    }
+
+    /// Register that no beacon came in before the deadline.
+    /// Returns true if that means the sync is lost.
+    fn register_missed_beacon(&mut self) -> bool {
+        let Some(beacon_interval) = self.beacon_interval else {
+            // The first beacon wasn't found
+            return true;
+        };
+
+        self.lost_beacons += 1;
+        self.deadline += beacon_interval;
+
+        self.lost_beacons >= MAX_LOST_BEACONS
+    }
+}
+
+pub async fn process_sync_request<P: Phy>(
+    phy: &mut P,
+    mac_pib: &MacPib,
+    mac_state: &mut MacState<'_>,
+    responder: RequestResponder<'_, SyncRequest>,
+) {
+    let request = responder.request.clone();
+    // The request has no confirm. Not finding the beacon is reported with a sync loss indication.
+    responder.respond(());
+
+    let result: Result<Instant, MacError<P::Error>> = async {
+        let channel_page =
+            ChannelPage::try_from(request.channel_page).map_err(MacError::UnknownChannelPage)?;
+
+        phy.update_phy_pib(|phy_pib| {
+            phy_pib.current_page = channel_page;
+            phy_pib.current_channel = request.channel_number;
+        })
+        .await?;
+
+        phy.start_receive().await?;
+
+        Ok(phy.get_instant().await?)
+    }
+    .await;
+
+    let current_time = match result {
+        Ok(current_time) => current_time,
+        Err(e) => {
+            error!("Could not start the sync: {}", e);
+            return;
+        }
+    };
+
+    // A new request restarts the sync, even when we're already tracking
+    mac_state.tracked_superframe = None;
+    mac_state.beacon_sync = Some(BeaconSync::new(
+        request.track_beacon,
+        current_time + phy.symbol_period() * search_duration(mac_pib) as i64,
+    ));
+
+    debug!(
+        "Searching for the beacon of the coordinator on channel {}",
+        request.channel_number
+    );
+}
+
+/// The number of symbols to search for the first beacon
+fn search_duration(mac_pib: &MacPib) -> u32 {
+    // Without a beacon order we don't know how long the interval is, so take the longest there is
+    let beacon_order = match mac_pib.beacon_order {
+        BeaconOrder::BeaconOrder(beacon_order) => beacon_order,
+        BeaconOrder::OnDemand => 14,
+    };
+
+    BASE_SUPERFRAME_DURATION * ((1 << beacon_order) + 1)
+}
+
+/// Process a beacon received outside of a scan.
+///
+/// Returns true if the sync is done and the receiver isn't needed for it anymore.
+pub fn process_received_beacon(
+    mac_state: &mut MacState<'_>,
+    mac_pib: &MacPib,
+    source: Option<Address>,
+    superframe_spec: SuperframeSpecification,
+    timestamp: Instant,
+    symbol_period: Duration,
+) -> bool {
+    let Some(beacon_sync) = mac_state.beacon_sync.as_mut() else {
+        trace!("Ignoring a beacon, because we're not synchronizing");
+        return false;
+    };
+
+    if !source.is_some_and(|source| is_from_coordinator(mac_pib, source)) {
+        trace!("Ignoring a beacon that's not from our coordinator");
+        return false;
+    }
+
+    let beacon_interval = match superframe_spec.beacon_order {
+        BeaconOrder::BeaconOrder(beacon_order) if beacon_sync.track_beacon => {
+            BASE_SUPERFRAME_DURATION << beacon_order
+        }
+        _ => {
+            // Only the one beacon was needed, or there are no periodic beacons to track
+            debug!("Synchronized with the beacon of the coordinator");
+            mac_state.beacon_sync = None;
+            return true;
+        }
+    };
+
+    trace!("Tracking the beacon of the coordinator");
+
+    mac_state.tracked_superframe = Some(TrackedSuperframe {
+        beacon_order: superframe_spec.beacon_order,
+        superframe_order: superframe_spec.superframe_order,
+        battery_life_extension: superframe_spec.battery_life_extension,
+    });
+
+    beacon_sync.register_beacon(timestamp, symbol_period * beacon_interval as i64);
+
+    false
+}
+
+fn is_from_coordinator(mac_pib: &MacPib, source: Address) -> bool {
+    source.pan_id() == mac_pib.pan_id
+        && match DeviceAddress::from(source) {
+            DeviceAddress::Short(short_address) => short_address == mac_pib.coord_short_address,
+            DeviceAddress::Extended(extended_address) => {
+                extended_address == mac_pib.coord_extended_address
+            }
+        }
+}
+
+/// No beacon of the coordinator came in before the deadline.
+/// After too many of those in a row, stop tracking and tell the higher layer.
+pub async fn process_missed_beacon(
+    phy: &mut impl Phy,
+    mac_pib: &MacPib,
+    mac_state: &mut MacState<'_>,
+    mac_handler: &MacHandler<'_>,
+) {
+    let Some(beacon_sync) = mac_state.beacon_sync.as_mut() else {
+        return;
+    };
+
+    if !beacon_sync.register_missed_beacon() {
+        debug!(
+            "Missed {} beacons of the coordinator in a row",
+            beacon_sync.lost_beacons
+        );
+        return;
+    }
+
+    warn!("Lost the beacon of the coordinator");
+
+    mac_state.beacon_sync = None;
+    mac_state.tracked_superframe = None;
+
+    if !mac_pib.rx_on_when_idle {
+        if let Err(e) = phy.stop_receive().await {
+            error!(
+                "Could not stop the radio receiving after losing sync: {}",
+                e
+            );
+        }
+    }
+
+    let phy_pib = phy.get_phy_pib();
+    let channel_number = phy_pib.current_channel;
+    let channel_page = phy_pib.current_page as u8;
+
+    mac_handler
+        .indicate_without_response(SyncLossIndication {
+            loss_reason: LossReason::BeaconLost,
+            pan_id: mac_pib.pan_id,
+            channel_number,
+            channel_page,
+            security_info: SecurityInfo::new_none_security(),
+        })
+        .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BEACON_INTERVAL: Duration = Duration::from_seconds(1);
+
+    #[test]
+    fn first_beacon_not_found_loses_sync() {
+        let mut beacon_sync = BeaconSync::new(true, Instant::from_seconds(10));
+        assert!(beacon_sync.register_missed_beacon());
+    }
+
+    #[test]
+    fn received_beacon_resets_the_lost_beacons() {
+        let mut beacon_sync = BeaconSync::new(true, Instant::from_seconds(10));
+        beacon_sync.register_beacon(Instant::from_seconds(5), BEACON_INTERVAL);
+        assert_eq!(
+            beacon_sync.deadline,
+            Instant::from_seconds(6) + Duration::from_millis(500)
+        );
+
+        for _ in 1..MAX_LOST_BEACONS {
+            assert!(!beacon_sync.register_missed_beacon());
+        }
+        assert_eq!(beacon_sync.lost_beacons, MAX_LOST_BEACONS - 1);
+
+        // A beacon that's a bit late still counts
+        let late_beacon = Instant::from_seconds(9) + Duration::from_millis(300);
+        beacon_sync.register_beacon(late_beacon, BEACON_INTERVAL);
+        assert_eq!(beacon_sync.lost_beacons, 0);
+        assert_eq!(
+            beacon_sync.deadline,
+            late_beacon + Duration::from_millis(1500)
+        );
+
+        for _ in 1..MAX_LOST_BEACONS {
+            assert!(!beacon_sync.register_missed_beacon());
+        }
+        assert!(beacon_sync.register_missed_beacon());
+    }
+}
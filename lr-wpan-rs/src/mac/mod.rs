@@ -5,57 +5,117 @@ use core::{
 
 use crate::{
     DeviceAddress,
-    phy::{Phy, ReceivedMessage, SendContinuation, SendResult},
-    pib::MacPib,
+    phy::{FrameFilter, Phy, ReceivedMessage, SendContinuation, SendResult},
+    pib::{MacPib, TxPolicy},
     sap::{
-        RequestValue, ResponseValue, SecurityInfo, Status, associate::AssociateConfirm,
+        RequestValue, ResponseValue, SecurityInfo, Status,
+        ack::{AckConfirm, AckRequest},
+        associate::AssociateConfirm,
+        data::DataIndication,
         scan::ScanType,
     },
     time::{DelayNsExt, Duration, Instant},
-    wire::{Address, FrameType, command::Command},
+    wire::{
+        Address, FrameType, FrameVersion, TimeCorrection,
+        command::{AssociationStatus, Command},
+    },
 };
 
 mod callback;
 mod commander;
+mod coord_realignment;
+mod csma;
+mod device_table;
+mod duty_cycle;
+#[cfg(feature = "frame-dump")]
+mod frame_dump;
+#[cfg(feature = "frame-tap")]
+pub mod frame_tap;
+mod gts;
+mod mcps_data;
 mod mlme_associate;
+mod mlme_disassociate;
 mod mlme_get;
 mod mlme_reset;
 mod mlme_scan;
 mod mlme_set;
+mod mlme_sounding;
 mod mlme_start;
+mod mlme_sync;
+mod operational_state;
+mod ranging;
+mod spectrum_survey;
 mod state;
-
-pub use commander::{IndicationResponder, MacCommander};
+#[cfg(feature = "test-hooks")]
+pub mod test_hooks;
+mod unimplemented;
+
+use callback::DataRequestCallback;
+pub use commander::{
+    ErrorDetail, IndicationOverflowPolicy, IndicationResponder, MAX_INDIRECT_INDICATIONS,
+    MacCommander,
+};
 use commander::{IndirectIndicationCollection, MacHandler};
-use embassy_futures::select::{Either, Either3, select3};
+pub use device_table::{AssociatedDevice, MAX_ASSOCIATED_DEVICES};
+pub use duty_cycle::DutyCycleLimit;
+use embassy_futures::select::{Either, Either3};
 use futures::FutureExt;
+use mcps_data::process_data_request;
+pub use mlme_associate::ShortAddressAssignment;
 use mlme_associate::{process_associate_request, process_associate_response};
+use mlme_disassociate::process_disassociate_request;
 use mlme_get::process_get_request;
 use mlme_reset::process_reset_request;
 use mlme_scan::{ScanAction, process_scan_request};
-use mlme_set::process_set_request;
+use mlme_set::{process_set_many_request, process_set_request};
+use mlme_sounding::process_sounding_request;
 use mlme_start::process_start_request;
+use mlme_sync::process_sync_request;
+use operational_state::process_operational_state_request;
 use rand_core::RngCore;
-use state::{BeaconMode, DataRequestMode, MacState, PendingDataValue, ScheduledDataRequest};
+use ranging::process_ranging_request;
+use spectrum_survey::process_spectrum_survey_request;
+use state::{
+    BeaconMode, DataRequestMode, MacState, PendingData, PendingDataValue, ScheduledDataRequest,
+};
+pub use state::{MAX_PENDING_TRANSACTIONS, PendingTransactionInfo, PendingTransactionKind};
+use unimplemented::process_unimplemented_request;
 
 use crate::wire::{ExtendedAddress, Frame, FrameContent, PanId, ShortAddress};
 
-const BEACON_PLANNING_HEADROOM: Duration = Duration::from_millis(20);
-const DATA_REQUEST_PLANNING_HEADROOM: Duration = Duration::from_millis(20);
-
 /// Run the MAC layer of the IEEE protocol.
 ///
 /// This is an async function that should always be polled in the background.
 /// The given [MacCommander] is the method of communicating with the MAC.
-pub async fn run_mac_engine<'a, Rng: RngCore, Delay: DelayNsExt>(
-    mut phy: impl Phy + 'a,
+///
+/// # Priorities
+///
+/// The MAC waits on three things: radio events, responses to indications and requests.
+/// When more than one of them is ready at the same time, the radio events go first, then the responses
+/// and then the requests. The radio events include the time-critical ones, like the start and end of our own
+/// superframe and the sends that are scheduled for a specific time, so a request that comes in at the same
+/// moment can't push a beacon back.
+///
+/// Something that's already being handled isn't interrupted though. A request that waits on the radio
+/// (like an association that waits for its ack) can still make the MAC miss the start of a superframe.
+pub async fn run_mac_engine<'a, P: Phy + 'a, Rng: RngCore, Delay: DelayNsExt>(
+    mut phy: P,
     commander: &'a MacCommander,
     mut config: MacConfig<Rng, Delay>,
 ) -> ! {
-    let handler = commander.get_handler();
-    let mut mac_pib = MacPib::dummy_new();
+    let handler = commander.get_handler(config.indication_overflow_policy);
+    // The same pib as after a reset that sets the default pib, so the MAC can be used before any reset
+    let mut mac_pib = MacPib::new_default(&P::MODULATION, config.extended_address, &mut config.rng);
+    mac_pib.sync_symbol_offset = config.sync_symbol_offset;
+    mac_pib.apply_tx_policy(&config.tx_policy);
     let mut mac_state = MacState::new(&config);
-    let mut indirect_indications = core::pin::pin!(IndirectIndicationCollection::new());
+    #[cfg(feature = "frame-tap")]
+    {
+        mac_state.frame_tap = Some(handler.frame_tap());
+    }
+    let mut indirect_indications = core::pin::pin!(IndirectIndicationCollection::new(
+        config.max_indirect_indications
+    ));
 
     loop {
         let current_time = match phy.get_instant().await {
@@ -66,10 +126,23 @@ pub async fn run_mac_engine<'a, Rng: RngCore, Delay: DelayNsExt>(
             }
         };
 
-        let result = select3(
-            wait_for_radio_event(&mut phy, &mac_pib, &mac_state, &config.delay),
-            indirect_indications.as_mut().wait(current_time),
-            handler.wait_for_request(),
+        update_frame_filter(&mut phy, &mac_pib, &mut mac_state).await;
+
+        let radio_event = wait_for_radio_event(&mut phy, &mac_pib, &mac_state, &config.delay);
+        let indirect_indication = indirect_indications.as_mut().wait(current_time);
+        let request = handler.wait_for_request();
+
+        // The branches are polled in order, so the first one that's ready wins. This gives the time-critical
+        // radio events priority over the requests, see the docs above. It must match `BranchOrder::DEFAULT`.
+        #[cfg(not(feature = "test-hooks"))]
+        let result =
+            embassy_futures::select::select3(radio_event, indirect_indication, request).await;
+        #[cfg(feature = "test-hooks")]
+        let result = test_hooks::select3_ordered(
+            commander.branch_order(),
+            radio_event,
+            indirect_indication,
+            request,
         )
         .await;
 
@@ -82,6 +155,7 @@ pub async fn run_mac_engine<'a, Rng: RngCore, Delay: DelayNsExt>(
                     &mut mac_state,
                     &handler,
                     indirect_indications.as_mut(),
+                    &mut config.rng,
                     &mut config.delay,
                 )
                 .await
@@ -100,6 +174,14 @@ pub async fn run_mac_engine<'a, Rng: RngCore, Delay: DelayNsExt>(
                 .await;
             }
         }
+
+        if mac_state.device_table.take_changed() {
+            handler.publish_device_table(mac_state.device_table.devices());
+        }
+        if mac_state.message_scheduler.take_pending_data_changed() {
+            handler
+                .publish_pending_transactions(mac_state.message_scheduler.pending_transactions());
+        }
     }
 }
 
@@ -112,17 +194,35 @@ async fn handle_request<'a, Rng: RngCore, Delay: DelayNsExt>(
 ) {
     match &responder.request {
         RequestValue::Associate(_) => {
-            process_associate_request(phy, mac_pib, mac_state, responder.into_concrete()).await
+            process_associate_request(
+                phy,
+                mac_pib,
+                mac_state,
+                &mut config.rng,
+                &mut config.delay,
+                responder.into_concrete(),
+            )
+            .await
+        }
+        RequestValue::Disassociate(_) => {
+            process_disassociate_request(
+                phy,
+                mac_pib,
+                mac_state,
+                &mut config.rng,
+                &mut config.delay,
+                responder.into_concrete(),
+            )
+            .await
         }
-        RequestValue::Disassociate(_) => todo!(),
         RequestValue::Get(_) => {
             process_get_request(phy, &*mac_pib, responder.into_concrete()).await
         }
-        RequestValue::Gts(_) => todo!(),
+        RequestValue::Gts(_) => process_unimplemented_request(responder),
         RequestValue::Reset(_) => {
             process_reset_request(phy, mac_pib, mac_state, config, responder.into_concrete()).await
         }
-        RequestValue::RxEnable(_) => todo!(),
+        RequestValue::RxEnable(_) => process_unimplemented_request(responder),
         RequestValue::Scan(_) => {
             process_scan_request(phy, mac_pib, mac_state, responder.into_concrete()).await
         }
@@ -132,13 +232,47 @@ async fn handle_request<'a, Rng: RngCore, Delay: DelayNsExt>(
         RequestValue::Start(_) => {
             process_start_request(phy, mac_pib, mac_state, responder.into_concrete()).await
         }
-        RequestValue::Sync(_) => todo!(),
-        RequestValue::Poll(_) => todo!(),
-        RequestValue::Dps(_) => todo!(),
-        RequestValue::Sounding(_) => todo!(),
-        RequestValue::Calibrate(_) => todo!(),
-        RequestValue::Data(_) => todo!(),
-        RequestValue::Purge(_) => todo!(),
+        RequestValue::Sync(_) => {
+            process_sync_request(phy, mac_pib, mac_state, responder.into_concrete()).await
+        }
+        RequestValue::Poll(_) => process_unimplemented_request(responder),
+        RequestValue::Dps(_) => process_unimplemented_request(responder),
+        RequestValue::Sounding(_) => process_sounding_request(responder.into_concrete()),
+        RequestValue::Calibrate(_) => process_unimplemented_request(responder),
+        RequestValue::Data(_) => {
+            process_data_request(
+                phy,
+                mac_pib,
+                mac_state,
+                &mut config.rng,
+                &mut config.delay,
+                responder.into_concrete(),
+            )
+            .await
+        }
+        RequestValue::Purge(_) => process_unimplemented_request(responder),
+        RequestValue::SpectrumSurvey(_) => {
+            process_spectrum_survey_request(phy, mac_state, responder.into_concrete()).await
+        }
+        RequestValue::Ack(_) => {
+            process_ack_request(phy, mac_pib, mac_state, responder.into_concrete()).await
+        }
+        RequestValue::Ranging(_) => {
+            process_ranging_request(
+                phy,
+                mac_pib,
+                mac_state,
+                &mut config.delay,
+                responder.into_concrete(),
+            )
+            .await
+        }
+        RequestValue::OperationalState(_) => {
+            process_operational_state_request(mac_state, responder.into_concrete())
+        }
+        RequestValue::SetMany(_) => {
+            process_set_many_request(phy, &mut mac_pib.pib_write, responder.into_concrete()).await
+        }
     }
 }
 
@@ -162,8 +296,11 @@ async fn handle_response(
         crate::sap::ResponseValue::Associate(associate_response) => {
             process_associate_response(associate_response, current_time, mac_state).await
         }
-        crate::sap::ResponseValue::Orphan(_orphan_response) => todo!(),
-        crate::sap::ResponseValue::None => todo!(),
+        crate::sap::ResponseValue::Orphan(_orphan_response) => {
+            warn!("Orphan responses are not implemented, dropping it");
+        }
+        // Nothing to do for indications that don't require a response
+        crate::sap::ResponseValue::None => {}
     }
 }
 
@@ -172,8 +309,105 @@ async fn handle_response(
 pub struct MacConfig<Rng: RngCore, Delay: DelayNsExt> {
     /// The unique EUI-64 address used by the mac layer
     pub extended_address: ExtendedAddress,
+    /// The source of all randomness in the mac layer, like the initial sequence numbers and the CSMA-CA backoffs.
+    ///
+    /// Seed this deterministically to get reproducible behavior, e.g. in tests.
     pub rng: Rng,
     pub delay: Delay,
+    /// When true, the mac acknowledges received frames that request it.
+    ///
+    /// Turn this off to let the higher layer decide when to acknowledge, using the [AckRequest](crate::sap::ack::AckRequest).
+    pub auto_ack: bool,
+    /// The offset in symbols between the timestamps the phy takes and the timestamps the mac reports.
+    ///
+    /// This becomes the read-only macSyncSymbolOffset when the pib is reset. Leave it at 0 if the
+    /// timestamps of the phy are already at the symbol boundary the higher layer expects.
+    pub sync_symbol_offset: u16,
+    /// What to do with indications when the higher layer doesn't keep up with them
+    pub indication_overflow_policy: IndicationOverflowPolicy,
+    /// The maximum number of indications that wait on a response of the higher layer at the same time,
+    /// like the association requests of devices. At most [MAX_INDIRECT_INDICATIONS].
+    ///
+    /// When this is reached, new ones are dropped with the status [TransactionOverflow](crate::sap::Status::TransactionOverflow).
+    /// Coordinators serving many devices may want to raise this.
+    pub max_indirect_indications: usize,
+    /// How hard the mac tries to get its frames across, like the number of retransmissions.
+    ///
+    /// This is applied to the pib when the mac starts and when the pib is reset to its defaults.
+    pub tx_policy: TxPolicy,
+    /// When true, the mac calculates the FCS of the frames it sends and checks it on the frames it receives.
+    ///
+    /// Turn this on when the phy doesn't append and check the FCS in hardware, but the other devices
+    /// on the network do expect one. Frames with a bad FCS are dropped.
+    pub mac_fcs: bool,
+    /// When true, the enhanced acks the mac sends carry a Time Correction IE.
+    ///
+    /// Enhanced acks are sent for frames of the 2015 version. The correction tells the sender
+    /// how far the frame was off the backoff period boundaries of our own superframe,
+    /// so it can adjust its timing. Outside of our own superframe, the correction is 0.
+    pub ack_time_correction: bool,
+    /// If some, the mac doesn't transmit more than the limit allows.
+    ///
+    /// Transmissions that would go over it are refused with the status [LimitReached](crate::sap::Status::LimitReached).
+    /// Only the frames sent for a request are refused, but the frames the mac sends by itself
+    /// (like beacons and acks) do count towards the limit.
+    pub duty_cycle_limit: Option<DutyCycleLimit>,
+    /// How long before a scheduled transmission the mac starts preparing it
+    pub planning_headroom: PlanningHeadroom,
+    /// If some, this picks the short address of a device that asks us for association and for a short address.
+    ///
+    /// It's asked when the association request comes in. The address it returns is in the
+    /// [AssociateIndication](crate::sap::associate::AssociateIndication), so the higher layer can respond with it.
+    /// Addresses that can't be given to a device, like the broadcast address, and addresses that are
+    /// already in use are refused.
+    pub short_address_assignment: Option<ShortAddressAssignment>,
+}
+
+impl<Rng: RngCore, Delay: DelayNsExt> MacConfig<Rng, Delay> {
+    /// A config with the default for every setting that has one.
+    ///
+    /// Change the settings you need with the struct update syntax, so new settings don't break your code:
+    /// `MacConfig { auto_ack: false, ..MacConfig::new(extended_address, rng, delay) }`
+    pub fn new(extended_address: ExtendedAddress, rng: Rng, delay: Delay) -> Self {
+        Self {
+            extended_address,
+            rng,
+            delay,
+            auto_ack: true,
+            sync_symbol_offset: 0,
+            indication_overflow_policy: IndicationOverflowPolicy::default(),
+            max_indirect_indications: 4,
+            tx_policy: TxPolicy::default(),
+            mac_fcs: false,
+            ack_time_correction: false,
+            duty_cycle_limit: None,
+            planning_headroom: PlanningHeadroom::default(),
+            short_address_assignment: None,
+        }
+    }
+}
+
+/// The time the mac reserves before a scheduled transmission to prepare it and hand it to the phy.
+///
+/// During the headroom the mac waits on the transmission and doesn't handle anything else.
+/// Fast radios (like UWB) need very little of it, while slow MCUs with sub-GHz radios may need more
+/// than the default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct PlanningHeadroom {
+    /// The headroom before the beacon that starts our own superframe
+    pub beacon: Duration,
+    /// The headroom before a data request that is scheduled for a specific time
+    pub data_request: Duration,
+}
+
+impl Default for PlanningHeadroom {
+    fn default() -> Self {
+        Self {
+            beacon: Duration::from_millis(20),
+            data_request: Duration::from_millis(20),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -190,6 +424,15 @@ impl<PE: Debug> Display for MacError<PE> {
     }
 }
 
+impl<PE: core::error::Error + 'static> core::error::Error for MacError<PE> {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            MacError::PhyError(e) => Some(e),
+            MacError::UnsupportedAttribute | MacError::UnknownChannelPage(_) => None,
+        }
+    }
+}
+
 impl<PE> From<PE> for MacError<PE> {
     fn from(v: PE) -> Self {
         Self::PhyError(v)
@@ -206,6 +449,29 @@ impl<PE> From<MacError<PE>> for Status {
     }
 }
 
+/// Give the phy the addresses to filter frames on in hardware, if they've changed.
+///
+/// In promiscuous mode and during scans all frames must come through, so the filter is disabled then.
+async fn update_frame_filter<P: Phy>(phy: &mut P, mac_pib: &MacPib, mac_state: &mut MacState<'_>) {
+    let frame_filter = (!mac_pib.promiscuous_mode && mac_state.current_scan_process.is_none())
+        .then_some(FrameFilter {
+            pan_id: mac_pib.pan_id,
+            short_address: mac_pib.short_address,
+            extended_address: mac_pib.extended_address,
+            pan_coordinator: mac_state.is_pan_coordinator,
+        });
+
+    if frame_filter == mac_state.frame_filter {
+        return;
+    }
+
+    match phy.set_frame_filter(frame_filter).await {
+        // Only remembered once the phy has it, so a failed update is tried again the next time around
+        Ok(()) => mac_state.frame_filter = frame_filter,
+        Err(e) => error!("Could not update the frame filter of the phy: {}", e),
+    }
+}
+
 /// Wait for a radio event. The event must be processed by the [handle_radio_event] function.
 /// The split is there because it allows this function to be cancellable.
 async fn wait_for_radio_event<P: Phy>(
@@ -224,6 +490,11 @@ async fn wait_for_radio_event<P: Phy>(
     let symbol_period = phy.symbol_period();
     let current_time_symbols = current_time / symbol_period;
 
+    // The frames that came in while we were busy are older than anything else we could wait on
+    if !mac_state.deferred_messages.is_empty() {
+        return RadioEvent::DeferredMessage;
+    }
+
     // TODO: Figure out when exactly we should put the radio in RX
     // - For example when PAN coordinator
     // - For example when PIB says so
@@ -238,7 +509,6 @@ async fn wait_for_radio_event<P: Phy>(
         mac_pib,
         mac_state,
         current_time,
-        current_time_symbols,
         symbol_period,
         delay.clone(),
     );
@@ -251,6 +521,15 @@ async fn wait_for_radio_event<P: Phy>(
         symbol_period,
     );
 
+    let batt_life_ext_window_end = wait_for_batt_life_ext_window_end(
+        mac_state,
+        current_time_symbols,
+        delay.clone(),
+        symbol_period,
+    );
+
+    let beacon_missed = wait_for_beacon_missed(mac_state, current_time, delay.clone());
+
     let scan_action = wait_for_channel_scan_action(mac_state, current_time, delay.clone());
 
     let independent_data_request =
@@ -274,6 +553,12 @@ async fn wait_for_radio_event<P: Phy>(
         event = own_superframe_end.fuse() => {
             event
         }
+        event = batt_life_ext_window_end.fuse() => {
+            event
+        }
+        event = beacon_missed.fuse() => {
+            event
+        }
         event = scan_action.fuse() => {
             event
         }
@@ -283,6 +568,7 @@ async fn wait_for_radio_event<P: Phy>(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn handle_radio_event<'a, P: Phy>(
     event: RadioEvent<P>,
     phy: &mut P,
@@ -290,6 +576,7 @@ async fn handle_radio_event<'a, P: Phy>(
     mac_state: &mut MacState<'a>,
     mac_handler: &MacHandler<'a>,
     mut indirect_indications: Pin<&mut IndirectIndicationCollection<'a>>,
+    rng: &mut impl RngCore,
     delay: &mut impl DelayNsExt,
 ) {
     let mut next_events = arraydeque::ArrayDeque::<_, 4>::new();
@@ -297,7 +584,8 @@ async fn handle_radio_event<'a, P: Phy>(
 
     while let Some(event) = next_events.pop_front() {
         match event {
-            RadioEvent::Error => todo!(),
+            // The error has been logged where it happened, so there's nothing left to do
+            RadioEvent::Error => {}
             RadioEvent::BeaconRequested => send_beacon(mac_state, mac_pib, phy, None, true).await,
             RadioEvent::OwnSuperframeStart { start_time } => {
                 send_beacon(mac_state, mac_pib, phy, Some(start_time), false).await
@@ -308,6 +596,7 @@ async fn handle_radio_event<'a, P: Phy>(
             }
             RadioEvent::OwnSuperframeEnd => {
                 mac_state.own_superframe_active = false;
+                mac_state.batt_life_ext_window_end = None;
 
                 if !mac_pib.rx_on_when_idle {
                     if let Err(e) = phy.stop_receive().await {
@@ -318,6 +607,29 @@ async fn handle_radio_event<'a, P: Phy>(
                     }
                 }
             }
+            RadioEvent::BattLifeExtWindowEnd => {
+                mac_state.batt_life_ext_window_end = None;
+
+                if !mac_pib.rx_on_when_idle {
+                    trace!("Battery life extension receive window has ended");
+                    if let Err(e) = phy.stop_receive().await {
+                        error!(
+                            "Could not stop the radio receiving at the end of the battery life extension window: {}",
+                            e
+                        );
+                    }
+                }
+            }
+            RadioEvent::BeaconMissed => {
+                mlme_sync::process_missed_beacon(phy, mac_pib, mac_state, mac_handler).await
+            }
+            RadioEvent::SyncDone => {
+                if !mac_pib.rx_on_when_idle {
+                    if let Err(e) = phy.stop_receive().await {
+                        error!("Could not stop the radio receiving after the sync: {}", e);
+                    }
+                }
+            }
             RadioEvent::PhyWaitDone { context } => match phy.process(context).await {
                 Ok(Some(message)) => {
                     process_message::<P>(
@@ -336,6 +648,21 @@ async fn handle_radio_event<'a, P: Phy>(
                     error!("Phy process error: {}", e);
                 }
             },
+            RadioEvent::DeferredMessage => {
+                if let Some(message) = mac_state.deferred_messages.pop_front() {
+                    process_tapped_message::<P>(
+                        message,
+                        mac_state,
+                        mac_pib,
+                        mac_handler,
+                        indirect_indications.as_mut(),
+                        phy.symbol_period(),
+                        &mut next_events,
+                        false,
+                    )
+                    .await;
+                }
+            }
             RadioEvent::ScanAction(scan_action) => {
                 debug!("Performing scan action");
                 perform_scan_action(scan_action, phy, mac_state, mac_pib).await
@@ -350,17 +677,76 @@ async fn handle_radio_event<'a, P: Phy>(
                     phy,
                     mac_state,
                     mac_pib,
+                    mac_handler,
+                    indirect_indications.as_mut(),
+                    rng,
                     delay,
+                    &mut next_events,
                 )
                 .await
             }
             RadioEvent::SendAck {
                 receive_time,
+                frame_length,
                 seq,
                 frame_pending,
+                enhanced,
             } => {
                 debug!("Sending ack");
-                send_ack(phy, mac_pib, mac_state, receive_time, seq, frame_pending).await
+                let frame_duration =
+                    mac_state.received_frame_duration(phy.get_phy_pib(), frame_length);
+                let receive_end_time = receive_time + phy.symbol_period() * frame_duration as i64;
+                if let Err(e) = send_ack(
+                    phy,
+                    mac_pib,
+                    mac_state,
+                    receive_time,
+                    receive_end_time,
+                    seq,
+                    frame_pending,
+                    enhanced,
+                )
+                .await
+                {
+                    error!("Could not send an ack: {}", e);
+                }
+            }
+            RadioEvent::SendRangingResponse {
+                poll_receive_time,
+                seq,
+                initiator,
+            } => {
+                debug!("Sending ranging response");
+                if let Err(e) = ranging::send_ranging_response(
+                    phy,
+                    mac_pib,
+                    mac_state,
+                    poll_receive_time,
+                    seq,
+                    initiator,
+                )
+                .await
+                {
+                    error!("Could not send a ranging response: {}", e);
+                }
+            }
+            RadioEvent::DataReceived(indication) => {
+                debug!("Indicating received data");
+                mac_handler.indicate_without_response(indication).await
+            }
+            RadioEvent::CoordinatorRealignment {
+                realignment,
+                security_info,
+            } => {
+                debug!("Following the coordinator realignment");
+                coord_realignment::process_received_coordinator_realignment(
+                    phy,
+                    mac_pib,
+                    mac_handler,
+                    realignment,
+                    security_info,
+                )
+                .await
             }
             RadioEvent::SendPendingData {
                 request_receive_time,
@@ -371,6 +757,8 @@ async fn handle_radio_event<'a, P: Phy>(
                     phy,
                     mac_pib,
                     mac_state,
+                    rng,
+                    delay,
                     request_receive_time,
                     device_address,
                 )
@@ -384,6 +772,8 @@ async fn send_pending_data(
     phy: &mut impl Phy,
     mac_pib: &mut MacPib,
     mac_state: &mut MacState<'_>,
+    rng: &mut impl RngCore,
+    delay: &mut impl DelayNsExt,
     #[expect(unused, reason = "TODO to use")] request_receive_time: Instant,
     device_address: DeviceAddress,
 ) {
@@ -391,20 +781,24 @@ async fn send_pending_data(
 
     let data = mac_state
         .message_scheduler
-        .take_pending_data(device_address);
-    let has_more_data = mac_state.message_scheduler.has_pending_data(device_address);
+        .take_pending_data(device_address, &mac_state.device_table);
+    let has_more_data = mac_state
+        .message_scheduler
+        .has_pending_data(device_address, &mac_state.device_table);
 
-    let dsn = mac_pib.dsn.increment();
+    let dsn = mac_pib.dsn.take_next();
 
     let frame = match data.as_ref().map(|pd| &pd.data_value) {
         Some(PendingDataValue::AssociationResponse {
             short_address,
             association_status,
+            ..
         }) => Frame {
             header: wire::Header {
                 frame_type: wire::FrameType::MacCommand,
                 frame_pending: has_more_data,
-                ack_request: true,
+                ack_request: Command::AssociationResponse(*short_address, *association_status)
+                    .requires_ack(),
                 pan_id_compress: true,
                 seq_no_suppress: false,
                 ie_present: false,
@@ -416,6 +810,7 @@ async fn send_pending_data(
                     mac_pib.extended_address,
                 )),
                 auxiliary_security_header: None,
+                time_correction: None,
             },
             content: wire::FrameContent::Command(Command::AssociationResponse(
                 *short_address,
@@ -424,6 +819,28 @@ async fn send_pending_data(
             payload: &[],
             footer: [0, 0],
         },
+        Some(PendingDataValue::DisassociationNotification { reason }) => Frame {
+            header: wire::Header {
+                frame_type: wire::FrameType::MacCommand,
+                frame_pending: has_more_data,
+                ack_request: Command::DisassociationNotification(*reason).requires_ack(),
+                pan_id_compress: true,
+                seq_no_suppress: false,
+                ie_present: false,
+                version: wire::FrameVersion::Ieee802154_2003,
+                seq: dsn,
+                destination: Some(device_address.with_pan(mac_pib.pan_id)),
+                source: Some(wire::Address::Extended(
+                    mac_pib.pan_id,
+                    mac_pib.extended_address,
+                )),
+                auxiliary_security_header: None,
+                time_correction: None,
+            },
+            content: wire::FrameContent::Command(Command::DisassociationNotification(*reason)),
+            payload: &[],
+            footer: [0, 0],
+        },
         // If no pending data, send an empty data response
         None => Frame {
             header: wire::Header {
@@ -441,6 +858,7 @@ async fn send_pending_data(
                     mac_pib.extended_address,
                 )),
                 auxiliary_security_header: None,
+                time_correction: None,
             },
             content: wire::FrameContent::Data,
             payload: &[],
@@ -451,74 +869,274 @@ async fn send_pending_data(
     let ack_required = frame.header.ack_request;
     let message = mac_state.serialize_frame(frame);
 
-    let ack_wait_duration = mac_pib.ack_wait_duration(phy.get_phy_pib()) as i64;
-
     // TODO: This can be sent without CSMA too if we're in a superframe and there's time remaining, and then only on a backoff period boundary: 5.1.6.3
     // That should probably be done if we're in a superframe since it's nice and efficient
-    let ack = match phy
-        .send(
+    if !ack_required {
+        // Only the empty data frame is sent without an ack, so there's nothing to follow up on
+        match send_with_csma(
+            phy,
+            mac_pib,
+            mac_state,
+            rng,
+            delay,
             &message,
             None,
-            false,
-            true,
-            if ack_required {
-                SendContinuation::WaitForResponse {
-                    turnaround_time: phy.symbol_period() * crate::consts::TURNAROUND_TIME as i64,
-                    timeout: phy.symbol_period() * ack_wait_duration,
-                }
-            } else {
-                SendContinuation::Idle
-            },
+            SendContinuation::Idle,
         )
         .await
-    {
-        Ok(SendResult::Success(_, None)) => None,
-        Ok(SendResult::Success(_, Some(mut response))) => {
-            // See if what we received was an Ack for us
-            match mac_state.deserialize_frame(&mut response.data) {
-                Some(frame) => {
-                    if matches!(frame.header.frame_type, FrameType::Acknowledgement)
-                        && frame.header.seq == dsn
-                    {
-                        Some((response.timestamp, frame.header.frame_pending))
-                    } else {
-                        None
-                    }
-                }
-                None => None,
+        {
+            Ok(SendResult::Success(send_time, _)) => {
+                mac_state.register_transmission(phy, mac_pib, send_time, &message)
             }
+            Ok(SendResult::ChannelAccessFailure) => {
+                warn!("CSMA failed for sending the empty data response")
+            }
+            Err(e) => error!("Could not send the empty data response: {}", e),
         }
-        Ok(SendResult::ChannelAccessFailure) => {
+        return;
+    }
+
+    let ack_timestamp = match send_with_ack(
+        phy, mac_pib, mac_state, rng, delay, &message, dsn, None, None,
+    )
+    .await
+    {
+        Ok(AckedSendResult::Acked { timestamp, .. }) => Some(timestamp),
+        Ok(AckedSendResult::NoAck) => {
+            warn!("The requested data was not acknowledged, not even after retransmitting it");
+            None
+        }
+        Ok(AckedSendResult::ChannelAccessFailure) => {
             warn!("CSMA failed for sending request data response");
-            if let Some(data) = data {
-                // We could not send, so push back onto the queue
-                mac_state.message_scheduler.push_pending_data(data).unwrap();
-            }
-            // TODO: We probably need to do something here
-            return;
+            None
+        }
+        Ok(AckedSendResult::LimitReached) => {
+            warn!("The duty cycle limit was reached for sending request data response");
+            None
         }
         Err(e) => {
-            error!("Could not send an ack: {}", e);
-            // TODO: Not sure how we can recover
-            return;
+            error!("Could not send the requested data: {}", e);
+            None
         }
     };
 
-    if ack_required && ack.is_none() {
-        todo!("No ack received. No retry implemented yet");
+    let Some(ack_timestamp) = ack_timestamp else {
+        if let Some(data) = data {
+            // The data didn't arrive, so push it back onto the queue for the next data request
+            mac_state.message_scheduler.push_pending_data(data).unwrap();
+        }
+        return;
+    };
+
+    // The device has received what we sent, so now it's (dis)associated
+    match data {
+        Some(PendingData {
+            device: DeviceAddress::Extended(extended_address),
+            data_value:
+                PendingDataValue::AssociationResponse {
+                    short_address,
+                    association_status:
+                        AssociationStatus::Successful | AssociationStatus::FastAssociationSuccesful,
+                    rx_on_when_idle,
+                },
+            ..
+        }) => {
+            let result = mac_state.device_table.insert(AssociatedDevice {
+                extended_address,
+                short_address,
+                rx_on_when_idle,
+                last_seen: ack_timestamp,
+            });
+
+            if let Err(status) = result {
+                warn!(
+                    "Could not add the associated device {:?} to the device table: {}",
+                    extended_address, status
+                );
+            }
+        }
+        Some(PendingData {
+            device,
+            data_value: PendingDataValue::DisassociationNotification { .. },
+            ..
+        }) => {
+            mac_state.device_table.remove(device);
+        }
+        _ => {}
     }
 }
 
-async fn send_ack(
-    phy: &mut impl Phy,
+/// The result of [send_with_ack]
+enum AckedSendResult {
+    /// The ack was received
+    Acked {
+        /// The time the transmission that was acked was sent
+        send_time: Instant,
+        /// The time the ack was received
+        timestamp: Instant,
+        frame_pending: bool,
+    },
+    /// No ack was received, not even after all retransmissions
+    NoAck,
+    ChannelAccessFailure,
+    /// Sending the frame would go over the [DutyCycleLimit]
+    LimitReached,
+}
+
+/// Send a frame as soon as possible using unslotted CSMA-CA (5.1.1.4).
+///
+/// The phy does a single CCA for every try, the random backoffs in between are done here.
+/// Without a `data_rate`, the frame is sent with the default data rate of the phy.
+#[allow(clippy::too_many_arguments)]
+async fn send_with_csma<P: Phy>(
+    phy: &mut P,
+    mac_pib: &MacPib,
+    mac_state: &MacState<'_>,
+    rng: &mut impl RngCore,
+    delay: &mut impl DelayNsExt,
+    data: &[u8],
+    data_rate: Option<u8>,
+    continuation: SendContinuation,
+) -> Result<SendResult, P::Error> {
+    let mut csma = csma::CsmaCa::new(mac_state, mac_pib);
+
+    loop {
+        csma.backoff(mac_pib, phy.symbol_period(), rng, delay).await;
+
+        let send_result = match data_rate {
+            Some(data_rate) => {
+                phy.send_with_data_rate(data, None, false, true, data_rate, continuation)
+                    .await?
+            }
+            None => phy.send(data, None, false, true, continuation).await?,
+        };
+
+        if matches!(send_result, SendResult::ChannelAccessFailure) && csma.channel_busy(mac_pib) {
+            trace!("The channel is busy, backing off again");
+            continue;
+        }
+
+        return Ok(send_result);
+    }
+}
+
+/// Send a frame that requests an ack using CSMA-CA and wait on the ack.
+///
+/// When the ack doesn't arrive in time, the frame is retransmitted up to macMaxFrameRetries times (5.1.6.4.4).
+/// Only the first transmission is sent at the `send_time`, the retransmissions follow right away.
+/// A transmission at the `send_time` does a single CCA, because it can't back off.
+/// Without a `data_rate`, the frame is sent with the default data rate of the phy.
+#[allow(clippy::too_many_arguments)]
+async fn send_with_ack<P: Phy>(
+    phy: &mut P,
+    mac_pib: &mut MacPib,
+    mac_state: &mut MacState<'_>,
+    rng: &mut impl RngCore,
+    delay: &mut impl DelayNsExt,
+    data: &[u8],
+    seq: u8,
+    send_time: Option<Instant>,
+    data_rate: Option<u8>,
+) -> Result<AckedSendResult, P::Error> {
+    let ack_timeout = mac_pib.ack_timeout(phy.get_phy_pib(), phy.turnaround_time_symbols()) as i64;
+
+    for attempt in 0..=mac_pib.max_frame_retries {
+        if attempt > 0 {
+            debug!(
+                "No ack received, retransmission {} of {}",
+                attempt, mac_pib.max_frame_retries
+            );
+        }
+
+        let attempt_send_time = if attempt == 0 { send_time } else { None };
+        let now = match attempt_send_time {
+            Some(send_time) => send_time,
+            None => phy.get_instant().await?,
+        };
+        if !mac_state.duty_cycle_allows(phy, now, data) {
+            warn!("Not sending a frame, because it would go over the duty cycle limit");
+            return Ok(AckedSendResult::LimitReached);
+        }
+
+        let continuation = SendContinuation::WaitForResponse {
+            turnaround_time: phy.symbol_period() * phy.turnaround_time_symbols() as i64,
+            timeout: phy.symbol_period() * ack_timeout,
+        };
+        let send_result = match (attempt_send_time, data_rate) {
+            (None, data_rate) => {
+                send_with_csma(
+                    phy,
+                    mac_pib,
+                    mac_state,
+                    rng,
+                    delay,
+                    data,
+                    data_rate,
+                    continuation,
+                )
+                .await?
+            }
+            (Some(send_time), Some(data_rate)) => {
+                phy.send_with_data_rate(data, Some(send_time), false, true, data_rate, continuation)
+                    .await?
+            }
+            (Some(send_time), None) => {
+                phy.send(data, Some(send_time), false, true, continuation)
+                    .await?
+            }
+        };
+
+        if let SendResult::Success(send_time, _) = send_result {
+            mac_state.register_transmission(phy, mac_pib, send_time, data);
+        }
+
+        match send_result {
+            SendResult::Success(send_time, Some(mut response)) => {
+                mac_state.tap_received(&response);
+
+                // See if what we received was an Ack for us
+                match mac_state.deserialize_message(response.crc_ok, &mut response.data) {
+                    Some(frame)
+                        if matches!(frame.header.frame_type, FrameType::Acknowledgement)
+                            && frame.header.seq == seq =>
+                    {
+                        return Ok(AckedSendResult::Acked {
+                            send_time,
+                            timestamp: response.timestamp,
+                            frame_pending: frame.header.frame_pending,
+                        });
+                    }
+                    _ => {}
+                }
+            }
+            SendResult::Success(_, None) => {}
+            SendResult::ChannelAccessFailure => return Ok(AckedSendResult::ChannelAccessFailure),
+        }
+    }
+
+    Ok(AckedSendResult::NoAck)
+}
+
+/// Send an ack for a frame.
+///
+/// The ack is sent macSIFSPeriod after the `receive_end_time`, the moment the last symbol of the frame came in.
+/// When `enhanced` is true, an enhanced ack is sent, which is needed for frames of the 2015 version.
+#[allow(clippy::too_many_arguments)]
+async fn send_ack<P: Phy>(
+    phy: &mut P,
     mac_pib: &mut MacPib,
     mac_state: &mut MacState<'_>,
     receive_time: Instant,
+    receive_end_time: Instant,
     seq: u8,
     frame_pending: bool,
-) {
+    enhanced: bool,
+) -> Result<(), P::Error> {
     use crate::wire;
 
+    let time_correction = (enhanced && mac_state.ack_time_correction)
+        .then(|| ack_time_correction(mac_pib, mac_state, receive_time, phy.symbol_period()));
+
     let data = mac_state.serialize_frame(Frame {
         header: wire::Header {
             frame_type: wire::FrameType::Acknowledgement,
@@ -526,12 +1144,17 @@ async fn send_ack(
             ack_request: false,
             pan_id_compress: false,
             seq_no_suppress: false,
-            ie_present: false,
-            version: wire::FrameVersion::Ieee802154_2003,
+            ie_present: time_correction.is_some(),
+            version: if enhanced {
+                wire::FrameVersion::Ieee802154
+            } else {
+                wire::FrameVersion::Ieee802154_2003
+            },
             seq,
             destination: None,
             source: None,
             auxiliary_security_header: None,
+            time_correction,
         },
         content: wire::FrameContent::Acknowledgement,
         payload: &[],
@@ -539,7 +1162,7 @@ async fn send_ack(
     });
 
     // TODO: Actually schedule this according to the rules (5.1.6.4.2)
-    let ack_send_time = receive_time + phy.symbol_period() * mac_pib.sifs_period as i64;
+    let ack_send_time = receive_end_time + phy.symbol_period() * mac_pib.sifs_period as i64;
 
     match phy
         .send(
@@ -549,56 +1172,165 @@ async fn send_ack(
             false,
             SendContinuation::Idle,
         )
-        .await
+        .await?
     {
-        Ok(SendResult::Success(_, _)) => {
-            // Cool, continue
+        SendResult::Success(send_time, _) => {
+            mac_state.register_transmission(phy, mac_pib, send_time, &data);
+            Ok(())
         }
-        Ok(SendResult::ChannelAccessFailure) => {
+        SendResult::ChannelAccessFailure => {
             unreachable!();
         }
-        Err(e) => {
-            error!("Could not send an ack: {}", e);
+    }
+}
+
+/// The time correction for the enhanced ack of a frame received at the receive time (7.4.2.7).
+///
+/// In our own superframe, frames are sent on the backoff period boundaries (5.1.1.4), so the correction
+/// is the distance to the nearest one. Outside of it there's nothing to compare against.
+fn ack_time_correction(
+    mac_pib: &MacPib,
+    mac_state: &MacState<'_>,
+    receive_time: Instant,
+    symbol_period: Duration,
+) -> TimeCorrection {
+    let microseconds = match mac_state.beacon_mode {
+        BeaconMode::OnAutonomous => {
+            let backoff_period = symbol_period * crate::consts::UNIT_BACKOFF_PERIOD as i64;
+            let superframe_start =
+                Instant::from_ticks((symbol_period * mac_pib.beacon_tx_time).ticks() as u64);
+            let offset = Duration::from_ticks(
+                receive_time
+                    .duration_since(superframe_start)
+                    .ticks()
+                    .rem_euclid(backoff_period.ticks()),
+            );
+
+            // Positive when the frame was early
+            if offset * 2 <= backoff_period {
+                -offset.micros()
+            } else {
+                (backoff_period - offset).micros()
+            }
         }
+        BeaconMode::Off | BeaconMode::OnTracking { .. } => 0,
+    };
+
+    TimeCorrection {
+        microseconds: microseconds.clamp(i16::MIN as i64, i16::MAX as i64) as i16,
+        nack: false,
     }
 }
 
-// 5.1.6.3
-async fn perform_data_request(
-    data_request: ScheduledDataRequest<'_>,
+async fn process_ack_request(
     phy: &mut impl Phy,
+    mac_pib: &mut MacPib,
     mac_state: &mut MacState<'_>,
+    responder: commander::RequestResponder<'_, AckRequest>,
+) {
+    let AckRequest {
+        seq,
+        receive_time,
+        frame_pending,
+    } = responder.request;
+
+    // The higher layer doesn't tell the version or the length of the frame it acks,
+    // so a plain ack is sent and the SIFS is counted from the receive time
+    match send_ack(
+        phy,
+        mac_pib,
+        mac_state,
+        receive_time,
+        receive_time,
+        seq,
+        frame_pending,
+        false,
+    )
+    .await
+    {
+        Ok(()) => responder.respond(AckConfirm {
+            status: Status::Success,
+        }),
+        Err(e) => {
+            responder.respond_with_error(MacError::PhyError(e), |status| AckConfirm { status })
+        }
+    }
+}
+
+// 5.1.6.3
+#[allow(clippy::too_many_arguments)]
+async fn perform_data_request<'a, P: Phy>(
+    data_request: ScheduledDataRequest<'a>,
+    phy: &mut P,
+    mac_state: &mut MacState<'a>,
     mac_pib: &mut MacPib,
+    mac_handler: &MacHandler<'a>,
+    indirect_indications: Pin<&mut IndirectIndicationCollection<'a>>,
+    rng: &mut impl RngCore,
     delay: &mut impl DelayNsExt,
+    next_events: &mut arraydeque::ArrayDeque<RadioEvent<P>, 4>,
 ) {
     let send_time = match data_request.mode {
-        DataRequestMode::InSuperFrame => todo!(),
+        DataRequestMode::InSuperFrame => {
+            warn!("Data requests in the superframe are not implemented");
+            data_request
+                .callback
+                .abort(Status::NotImplemented, mac_pib)
+                .await;
+            return;
+        }
         DataRequestMode::Independent { timestamp } => timestamp,
     };
 
     let (destination_address, source_address) = match data_request.trigger {
-        state::DataRequestTrigger::BeaconPendingDataIndication => todo!(),
-        state::DataRequestTrigger::MlmePoll => todo!(),
-        state::DataRequestTrigger::Association => {
-            let destination = if mac_pib.coord_short_address.0 == 0xFFFE {
+        state::DataRequestTrigger::BeaconPendingDataIndication
+        | state::DataRequestTrigger::MlmePoll => {
+            warn!("Data requests of a beacon indication or a poll are not implemented");
+            data_request
+                .callback
+                .abort(Status::NotImplemented, mac_pib)
+                .await;
+            return;
+        }
+        state::DataRequestTrigger::Association { coord_address } => {
+            // We don't have a short address yet
+            let source = Address::Extended(coord_address.pan_id(), mac_pib.extended_address);
+
+            (Some(coord_address), source)
+        }
+        state::DataRequestTrigger::FramePending => {
+            if mac_pib.pan_id == PanId::broadcast() {
+                // The coordinator has told us to leave in the meantime
+                trace!("Not picking up more data, we're no longer in the PAN");
+                return;
+            }
+
+            let destination = if mac_pib.coord_short_address.0 >= 0xFFFE {
                 Address::Extended(mac_pib.pan_id, mac_pib.coord_extended_address)
             } else {
                 Address::Short(mac_pib.pan_id, mac_pib.coord_short_address)
             };
 
-            let source = Address::Extended(mac_pib.pan_id, mac_pib.extended_address);
+            let source = if mac_pib.short_address.0 >= 0xFFFE {
+                Address::Extended(mac_pib.pan_id, mac_pib.extended_address)
+            } else {
+                Address::Short(mac_pib.pan_id, mac_pib.short_address)
+            };
 
             (Some(destination), source)
         }
     };
 
-    let dsn = mac_pib.dsn.increment();
+    let dsn = mac_pib.dsn.take_next();
     let data_request_frame = Frame {
         header: crate::wire::Header {
             frame_type: crate::wire::FrameType::MacCommand,
             frame_pending: false,
-            ack_request: true,
-            pan_id_compress: destination_address.is_none(),
+            ack_request: Command::DataRequest.requires_ack(),
+            pan_id_compress: crate::wire::Header::pan_id_compression(
+                destination_address,
+                Some(source_address),
+            ),
             seq_no_suppress: false,
             ie_present: false,
             version: crate::wire::FrameVersion::Ieee802154_2003,
@@ -606,6 +1338,7 @@ async fn perform_data_request(
             destination: destination_address,
             source: Some(source_address),
             auxiliary_security_header: None,
+            time_correction: None,
         },
         content: FrameContent::Command(Command::DataRequest),
         payload: &[],
@@ -614,93 +1347,179 @@ async fn perform_data_request(
 
     let message = mac_state.serialize_frame(data_request_frame);
 
-    let ack_wait_duration = mac_pib.ack_wait_duration(phy.get_phy_pib()) as i64;
-
-    let send_result = phy
-        .send(
-            &message,
-            send_time,
-            false,
-            true, // TODO: Unless in superframe
-            SendContinuation::WaitForResponse {
-                turnaround_time: phy.symbol_period() * crate::consts::TURNAROUND_TIME as i64,
-                timeout: phy.symbol_period() * ack_wait_duration,
-            },
-        )
-        .await;
+    // TODO: No CSMA when in superframe
+    let send_result = send_with_ack(
+        phy, mac_pib, mac_state, rng, delay, &message, dsn, send_time, None,
+    )
+    .await;
 
-    let ack = match send_result {
-        Ok(SendResult::Success(_, None)) => None,
-        Ok(SendResult::Success(_, Some(mut response))) => {
-            // See if what we received was an Ack for us
-            match mac_state.deserialize_frame(&mut response.data) {
-                Some(frame) => {
-                    if matches!(frame.header.frame_type, FrameType::Acknowledgement)
-                        && frame.header.seq == dsn
-                    {
-                        Some((response.timestamp, frame.header.frame_pending))
-                    } else {
-                        None
-                    }
-                }
-                None => None,
-            }
+    let status = match send_result {
+        Ok(AckedSendResult::Acked {
+            frame_pending: true,
+            ..
+        }) => None,
+        Ok(AckedSendResult::Acked {
+            frame_pending: false,
+            ..
+        }) => {
+            trace!("No data available at the coordinator");
+            Some(Status::NoData)
         }
-        Ok(SendResult::ChannelAccessFailure) => {
+        Ok(AckedSendResult::NoAck) => {
+            warn!("Could not send the data request: NoAck");
+            Some(Status::NoAck)
+        }
+        Ok(AckedSendResult::ChannelAccessFailure) => {
             warn!("Could not send the data request: ChannelAccessFailure");
-            data_request
-                .callback
-                .run_associate(Err(Err(Status::ChannelAccessFailure)), mac_pib)
-                .await;
-            return;
+            Some(Status::ChannelAccessFailure)
+        }
+        Ok(AckedSendResult::LimitReached) => {
+            warn!("Could not send the data request: LimitReached");
+            Some(Status::LimitReached)
         }
         Err(e) => {
             error!("Could not send the data request: {}", e);
-            data_request
+            Some(Status::PhyError)
+        }
+    };
+
+    if let Some(status) = status {
+        data_request.callback.abort(status, mac_pib).await;
+        return;
+    }
+
+    let frame_pending = match receive_pending_frame(phy, mac_state, mac_pib, delay).await {
+        Ok(PendingFrame::AssociationResponse {
+            associate_confirm,
+            coord_extended_address,
+            frame_pending,
+        }) => {
+            let associated = data_request
                 .callback
-                .run_associate(Err(Err(Status::PhyError)), mac_pib)
+                .run_associate(Ok(associate_confirm), mac_pib)
                 .await;
+            if associated {
+                mac_state.is_associated = true;
+
+                // Commands like the coordinator realignment come from the extended address of the coordinator,
+                // even when we've associated using its short address
+                if let Some(coord_extended_address) = coord_extended_address {
+                    mac_pib.coord_extended_address = coord_extended_address;
+                }
+            }
+
+            // Now that we're associated, we can pick up the rest of what the coordinator has for us
+            if frame_pending && associated {
+                trace!("The coordinator has more data after the association response");
+                mac_state
+                    .message_scheduler
+                    .schedule_data_request(ScheduledDataRequest {
+                        mode: DataRequestMode::Independent { timestamp: None },
+                        trigger: state::DataRequestTrigger::FramePending,
+                        used_security_info: SecurityInfo::new_none_security(),
+                        callback: DataRequestCallback::FramePending,
+                        follow_ups: 0,
+                    });
+            }
+
             return;
         }
-    };
+        Ok(PendingFrame::Other {
+            message,
+            frame_pending,
+        }) => {
+            // This also queues the ack, which is sent before the next data request
+            process_tapped_message::<P>(
+                message,
+                mac_state,
+                mac_pib,
+                mac_handler,
+                indirect_indications,
+                phy.symbol_period(),
+                next_events,
+                true,
+            )
+            .await;
 
-    let Some((_ack_timestamp, frame_pending)) = ack else {
-        todo!("No ack received for data request. Retransmission: TODO");
+            frame_pending
+        }
+        Err(status) => {
+            data_request.callback.abort(status, mac_pib).await;
+            return;
+        }
     };
 
     if !frame_pending {
-        trace!("No data available at the coordinator");
-        data_request
-            .callback
-            .run_associate(Err(Err(Status::NoData)), mac_pib)
-            .await;
-        return;
+        // An association that gets here didn't get its response
+        data_request.callback.abort(Status::NoData, mac_pib).await;
+    } else if data_request.follow_ups >= MAX_FOLLOW_UP_DATA_REQUESTS {
+        warn!("The coordinator keeps indicating it has more data, giving up");
+        data_request.callback.abort(Status::NoData, mac_pib).await;
+    } else {
+        trace!("The coordinator has more data, sending another data request");
+        mac_state
+            .message_scheduler
+            .schedule_data_request(ScheduledDataRequest {
+                // The follow-up goes right away
+                mode: DataRequestMode::Independent { timestamp: None },
+                follow_ups: data_request.follow_ups + 1,
+                ..data_request
+            });
     }
+}
+
+/// The amount of extra data requests a device sends when the coordinator keeps indicating it has
+/// more data for it, so a coordinator can't keep it busy forever
+const MAX_FOLLOW_UP_DATA_REQUESTS: usize = super::state::MAX_PENDING_TRANSACTIONS;
+
+/// A frame the coordinator sent in response to a data request
+enum PendingFrame {
+    /// The association response, which has already been acked
+    AssociationResponse {
+        associate_confirm: AssociateConfirm,
+        /// The extended address the coordinator sent the response from
+        coord_extended_address: Option<ExtendedAddress>,
+        frame_pending: bool,
+    },
+    /// Anything else, which still has to be processed
+    Other {
+        message: ReceivedMessage,
+        frame_pending: bool,
+    },
+}
 
+/// Receive the frame the coordinator sends after it has acked our data request with the frame pending bit set
+async fn receive_pending_frame(
+    phy: &mut impl Phy,
+    mac_state: &mut MacState<'_>,
+    mac_pib: &mut MacPib,
+    delay: &mut impl DelayNsExt,
+) -> Result<PendingFrame, Status> {
     // TODO: Refactor listening to common function
 
-    // Turn on receiver for macMaxFrameTotalWaitTime to receive the association response
+    // Turn on receiver for macMaxFrameTotalWaitTime to receive the pending frame.
+    // The macResponseWaitTime has already passed before the data request was sent (5.1.3.1),
+    // so the coordinator has had its chance to respond. If it doesn't come now, it won't come at all.
     let on_duration =
         phy.symbol_period() * mac_pib.max_frame_total_wait_time(phy.get_phy_pib()).into();
     let mut on_delay = pin!(delay.delay_duration(on_duration));
 
     if let Err(e) = phy.start_receive().await {
         error!(
-            "Could not turn on phy for receiving association response: {}",
+            "Could not turn on phy for receiving the pending frame: {}",
             e
         );
-        data_request
-            .callback
-            .run_associate(Err(Err(Status::PhyError)), mac_pib)
-            .await;
-        return;
+        return Err(Status::PhyError);
     }
 
-    let response = loop {
+    let pending_frame = loop {
         match embassy_futures::select::select(phy.wait(), &mut on_delay).await {
             Either::First(Ok(processing_context)) => match phy.process(processing_context).await {
                 Ok(Some(mut received_message)) => {
-                    let Some(frame) = mac_state.deserialize_frame(&mut received_message.data)
+                    mac_state.tap_received(&received_message);
+
+                    let Some(frame) = mac_state
+                        .deserialize_message(received_message.crc_ok, &mut received_message.data)
                     else {
                         trace!("Received a frame that can't be deserialized");
                         continue;
@@ -708,36 +1527,56 @@ async fn perform_data_request(
 
                     trace!("Received a frame in the data request routine: {:?}", frame);
 
-                    if !filter_frame(&frame) {
+                    if !filter_frame(&frame) || !is_addressed_to_us(mac_pib, &frame) {
                         // Frame not for us
                         continue;
                     }
 
+                    let frame_pending = frame.header.frame_pending;
+
                     let FrameContent::Command(Command::AssociationResponse(
                         assoc_short_address,
                         association_status,
                     )) = frame.content
                     else {
-                        warn!("Received something other than the expected AssociationResponse");
-                        continue;
+                        trace!("Received something other than an association response");
+                        break Ok(PendingFrame::Other {
+                            message: received_message,
+                            frame_pending,
+                        });
                     };
 
                     if frame.header.ack_request {
-                        send_ack(
+                        if let Err(e) = send_ack(
                             phy,
                             mac_pib,
                             mac_state,
                             received_message.timestamp,
                             frame.header.seq,
                             false,
+                            frame.header.version == FrameVersion::Ieee802154,
                         )
-                        .await;
+                        .await
+                        {
+                            error!("Could not send an ack: {}", e);
+                        }
                     }
 
-                    break Ok(AssociateConfirm {
-                        assoc_short_address,
-                        status: Ok(association_status),
-                        security_info: SecurityInfo::new_none_security(),
+                    let coord_extended_address = match frame.header.source {
+                        Some(Address::Extended(_, extended_address)) => Some(extended_address),
+                        _ => None,
+                    };
+
+                    break Ok(PendingFrame::AssociationResponse {
+                        coord_extended_address,
+                        associate_confirm: AssociateConfirm {
+                            assoc_short_address,
+                            status: mlme_associate::association_status_to_confirm_status(
+                                association_status,
+                            ),
+                            security_info: SecurityInfo::new_none_security(),
+                        },
+                        frame_pending,
                     });
                 }
                 Ok(None) => {
@@ -745,33 +1584,40 @@ async fn perform_data_request(
                 }
                 Err(e) => {
                     error!("Could not process phy: {}", e);
-                    break Err(Err(Status::PhyError));
+                    break Err(Status::PhyError);
                 }
             },
             Either::First(Err(e)) => {
                 error!("Could not wait on phy: {}", e);
-                break Err(Err(Status::PhyError));
+                break Err(Status::PhyError);
             }
             Either::Second(()) => {
                 // Timeout
-                break Err(Err(Status::NoData));
+                break Err(Status::NoData);
             }
         }
     };
 
     if let Err(e) = phy.stop_receive().await {
         error!(
-            "Could not turn off phy for receiving association response: {}",
+            "Could not turn off phy for receiving the pending frame: {}",
             e
         );
-        data_request
-            .callback
-            .run_associate(Err(Err(Status::PhyError)), mac_pib)
-            .await;
-        return;
+        return Err(Status::PhyError);
     }
 
-    data_request.callback.run_associate(response, mac_pib).await;
+    pending_frame
+}
+
+/// The frame is addressed to our extended address or to the short address we have in our PAN
+fn is_addressed_to_us(mac_pib: &MacPib, frame: &Frame<'_>) -> bool {
+    match frame.header.destination {
+        Some(Address::Extended(_, address)) => address == mac_pib.extended_address,
+        Some(Address::Short(pan_id, address)) => {
+            pan_id == mac_pib.pan_id && address == mac_pib.short_address
+        }
+        None => false,
+    }
 }
 
 async fn perform_scan_action(
@@ -788,7 +1634,23 @@ async fn perform_scan_action(
             page,
             scan_type,
             current_code: _,
+            include_source_address,
         } => {
+            // A channel the phy doesn't support is left in the unscanned channels, the rest is still scanned
+            if !phy.get_phy_pib().supports_channel(page, channel) {
+                warn!(
+                    "Skipping channel '{}' of page '{:?}' in the scan, because the phy doesn't support it",
+                    channel, page
+                );
+                mac_state
+                    .current_scan_process
+                    .as_mut()
+                    .unwrap()
+                    .register_action_as_failed(action, phy)
+                    .await;
+                return;
+            }
+
             // Update the radio so it uses the correct channel and page
             if let Err(e) = phy
                 .update_phy_pib(|pib| {
@@ -815,26 +1677,47 @@ async fn perform_scan_action(
             );
             loop {
                 match scan_type {
-                    ScanType::Ed => {
-                        todo!("Pick up later since it requires more phy implementation")
+                    ScanType::Ed | ScanType::Orphan => {
+                        // These are rejected when the scan is requested, but don't trust that here
+                        warn!("Scan type {:?} is not implemented", scan_type);
+                        mac_state
+                            .current_scan_process
+                            .take()
+                            .unwrap()
+                            .abort_scan(mac_pib, Status::NotImplemented, phy)
+                            .await;
+                        return;
                     }
                     ScanType::Active => {
+                        let destination = Some(wire::Address::Short(
+                            PanId::broadcast(),
+                            ShortAddress::BROADCAST,
+                        ));
+                        let source = include_source_address.then(|| {
+                            if device_table::is_allocated(mac_pib.short_address) {
+                                wire::Address::Short(mac_pib.pan_id, mac_pib.short_address)
+                            } else {
+                                wire::Address::Extended(mac_pib.pan_id, mac_pib.extended_address)
+                            }
+                        });
+
                         let data = mac_state.serialize_frame(Frame {
                             header: wire::Header {
                                 frame_type: wire::FrameType::MacCommand,
                                 frame_pending: false,
-                                ack_request: false,
-                                pan_id_compress: false,
+                                ack_request: wire::command::Command::BeaconRequest.requires_ack(),
+                                pan_id_compress: wire::Header::pan_id_compression(
+                                    destination,
+                                    source,
+                                ),
                                 seq_no_suppress: false,
                                 ie_present: false,
                                 version: wire::FrameVersion::Ieee802154_2003,
                                 seq: 0,
-                                destination: Some(wire::Address::Short(
-                                    PanId::broadcast(),
-                                    ShortAddress::BROADCAST,
-                                )),
-                                source: None,
+                                destination,
+                                source,
                                 auxiliary_security_header: None,
+                                time_correction: None,
                             },
                             content: wire::FrameContent::Command(
                                 wire::command::Command::BeaconRequest,
@@ -843,6 +1726,33 @@ async fn perform_scan_action(
                             footer: [0, 0],
                         });
 
+                        let now = match phy.get_instant().await {
+                            Ok(now) => now,
+                            Err(e) => {
+                                error!("Could not get the current time: {}", e);
+                                mac_state
+                                    .current_scan_process
+                                    .take()
+                                    .unwrap()
+                                    .abort_scan(mac_pib, Status::PhyError, phy)
+                                    .await;
+                                return;
+                            }
+                        };
+                        if !mac_state.duty_cycle_allows(phy, now, &data) {
+                            // Like a channel access failure, the channel is left unscanned
+                            warn!(
+                                "Not sending a beacon request, because it would go over the duty cycle limit"
+                            );
+                            mac_state
+                                .current_scan_process
+                                .as_mut()
+                                .unwrap()
+                                .register_action_as_failed(action, phy)
+                                .await;
+                            return;
+                        }
+
                         trace!("Sending beacon request");
                         match phy
                             .send(
@@ -854,8 +1764,8 @@ async fn perform_scan_action(
                             )
                             .await
                         {
-                            Ok(SendResult::Success(_, _)) => {
-                                // Cool, continue
+                            Ok(SendResult::Success(send_time, _)) => {
+                                mac_state.register_transmission(phy, mac_pib, send_time, &data);
                             }
                             Ok(SendResult::ChannelAccessFailure) => {
                                 // We could not send the beacon request, so let the scan process know it failed
@@ -897,9 +1807,6 @@ async fn perform_scan_action(
                         }
                         break;
                     }
-                    ScanType::Orphan => {
-                        todo!("Pick up later when we implement orphan messages")
-                    }
                 }
             }
 
@@ -950,7 +1857,7 @@ async fn send_beacon(
             seq_no_suppress: false,
             ie_present: false,
             version: mac_state.beacon_security_info.get_frame_version(),
-            seq: mac_pib.bsn.increment(),
+            seq: mac_pib.bsn.take_next(),
             destination: None,
             source: Some(if mac_pib.short_address == ShortAddress(0xFFFE) {
                 wire::Address::Extended(mac_pib.pan_id, mac_pib.extended_address)
@@ -958,6 +1865,7 @@ async fn send_beacon(
                 wire::Address::Short(mac_pib.pan_id, mac_pib.short_address)
             }),
             auxiliary_security_header: mac_state.beacon_security_info.into(),
+            time_correction: None,
         },
         content: wire::FrameContent::Beacon(wire::beacon::Beacon {
             superframe_spec: wire::beacon::SuperframeSpecification {
@@ -981,66 +1889,116 @@ async fn send_beacon(
         footer: Default::default(),
     };
 
-    let send_time = match phy
-        .send(
-            &mac_state.serialize_frame(beacon_frame),
+    let beacon_data = mac_state.serialize_frame(beacon_frame);
+    let ranging = mac_pib.ranging_enabled(phy.get_phy_pib());
+
+    let Some(broadcast) = mac_state.message_scheduler.take_scheduled_broadcast() else {
+        let send_time = match phy
+            .send(
+                &beacon_data,
+                send_time,
+                ranging,
+                use_beacon_csma,
+                beacon_send_continuation,
+            )
+            .await
+        {
+            Ok(SendResult::Success(send_time, _)) => {
+                mac_state.register_transmission(phy, mac_pib, send_time, &beacon_data);
+                start_batt_life_ext_window(mac_state, mac_pib, phy, send_time, &beacon_data);
+                send_time
+            }
+            Ok(SendResult::ChannelAccessFailure) => {
+                warn!("Could not send beacon due to channel access failure");
+                return;
+            }
+            Err(e) => {
+                error!("Could not send beacon: {}", e);
+                return;
+            }
+        };
+
+        mac_pib.beacon_tx_time = send_time / phy.symbol_period();
+        return;
+    };
+
+    // Send the broadcast right after the beacon so the radio doesn't need a roundtrip through the MAC
+    let (send_time, broadcast_send_result) = match phy
+        .send_back_to_back(
+            &beacon_data,
+            &broadcast.data,
             send_time,
-            mac_pib.ranging_supported,
+            ranging,
             use_beacon_csma,
-            if !has_broadcast_scheduled {
-                beacon_send_continuation
-            } else {
-                SendContinuation::Idle
-            },
+            phy.symbol_period() * mac_pib.lifs_period as i64,
+            beacon_send_continuation,
         )
         .await
     {
-        Ok(SendResult::Success(send_time, _)) => send_time,
-        Ok(SendResult::ChannelAccessFailure) => {
+        Ok((SendResult::Success(send_time, _), Some(broadcast_send_result))) => {
+            mac_state.register_transmission(phy, mac_pib, send_time, &beacon_data);
+            start_batt_life_ext_window(mac_state, mac_pib, phy, send_time, &beacon_data);
+            if let SendResult::Success(broadcast_send_time, _) = broadcast_send_result {
+                mac_state.register_transmission(phy, mac_pib, broadcast_send_time, &broadcast.data);
+            }
+
+            (send_time, broadcast_send_result)
+        }
+        Ok((_, _)) => {
             warn!("Could not send beacon due to channel access failure");
+            // The broadcast hasn't been sent, so it can go with the next beacon
+            mac_state
+                .message_scheduler
+                .schedule_broadcast_priority(broadcast.data, broadcast.callback);
             return;
         }
         Err(e) => {
-            error!("Could not send beacon: {}", e);
+            error!("Could not send beacon and broadcast: {}", e);
+            broadcast
+                .callback
+                .run(
+                    crate::phy::SendResult::ChannelAccessFailure,
+                    phy,
+                    mac_pib,
+                    mac_state,
+                )
+                .await;
             return;
         }
     };
 
-    if let Some(broadcast) = mac_state.message_scheduler.take_scheduled_broadcast() {
-        match phy
-            .send(
-                &broadcast.data,
-                Some(send_time),
-                mac_pib.ranging_supported,
-                false,
-                beacon_send_continuation,
-            )
-            .await
-        {
-            Err(e) => {
-                error!("Could not send broadcast: {}", e);
-                broadcast
-                    .callback
-                    .run(
-                        crate::phy::SendResult::ChannelAccessFailure,
-                        phy,
-                        mac_pib,
-                        mac_state,
-                    )
-                    .await
-            }
-            Ok(send_result) => {
-                broadcast
-                    .callback
-                    .run(send_result, phy, mac_pib, mac_state)
-                    .await
-            }
-        }
-    }
+    broadcast
+        .callback
+        .run(broadcast_send_result, phy, mac_pib, mac_state)
+        .await;
 
     mac_pib.beacon_tx_time = send_time / phy.symbol_period();
 }
 
+/// With battery life extension, the receiver only stays on for a few backoff periods after the IFS of our beacon
+/// (5.1.1.5). This registers when that window ends.
+fn start_batt_life_ext_window(
+    mac_state: &mut MacState<'_>,
+    mac_pib: &MacPib,
+    phy: &mut impl Phy,
+    send_time: Instant,
+    beacon_data: &[u8],
+) {
+    if !mac_state.own_superframe_active || !mac_pib.batt_life_ext {
+        mac_state.batt_life_ext_window_end = None;
+        return;
+    }
+
+    let send_time_symbols = send_time / phy.symbol_period();
+    let phy_pib = phy.get_phy_pib();
+    let window_end = send_time_symbols
+        + mac_state.frame_duration(phy_pib, beacon_data) as i64
+        + mac_state.ifs_period(mac_pib, beacon_data) as i64
+        + mac_pib.batt_life_ext_periods(phy_pib) as i64 * crate::consts::UNIT_BACKOFF_PERIOD as i64;
+
+    mac_state.batt_life_ext_window_end = Some(window_end);
+}
+
 enum RadioEvent<P: Phy> {
     Error,
     BeaconRequested,
@@ -1051,18 +2009,36 @@ enum RadioEvent<P: Phy> {
         start_time: Instant,
     },
     OwnSuperframeEnd,
+    BattLifeExtWindowEnd,
+    /// No beacon of the coordinator we're synchronizing with was received in time
+    BeaconMissed,
+    /// The beacon we synchronized with was found and we're not tracking it
+    SyncDone,
     PhyWaitDone {
         context: P::ProcessingContext,
     },
     ScanAction(ScanAction),
     SendScheduledIndependentDataRequest,
+    /// There's a frame in [MacState::deferred_messages] to process
+    DeferredMessage,
     SendAck {
         /// The time the message we're acking was received
         receive_time: Instant,
+        /// The number of octets of the message we're acking, as the phy passed them on
+        frame_length: usize,
         /// The sequence number of the received message
         seq: u8,
         /// True if the frame pending bit should be set
         frame_pending: bool,
+        /// True if an enhanced ack should be sent
+        enhanced: bool,
+    },
+    DataReceived(DataIndication),
+    CoordinatorRealignment {
+        /// The new settings of the PAN
+        realignment: crate::wire::command::CoordinatorRealignmentData,
+        /// The security that was used for the realignment command
+        security_info: SecurityInfo,
     },
     SendPendingData {
         /// The time at which we received the data request
@@ -1070,13 +2046,20 @@ enum RadioEvent<P: Phy> {
         /// The address of the requester
         device_address: DeviceAddress,
     },
+    SendRangingResponse {
+        /// The time at which we received the ranging poll
+        poll_receive_time: Instant,
+        /// The sequence number of the poll
+        seq: u8,
+        /// The address of the initiator of the ranging
+        initiator: Address,
+    },
 }
 
 async fn wait_for_own_superframe_start<P: Phy>(
     mac_pib: &MacPib,
     mac_state: &MacState<'_>,
     current_time: Instant,
-    current_time_symbols: i64,
     symbol_period: Duration,
     mut delay: impl DelayNsExt,
 ) -> RadioEvent<P> {
@@ -1090,33 +2073,43 @@ async fn wait_for_own_superframe_start<P: Phy>(
             panic!("Beacon interval is valid while the beacon mode is off")
         }
         (Some(bi), BeaconMode::OnAutonomous) => {
+            // The start is on the symbol boundary itself, so it doesn't depend on when in the symbol we're
+            // looking. Otherwise the beacon would move around a bit depending on what the mac did before.
             let next_start_time_symbols = mac_pib.beacon_tx_time + bi.get() as i64;
-            let timeout_symbols = next_start_time_symbols - current_time_symbols;
-            Some(timeout_symbols * symbol_period)
+            let next_start_time = Instant::from_ticks(0) + next_start_time_symbols * symbol_period;
+            Some(next_start_time.duration_since(current_time))
         }
-        (Some(_), BeaconMode::OnTracking { .. }) => {
-            // This beacon tracks another beacon, so will be done in response to a tracked beacon event
-            None
+        (Some(_), BeaconMode::OnTracking { start_time }) => {
+            // Our beacon goes out `start_time` symbols after the last beacon we tracked.
+            // Once it has been sent (or missed), we wait for the next tracked beacon.
+            mac_state
+                .beacon_sync
+                .as_ref()
+                .and_then(|beacon_sync| beacon_sync.last_beacon)
+                .map(|last_beacon| last_beacon / symbol_period + start_time as i64)
+                .filter(|&next_start_time_symbols| next_start_time_symbols > mac_pib.beacon_tx_time)
+                .map(|next_start_time_symbols| {
+                    let next_start_time =
+                        Instant::from_ticks(0) + next_start_time_symbols * symbol_period;
+                    next_start_time.duration_since(current_time)
+                })
         }
     };
 
     let scan_active = mac_state.current_scan_process.is_some();
+    let headroom = mac_state.planning_headroom.beacon;
 
     match (scan_active, timeout) {
         // When the scan is active we must not send out beacons
         (true, Some(timeout)) => {
-            delay
-                .delay_duration(timeout - BEACON_PLANNING_HEADROOM)
-                .await;
+            delay.delay_duration(timeout - headroom).await;
             warn!("Beacon is missed due to active scan in progress");
             RadioEvent::OwnSuperframeStartMissed {
                 start_time: current_time + timeout,
             }
         }
-        (false, Some(timeout)) if timeout > BEACON_PLANNING_HEADROOM => {
-            delay
-                .delay_duration(timeout - BEACON_PLANNING_HEADROOM)
-                .await;
+        (false, Some(timeout)) if timeout > headroom => {
+            delay.delay_duration(timeout - headroom).await;
             RadioEvent::OwnSuperframeStart {
                 start_time: current_time + timeout,
             }
@@ -1162,6 +2155,38 @@ async fn wait_for_own_super_frame_end<P: Phy>(
     }
 }
 
+async fn wait_for_batt_life_ext_window_end<P: Phy>(
+    mac_state: &MacState<'_>,
+    current_time_symbols: i64,
+    mut delay: impl DelayNsExt,
+    symbol_period: Duration,
+) -> RadioEvent<P> {
+    match mac_state.batt_life_ext_window_end {
+        Some(window_end) => {
+            let duration_to_go = window_end - current_time_symbols;
+            delay.delay_duration(duration_to_go * symbol_period).await;
+            RadioEvent::BattLifeExtWindowEnd
+        }
+        None => core::future::pending().await,
+    }
+}
+
+async fn wait_for_beacon_missed<P: Phy>(
+    mac_state: &MacState<'_>,
+    current_time: Instant,
+    mut delay: impl DelayNsExt,
+) -> RadioEvent<P> {
+    match &mac_state.beacon_sync {
+        Some(beacon_sync) => {
+            delay
+                .delay_duration(beacon_sync.deadline.duration_since(current_time))
+                .await;
+            RadioEvent::BeaconMissed
+        }
+        None => core::future::pending().await,
+    }
+}
+
 async fn wait_for_channel_scan_action<P: Phy>(
     mac_state: &MacState<'_>,
     current_time: Instant,
@@ -1184,46 +2209,94 @@ async fn wait_for_independent_data_request<P: Phy>(
     match mac_state
         .message_scheduler
         .get_scheduled_independent_data_request()
+        .map(|request| &request.mode)
     {
-        Some(ScheduledDataRequest {
-            mode:
-                DataRequestMode::Independent {
-                    timestamp: Some(send_time),
-                },
-            ..
+        Some(DataRequestMode::Independent {
+            timestamp: Some(send_time),
         }) => {
             delay
                 .delay_duration(
-                    send_time.duration_since(current_time) - DATA_REQUEST_PLANNING_HEADROOM,
+                    send_time.duration_since(current_time)
+                        - mac_state.planning_headroom.data_request,
                 )
                 .await;
             RadioEvent::SendScheduledIndependentDataRequest
         }
-        Some(ScheduledDataRequest {
-            mode: DataRequestMode::Independent { timestamp: None },
-            ..
-        }) => RadioEvent::SendScheduledIndependentDataRequest,
-        Some(_) => todo!(),
+        // Without a timestamp it's sent right away
+        Some(_) => RadioEvent::SendScheduledIndependentDataRequest,
         None => core::future::pending().await,
     }
 }
 
 async fn process_message<'a, P: Phy>(
+    message: ReceivedMessage,
+    mac_state: &mut MacState<'a>,
+    mac_pib: &mut MacPib,
+    mac_handler: &MacHandler<'a>,
+    indirect_indications: Pin<&mut IndirectIndicationCollection<'a>>,
+    symbol_period: Duration,
+    next_events: &mut arraydeque::ArrayDeque<RadioEvent<P>, 4>,
+) {
+    mac_state.tap_received(&message);
+
+    process_tapped_message(
+        message,
+        mac_state,
+        mac_pib,
+        mac_handler,
+        indirect_indications,
+        symbol_period,
+        next_events,
+        true,
+    )
+    .await;
+}
+
+/// Process a message that has already been given to the frame tap.
+///
+/// When the message isn't `ack_in_time`, the moment to ack it has passed.
+#[allow(clippy::too_many_arguments)]
+async fn process_tapped_message<'a, P: Phy>(
     mut message: ReceivedMessage,
     mac_state: &mut MacState<'a>,
-    mac_pib: &MacPib,
+    mac_pib: &mut MacPib,
     mac_handler: &MacHandler<'a>,
     indirect_indications: Pin<&mut IndirectIndicationCollection<'a>>,
     symbol_period: Duration,
     next_events: &mut arraydeque::ArrayDeque<RadioEvent<P>, 4>,
+    ack_in_time: bool,
 ) {
-    let Some(frame) = mac_state.deserialize_frame(&mut message.data) else {
+    // Taken now, because the deserialized frame borrows the data
+    let frame_length = message.data.len();
+
+    // Reserved frame types don't deserialize, so the type is checked on the raw frame.
+    // A frame with a bad CRC is left to the deserialization, since its type can't be trusted.
+    if message.crc_ok != Some(false) && !has_supported_frame_type(&message.data) {
+        debug!("Discarding a frame with a reserved or unsupported frame type");
+        mac_handler.count_discarded_frame();
+        return;
+    }
+
+    // During a scan, the PAN of a secured beacon that can't be unsecured is still reported
+    let deserialized = if mac_state.current_scan_process.is_some() {
+        mac_state.deserialize_unverified_message(message.crc_ok, &mut message.data)
+    } else {
+        mac_state
+            .deserialize_message(message.crc_ok, &mut message.data)
+            .map(|frame| (frame, None))
+    };
+    let Some((frame, security_status)) = deserialized else {
         trace!("Received a frame that could not be deserialized");
         return;
     };
 
     trace!("Received a frame: {:?}", frame);
 
+    if security_status.is_some() && !matches!(frame.content, FrameContent::Beacon(_)) {
+        trace!("Ignoring a frame that could not be unsecured");
+        return;
+    }
+
     // Now decide what to do with the frame...
 
     if !filter_frame(&frame) {
@@ -1231,6 +2304,18 @@ async fn process_message<'a, P: Phy>(
         return;
     }
 
+    if !ack_in_time && frame.header.ack_request && !is_broadcast(&frame) {
+        // The sender didn't get its ack, so it considers the frame lost and may send it again
+        trace!("Dropping a frame that could not be acked in time");
+        return;
+    }
+
+    if let (Some(source), None) = (frame.header.source, security_status) {
+        mac_state
+            .device_table
+            .mark_seen(source.into(), message.timestamp);
+    }
+
     if mac_state.current_scan_process.is_some() {
         // During a scan, all non-beacon frames are rejected
         if !matches!(frame.content, FrameContent::Beacon(_)) {
@@ -1255,11 +2340,12 @@ async fn process_message<'a, P: Phy>(
 
         scan_process
             .register_received_beacon(
-                message.timestamp,
+                mac_pib.reported_timestamp(message.timestamp, symbol_period),
                 message.lqi,
                 message.channel,
                 message.page,
                 frame,
+                security_status,
                 mac_pib,
                 mac_handler,
             )
@@ -1275,6 +2361,7 @@ async fn process_message<'a, P: Phy>(
                     mlme_associate::process_received_associate_request(
                         mac_handler,
                         mac_pib,
+                        mac_state,
                         indirect_indications,
                         device_address,
                         capability_information,
@@ -1299,12 +2386,118 @@ async fn process_message<'a, P: Phy>(
                     })
                     .unwrap();
 
-                mac_state.message_scheduler.has_pending_data(source.into())
+                mac_state
+                    .message_scheduler
+                    .has_pending_data(source.into(), &mac_state.device_table)
             } else {
                 warn!("Got a datarequest without source address. Ignored");
                 false
             }
         }
+        FrameContent::Data if ranging::is_ranging_frame(&frame, message.ranging) => {
+            match ranging::poll_for_us(mac_pib, &frame) {
+                Some(initiator) => next_events
+                    .push_back(RadioEvent::SendRangingResponse {
+                        poll_receive_time: message.timestamp,
+                        seq: frame.header.seq,
+                        initiator,
+                    })
+                    .unwrap(),
+                None => trace!("Ignoring a ranging frame that's not meant for us"),
+            }
+
+            false
+        }
+        FrameContent::Data => {
+            // Delivered as a separate event so a possible ack is sent first
+            if let Some(indication) = mcps_data::data_indication(
+                &frame,
+                mac_pib.reported_timestamp(message.timestamp, symbol_period),
+                message.lqi,
+                message.ranging,
+                message.data_rate,
+                mac_pib,
+            ) {
+                next_events
+                    .push_back(RadioEvent::DataReceived(indication))
+                    .unwrap();
+            }
+
+            false
+        }
+        FrameContent::Command(Command::CoordinatorRealignment(realignment)) => {
+            match frame.header.source {
+                Some(source) if coord_realignment::is_for_us(mac_pib, source, &realignment) => {
+                    // Following the coordinator requires the phy, so it's done as a separate event
+                    next_events
+                        .push_back(RadioEvent::CoordinatorRealignment {
+                            realignment,
+                            security_info: frame.header.auxiliary_security_header.into(),
+                        })
+                        .unwrap();
+                }
+                _ => trace!("Ignoring a coordinator realignment that's not meant for us"),
+            }
+
+            false
+        }
+        FrameContent::Command(Command::DisassociationNotification(reason)) => {
+            match frame.header.source {
+                Some(Address::Extended(_, device_address)) => {
+                    mlme_disassociate::process_received_disassociation_notification(
+                        mac_handler,
+                        mac_pib,
+                        mac_state,
+                        indirect_indications,
+                        device_address,
+                        reason,
+                        frame.header.auxiliary_security_header.into(),
+                        message.timestamp,
+                        symbol_period,
+                    )
+                }
+                _ => warn!(
+                    "Disassociation notification came from frame without correct source field. Ignored"
+                ),
+            }
+
+            false
+        }
+        FrameContent::Command(Command::GuaranteedTimeSlotRequest(characteristics)) => {
+            // A GTS can only be requested by a device with a short address (5.3.9)
+            match frame.header.source {
+                Some(Address::Short(_, device_address)) => gts::process_received_gts_request(
+                    mac_handler,
+                    mac_pib,
+                    mac_state,
+                    indirect_indications,
+                    device_address,
+                    characteristics,
+                    frame.header.auxiliary_security_header.into(),
+                    message.timestamp,
+                    symbol_period,
+                ),
+                _ => warn!("GTS request came from frame without a short source address. Ignored"),
+            }
+
+            false
+        }
+        FrameContent::Beacon(crate::wire::beacon::Beacon {
+            superframe_spec, ..
+        }) => {
+            if mlme_sync::process_received_beacon(
+                mac_state,
+                mac_pib,
+                frame.header.source,
+                superframe_spec,
+                message.timestamp,
+                symbol_period,
+            ) {
+                next_events.push_back(RadioEvent::SyncDone).unwrap();
+            }
+
+            false
+        }
         content => {
             warn!(
                 "Received frame has content we don't yet process: {}",
@@ -1315,15 +2508,19 @@ async fn process_message<'a, P: Phy>(
     };
 
     // Filtering has been done, so we know this is meant for us.
-    // If it needs to be acked, we should do it now.
+    // If it needs to be acked, we should do it now, unless the higher layer takes care of that.
+    // Broadcasts are never acked, even if they (wrongly) request it.
     // TODO: Look at the exact rules, because this is currently likely not correct
-    if frame.header.ack_request {
+    if frame.header.ack_request && mac_state.auto_ack && !is_broadcast(&frame) {
         // Push to the front because acks need to processed first
         next_events
             .push_front(RadioEvent::SendAck {
                 receive_time: message.timestamp,
+                frame_length,
                 seq: frame.header.seq,
                 frame_pending,
+                // Frames of the 2015 version must be acked with an enhanced ack
+                enhanced: frame.header.version == FrameVersion::Ieee802154,
             })
             .unwrap();
     }
@@ -1333,7 +2530,47 @@ async fn process_message<'a, P: Phy>(
 ///
 /// If the frame should be processed, this function returns true.
 /// If the frame can be discarded, this function returns false.
-fn filter_frame(_frame: &Frame<'_>) -> bool {
-    // TODO: Actually implement
+fn filter_frame(frame: &Frame<'_>) -> bool {
+    if matches!(frame.header.frame_type, FrameType::Acknowledgement) {
+        // Acks have no addressing fields, so they're only for us when we're waiting for one.
+        // That's done with the response of the send itself, which also checks the sequence number.
+        // An ack that ends up here is stray.
+        trace!("Ignoring an ack that's not awaited");
+        return false;
+    }
+
+    // TODO: Match the destination PAN ID and address with our own (third level of filtering).
+    // Until then every other frame is processed, also when it's addressed to another device.
     true
 }
+
+/// Does the frame in the data have a frame type we process?
+///
+/// Frames with a reserved frame type must be discarded (5.1.6.2). The same goes for
+/// the frame types we don't support yet.
+fn has_supported_frame_type(data: &[u8]) -> bool {
+    let Some(frame_control) = data.first() else {
+        // Not a frame at all, which the deserialization will find out
+        return true;
+    };
+
+    matches!(
+        FrameType::from_bits(frame_control & 0b111),
+        Some(
+            FrameType::Beacon
+                | FrameType::Data
+                | FrameType::Acknowledgement
+                | FrameType::MacCommand
+        )
+    )
+}
+
+/// Is the frame sent to the broadcast address?
+///
+/// A frame to a single device on the broadcast PAN is not a broadcast and still gets acked.
+fn is_broadcast(frame: &Frame<'_>) -> bool {
+    matches!(
+        frame.header.destination,
+        Some(Address::Short(_, ShortAddress::BROADCAST))
+    )
+}
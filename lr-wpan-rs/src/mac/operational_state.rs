@@ -0,0 +1,29 @@
+use super::{commander::RequestResponder, state::MacState};
+use crate::sap::{
+    Status,
+    operational_state::{MacOperationalState, OperationalStateConfirm, OperationalStateRequest},
+};
+
+pub fn process_operational_state_request(
+    mac_state: &MacState<'_>,
+    responder: RequestResponder<'_, OperationalStateRequest>,
+) {
+    responder.respond(OperationalStateConfirm {
+        status: Status::Success,
+        state: operational_state(mac_state),
+    });
+}
+
+fn operational_state(mac_state: &MacState<'_>) -> MacOperationalState {
+    if mac_state.current_scan_process.is_some() {
+        MacOperationalState::Scanning
+    } else if mac_state.is_pan_coordinator {
+        MacOperationalState::PanCoordinator
+    } else if mac_state.tracked_superframe.is_some() {
+        MacOperationalState::Tracking
+    } else if mac_state.is_associated {
+        MacOperationalState::Associated
+    } else {
+        MacOperationalState::Idle
+    }
+}
@@ -0,0 +1,288 @@
+//! Single-sided two-way ranging (SS-TWR) between two macs, built on the ranging support of the phy.
+//!
+//! This is not part of the standard. The initiator sends a poll, which is a data frame with the ranging bit set
+//! and [POLL_PAYLOAD] as its payload. The responder answers with a data frame with the same sequence number
+//! exactly the [reply_delay] after it received the poll. Its payload is [RESPONSE_TAG] followed by
+//! the receive time of the poll and the send time of the response, both as little endian ticks.
+//!
+//! The initiator measures the round trip and subtracts the time the responder held on to the poll,
+//! which leaves twice the time of flight.
+
+use core::pin::pin;
+
+use embassy_futures::select::{Either, select};
+
+use super::{MacError, commander::RequestResponder, device_table, state::MacState};
+use crate::{
+    phy::{Phy, SendContinuation, SendResult},
+    pib::MacPib,
+    sap::{
+        Status,
+        ranging::{Meters, RangingConfirm, RangingRequest},
+    },
+    time::{DelayNsExt, Duration, Instant},
+    wire::{Address, Frame, FrameContent, FrameType, FrameVersion, Header},
+};
+
+/// The payload of a poll
+const POLL_PAYLOAD: [u8; 2] = *b"RP";
+/// The start of the payload of a response
+const RESPONSE_TAG: [u8; 2] = *b"RR";
+/// The tag, the receive time of the poll and the send time of the response
+const RESPONSE_PAYLOAD_LENGTH: usize = RESPONSE_TAG.len() + 8 + 8;
+/// The time the responder's mac gets to process the poll before the send of the response must be set up
+const REPLY_PROCESSING_SYMBOLS: i64 = 1000;
+
+pub async fn process_ranging_request<P: Phy>(
+    phy: &mut P,
+    mac_pib: &mut MacPib,
+    mac_state: &mut MacState<'_>,
+    delay: &mut impl DelayNsExt,
+    responder: RequestResponder<'_, RangingRequest>,
+) {
+    if !mac_pib.ranging_enabled(phy.get_phy_pib()) {
+        warn!("Can't range, because ranging is not supported");
+        responder.respond(failed_confirm(Status::RangingNotSupported));
+        return;
+    }
+
+    // The scan is using the radio
+    if mac_state.current_scan_process.is_some() {
+        responder.respond(failed_confirm(Status::ScanInProgress));
+        return;
+    }
+
+    let destination = responder.request.destination;
+    let result = range(phy, mac_pib, mac_state, delay, destination).await;
+
+    // The receiver is turned on again by the run loop if it's needed
+    let stop_result = phy.stop_receive().await;
+
+    match stop_result.and(result) {
+        Ok(Ok(time_of_flight)) => {
+            debug!("Ranged to {:?}: {:?}", destination, time_of_flight);
+            responder.respond(RangingConfirm {
+                status: Status::Success,
+                time_of_flight,
+                distance: Meters::from_time_of_flight(time_of_flight),
+            })
+        }
+        Ok(Err(status)) => {
+            warn!("Could not range to {:?}: {}", destination, status);
+            responder.respond(failed_confirm(status))
+        }
+        Err(e) => {
+            error!("Could not range: {}", e);
+            responder.respond_with_error(MacError::PhyError(e), failed_confirm);
+        }
+    }
+}
+
+fn failed_confirm(status: Status) -> RangingConfirm {
+    RangingConfirm {
+        status,
+        time_of_flight: Duration::from_ticks(0),
+        distance: Meters(0.0),
+    }
+}
+
+/// Send the poll and wait on the response. Returns the time of flight.
+async fn range<P: Phy>(
+    phy: &mut P,
+    mac_pib: &mut MacPib,
+    mac_state: &mut MacState<'_>,
+    delay: &mut impl DelayNsExt,
+    destination: Address,
+) -> Result<Result<Duration, Status>, P::Error> {
+    let seq = mac_pib.dsn.take_next();
+    let poll = mac_state.serialize_frame(ranging_frame(mac_pib, destination, seq, &POLL_PAYLOAD));
+
+    let now = phy.get_instant().await?;
+    if !mac_state.duty_cycle_allows(phy, now, &poll) {
+        return Ok(Err(Status::LimitReached));
+    }
+
+    let poll_send_time = match phy
+        .send(&poll, None, true, true, SendContinuation::ReceiveContinuous)
+        .await?
+    {
+        SendResult::Success(send_time, _) => send_time,
+        SendResult::ChannelAccessFailure => return Ok(Err(Status::ChannelAccessFailure)),
+    };
+    mac_state.register_transmission(phy, mac_pib, poll_send_time, &poll);
+
+    // The response comes after the reply delay and then still has to be received completely.
+    // The responder is expected to have a phy like ours, so it needs the same time to set up the send.
+    let timeout = reply_delay(phy)
+        + phy.symbol_period()
+            * mac_pib.ack_wait_duration(phy.get_phy_pib(), phy.turnaround_time_symbols()) as i64;
+    let mut timeout = pin!(delay.delay_duration(timeout));
+
+    loop {
+        let context = match select(phy.wait(), &mut timeout).await {
+            Either::First(context) => context?,
+            Either::Second(()) => return Ok(Err(Status::NoAck)),
+        };
+
+        let Some(mut message) = phy.process(context).await? else {
+            continue;
+        };
+        mac_state.tap_received(&message);
+
+        let response = mac_state
+            .deserialize_message(message.crc_ok, &mut message.data)
+            .and_then(|frame| parse_response(&frame, message.ranging, seq));
+
+        match response {
+            Some(reply_duration) => {
+                let round_trip = message.timestamp.duration_since(poll_send_time);
+                return Ok(Ok((round_trip - reply_duration) / 2));
+            }
+            None => {
+                trace!("Deferring a frame received while waiting on the ranging response");
+                mac_state.defer_message(message);
+            }
+        }
+    }
+}
+
+/// The time the responder takes between receiving the poll and sending the response.
+///
+/// Its phy needs to be ready to send the response by then, but every symbol of it adds to the error
+/// caused by the clock drift between the devices.
+fn reply_delay<P: Phy>(phy: &P) -> Duration {
+    phy.symbol_period() * (REPLY_PROCESSING_SYMBOLS + phy.send_setup_time_symbols() as i64)
+}
+
+/// Get the time the responder held on to our poll out of its response
+fn parse_response(frame: &Frame<'_>, ranging: bool, seq: u8) -> Option<Duration> {
+    if !ranging
+        || frame.content != FrameContent::Data
+        || frame.header.seq != seq
+        || frame.payload.len() != RESPONSE_PAYLOAD_LENGTH
+        || frame.payload[..RESPONSE_TAG.len()] != RESPONSE_TAG
+    {
+        return None;
+    }
+
+    let (receive_time, send_time) = frame.payload[RESPONSE_TAG.len()..].split_at(8);
+    let receive_time = Instant::from_ticks(u64::from_le_bytes(receive_time.try_into().unwrap()));
+    let send_time = Instant::from_ticks(u64::from_le_bytes(send_time.try_into().unwrap()));
+
+    Some(send_time.duration_since(receive_time))
+}
+
+/// Is the received frame a poll or a response of the ranging?
+pub fn is_ranging_frame(frame: &Frame<'_>, ranging: bool) -> bool {
+    ranging
+        && frame.content == FrameContent::Data
+        && (frame.payload == POLL_PAYLOAD
+            || (frame.payload.len() == RESPONSE_PAYLOAD_LENGTH
+                && frame.payload[..RESPONSE_TAG.len()] == RESPONSE_TAG))
+}
+
+/// If the received frame is a poll meant for us, get the address to send the response to
+pub fn poll_for_us(mac_pib: &MacPib, frame: &Frame<'_>) -> Option<Address> {
+    if frame.payload != POLL_PAYLOAD {
+        return None;
+    }
+
+    let is_for_us = match frame.header.destination? {
+        Address::Short(pan_id, short_address) => {
+            pan_id == mac_pib.pan_id
+                && device_table::is_allocated(short_address)
+                && short_address == mac_pib.short_address
+        }
+        Address::Extended(pan_id, extended_address) => {
+            pan_id == mac_pib.pan_id && extended_address == mac_pib.extended_address
+        }
+    };
+
+    if is_for_us { frame.header.source } else { None }
+}
+
+/// Answer a poll we received at the receive time
+pub async fn send_ranging_response<P: Phy>(
+    phy: &mut P,
+    mac_pib: &mut MacPib,
+    mac_state: &mut MacState<'_>,
+    poll_receive_time: Instant,
+    seq: u8,
+    destination: Address,
+) -> Result<(), P::Error> {
+    if !mac_pib.ranging_enabled(phy.get_phy_pib()) {
+        warn!("Not answering a ranging poll, because ranging is not supported");
+        return Ok(());
+    }
+
+    let requested_send_time = poll_receive_time + reply_delay(phy);
+    // The radio may not send at exactly the requested time, while the response must contain the real send time
+    let send_time = phy.scheduled_send_time(requested_send_time);
+
+    let mut payload = [0; RESPONSE_PAYLOAD_LENGTH];
+    let (tag, times) = payload.split_at_mut(RESPONSE_TAG.len());
+    tag.copy_from_slice(&RESPONSE_TAG);
+    times[..8].copy_from_slice(&poll_receive_time.ticks().to_le_bytes());
+    times[8..].copy_from_slice(&send_time.ticks().to_le_bytes());
+
+    let data = mac_state.serialize_frame(ranging_frame(mac_pib, destination, seq, &payload));
+
+    if !mac_state.duty_cycle_allows(phy, send_time, &data) {
+        warn!("Not answering a ranging poll, because it would go over the duty cycle limit");
+        return Ok(());
+    }
+
+    // The send time is in the response, so it must be sent at exactly that time
+    match phy
+        .send(
+            &data,
+            Some(requested_send_time),
+            true,
+            false,
+            SendContinuation::Idle,
+        )
+        .await?
+    {
+        SendResult::Success(send_time, _) => {
+            mac_state.register_transmission(phy, mac_pib, send_time, &data);
+            Ok(())
+        }
+        SendResult::ChannelAccessFailure => {
+            unreachable!();
+        }
+    }
+}
+
+/// A data frame from us to the destination with the ranging payload
+fn ranging_frame<'p>(
+    mac_pib: &MacPib,
+    destination: Address,
+    seq: u8,
+    payload: &'p [u8],
+) -> Frame<'p> {
+    let source = if device_table::is_allocated(mac_pib.short_address) {
+        Address::Short(mac_pib.pan_id, mac_pib.short_address)
+    } else {
+        Address::Extended(mac_pib.pan_id, mac_pib.extended_address)
+    };
+
+    Frame {
+        header: Header {
+            frame_type: FrameType::Data,
+            frame_pending: false,
+            ack_request: false,
+            pan_id_compress: Header::pan_id_compression(Some(destination), Some(source)),
+            seq_no_suppress: false,
+            ie_present: false,
+            version: FrameVersion::Ieee802154_2003,
+            seq,
+            destination: Some(destination),
+            source: Some(source),
+            auxiliary_security_header: None,
+            time_correction: None,
+        },
+        content: FrameContent::Data,
+        payload,
+        footer: [0, 0],
+    }
+}
@@ -0,0 +1,94 @@
+use heapless::Vec;
+
+use super::{MacError, commander::RequestResponder, state::MacState};
+use crate::{
+    ChannelPage,
+    phy::Phy,
+    sap::{
+        Status,
+        spectrum_survey::{MAX_SURVEY_CHANNELS, SpectrumSurveyConfirm, SpectrumSurveyRequest},
+    },
+};
+
+pub async fn process_spectrum_survey_request<P: Phy>(
+    phy: &mut P,
+    mac_state: &MacState<'_>,
+    responder: RequestResponder<'_, SpectrumSurveyRequest>,
+) {
+    // The scan is using the radio, so we can't switch channels
+    if mac_state.current_scan_process.is_some() {
+        responder.respond(SpectrumSurveyConfirm {
+            status: Status::ScanInProgress,
+            energy_map: Vec::new(),
+        });
+        return;
+    }
+
+    let phy_pib = phy.get_phy_pib();
+    let original_channel = phy_pib.current_channel;
+    let original_page = phy_pib.current_page;
+
+    let mut energy_map = Vec::new();
+    let survey_result = survey(phy, &mut energy_map).await;
+
+    let restore_result = phy
+        .update_phy_pib(|pib| {
+            pib.current_channel = original_channel;
+            pib.current_page = original_page;
+        })
+        .await;
+
+    match survey_result.and(restore_result) {
+        Ok(()) => responder.respond(SpectrumSurveyConfirm {
+            status: Status::Success,
+            energy_map,
+        }),
+        Err(e) if P::is_unsupported(&e) => {
+            warn!("The radio can't measure the energy of a channel: {}", e);
+            responder.respond(SpectrumSurveyConfirm {
+                status: Status::NotImplemented,
+                energy_map,
+            });
+        }
+        Err(e) => {
+            error!("Could not do the spectrum survey: {}", e);
+            responder.respond_with_error(MacError::PhyError(e), |status| SpectrumSurveyConfirm {
+                status,
+                energy_map,
+            });
+        }
+    }
+}
+
+async fn survey<P: Phy>(
+    phy: &mut P,
+    energy_map: &mut Vec<(ChannelPage, u8, u8), MAX_SURVEY_CHANNELS>,
+) -> Result<(), P::Error> {
+    let channels_supported = phy.get_phy_pib().channels_supported;
+
+    for description in channels_supported {
+        for &channel in description.channel_numbers {
+            phy.update_phy_pib(|pib| {
+                pib.current_channel = channel;
+                pib.current_page = description.page;
+            })
+            .await?;
+
+            let energy = phy.measure_energy().await?;
+            trace!(
+                "Measured energy {} on channel '{}' of page '{:?}'",
+                energy, channel, description.page
+            );
+
+            if energy_map
+                .push((description.page, channel, energy))
+                .is_err()
+            {
+                warn!("Too many channels to survey, skipping the rest");
+                return Ok(());
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -2,20 +2,31 @@ use arraydeque::ArrayDeque;
 use heapless::Vec;
 use rand_core::RngCore;
 
+#[cfg(feature = "frame-tap")]
+use super::frame_tap::{FrameDirection, FrameTap};
 use super::{
-    MacConfig,
+    MacConfig, PlanningHeadroom,
     callback::{DataRequestCallback, SendCallback},
+    commander::MAX_INDIRECT_INDICATIONS,
+    device_table::DeviceTable,
+    duty_cycle::DutyCycleGovernor,
+    mlme_associate::ShortAddressAssignment,
     mlme_scan::ScanProcess,
+    mlme_sync::BeaconSync,
 };
 use crate::{
     DeviceAddress,
+    consts::MAX_SIFS_FRAME_SIZE,
+    phy::{FrameFilter, Phy, ReceivedMessage},
+    pib::{MacPib, PhyPib},
     sap::{SecurityInfo, Status},
-    time::{DelayNsExt, Instant},
+    time::{DelayNsExt, Duration, Instant},
     wire::{
-        FooterMode, FrameSerDesContext, ShortAddress,
-        beacon::{GuaranteedTimeSlotInformation, PendingAddress},
-        command::AssociationStatus,
-        security::{SecurityContext, default::Unimplemented},
+        Address, ExtendedAddress, FooterMode, FrameSerDesContext, ShortAddress,
+        beacon::{BeaconOrder, GuaranteedTimeSlotInformation, PendingAddress, SuperframeOrder},
+        command::{AssociationStatus, CapabilityInformation, DisassociationReason},
+        fcs::FCS_LENGTH,
+        security::{SecurityContext, SecurityError, default::Unimplemented},
     },
 };
 
@@ -23,22 +34,64 @@ pub struct MacState<'a> {
     pub message_scheduler: MessageScheduler<'a>,
     /// The security info of the beacons this mac is sending
     pub beacon_security_info: SecurityInfo,
-    /// If true, the beacon of the coordinator this device is associated to is actively being tracked
-    pub coordinator_beacon_tracked: bool,
+    /// If some, the beacon of the coordinator this device is associated to is actively being tracked
+    /// and this is the superframe it announces
+    pub tracked_superframe: Option<TrackedSuperframe>,
+    /// If some, an MLME-SYNC.request is being carried out
+    pub beacon_sync: Option<BeaconSync>,
     /// If and how this device sends out beacons
     pub beacon_mode: BeaconMode,
     /// Are we the pan coordinator?
     pub is_pan_coordinator: bool,
+    /// Are we associated to a coordinator? Set by a successful association and cleared by a disassociation.
+    pub is_associated: bool,
     /// Our current GTS setup we send out in our beacons
     pub current_gts: GuaranteedTimeSlotInformation,
     /// Are we currently in our own superframe?
     pub own_superframe_active: bool,
+    /// With battery life extension, the time in symbols at which the receiver is turned off again
+    /// after our last beacon
+    pub batt_life_ext_window_end: Option<i64>,
     /// If some, contains the state of the current scan being done
     pub current_scan_process: Option<ScanProcess<'a>>,
+    /// Should received frames be acked by the mac? Copied from the config.
+    pub auto_ack: bool,
+    /// Should enhanced acks carry a time correction? Copied from the config.
+    pub ack_time_correction: bool,
+    /// The devices that have associated to us
+    pub device_table: DeviceTable,
+    /// The capabilities of the devices whose association requests are waiting on a response of the higher layer,
+    /// and the short address the [MacConfig::short_address_assignment] picked for them
+    pub association_requests: Vec<
+        (ExtendedAddress, CapabilityInformation, Option<ShortAddress>),
+        MAX_INDIRECT_INDICATIONS,
+    >,
+    /// How many association requests are remembered at most.
+    /// Copied from [MacConfig::max_indirect_indications], since every request waits on an indirect indication.
+    pub max_association_requests: usize,
+    /// Copied from the config
+    pub short_address_assignment: Option<ShortAddressAssignment>,
+    /// How the footer of frames is handled, based on [MacConfig::mac_fcs]
+    footer_mode: FooterMode,
+    /// Keeps the transmissions within the [MacConfig::duty_cycle_limit]
+    pub duty_cycle: DutyCycleGovernor,
+    /// Copied from the config
+    pub planning_headroom: PlanningHeadroom,
+    /// The frame filter the phy was last given. Phys start without one.
+    pub frame_filter: Option<FrameFilter>,
+    /// Frames that came in while the mac was listening for something specific, like a ranging response.
+    /// They are processed once the mac is back in its run loop.
+    pub deferred_messages: ArrayDeque<ReceivedMessage, MAX_DEFERRED_MESSAGES>,
+    /// Where the sent and received frames are copied to. Set by the mac engine.
+    #[cfg(feature = "frame-tap")]
+    pub frame_tap: Option<&'a FrameTap>,
 
     security_context: SecurityContext<Unimplemented, Unimplemented>,
 }
 
+/// The amount of frames that can wait in [MacState::deferred_messages]
+const MAX_DEFERRED_MESSAGES: usize = 4;
+
 impl MacState<'_> {
     pub fn new<Rng: RngCore, Delay: DelayNsExt>(config: &MacConfig<Rng, Delay>) -> Self {
         Self {
@@ -46,22 +99,126 @@ impl MacState<'_> {
                 scheduled_broadcasts: ArrayDeque::new(),
                 data_requests: Vec::new(),
                 pending_data: Vec::new(),
+                // A new scheduler replaces any old one, which must be known outside the mac too
+                pending_data_changed: true,
             },
             beacon_security_info: Default::default(),
-            coordinator_beacon_tracked: false,
+            tracked_superframe: None,
+            beacon_sync: None,
             beacon_mode: BeaconMode::Off,
             security_context: SecurityContext::new(config.extended_address.0, 0, Unimplemented),
             is_pan_coordinator: false,
+            is_associated: false,
             current_gts: GuaranteedTimeSlotInformation::new(),
             own_superframe_active: false,
+            batt_life_ext_window_end: None,
             current_scan_process: None,
+            auto_ack: config.auto_ack,
+            ack_time_correction: config.ack_time_correction,
+            device_table: DeviceTable::new(),
+            association_requests: Vec::new(),
+            max_association_requests: config
+                .max_indirect_indications
+                .min(MAX_INDIRECT_INDICATIONS),
+            short_address_assignment: config.short_address_assignment,
+            footer_mode: if config.mac_fcs {
+                FooterMode::Fcs
+            } else {
+                FooterMode::None
+            },
+            duty_cycle: DutyCycleGovernor::new(config.duty_cycle_limit),
+            planning_headroom: config.planning_headroom,
+            frame_filter: None,
+            deferred_messages: ArrayDeque::new(),
+            #[cfg(feature = "frame-tap")]
+            frame_tap: None,
+        }
+    }
+
+    /// Keep a received frame to process it later, see [Self::deferred_messages]
+    pub fn defer_message(&mut self, message: ReceivedMessage) {
+        if self.deferred_messages.push_back(message).is_err() {
+            warn!("Dropping a received frame, because too many are waiting to be processed");
         }
     }
 
     fn frame_ser_des_context(&mut self) -> FrameSerDesContext<'_, Unimplemented, Unimplemented> {
-        FrameSerDesContext::new(FooterMode::None, Some(&mut self.security_context))
+        FrameSerDesContext::new(self.footer_mode, Some(&mut self.security_context))
+    }
+
+    /// The length of a serialized frame of `data_length` octets as it goes over the air
+    fn psdu_length(&self, data_length: usize) -> usize {
+        // Without a footer in the data, the phy adds the FCS
+        match self.footer_mode {
+            FooterMode::Fcs | FooterMode::Explicit => data_length,
+            FooterMode::None => data_length + FCS_LENGTH,
+        }
+    }
+
+    /// The number of symbols it takes to send the serialized frame
+    pub fn frame_duration(&self, phy_pib: &PhyPib, data: &[u8]) -> u32 {
+        phy_pib.frame_duration(self.psdu_length(data.len()))
+    }
+
+    /// The number of symbols it took to receive a frame of which the phy passed on `data_length` octets.
+    ///
+    /// The phy strips the FCS from received frames exactly when it adds it to the frames we send.
+    pub fn received_frame_duration(&self, phy_pib: &PhyPib, data_length: usize) -> u32 {
+        phy_pib.frame_duration(self.psdu_length(data_length))
+    }
+
+    /// The number of symbols of the IFS that has to follow the serialized frame
+    pub fn ifs_period(&self, mac_pib: &MacPib, data: &[u8]) -> u8 {
+        if self.psdu_length(data.len()) > MAX_SIFS_FRAME_SIZE as usize {
+            mac_pib.lifs_period
+        } else {
+            mac_pib.sifs_period
+        }
+    }
+
+    /// Can the serialized frame be sent now without going over the duty cycle limit?
+    pub fn duty_cycle_allows(&mut self, phy: &mut impl Phy, now: Instant, data: &[u8]) -> bool {
+        let duration = phy.symbol_period() * self.frame_duration(phy.get_phy_pib(), data) as i64;
+        self.duty_cycle.allows(now, duration)
+    }
+
+    /// Register that a serialized frame was sent at the send time.
+    ///
+    /// Its duration is added to macTxTotalDuration and counts towards the duty cycle limit.
+    pub fn register_transmission(
+        &mut self,
+        phy: &mut impl Phy,
+        mac_pib: &mut MacPib,
+        send_time: Instant,
+        data: &[u8],
+    ) {
+        #[cfg(feature = "frame-tap")]
+        if let Some(frame_tap) = self.frame_tap {
+            frame_tap.tap(FrameDirection::Sent, send_time, data);
+        }
+
+        let symbols = self.frame_duration(phy.get_phy_pib(), data);
+
+        mac_pib.tx_total_duration = mac_pib.tx_total_duration.saturating_add(symbols);
+        self.duty_cycle
+            .register(send_time, phy.symbol_period() * symbols as i64);
+    }
+
+    /// Copy a message the phy received to the frame tap, if there is one
+    pub fn tap_received(&self, message: &ReceivedMessage) {
+        #[cfg(feature = "frame-tap")]
+        if let Some(frame_tap) = self.frame_tap {
+            frame_tap.tap(FrameDirection::Received, message.timestamp, &message.data);
+        }
+        #[cfg(not(feature = "frame-tap"))]
+        let _ = message;
     }
 
+    /// Serialize the frame, securing it if needed.
+    ///
+    /// Every call returns a new buffer that's owned by the caller. No scratch buffer is shared between calls,
+    /// so frames can be built back to back (like a beacon and the broadcast that follows it)
+    /// without the second one clobbering the first.
     pub fn serialize_frame(
         &mut self,
         frame: crate::wire::Frame<'_>,
@@ -72,30 +229,110 @@ impl MacState<'_> {
         buffer
             .resize_default(crate::consts::MAX_PHY_PACKET_SIZE)
             .unwrap();
-        let length = frame
-            .try_write(&mut buffer, &mut self.frame_ser_des_context())
-            .expect("Buffer is always big enough");
+        let result = frame.try_write(&mut buffer, &mut self.frame_ser_des_context());
+
+        // We don't know how far the serialization got, so dump the whole buffer
+        #[cfg(feature = "frame-dump")]
+        if result.is_err() {
+            super::frame_dump::dump_frame("Frame that could not be serialized", &buffer);
+        }
+
+        let length = result.expect("Buffer is always big enough");
         buffer.truncate(length);
 
         buffer
     }
 
+    /// Deserialize the frame of a received message, given its `crc_ok` and `data`.
+    ///
+    /// Messages that failed the CRC check of the phy are dropped here.
+    /// Only the data is borrowed, so the other fields of the message can be used along with the frame.
+    pub fn deserialize_message<'data>(
+        &mut self,
+        crc_ok: Option<bool>,
+        data: &'data mut [u8],
+    ) -> Option<crate::wire::Frame<'data>> {
+        if crc_ok == Some(false) {
+            trace!("Dropping a frame with an invalid CRC");
+            return None;
+        }
+
+        self.deserialize_frame(data)
+    }
+
+    /// Deserialize the frame of a received message like [Self::deserialize_message],
+    /// but keep a secured frame that can't be unsecured.
+    ///
+    /// Such a frame comes with the reason it couldn't be unsecured and its payload is left as it was received,
+    /// see [Frame::try_read_and_verify](crate::wire::Frame::try_read_and_verify).
+    pub fn deserialize_unverified_message<'data>(
+        &mut self,
+        crc_ok: Option<bool>,
+        data: &'data mut [u8],
+    ) -> Option<(crate::wire::Frame<'data>, Option<SecurityError>)> {
+        if crc_ok == Some(false) {
+            trace!("Dropping a frame with an invalid CRC");
+            return None;
+        }
+
+        self.deserialize_unverified_frame(data)
+    }
+
+    /// Deserialize the frame in the data, unsecuring it if needed.
+    ///
+    /// Unsecuring is done in place, so the data is changed. The returned frame borrows from the data,
+    /// which means the data can't be touched for as long as the frame is used.
     pub fn deserialize_frame<'data>(
         &mut self,
         data: &'data mut [u8],
     ) -> Option<crate::wire::Frame<'data>> {
-        match crate::wire::Frame::try_read_and_unsecure(
+        match self.deserialize_unverified_frame(data)? {
+            (frame, None) => Some(frame),
+            (_, Some(_)) => None,
+        }
+    }
+
+    fn deserialize_unverified_frame<'data>(
+        &mut self,
+        data: &'data mut [u8],
+    ) -> Option<(crate::wire::Frame<'data>, Option<SecurityError>)> {
+        #[cfg(feature = "frame-dump")]
+        let original_data =
+            Vec::<u8, { crate::consts::MAX_PHY_PACKET_SIZE }>::from_slice(data).unwrap_or_default();
+
+        match crate::wire::Frame::try_read_and_verify(
             data,
             &mut self.frame_ser_des_context(),
             &mut Unimplemented,
         ) {
-            Ok((frame, _)) => Some(frame),
+            Ok((frame, _, security_error)) => {
+                if let Some(e) = security_error {
+                    #[cfg(feature = "defmt-03")]
+                    warn!("Could not unsecure a frame: {}", defmt::Debug2Format(&e));
+                    #[cfg(not(feature = "defmt-03"))]
+                    warn!("Could not unsecure a frame: {:?}", e);
+
+                    #[cfg(feature = "frame-dump")]
+                    super::frame_dump::dump_frame(
+                        "Frame that could not be unsecured",
+                        &original_data,
+                    );
+                }
+
+                Some((frame, security_error))
+            }
             Err(e) => {
                 #[cfg(feature = "defmt-03")]
                 warn!("Could not deserialize a frame: {}", defmt::Debug2Format(&e));
                 #[cfg(not(feature = "defmt-03"))]
                 warn!("Could not deserialize a frame: {:?}", e);
 
+                #[cfg(feature = "frame-dump")]
+                super::frame_dump::dump_frame(
+                    "Frame that could not be deserialized",
+                    &original_data,
+                );
+
                 None
             }
         }
@@ -115,7 +352,8 @@ pub struct MessageScheduler<'a> {
     scheduled_broadcasts: ArrayDeque<ScheduledMessage<'a>, 4>,
     data_requests: Vec<ScheduledDataRequest<'a>, 1>,
     /// Data that's pending being requested by a data request
-    pending_data: Vec<PendingData, 16>,
+    pending_data: Vec<PendingData, MAX_PENDING_TRANSACTIONS>,
+    pending_data_changed: bool,
 }
 
 impl<'a> MessageScheduler<'a> {
@@ -164,24 +402,59 @@ impl<'a> MessageScheduler<'a> {
     pub fn push_pending_data(&mut self, data: PendingData) -> Result<(), Status> {
         // TODO: Clean up data based on time
         match self.pending_data.push(data) {
-            Ok(()) => Ok(()),
+            Ok(()) => {
+                self.pending_data_changed = true;
+                Ok(())
+            }
             Err(_) => Err(Status::TransactionOverflow),
         }
     }
 
-    pub fn take_pending_data(&mut self, device_address: DeviceAddress) -> Option<PendingData> {
+    /// Take the pending data of the device.
+    /// The device table is used so data for a device is found under both its short and extended address.
+    pub fn take_pending_data(
+        &mut self,
+        device_address: DeviceAddress,
+        device_table: &DeviceTable,
+    ) -> Option<PendingData> {
         let position = self
             .pending_data
             .iter()
-            .position(|pd| pd.device == device_address)?;
+            .position(|pd| device_table.is_same_device(pd.device, device_address))?;
+        self.pending_data_changed = true;
         Some(self.pending_data.remove(position))
     }
 
     /// Returns true if there's pending data for the given device
-    pub fn has_pending_data(&self, device_address: DeviceAddress) -> bool {
+    pub fn has_pending_data(
+        &self,
+        device_address: DeviceAddress,
+        device_table: &DeviceTable,
+    ) -> bool {
         self.pending_data
             .iter()
-            .any(|pd| pd.device == device_address)
+            .any(|pd| device_table.is_same_device(pd.device, device_address))
+    }
+
+    /// The pending data, in the order it was queued
+    pub fn pending_transactions(&self) -> impl Iterator<Item = PendingTransactionInfo> + '_ {
+        self.pending_data.iter().map(|pd| PendingTransactionInfo {
+            device: pd.device,
+            kind: match pd.data_value {
+                PendingDataValue::AssociationResponse { .. } => {
+                    PendingTransactionKind::AssociationResponse
+                }
+                PendingDataValue::DisassociationNotification { .. } => {
+                    PendingTransactionKind::DisassociationNotification
+                }
+            },
+            registration_time: pd.registration_time,
+        })
+    }
+
+    /// Returns true if the pending data has changed since the last call
+    pub fn take_pending_data_changed(&mut self) -> bool {
+        core::mem::take(&mut self.pending_data_changed)
     }
 
     pub fn schedule_data_request(&mut self, data_request: ScheduledDataRequest<'a>) {
@@ -190,6 +463,10 @@ impl<'a> MessageScheduler<'a> {
         }
     }
 
+    pub fn take_scheduled_data_request(&mut self) -> Option<ScheduledDataRequest<'a>> {
+        self.data_requests.pop()
+    }
+
     #[expect(unused, reason = "For now")]
     pub fn get_scheduled_superframe_data_request(&self) -> Option<&ScheduledDataRequest<'a>> {
         self.data_requests
@@ -233,14 +510,47 @@ pub struct ScheduledMessage<'a> {
 pub struct PendingData {
     pub device: DeviceAddress,
     pub data_value: PendingDataValue,
-    #[expect(unused, reason = "For now")]
     pub registration_time: Instant,
 }
 
+/// The maximum number of indirect transactions a coordinator can hold
+pub const MAX_PENDING_TRANSACTIONS: usize = 16;
+
+/// An indirect transaction that waits for the device to pick it up with a data request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct PendingTransactionInfo {
+    /// The device the transaction is for
+    pub device: DeviceAddress,
+    pub kind: PendingTransactionKind,
+    /// The time the transaction was queued
+    pub registration_time: Instant,
+}
+
+impl PendingTransactionInfo {
+    /// How long the transaction has been waiting at the given time
+    pub fn age(&self, now: Instant) -> Duration {
+        now.duration_since(self.registration_time)
+    }
+}
+
+/// What an indirect transaction sends to the device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum PendingTransactionKind {
+    AssociationResponse,
+    DisassociationNotification,
+}
+
 pub enum PendingDataValue {
     AssociationResponse {
         short_address: ShortAddress,
         association_status: AssociationStatus,
+        /// Whether the device said it keeps its receiver on when idle
+        rx_on_when_idle: bool,
+    },
+    DisassociationNotification {
+        reason: DisassociationReason,
     },
 }
 
@@ -250,6 +560,9 @@ pub struct ScheduledDataRequest<'a> {
     #[expect(unused, reason = "For now")]
     pub used_security_info: SecurityInfo,
     pub callback: DataRequestCallback<'a>,
+    /// The amount of data requests that came before this one, because the coordinator kept
+    /// setting the frame pending bit (5.1.6.3)
+    pub follow_ups: usize,
 }
 
 pub enum DataRequestMode {
@@ -280,7 +593,19 @@ pub enum DataRequestTrigger {
     BeaconPendingDataIndication,
     #[expect(unused, reason = "For now")]
     MlmePoll,
-    Association,
+    /// We're waiting on the association response of the given coordinator
+    Association { coord_address: Address },
+    /// The coordinator set the frame pending bit in the last frame it sent us
+    FramePending,
+}
+
+/// The superframe of the coordinator whose beacon is being tracked
+#[derive(Debug, Clone, Copy)]
+pub struct TrackedSuperframe {
+    pub beacon_order: BeaconOrder,
+    pub superframe_order: SuperframeOrder,
+    /// The coordinator uses battery life extension, so the CSMA-CA must use the shorter backoffs
+    pub battery_life_extension: bool,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -290,7 +615,74 @@ pub enum BeaconMode {
     /// A beacon will be sent out according to the mac pib on its own time schedule.
     OnAutonomous,
     /// A beacon will be sent out after every tracked beacon with the given `start_time` offset.
-    /// This is only valid if [MacState::pan_coordinator_beacon_tracked] is set to true.
-    #[expect(dead_code, reason = "for future use")]
+    /// This is only valid if [MacState::tracked_superframe] is set.
     OnTracking { start_time: u32 },
 }
+
+#[cfg(test)]
+mod tests {
+    use embedded_hal_async::delay::DelayNs;
+    use rand::{SeedableRng, rngs::StdRng};
+
+    use super::*;
+    use crate::wire::{
+        Address, ExtendedAddress, Frame, FrameContent, FrameType, FrameVersion, Header, PanId,
+    };
+
+    #[derive(Clone)]
+    struct NoDelay;
+
+    impl DelayNs for NoDelay {
+        async fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    fn mac_state() -> MacState<'static> {
+        MacState::new(&MacConfig::new(
+            ExtendedAddress(1),
+            StdRng::seed_from_u64(0),
+            NoDelay,
+        ))
+    }
+
+    fn data_frame(seq: u8, payload: &[u8]) -> Frame<'_> {
+        Frame {
+            header: Header {
+                frame_type: FrameType::Data,
+                frame_pending: false,
+                ack_request: false,
+                pan_id_compress: false,
+                seq_no_suppress: false,
+                ie_present: false,
+                version: FrameVersion::Ieee802154_2003,
+                seq,
+                destination: Some(Address::Short(PanId(1), ShortAddress(2))),
+                source: Some(Address::Short(PanId(1), ShortAddress(3))),
+                auxiliary_security_header: None,
+                time_correction: None,
+            },
+            content: FrameContent::Data,
+            payload,
+            footer: [0, 0],
+        }
+    }
+
+    #[test]
+    fn back_to_back_frames_dont_clobber_each_other() {
+        let mut mac_state = mac_state();
+
+        let first = mac_state.serialize_frame(data_frame(1, b"first"));
+        let second = mac_state.serialize_frame(data_frame(2, b"second frame"));
+        assert_ne!(first, second);
+
+        // The first buffer is still intact and deserializes to the first frame
+        let mut first_data = first.clone();
+        let frame = mac_state.deserialize_frame(&mut first_data).unwrap();
+        assert_eq!(frame.header.seq, 1);
+        assert_eq!(frame.payload, b"first");
+
+        let mut second_data = second.clone();
+        let frame = mac_state.deserialize_frame(&mut second_data).unwrap();
+        assert_eq!(frame.header.seq, 2);
+        assert_eq!(frame.payload, b"second frame");
+    }
+}
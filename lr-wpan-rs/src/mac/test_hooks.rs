@@ -0,0 +1,163 @@
+//! Hooks that let a test harness make the mac engine behave deterministically.
+//!
+//! Only available with the `test-hooks` feature. Never enable it in production builds.
+//!
+//! # Forcing the order of the engine branches
+//!
+//! Every iteration, the mac engine waits for one of three things to happen (see [EngineBranch]).
+//! When multiple of them are ready at the same time, the first ready one in the [BranchOrder]
+//! wins and the others are handled in a later iteration.
+//! Normally the radio events go first, but a test can change this with
+//! [MacCommander::set_branch_order](super::MacCommander::set_branch_order).
+//!
+//! For example, to reproduce a request that arrives exactly at the start of a superframe
+//! and gets handled before the beacon is sent:
+//!
+//! ```rust,ignore
+//! use lr_wpan_rs::mac::test_hooks::{BranchOrder, EngineBranch};
+//!
+//! commander.set_branch_order(BranchOrder::new(
+//!     EngineBranch::Request,
+//!     EngineBranch::RadioEvent,
+//!     EngineBranch::IndirectIndication,
+//! ));
+//!
+//! // Wait until just before the superframe starts, so both the superframe start
+//! // and the request are ready when the engine gets polled again
+//! simulation_time.delay_until(superframe_start).await;
+//! let confirm = commander.request(request).await;
+//!
+//! // Go back to the normal behavior
+//! commander.set_branch_order(BranchOrder::DEFAULT);
+//! ```
+
+use core::{
+    future::{Future, poll_fn},
+    pin::pin,
+    task::Poll,
+};
+
+use embassy_futures::select::Either3;
+
+/// The things the mac engine waits for in its main loop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum EngineBranch {
+    /// Something happened on the radio or a timer of the mac expired, like the start of a superframe
+    RadioEvent,
+    /// A response to an indication that was given to the higher layer
+    IndirectIndication,
+    /// A request from the higher layer
+    Request,
+}
+
+/// The order in which the [EngineBranch]es are checked.
+/// The first branch that is ready is handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct BranchOrder([EngineBranch; 3]);
+
+impl BranchOrder {
+    /// The order the engine uses when no other order is set
+    pub const DEFAULT: Self = Self::new(
+        EngineBranch::RadioEvent,
+        EngineBranch::IndirectIndication,
+        EngineBranch::Request,
+    );
+
+    /// Create a new order, from highest to lowest priority.
+    ///
+    /// Panics if a branch is given more than once.
+    pub const fn new(first: EngineBranch, second: EngineBranch, third: EngineBranch) -> Self {
+        assert!(
+            first as u8 != second as u8
+                && first as u8 != third as u8
+                && second as u8 != third as u8,
+            "Every branch must be given exactly once"
+        );
+
+        Self([first, second, third])
+    }
+}
+
+impl Default for BranchOrder {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Like [embassy_futures::select::select3], but the futures are polled in the given order
+pub(crate) async fn select3_ordered<A: Future, B: Future, C: Future>(
+    order: BranchOrder,
+    radio_event: A,
+    indirect_indication: B,
+    request: C,
+) -> Either3<A::Output, B::Output, C::Output> {
+    let mut radio_event = pin!(radio_event);
+    let mut indirect_indication = pin!(indirect_indication);
+    let mut request = pin!(request);
+
+    poll_fn(|cx| {
+        for branch in order.0 {
+            match branch {
+                EngineBranch::RadioEvent => {
+                    if let Poll::Ready(output) = radio_event.as_mut().poll(cx) {
+                        return Poll::Ready(Either3::First(output));
+                    }
+                }
+                EngineBranch::IndirectIndication => {
+                    if let Poll::Ready(output) = indirect_indication.as_mut().poll(cx) {
+                        return Poll::Ready(Either3::Second(output));
+                    }
+                }
+                EngineBranch::Request => {
+                    if let Poll::Ready(output) = request.as_mut().poll(cx) {
+                        return Poll::Ready(Either3::Third(output));
+                    }
+                }
+            }
+        }
+
+        Poll::Pending
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use core::future::ready;
+
+    use super::*;
+
+    #[futures_test::test]
+    async fn default_order_prefers_radio_events() {
+        let result = select3_ordered(BranchOrder::DEFAULT, ready(1), ready(2), ready(3)).await;
+        assert!(matches!(result, Either3::First(1)));
+    }
+
+    #[futures_test::test]
+    async fn first_ready_branch_in_order_wins() {
+        let order = BranchOrder::new(
+            EngineBranch::Request,
+            EngineBranch::IndirectIndication,
+            EngineBranch::RadioEvent,
+        );
+
+        let result = select3_ordered(order, ready(1), ready(2), ready(3)).await;
+        assert!(matches!(result, Either3::Third(3)));
+
+        let result =
+            select3_ordered(order, ready(1), ready(2), core::future::pending::<i32>()).await;
+        assert!(matches!(result, Either3::Second(2)));
+    }
+
+    #[test]
+    #[should_panic]
+    fn duplicate_branches_are_rejected() {
+        BranchOrder::new(
+            EngineBranch::Request,
+            EngineBranch::Request,
+            EngineBranch::RadioEvent,
+        );
+    }
+}
@@ -0,0 +1,71 @@
+//! The requests that aren't implemented (yet).
+//!
+//! Instead of bringing down the mac, they're confirmed with [Status::NotImplemented].
+
+use super::commander::RequestResponder;
+use crate::{
+    sap::{
+        RequestValue, Status,
+        calibrate::{CalibrateConfirm, CalibrateRequest},
+        dps::{DpsConfirm, DpsRequest},
+        gts::{GtsConfirm, GtsRequest},
+        poll::{PollConfirm, PollRequest},
+        purge::{PurgeConfirm, PurgeRequest},
+        rx_enable::{RxEnableConfirm, RxEnableRequest},
+    },
+    time::Duration,
+};
+
+pub fn process_unimplemented_request(responder: RequestResponder<'_, RequestValue>) {
+    const STATUS: Status = Status::NotImplemented;
+
+    match &responder.request {
+        RequestValue::Gts(_) => {
+            warn!("The MLME-GTS.request is not implemented yet");
+            let responder = responder.into_concrete::<GtsRequest>();
+            let gts_characteristics = responder.request.gts_characteristics;
+            responder.respond(GtsConfirm {
+                gts_characteristics,
+                status: STATUS,
+            });
+        }
+        RequestValue::RxEnable(_) => {
+            warn!("The MLME-RX-ENABLE.request is not implemented yet");
+            responder
+                .into_concrete::<RxEnableRequest>()
+                .respond(RxEnableConfirm { status: STATUS });
+        }
+        RequestValue::Poll(_) => {
+            warn!("The MLME-POLL.request is not implemented yet");
+            responder
+                .into_concrete::<PollRequest>()
+                .respond(PollConfirm { status: STATUS });
+        }
+        RequestValue::Dps(_) => {
+            warn!("The MLME-DPS.request is not implemented yet");
+            responder
+                .into_concrete::<DpsRequest>()
+                .respond(DpsConfirm { status: STATUS });
+        }
+        RequestValue::Calibrate(_) => {
+            warn!("The MLME-CALIBRATE.request is not implemented yet");
+            responder
+                .into_concrete::<CalibrateRequest>()
+                .respond(CalibrateConfirm {
+                    status: STATUS,
+                    cal_tx_rmarker_offset: Duration::from_ticks(0),
+                    cal_rx_rmarker_offset: Duration::from_ticks(0),
+                });
+        }
+        RequestValue::Purge(_) => {
+            warn!("The MCPS-PURGE.request is not implemented yet");
+            let responder = responder.into_concrete::<PurgeRequest>();
+            let msdu_handle = responder.request.msdu_handle;
+            responder.respond(PurgeConfirm {
+                msdu_handle,
+                status: STATUS,
+            });
+        }
+        _ => unreachable!("The request is implemented"),
+    }
+}
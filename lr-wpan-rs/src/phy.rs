@@ -4,6 +4,7 @@ use crate::{
     ChannelPage,
     pib::{PhyPib, PhyPibWrite},
     time::{Duration, Instant},
+    wire::{ExtendedAddress, PanId, ShortAddress, fcs::FCS_LENGTH},
 };
 
 pub trait Phy {
@@ -17,23 +18,65 @@ pub trait Phy {
     const MODULATION: ModulationType;
 
     /// Reset the phy and the pib back to the defeaults as if it was newly created.
+    ///
+    /// The MAC only calls this for an MLME-RESET.request that sets the default PIB.
+    /// Otherwise the phy keeps its PIB, so the channel and transmit power set by the user survive the reset.
     async fn reset(&mut self) -> Result<(), Self::Error>;
 
     /// Get the current time of the radio.
     /// This is not very accurate, but can be used for e.g. logging.
+    ///
+    /// The returned time must be monotonic: it may never be earlier than a previously returned instant.
+    /// The MAC relies on this for calculating durations and scheduling.
     async fn get_instant(&mut self) -> Result<Instant, Self::Error>;
 
     /// Get the amount of time each symbol takes.
     fn symbol_period(&self) -> Duration;
 
+    /// The time in symbols the radio needs to switch from sending to receiving and back (aTurnaroundTime).
+    ///
+    /// After a send with [SendContinuation::WaitForResponse], the MAC expects the receiver to be on this long
+    /// after the frame was sent. The default is the [TURNAROUND_TIME](crate::consts::TURNAROUND_TIME) of the
+    /// standard, which phys that are slower to turn around should override.
+    fn turnaround_time_symbols(&self) -> u32 {
+        crate::consts::TURNAROUND_TIME
+    }
+
+    /// The time in symbols the radio needs to come out of sleep with [Self::wake].
+    ///
+    /// A radio that sleeps can't send at a scheduled `send_time` unless it's woken up at least this long before it.
+    /// The default is 0, which is right for phys that don't implement sleeping.
+    fn wake_up_time_symbols(&self) -> u32 {
+        0
+    }
+
+    /// The time in symbols a send with a `send_time` must at least be scheduled ahead.
+    ///
+    /// Radios that have to be programmed before a delayed send refuse a `send_time` that's closer than this.
+    /// The default is 0, which is right for phys that can send at any time that hasn't passed yet.
+    fn send_setup_time_symbols(&self) -> u32 {
+        0
+    }
+
+    /// The time a send with the given `send_time` really goes out, as it's returned by [Self::send].
+    ///
+    /// Radios schedule a delayed send with a limited resolution, and may add their antenna delay to the time they report.
+    /// The MAC uses this when it has to know the send time before sending, like in the response of ranging.
+    /// The default returns the `send_time` as it is, which is right for phys that send at exactly the given time.
+    fn scheduled_send_time(&self, send_time: Instant) -> Instant {
+        send_time
+    }
+
     /// Send some data.
     ///
     /// If the radio was receiving, it will automatically stop to do the transmission.
     ///
-    /// - The `data` must be a valid MAC frame.
+    /// - The `data` must be a valid MAC frame. If it's empty or longer than [crate::consts::MAX_PHY_PACKET_SIZE], an error must be returned.
     /// - If `send_time` is some, then that must be the time at which the data is sent. This must be done as accurately as possible.
     /// - If `ranging` is true, then the ranging bit must be set.
-    /// - If `use_csma` is true, then the carrier sense mechanism should be used. If the channel is busy, then the send is aborted and [SendResult::ChannelAccessFailure] is returned
+    /// - If `use_csma` is true, then a single clear channel assessment (CCA) must be done first. If the channel is busy, then the send is aborted and [SendResult::ChannelAccessFailure] is returned.
+    ///   The MAC does the random backoffs of the CSMA-CA algorithm and tries again.
+    ///   With the ALOHA [CcaMode](crate::pib::CcaMode) the channel is always clear, so the send is never aborted.
     /// - The `continuation` specifies what the radio should do after the transmission
     ///
     /// The actual time the data frame was sent is returned. This needs to be accurate, especially when `ranging` is true
@@ -46,6 +89,78 @@ pub trait Phy {
         continuation: SendContinuation,
     ) -> Result<SendResult, Self::Error>;
 
+    /// Send some data like [Self::send], but with the given data rate instead of the default one of the phy.
+    ///
+    /// The `data_rate` is encoded like the DataRate of the MCPS-DATA.request. For UWB phys, values 1–4 select
+    /// the data rates of 14.2.6.1 (0.11, 0.85, 6.81 and 27.24 Mb/s). For CSS phys, zero is 250 kb/s and one is 1 Mb/s.
+    /// For all other phys it's zero. The data rate only applies to this frame.
+    ///
+    /// The MAC only uses data rates the [PhyPib::supports_data_rate] says are supported.
+    /// The default implementation ignores the data rate, which is only correct for phys with a single data rate.
+    #[allow(clippy::too_many_arguments)]
+    async fn send_with_data_rate(
+        &mut self,
+        data: &[u8],
+        send_time: Option<Instant>,
+        ranging: bool,
+        use_csma: bool,
+        data_rate: u8,
+        continuation: SendContinuation,
+    ) -> Result<SendResult, Self::Error> {
+        let _ = data_rate;
+        self.send(data, send_time, ranging, use_csma, continuation)
+            .await
+    }
+
+    /// Send two frames back to back.
+    ///
+    /// The `first` frame is sent like it would be with [Self::send], but without a continuation.
+    /// The `second` frame is then sent with a `gap` after the end of the first one, without going through
+    /// the MAC in between. This keeps the gap between the frames as small as the radio allows.
+    /// The gap is measured from the end of the first frame, like the interframe spacing (IFS) of the standard,
+    /// so the time between the send times of the frames is the duration of the first frame plus the gap.
+    /// CSMA is only ever used for the first frame.
+    ///
+    /// The result of the second send is [None] if the first frame could not be sent.
+    /// The `continuation` specifies what the radio should do after the second transmission.
+    ///
+    /// The default implementation simply does two calls to [Self::send]. The duration of the first frame is
+    /// taken from the pib with the FCS the phy adds, so the gap is two octets longer when the frame already has one.
+    #[allow(clippy::too_many_arguments)]
+    async fn send_back_to_back(
+        &mut self,
+        first: &[u8],
+        second: &[u8],
+        send_time: Option<Instant>,
+        ranging: bool,
+        use_csma: bool,
+        gap: Duration,
+        continuation: SendContinuation,
+    ) -> Result<(SendResult, Option<SendResult>), Self::Error> {
+        let first_duration = self.symbol_period()
+            * self.get_phy_pib().frame_duration(first.len() + FCS_LENGTH) as i64;
+
+        let first_result = self
+            .send(first, send_time, ranging, use_csma, SendContinuation::Idle)
+            .await?;
+
+        let SendResult::Success(first_send_time, _) = first_result else {
+            return Ok((first_result, None));
+        };
+
+        let second_result = self
+            .send(
+                second,
+                Some(first_send_time + first_duration + gap),
+                ranging,
+                false,
+                continuation,
+            )
+            .await?;
+
+        Ok((first_result, Some(second_result)))
+    }
+
     /// Start the receiver of the radio.
     ///
     /// It will continuously receive messages according to the PIB settings.
@@ -53,7 +168,8 @@ pub trait Phy {
     /// even if that disrupts the operation for a little bit.
     ///
     /// If this function is called when the radio is already receiving, then nothing should happen and the
-    /// radio should continue receiving.
+    /// radio should continue receiving. This must not return an error: the MAC doesn't keep track of the
+    /// receiver state and calls this every time it wants the receiver to be on.
     ///
     /// A received message is returned in the [Self::process] function.
     async fn start_receive(&mut self) -> Result<(), Self::Error>;
@@ -61,17 +177,80 @@ pub trait Phy {
     /// Stop the receiver and go back to idle mode
     async fn stop_receive(&mut self) -> Result<(), Self::Error>;
 
+    /// Put the radio in its lowest power state that it can still be woken up from with [Self::wake].
+    ///
+    /// The receiver is stopped first. While sleeping, the radio doesn't send or receive anything and the other
+    /// functions of this trait may return an error. The pib, the frame filter and any other configuration must be
+    /// the same after waking up, so a phy whose radio loses its configuration must restore it in [Self::wake].
+    /// The clock of [Self::get_instant] must stay monotonic over the sleep, but may stop counting during it.
+    ///
+    /// The MAC doesn't sleep the radio by itself. The default implementation does nothing, which keeps the radio idle.
+    async fn sleep(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Wake the radio up from [Self::sleep] and go back to idle mode.
+    ///
+    /// This takes [Self::wake_up_time_symbols]. Once this returns, the radio can be used normally again,
+    /// but a transmission scheduled with a `send_time` can only be met if it's still at least
+    /// [Self::send_setup_time_symbols] away. Waking up a radio that is not sleeping does nothing.
+    async fn wake(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Abort a transmission that is scheduled or in progress and go back to idle mode.
+    ///
+    /// A transmission is left behind when the future of [Self::send] or [Self::send_back_to_back] is dropped
+    /// before it's done, e.g. when a delayed send is cancelled. Frames that haven't gone on air yet must not be sent anymore.
+    ///
+    /// If no transmission is in progress, the radio just goes to idle mode.
+    async fn abort_send(&mut self) -> Result<(), Self::Error>;
+
+    /// Set the addresses the radio filters received frames on, or disable the filtering with [None].
+    ///
+    /// Radios that can filter frames in hardware (5.1.6.2) only pass on the frames that are addressed to
+    /// the addresses in the filter or to the broadcast addresses. This saves processing frames the MAC would drop anyway.
+    /// The MAC disables the filtering when it needs to see all frames, like in promiscuous mode.
+    ///
+    /// After a reset, filtering is disabled. The default implementation does nothing,
+    /// so the radio keeps passing on all frames it receives.
+    async fn set_frame_filter(&mut self, filter: Option<FrameFilter>) -> Result<(), Self::Error> {
+        let _ = filter;
+        Ok(())
+    }
+
+    /// Measure the energy on the current channel (ED) as described in 10.2.5.
+    ///
+    /// The result is scaled so that `0x00` is the lowest and `0xff` is the highest energy the radio can detect.
+    /// After the measurement, the radio must be back in the state it was in before (receiving or idle).
+    /// Radios that can't measure the energy return an error for which [Phy::is_unsupported] is true.
+    async fn measure_energy(&mut self) -> Result<u8, Self::Error>;
+
+    /// Returns true if the error means the radio doesn't support the operation, rather than that the operation failed.
+    ///
+    /// The MAC reports these errors as [Status::NotImplemented](crate::sap::Status::NotImplemented) instead of
+    /// a [Status::PhyError](crate::sap::Status::PhyError). By default no error is one of these.
+    fn is_unsupported(error: &Self::Error) -> bool {
+        let _ = error;
+        false
+    }
+
     /// Wait on something to happen. When not doing anything with the phy, this function should be running.
     /// The function is cancellable, so you can use it in a select while remaining to have access to the other functions
     /// of this trait.
     ///
+    /// When there's nothing to wait for, e.g. because the radio isn't receiving, this function must stay pending
+    /// instead of returning right away. The MAC would otherwise keep calling it in a busy loop.
+    ///
     /// When this function is done, it returns a context that should be passed to [Self::process].
     async fn wait(&mut self) -> Result<Self::ProcessingContext, Self::Error>;
 
     /// Do some processing. This function ought to be called after the [Self::wait] function returned.
     /// This function is not cancel-safe.
     ///
-    /// If a message was received, it is returned.
+    /// If a message was received, it is returned. Its timestamp must be the time at which it was received,
+    /// not the time at which it was processed. If the radio only reports the time of the interrupt, take the time
+    /// in [Self::wait] and pass it along in the context.
     async fn process(
         &mut self,
         ctx: Self::ProcessingContext,
@@ -86,6 +265,20 @@ pub trait Phy {
     fn get_phy_pib(&mut self) -> &PhyPib;
 }
 
+/// The addresses of the device, used to filter received frames in hardware
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct FrameFilter {
+    /// macPanId
+    pub pan_id: PanId,
+    /// macShortAddress
+    pub short_address: ShortAddress,
+    /// macExtendedAddress
+    pub extended_address: ExtendedAddress,
+    /// Whether we're the PAN coordinator, which must also accept frames without a destination address
+    pub pan_coordinator: bool,
+}
+
 pub enum SendResult {
     /// The message has been sent successfully at the given time.
     ///
@@ -101,7 +294,7 @@ pub enum SendContinuation {
     /// Go back to idle
     Idle,
     /// Go into receive mode to receive one message.
-    /// The radio must wait for the turnaround time to actually start the receiver.
+    /// The radio must wait for the turnaround time after the end of the sent frame to actually start the receiver.
     /// After that, the receiver stays on until a message is received or rx time exceeds the timeout value.
     ///
     /// This is useful for receiving acks.
@@ -114,7 +307,10 @@ pub enum SendContinuation {
 }
 
 pub struct ReceivedMessage {
-    /// The time at which the message was received
+    /// The time at which the message was received.
+    ///
+    /// This uses the same reference point in the frame as the times of [Phy::send].
+    /// It must be captured together with the data, before the radio can receive the next frame.
     pub timestamp: Instant,
     pub data: Vec<u8, 127>,
     /// The LQI at which the network beacon was received. Lower values represent lower LQI, as defined in 8.2.6.
@@ -122,11 +318,32 @@ pub struct ReceivedMessage {
     /// The channel on which the message was received
     pub channel: u8,
     pub page: ChannelPage,
+    /// Whether the frame passed the CRC check of the phy.
+    ///
+    /// This is None if the phy didn't check the CRC.
+    /// Frames with a failed check are still delivered so they can be inspected (e.g. by a sniffer),
+    /// but the MAC drops them.
+    pub crc_ok: Option<bool>,
+    /// Whether the ranging bit was set in the PHY header (PHR) of the frame.
+    ///
+    /// This is always false for phys that don't support ranging.
+    pub ranging: bool,
+    /// The data rate the frame was received with, encoded like the `data_rate` of [Phy::send_with_data_rate]
+    pub data_rate: u8,
 }
 
+/// The minimum number of symbols forming a SIFS period, which is the same for all PHYs (8.1.3)
+#[doc(alias = "macSIFSPeriod")]
+pub const SIFS_PERIOD: u8 = 12;
+
+/// The minimum number of symbols forming a LIFS period, which is the same for all PHYs (8.1.3)
+#[doc(alias = "macLIFSPeriod")]
+pub const LIFS_PERIOD: u8 = 40;
+
 pub enum ModulationType {
     BPSK,
     GFSK,
+    OQPSK,
 }
 
 impl ModulationType {
@@ -134,6 +351,7 @@ impl ModulationType {
         match self {
             ModulationType::BPSK => 2000,
             ModulationType::GFSK => 10000,
+            ModulationType::OQPSK => 2000,
         }
     }
 
@@ -141,6 +359,7 @@ impl ModulationType {
         match self {
             ModulationType::BPSK => 2000,
             ModulationType::GFSK => 10000,
+            ModulationType::OQPSK => 2000,
         }
     }
 }
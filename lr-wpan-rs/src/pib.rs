@@ -1,9 +1,13 @@
 use core::num::{NonZero, NonZeroU32};
 
+use rand_core::RngCore;
+
 use crate::{
     ChannelPage,
-    consts::{MAX_BEACON_PAYLOAD_LENGTH, TURNAROUND_TIME, UNIT_BACKOFF_PERIOD},
+    consts::{MAX_BEACON_PAYLOAD_LENGTH, UNIT_BACKOFF_PERIOD},
+    phy::{LIFS_PERIOD, ModulationType, SIFS_PERIOD},
     sap::Status,
+    time::{Duration, Instant},
     wire::{
         ExtendedAddress, PanId, ShortAddress,
         beacon::{BeaconOrder, SuperframeOrder},
@@ -127,9 +131,10 @@ impl PhyPib {
                 rframe_processing_time: 0,
                 cca_duration: 0,
             },
+            // All the channels of the UWB phy
             channels_supported: &[ChannelDescription {
                 page: ChannelPage::Uwb,
-                channel_numbers: &[1, 2, 3, 4, 5, 7],
+                channel_numbers: &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
             }],
             max_frame_duration,
             shr_duration: SHR_DURATION,
@@ -146,6 +151,42 @@ impl PhyPib {
         }
     }
 
+    /// The number of symbols it takes to send a frame with a PSDU of the given number of octets.
+    ///
+    /// This includes the SHR and the PHR, like the [max_frame_duration](Self::max_frame_duration).
+    pub fn frame_duration(&self, psdu_length: usize) -> u32 {
+        #[allow(unused_imports)]
+        use micromath::F32Ext;
+
+        self.shr_duration + ((psdu_length + 1) as f32 * self.symbols_per_octet).ceil() as u32
+    }
+
+    /// Is the channel of the page in the [channels_supported](Self::channels_supported)?
+    pub fn supports_channel(&self, page: ChannelPage, channel: u8) -> bool {
+        self.channels_supported.iter().any(|description| {
+            description.page == page && description.channel_numbers.contains(&channel)
+        })
+    }
+
+    /// Can a frame be sent with the data rate on the current page?
+    ///
+    /// The data rate is encoded like the DataRate of the MCPS-DATA.request. For UWB it's one more than
+    /// the encoding in the [uwb_data_rates_supported](Self::uwb_data_rates_supported) (Table 105).
+    /// For CSS, zero is the 250 kb/s of [css_low_data_rate_supported](Self::css_low_data_rate_supported)
+    /// and one is 1 Mb/s.
+    pub fn supports_data_rate(&self, data_rate: u8) -> bool {
+        match self.current_page {
+            ChannelPage::Uwb => {
+                (1..=4).contains(&data_rate)
+                    && self.uwb_data_rates_supported.contains(&(data_rate - 1))
+            }
+            ChannelPage::Css => {
+                data_rate == 1 || (data_rate == 0 && self.css_low_data_rate_supported)
+            }
+            _ => data_rate == 0,
+        }
+    }
+
     #[rustfmt::skip]
     pub fn get(&self, attribute: &str) -> Option<PibValue> {
         if !attribute.starts_with("phy") {
@@ -434,6 +475,47 @@ impl PhyPibWrite {
     }
 }
 
+/// The settings that decide how hard the MAC tries to get its frames across.
+///
+/// More retries and backoffs make transmissions more reliable on a busy or noisy channel,
+/// at the cost of latency. The policy of the [MacConfig](crate::mac::MacConfig) is applied to the pib
+/// when the MAC starts and every time the pib is reset to its defaults. The values that are pib attributes
+/// can still be changed later with a [SetRequest](crate::sap::set::SetRequest).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct TxPolicy {
+    /// The number of retransmissions of a frame that isn't acknowledged, 0–7.
+    /// Becomes macMaxFrameRetries.
+    pub max_frame_retries: u8,
+    /// The minimum backoff exponent of the CSMA-CA algorithm, 0–`max_be`.
+    /// Becomes macMinBE.
+    pub min_be: u8,
+    /// The maximum backoff exponent of the CSMA-CA algorithm, 3–8.
+    /// Becomes macMaxBE.
+    pub max_be: u8,
+    /// The number of backoffs the CSMA-CA algorithm attempts before declaring a channel access failure, 0–5.
+    /// Becomes macMaxCSMABackoffs.
+    pub max_csma_backoffs: u8,
+    /// The time waited on an ack is macAckWaitDuration multiplied by this.
+    ///
+    /// Raise it when the receiving radios take longer to turn around than the standard assumes.
+    /// A multiplier of 0 is treated as 1.
+    pub ack_wait_multiplier: u8,
+}
+
+impl Default for TxPolicy {
+    /// The defaults of the standard
+    fn default() -> Self {
+        Self {
+            max_frame_retries: 3,
+            min_be: 3,
+            max_be: 5,
+            max_csma_backoffs: 4,
+            ack_wait_multiplier: 1,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct MacPib {
     pub pib_write: MacPibWrite,
@@ -491,6 +573,9 @@ pub struct MacPib {
     /// - 0x000–0x400 for the 868 MHz and 915 MHz bands
     #[doc(alias = "macSyncSymbolOffset")]
     pub sync_symbol_offset: u16,
+    /// The multiplier of macAckWaitDuration to get the time waited on an ack.
+    /// Not part of the standard, see [TxPolicy::ack_wait_multiplier].
+    pub ack_wait_multiplier: u8,
     /// Indication of whether the MAC sublayer
     /// supports the optional timestamping feature for incoming and outgoing data
     /// frames.
@@ -499,6 +584,55 @@ pub struct MacPib {
 }
 
 impl MacPib {
+    /// Create a pib with the default values of the standard.
+    ///
+    /// The sequence numbers are initialized with a random value.
+    pub fn new_default(
+        modulation: &ModulationType,
+        extended_address: ExtendedAddress,
+        rng: &mut impl RngCore,
+    ) -> Self {
+        Self {
+            pib_write: MacPibWrite {
+                associated_pan_coord: false,
+                association_permit: false,
+                auto_request: true,
+                batt_life_ext: false,
+                beacon_payload: [0; MAX_BEACON_PAYLOAD_LENGTH],
+                beacon_payload_length: 0,
+                beacon_order: BeaconOrder::OnDemand,
+                bsn: SequenceNumber::new(rng.next_u32() as u8),
+                coord_extended_address: ExtendedAddress::BROADCAST,
+                coord_short_address: ShortAddress::BROADCAST,
+                dsn: SequenceNumber::new(rng.next_u32() as u8),
+                gts_permit: true,
+                max_be: 5,
+                max_csma_backoffs: 4,
+                max_frame_retries: 3,
+                min_be: 3,
+                pan_id: PanId::broadcast(),
+                promiscuous_mode: false,
+                response_wait_time: 32,
+                rx_on_when_idle: false,
+                security_enabled: false,
+                short_address: ShortAddress::BROADCAST,
+                transaction_persistence_time: 0x01F4,
+                tx_control_active_duration: modulation.tx_control_active_duration(),
+                tx_control_pause_duration: modulation.tx_control_pause_duration(),
+                tx_total_duration: 0,
+            },
+            extended_address,
+            beacon_tx_time: 0,
+            lifs_period: LIFS_PERIOD,
+            sifs_period: SIFS_PERIOD,
+            ranging_supported: true,
+            superframe_order: SuperframeOrder::Inactive,
+            sync_symbol_offset: 0,
+            ack_wait_multiplier: 1,
+            timestamp_supported: true,
+        }
+    }
+
     /// Dummy values to create just any old mac pib without caring about the values.
     /// TODO: Remove later when there are better PIB init apis
     pub fn dummy_new() -> Self {
@@ -533,24 +667,25 @@ impl MacPib {
             },
             extended_address: ExtendedAddress::BROADCAST,
             beacon_tx_time: 0,
-            lifs_period: 40,
-            sifs_period: 12,
+            lifs_period: LIFS_PERIOD,
+            sifs_period: SIFS_PERIOD,
             ranging_supported: false,
             superframe_order: SuperframeOrder::Inactive,
             sync_symbol_offset: 0,
+            ack_wait_multiplier: 1,
             timestamp_supported: false,
         }
     }
 
     #[rustfmt::skip]
-    pub fn get(&self, attribute: &str, phy_pib: &PhyPib) -> Option<PibValue> {
+    pub fn get(&self, attribute: &str, phy_pib: &PhyPib, turnaround_time: u32) -> Option<PibValue> {
         if !attribute.starts_with("mac") {
             return None;
         }
 
         match attribute {
             PibValue::MAC_EXTENDED_ADDRESS => Some(PibValue::MacExtendedAddress(self.extended_address)),
-            PibValue::MAC_ACK_WAIT_DURATION => Some(PibValue::MacAckWaitDuration(self.ack_wait_duration(phy_pib))),
+            PibValue::MAC_ACK_WAIT_DURATION => Some(PibValue::MacAckWaitDuration(self.ack_wait_duration(phy_pib, turnaround_time))),
             PibValue::MAC_ASSOCIATED_PAN_COORD => Some(PibValue::MacAssociatedPanCoord(self.associated_pan_coord)),
             PibValue::MAC_BEACON_PAYLOAD => Some(PibValue::MacBeaconPayload(self.beacon_payload)),
             PibValue::MAC_BEACON_PAYLOAD_LENGTH => Some(PibValue::MacBeaconPayloadLength(self.beacon_payload_length)),
@@ -600,20 +735,83 @@ impl MacPib {
     /// length of the ACK frame. The commencement time is described in
     /// 5.1.6.4.2.
     ///
+    /// The `turnaround_time` is the aTurnaroundTime of the phy in symbols,
+    /// see [Phy::turnaround_time_symbols](crate::phy::Phy::turnaround_time_symbols).
+    ///
     /// ## Range
     ///
     /// As defined in 6.4.3
     #[doc(alias = "macAckWaitDuration")]
-    pub fn ack_wait_duration(&self, phy_pib: &PhyPib) -> u32 {
+    pub fn ack_wait_duration(&self, phy_pib: &PhyPib, turnaround_time: u32) -> u32 {
         #[allow(unused)]
         use micromath::F32Ext;
 
         UNIT_BACKOFF_PERIOD
-            + TURNAROUND_TIME
+            + turnaround_time
             + phy_pib.shr_duration
             + (6.0 * phy_pib.symbols_per_octet).ceil() as u32
     }
 
+    /// The number of symbols to wait on an ack before a frame is retransmitted.
+    ///
+    /// This is the [macAckWaitDuration](Self::ack_wait_duration) times the ack wait multiplier of the [TxPolicy].
+    pub fn ack_timeout(&self, phy_pib: &PhyPib, turnaround_time: u32) -> u32 {
+        self.ack_wait_duration(phy_pib, turnaround_time) * self.ack_wait_multiplier.max(1) as u32
+    }
+
+    /// Apply the [TxPolicy] to the pib.
+    ///
+    /// A value that's outside of the range of its attribute is ignored with a warning.
+    pub fn apply_tx_policy(&mut self, tx_policy: &TxPolicy) {
+        // macMaxBE goes first since it limits macMinBE
+        let values = [
+            PibValue::MacMaxFrameRetries(tx_policy.max_frame_retries),
+            PibValue::MacMaxBe(tx_policy.max_be),
+            PibValue::MacMinBe(tx_policy.min_be),
+            PibValue::MacMaxCsmaBackoffs(tx_policy.max_csma_backoffs),
+        ];
+
+        for value in values {
+            let status = self.pib_write.set(&value);
+            if status != Status::Success {
+                warn!(
+                    "The {} of the tx policy can't be applied to the pib: {}",
+                    value.name(),
+                    status
+                );
+            }
+        }
+
+        self.ack_wait_multiplier = tx_policy.ack_wait_multiplier;
+    }
+
+    /// Whether frames can be sent with ranging enabled.
+    ///
+    /// This requires both the MAC (macRangingSupported) and the PHY (phyRanging) to support it,
+    /// so a mismatch between the two never asks the PHY to do something it can't.
+    pub fn ranging_enabled(&self, phy_pib: &PhyPib) -> bool {
+        self.ranging_supported && phy_pib.ranging
+    }
+
+    /// The maximum number of symbols to wait for a response command frame
+    /// to be available following a request command frame.
+    ///
+    /// This is macResponseWaitTime converted from multiples of aBaseSuperframeDuration to symbols.
+    /// For an association (5.1.3.1) this is the time a device waits before requesting the
+    /// association response and the time a coordinator keeps the request open for its higher layer.
+    pub fn response_wait_duration(&self) -> u32 {
+        crate::consts::BASE_SUPERFRAME_DURATION * self.response_wait_time as u32
+    }
+
+    /// Convert the timestamp the phy took of a frame to the timestamp the mac reports to the higher layer.
+    ///
+    /// The phy timestamps the onset of the first symbol past the SFD.
+    /// The mac reports the symbol boundary that's macSyncSymbolOffset symbols later.
+    /// Internally the mac keeps using the timestamps of the phy for its timing.
+    pub fn reported_timestamp(&self, phy_timestamp: Instant, symbol_period: Duration) -> Instant {
+        phy_timestamp + symbol_period * self.sync_symbol_offset as i64
+    }
+
     /// The maximum time to wait either for a
     /// frame intended as a response to a data
     /// request frame or for a broadcast frame
@@ -623,18 +821,19 @@ impl MacPib {
     /// As defined in 6.4.3
     #[doc(alias = "macMaxFrameTotalWaitTime")]
     pub fn max_frame_total_wait_time(&self, phy_pib: &PhyPib) -> u32 {
-        let m = (self.max_be - self.min_be).min(self.max_csma_backoffs);
+        let min_be = self.min_be as u32;
+        let max_be = self.max_be as u32;
+        let max_csma_backoffs = self.max_csma_backoffs as u32;
 
-        let mut max_frame_total_wait_time =
-            (self.max_csma_backoffs - m) as u32 * ((1 << self.max_be as u32) - 1);
+        // The number of backoffs before the backoff exponent reaches macMaxBE.
+        // macMinBE can be above macMaxBE when macMaxBE is lowered after macMinBE is set.
+        let m = max_be.saturating_sub(min_be).min(max_csma_backoffs);
 
-        for k in 0..m {
-            max_frame_total_wait_time += 1 << (self.min_be + k);
-        }
+        // The sum of 2^(macMinBE + k) for k in 0..m
+        let growing_backoffs = (1 << min_be) * ((1 << m) - 1);
+        let max_backoffs = (max_csma_backoffs - m) * ((1 << max_be) - 1);
 
-        max_frame_total_wait_time *= UNIT_BACKOFF_PERIOD;
-        max_frame_total_wait_time += phy_pib.max_frame_duration;
-        max_frame_total_wait_time
+        (growing_backoffs + max_backoffs) * UNIT_BACKOFF_PERIOD + phy_pib.max_frame_duration
     }
 
     /// In BLE mode, the number of backoff
@@ -677,6 +876,13 @@ impl MacPib {
             }
         }
     }
+
+    /// The [superframe duration](Self::superframe_duration) in time instead of symbols.
+    /// This is the length of the active portion of the superframe.
+    pub fn superframe_active_duration(&self, symbol_period: Duration) -> Option<Duration> {
+        self.superframe_duration()
+            .map(|superframe_duration| symbol_period * superframe_duration.get() as i64)
+    }
 }
 
 impl core::ops::DerefMut for MacPib {
@@ -989,7 +1195,10 @@ impl MacPibWrite {
             }
             PibValue::MacBattLifeExtPeriods(_) => return Status::InvalidParameter,
             PibValue::MacBeaconPayload(value) => *beacon_payload = *value,
-            PibValue::MacBeaconPayloadLength(value) => *beacon_payload_length = *value,
+            PibValue::MacBeaconPayloadLength(value) if *value <= MAX_BEACON_PAYLOAD_LENGTH => {
+                *beacon_payload_length = *value
+            }
+            PibValue::MacBeaconPayloadLength(_) => return Status::InvalidParameter,
             PibValue::MacBeaconOrder(value) => *beacon_order = *value,
             PibValue::MacBsn(value) => bsn.value = *value,
             PibValue::MacCoordExtendedAddress(value) => *coord_extended_address = *value,
@@ -1009,7 +1218,8 @@ impl MacPibWrite {
                 *max_frame_retries = *value
             }
             PibValue::MacMaxFrameRetries(_) => return Status::InvalidParameter,
-            PibValue::MacMinBe(value) if (0..=*max_be).contains(value) => *min_be = *value,
+            // That it's not above macMaxBE is checked by `check_consistency`
+            PibValue::MacMinBe(value) if (0..=8).contains(value) => *min_be = *value,
             PibValue::MacMinBe(_) => return Status::InvalidParameter,
             PibValue::MacPanId(value) => *pan_id = *value,
             PibValue::MacPromiscuousMode(value) => *promiscuous_mode = *value,
@@ -1038,6 +1248,19 @@ impl MacPibWrite {
         Status::Success
     }
 
+    /// Check the constraints between attributes.
+    ///
+    /// These can't be checked when setting a single value, because other values may be set along with it.
+    /// Returns the attribute that breaks a constraint.
+    pub fn check_consistency(&self) -> Result<(), &'static str> {
+        // macMinBE can't be larger than macMaxBE
+        if self.min_be > self.max_be {
+            return Err(PibValue::MAC_MIN_BE);
+        }
+
+        Ok(())
+    }
+
     #[doc(alias = "BI")]
     pub fn beacon_interval(&self) -> Option<NonZeroU32> {
         match self.beacon_order {
@@ -1047,6 +1270,12 @@ impl MacPibWrite {
             }
         }
     }
+
+    /// The [beacon interval](Self::beacon_interval) in time instead of symbols
+    pub fn beacon_interval_duration(&self, symbol_period: Duration) -> Option<Duration> {
+        self.beacon_interval()
+            .map(|beacon_interval| symbol_period * beacon_interval.get() as i64)
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -1345,6 +1574,10 @@ impl PibValue {
     }
 }
 
+/// A sequence number like macDSN and macBSN.
+///
+/// The value is the one the next transmitted frame gets. So after setting the attribute,
+/// the next frame carries exactly the set value and the frames after it count up from there.
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
 pub struct SequenceNumber {
@@ -1358,8 +1591,267 @@ impl SequenceNumber {
         }
     }
 
-    pub fn increment(&mut self) -> u8 {
+    /// Take the value for the frame that's about to be sent and move on to the next one,
+    /// wrapping around from 255 to 0
+    pub fn take_next(&mut self) -> u8 {
+        let value = self.value;
         self.value = self.value.wrapping_add(1);
-        self.value
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consts::TURNAROUND_TIME;
+
+    fn mac_pib(min_be: u8, max_be: u8, max_csma_backoffs: u8) -> MacPib {
+        MacPib {
+            pib_write: MacPibWrite {
+                min_be,
+                max_be,
+                max_csma_backoffs,
+                ..MacPib::dummy_new().pib_write
+            },
+            ..MacPib::dummy_new()
+        }
+    }
+
+    /// The formula of 6.4.3, worked out per backoff of the CSMA-CA algorithm
+    fn reference_max_frame_total_wait_time(
+        min_be: u8,
+        max_be: u8,
+        max_csma_backoffs: u8,
+        max_frame_duration: u32,
+    ) -> u32 {
+        let backoff_periods: u32 = (0..max_csma_backoffs as u32)
+            .map(|k| {
+                let be = min_be as u32 + k;
+                if be < max_be as u32 {
+                    1 << be
+                } else {
+                    (1 << max_be) - 1
+                }
+            })
+            .sum();
+
+        backoff_periods * UNIT_BACKOFF_PERIOD + max_frame_duration
+    }
+
+    #[test]
+    fn max_frame_total_wait_time_matches_reference() {
+        let phy_pib = PhyPib::unspecified_new();
+
+        for max_be in 3..=8 {
+            // Also the values macMinBE can have when macMaxBE is lowered after setting it
+            for min_be in 0..=8 {
+                for max_csma_backoffs in 0..=5 {
+                    assert_eq!(
+                        mac_pib(min_be, max_be, max_csma_backoffs)
+                            .max_frame_total_wait_time(&phy_pib),
+                        reference_max_frame_total_wait_time(
+                            min_be,
+                            max_be,
+                            max_csma_backoffs,
+                            phy_pib.max_frame_duration
+                        ),
+                        "macMinBE: {min_be}, macMaxBE: {max_be}, macMaxCSMABackoffs: {max_csma_backoffs}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn max_frame_total_wait_time_edge_cases() {
+        let phy_pib = PhyPib::unspecified_new();
+        let wait_time = |min_be, max_be, max_csma_backoffs| {
+            mac_pib(min_be, max_be, max_csma_backoffs).max_frame_total_wait_time(&phy_pib)
+                - phy_pib.max_frame_duration
+        };
+
+        // The defaults: 8 + 16 + 2 * 31 backoff periods
+        assert_eq!(wait_time(3, 5, 4), 86 * UNIT_BACKOFF_PERIOD);
+        // Without backoffs there's only the frame itself
+        assert_eq!(wait_time(3, 5, 0), 0);
+        // The backoff exponent starts at its maximum
+        assert_eq!(wait_time(5, 5, 4), 4 * 31 * UNIT_BACKOFF_PERIOD);
+        // macMinBE above macMaxBE behaves as if they're equal
+        assert_eq!(wait_time(6, 5, 4), wait_time(5, 5, 4));
+    }
+
+    #[test]
+    fn tx_policy_is_applied() {
+        let mut mac_pib = MacPib::dummy_new();
+        let phy_pib = PhyPib::unspecified_new();
+
+        mac_pib.apply_tx_policy(&TxPolicy {
+            max_frame_retries: 7,
+            min_be: 8,
+            max_be: 8,
+            max_csma_backoffs: 0,
+            ack_wait_multiplier: 3,
+        });
+
+        assert_eq!(mac_pib.max_frame_retries, 7);
+        assert_eq!(mac_pib.min_be, 8);
+        assert_eq!(mac_pib.max_be, 8);
+        assert_eq!(mac_pib.max_csma_backoffs, 0);
+        assert_eq!(
+            mac_pib.ack_timeout(&phy_pib, TURNAROUND_TIME),
+            3 * mac_pib.ack_wait_duration(&phy_pib, TURNAROUND_TIME)
+        );
+    }
+
+    #[test]
+    fn invalid_tx_policy_values_are_ignored() {
+        let mut mac_pib = mac_pib(3, 5, 4);
+        mac_pib.max_frame_retries = 3;
+
+        mac_pib.apply_tx_policy(&TxPolicy {
+            max_frame_retries: 8,
+            min_be: 6,
+            max_be: 9,
+            max_csma_backoffs: 6,
+            ack_wait_multiplier: 0,
+        });
+
+        assert_eq!(mac_pib.max_frame_retries, 3);
+        assert_eq!(mac_pib.min_be, 3);
+        assert_eq!(mac_pib.max_be, 5);
+        assert_eq!(mac_pib.max_csma_backoffs, 4);
+
+        // A multiplier of 0 would never wait on an ack
+        let phy_pib = PhyPib::unspecified_new();
+        assert_eq!(
+            mac_pib.ack_timeout(&phy_pib, TURNAROUND_TIME),
+            mac_pib.ack_wait_duration(&phy_pib, TURNAROUND_TIME)
+        );
+    }
+
+    /// The symbol period of the UWB phy at 850 kb/s
+    const UWB_SYMBOL_PERIOD: Duration = Duration::from_ticks(65536);
+    /// The symbol period of a SUN FSK phy at 50 kb/s
+    const SUN_SYMBOL_PERIOD: Duration = Duration::from_micros(20);
+
+    fn superframe_pib(beacon_order: BeaconOrder, superframe_order: SuperframeOrder) -> MacPib {
+        MacPib {
+            pib_write: MacPibWrite {
+                beacon_order,
+                ..MacPib::dummy_new().pib_write
+            },
+            superframe_order,
+            ..MacPib::dummy_new()
+        }
+    }
+
+    #[test]
+    fn set_sequence_number_is_used_first() {
+        let mut mac_pib = MacPib::dummy_new();
+        let phy_pib = PhyPib::unspecified_new();
+
+        mac_pib.try_set(PibValue::MAC_DSN, &PibValue::MacDsn(254));
+        assert_eq!(mac_pib.dsn.take_next(), 254);
+        assert_eq!(mac_pib.dsn.take_next(), 255);
+        assert_eq!(mac_pib.dsn.take_next(), 0);
+        assert_eq!(
+            mac_pib.get(PibValue::MAC_DSN, &phy_pib, TURNAROUND_TIME),
+            Some(PibValue::MacDsn(1))
+        );
+
+        mac_pib.try_set(PibValue::MAC_BSN, &PibValue::MacBsn(7));
+        assert_eq!(mac_pib.bsn.take_next(), 7);
+        assert_eq!(
+            mac_pib.get(PibValue::MAC_BSN, &phy_pib, TURNAROUND_TIME),
+            Some(PibValue::MacBsn(8))
+        );
+    }
+
+    #[test]
+    fn superframe_timing_in_time() {
+        let mac_pib = superframe_pib(
+            BeaconOrder::BeaconOrder(0),
+            SuperframeOrder::SuperframeOrder(0),
+        );
+        assert_eq!(
+            mac_pib.beacon_interval_duration(SUN_SYMBOL_PERIOD),
+            Some(Duration::from_micros(19_200))
+        );
+        assert_eq!(
+            mac_pib.superframe_active_duration(SUN_SYMBOL_PERIOD),
+            Some(Duration::from_micros(19_200))
+        );
+
+        let mac_pib = superframe_pib(
+            BeaconOrder::BeaconOrder(10),
+            SuperframeOrder::SuperframeOrder(5),
+        );
+        assert_eq!(
+            mac_pib.beacon_interval_duration(SUN_SYMBOL_PERIOD),
+            Some(Duration::from_micros(19_660_800))
+        );
+        assert_eq!(
+            mac_pib
+                .beacon_interval_duration(UWB_SYMBOL_PERIOD)
+                .unwrap()
+                .millis(),
+            1008
+        );
+        assert_eq!(
+            mac_pib
+                .superframe_active_duration(UWB_SYMBOL_PERIOD)
+                .unwrap()
+                .millis(),
+            31
+        );
+    }
+
+    #[test]
+    fn no_superframe_timing_without_beacons() {
+        let mac_pib = superframe_pib(BeaconOrder::OnDemand, SuperframeOrder::Inactive);
+        assert_eq!(mac_pib.beacon_interval_duration(UWB_SYMBOL_PERIOD), None);
+        assert_eq!(mac_pib.superframe_active_duration(UWB_SYMBOL_PERIOD), None);
+    }
+
+    #[test]
+    fn supported_channels() {
+        let phy_pib = PhyPib {
+            channels_supported: &[ChannelDescription {
+                page: ChannelPage::Uwb,
+                channel_numbers: &[1, 2, 3, 4, 5, 7],
+            }],
+            ..PhyPib::unspecified_new()
+        };
+
+        assert!(phy_pib.supports_channel(ChannelPage::Uwb, 5));
+        assert!(!phy_pib.supports_channel(ChannelPage::Uwb, 6));
+        assert!(!phy_pib.supports_channel(ChannelPage::Css, 5));
+    }
+
+    #[test]
+    fn supported_data_rates() {
+        let phy_pib = PhyPib::unspecified_new();
+
+        // 0.11, 0.85 and 6.81 Mb/s, but not 27.24 Mb/s
+        assert!(!phy_pib.supports_data_rate(0));
+        assert!(phy_pib.supports_data_rate(1));
+        assert!(phy_pib.supports_data_rate(3));
+        assert!(!phy_pib.supports_data_rate(4));
+        assert!(!phy_pib.supports_data_rate(5));
+    }
+
+    #[test]
+    fn supported_css_data_rates() {
+        let mut phy_pib = PhyPib::unspecified_new();
+        phy_pib.pib_write.current_page = ChannelPage::Css;
+
+        // 1 Mb/s is mandatory, 250 kb/s is optional
+        assert!(!phy_pib.supports_data_rate(0));
+        assert!(phy_pib.supports_data_rate(1));
+        assert!(!phy_pib.supports_data_rate(2));
+
+        phy_pib.css_low_data_rate_supported = true;
+        assert!(phy_pib.supports_data_rate(0));
+        assert!(phy_pib.supports_data_rate(1));
     }
 }
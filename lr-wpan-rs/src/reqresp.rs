@@ -7,7 +7,7 @@ use core::{
 use embassy_futures::join::{Join, join};
 use embassy_sync::{
     blocking_mutex::raw::CriticalSectionRawMutex,
-    channel::{Channel, SendFuture},
+    channel::{Channel, SendFuture, TrySendError},
 };
 use maitake_sync::{WaitMap, wait_map::Wait};
 
@@ -37,6 +37,21 @@ impl<Request, Response, const N: usize> ReqResp<Request, Response, N> {
         }
     }
 
+    /// Send a request without waiting for its response.
+    /// If the channel is full, the request is given back.
+    pub fn try_send_request(&self, request: Request) -> Result<(), Request> {
+        let current_id = self.next_id.fetch_add(1, Ordering::Relaxed);
+
+        self.requests
+            .try_send((current_id, request))
+            .map_err(|TrySendError::Full((_, request))| request)
+    }
+
+    /// Remove the oldest request that hasn't been received yet
+    pub fn remove_oldest_request(&self) -> Option<Request> {
+        self.requests.try_receive().ok().map(|(_, request)| request)
+    }
+
     pub async fn wait_for_request(&self) -> (u32, Request) {
         self.requests.receive().await
     }
@@ -0,0 +1,52 @@
+use super::{ConfirmValue, DynamicRequest, Request, RequestValue, Status};
+use crate::time::Instant;
+
+/// Request to acknowledge a received frame.
+///
+/// This is not a primitive of the standard. Normally the MAC acknowledges frames by itself,
+/// but when `auto_ack` is turned off in the [MacConfig](crate::mac::MacConfig),
+/// the higher layer is responsible for sending the acknowledgments with this request.
+///
+/// The ack is sent macSIFSPeriod after the receive time, or as soon as possible if that time has already passed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AckRequest {
+    /// The sequence number of the frame that is acknowledged
+    pub seq: u8,
+    /// The time the frame that is acknowledged was received
+    pub receive_time: Instant,
+    /// The value of the frame pending bit in the ack
+    pub frame_pending: bool,
+}
+
+impl From<RequestValue> for AckRequest {
+    fn from(value: RequestValue) -> Self {
+        match value {
+            RequestValue::Ack(val) => val,
+            _ => panic!("Bad cast"),
+        }
+    }
+}
+
+impl DynamicRequest for AckRequest {
+    type Confirm = AckConfirm;
+    type AllocationElement = core::convert::Infallible;
+}
+
+impl Request for AckRequest {}
+
+/// The result of an [AckRequest].
+///
+/// If the phy fails to send the ack, the status is PHY_ERROR.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AckConfirm {
+    pub status: Status,
+}
+
+impl From<ConfirmValue> for AckConfirm {
+    fn from(value: ConfirmValue) -> Self {
+        match value {
+            ConfirmValue::Ack(val) => val,
+            _ => panic!("Bad cast"),
+        }
+    }
+}
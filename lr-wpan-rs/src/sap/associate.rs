@@ -63,6 +63,12 @@ pub struct AssociateIndication {
     /// The operational capabilities of the device requesting association.
     pub capability_information: CapabilityInformation,
     pub security_info: SecurityInfo,
+    /// The short address the [MacConfig::short_address_assignment](crate::mac::MacConfig::short_address_assignment)
+    /// picked for the device. Respond with it to give it to the device.
+    ///
+    /// This is not part of the standard. It's [None] when there's no assignment, when the device didn't
+    /// ask for a short address or when the assignment didn't give an address that can be used.
+    pub assigned_short_address: Option<ShortAddress>,
 }
 
 impl From<IndicationValue> for AssociateIndication {
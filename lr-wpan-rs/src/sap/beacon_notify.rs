@@ -1,7 +1,10 @@
 use heapless::Vec;
 
 use super::{Indication, IndicationValue, PanDescriptor};
-use crate::{consts::MAX_BEACON_PAYLOAD_LENGTH, wire::beacon::PendingAddress};
+use crate::{
+    consts::MAX_BEACON_PAYLOAD_LENGTH,
+    wire::beacon::{GuaranteedTimeSlotDescriptor, PendingAddress},
+};
 
 /// The MLME-BEACON-NOTIFY.indication primitive is used to send parameters contained within a beacon
 /// frame received by the MAC sublayer to the next higher layer when either `macAutoRequest` is set to FALSE
@@ -13,6 +16,9 @@ pub struct BeaconNotifyIndication {
     pub pan_descriptor: PanDescriptor,
     /// The list of addresses of the devices for which the beacon source has data.
     pub address_list: PendingAddress,
+    /// The GTSs the beacon source has allocated, with their directions.
+    /// A device can use this to plan its own GTS request.
+    pub gts_slots: Vec<GuaranteedTimeSlotDescriptor, 7>,
     /// The set of octets comprising the beacon
     /// payload to be transferred from the MAC
     /// sublayer entity to the next higher layer.
@@ -50,6 +50,11 @@ pub struct DataRequest {
     /// The individual device address of the entity to which the MSDU is being transferred.
     pub dst_addr: Option<DeviceAddress>,
     /// The set of octets forming the MSDU to be transmitted by the MAC sublayer entity.
+    ///
+    /// The MSDU is never truncated or fragmented. How much of the [MAX_MAC_PAYLOAD_SIZE](crate::consts::MAX_MAC_PAYLOAD_SIZE)
+    /// can be used depends on the addressing and security of the frame. If the resulting frame doesn't fit in
+    /// [MAX_PHY_PACKET_SIZE](crate::consts::MAX_PHY_PACKET_SIZE), the request is confirmed with FRAME_TOO_LONG.
+    /// Up to [MAX_MAC_SAFE_PAYLOAD_SIZE](crate::consts::MAX_MAC_SAFE_PAYLOAD_SIZE) bytes always fit in an unsecured frame.
     pub msdu: Vec<u8, { crate::consts::MAX_MAC_PAYLOAD_SIZE }>,
     /// The handle associated with the MSDU to be transmitted by the MAC sublayer entity.
     pub msdu_handle: u8,
@@ -74,7 +79,7 @@ pub struct DataRequest {
     /// frame. A zero value is used for non-UWB PHYs.
     pub uwb_preamble_symbol_repetitions: UwbPreambleSymbolRepetitions,
     /// Indicates the data rate. For CSS PHYs, a value of
-    /// one indicates 250 kb/s while a value of two
+    /// zero indicates 250 kb/s while a value of one
     /// indicates 1 Mb/s. For UWB PHYs, values 1–4 are
     /// valid and are defined in 14.2.6.1. For all other
     /// PHYs, the parameter is set to zero.
@@ -223,7 +228,8 @@ pub struct DataIndication {
     /// shall be a minimum of 20 bits, with the lowest 4 bits
     /// being the least significant.
     pub timestamp: Instant,
-    /// The security info purportedly used by the received data frame
+    /// The security the received data frame was secured with, taken from its auxiliary security header.
+    /// The higher layer can use this to reject data that isn't secured well enough.
     pub security_info: SecurityInfo,
     /// The pulse repetition value of the received PPDU.
     /// This parameter shall be ignored by non-UWB PHYs.
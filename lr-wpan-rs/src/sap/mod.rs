@@ -1,5 +1,6 @@
 use core::fmt::Debug;
 
+use ack::{AckConfirm, AckRequest};
 use associate::{AssociateConfirm, AssociateIndication, AssociateRequest, AssociateResponse};
 use beacon_notify::BeaconNotifyIndication;
 use calibrate::{CalibrateConfirm, CalibrateRequest};
@@ -10,14 +11,18 @@ use disassociate::{DisassociateConfirm, DisassociateIndication, DisassociateRequ
 use dps::{DpsConfirm, DpsIndication, DpsRequest};
 use get::{GetConfirm, GetRequest};
 use gts::{GtsConfirm, GtsIndication, GtsRequest};
+use operational_state::{OperationalStateConfirm, OperationalStateRequest};
 use orphan::{OrphanIndication, OrphanResponse};
 use poll::{PollConfirm, PollRequest};
 use purge::{PurgeConfirm, PurgeRequest};
+use ranging::{RangingConfirm, RangingRequest};
 use reset::{ResetConfirm, ResetRequest};
 use rx_enable::{RxEnableConfirm, RxEnableRequest};
 use scan::{ScanConfirm, ScanRequest};
 use set::{SetConfirm, SetRequest};
+use set_many::{SetManyConfirm, SetManyRequest};
 use sounding::{SoundingConfirm, SoundingRequest};
+use spectrum_survey::{SpectrumSurveyConfirm, SpectrumSurveyRequest};
 use start::{StartConfirm, StartRequest};
 use sync::{SyncLossIndication, SyncRequest};
 
@@ -35,6 +40,7 @@ use crate::{
     },
 };
 
+pub mod ack;
 pub mod associate;
 pub mod beacon_notify;
 pub mod calibrate;
@@ -44,14 +50,18 @@ pub mod disassociate;
 pub mod dps;
 pub mod get;
 pub mod gts;
+pub mod operational_state;
 pub mod orphan;
 pub mod poll;
 pub mod purge;
+pub mod ranging;
 pub mod reset;
 pub mod rx_enable;
 pub mod scan;
 pub mod set;
+pub mod set_many;
 pub mod sounding;
+pub mod spectrum_survey;
 pub mod start;
 pub mod sync;
 
@@ -96,6 +106,8 @@ pub enum Status {
     PhyError,
     ReadOnly,
     AlreadyAssociated,
+    /// Not a status of the standard: the request is not implemented (yet) by this mac
+    NotImplemented,
 }
 
 impl Status {
@@ -232,6 +244,41 @@ pub(crate) enum RequestValue {
     Calibrate(CalibrateRequest),
     Data(DataRequest),
     Purge(PurgeRequest),
+    SpectrumSurvey(SpectrumSurveyRequest),
+    Ack(AckRequest),
+    Ranging(RangingRequest),
+    OperationalState(OperationalStateRequest),
+    SetMany(SetManyRequest),
+}
+
+impl From<SetManyRequest> for RequestValue {
+    fn from(v: SetManyRequest) -> Self {
+        Self::SetMany(v)
+    }
+}
+
+impl From<OperationalStateRequest> for RequestValue {
+    fn from(v: OperationalStateRequest) -> Self {
+        Self::OperationalState(v)
+    }
+}
+
+impl From<RangingRequest> for RequestValue {
+    fn from(v: RangingRequest) -> Self {
+        Self::Ranging(v)
+    }
+}
+
+impl From<AckRequest> for RequestValue {
+    fn from(v: AckRequest) -> Self {
+        Self::Ack(v)
+    }
+}
+
+impl From<SpectrumSurveyRequest> for RequestValue {
+    fn from(v: SpectrumSurveyRequest) -> Self {
+        Self::SpectrumSurvey(v)
+    }
 }
 
 impl From<PurgeRequest> for RequestValue {
@@ -346,9 +393,38 @@ pub(crate) enum ConfirmValue {
     Calibrate(CalibrateConfirm),
     Data(DataConfirm),
     Purge(PurgeConfirm),
+    SpectrumSurvey(SpectrumSurveyConfirm),
+    Ack(AckConfirm),
+    Ranging(RangingConfirm),
+    OperationalState(OperationalStateConfirm),
+    SetMany(SetManyConfirm),
     None,
 }
 
+impl From<SetManyConfirm> for ConfirmValue {
+    fn from(v: SetManyConfirm) -> Self {
+        Self::SetMany(v)
+    }
+}
+
+impl From<OperationalStateConfirm> for ConfirmValue {
+    fn from(v: OperationalStateConfirm) -> Self {
+        Self::OperationalState(v)
+    }
+}
+
+impl From<RangingConfirm> for ConfirmValue {
+    fn from(v: RangingConfirm) -> Self {
+        Self::Ranging(v)
+    }
+}
+
+impl From<AckConfirm> for ConfirmValue {
+    fn from(v: AckConfirm) -> Self {
+        Self::Ack(v)
+    }
+}
+
 impl From<ConfirmValue> for () {
     fn from(v: ConfirmValue) -> Self {
         match v {
@@ -364,6 +440,12 @@ impl From<()> for ConfirmValue {
     }
 }
 
+impl From<SpectrumSurveyConfirm> for ConfirmValue {
+    fn from(v: SpectrumSurveyConfirm) -> Self {
+        Self::SpectrumSurvey(v)
+    }
+}
+
 impl From<PurgeConfirm> for ConfirmValue {
     fn from(v: PurgeConfirm) -> Self {
         Self::Purge(v)
@@ -0,0 +1,59 @@
+use super::{ConfirmValue, DynamicRequest, Request, RequestValue, Status};
+
+/// Request to get the [MacOperationalState] the MAC is in right now.
+///
+/// This is not a primitive of the standard. It can be used by the higher layer to avoid making requests
+/// that would be rejected, like a scan while another scan is in progress.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OperationalStateRequest;
+
+impl From<RequestValue> for OperationalStateRequest {
+    fn from(value: RequestValue) -> Self {
+        match value {
+            RequestValue::OperationalState(val) => val,
+            _ => panic!("Bad cast"),
+        }
+    }
+}
+
+impl DynamicRequest for OperationalStateRequest {
+    type Confirm = OperationalStateConfirm;
+    type AllocationElement = core::convert::Infallible;
+}
+
+impl Request for OperationalStateRequest {}
+
+/// The result of an [OperationalStateRequest]. The status is always SUCCESS.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OperationalStateConfirm {
+    pub status: Status,
+    pub state: MacOperationalState,
+}
+
+impl From<ConfirmValue> for OperationalStateConfirm {
+    fn from(value: ConfirmValue) -> Self {
+        match value {
+            ConfirmValue::OperationalState(val) => val,
+            _ => panic!("Bad cast"),
+        }
+    }
+}
+
+/// What the MAC is doing at the moment.
+///
+/// The MAC can be in more than one of these at the same time, e.g. associated and tracking the beacon of its
+/// coordinator. The first one that applies is reported, in the order of the variants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum MacOperationalState {
+    /// A scan is in progress, which keeps the radio busy
+    Scanning,
+    /// We've started a PAN as its PAN coordinator
+    PanCoordinator,
+    /// We're synchronized with the beacons of our coordinator and are tracking them
+    Tracking,
+    /// We've got a short address, which we get when associating to a coordinator
+    Associated,
+    /// None of the above
+    Idle,
+}
@@ -0,0 +1,73 @@
+use super::{ConfirmValue, DynamicRequest, Request, RequestValue, Status};
+use crate::{
+    time::{Duration, TICKS_PER_SECOND},
+    wire::Address,
+};
+
+/// The speed of light in a vacuum, in meters per second
+const SPEED_OF_LIGHT: f32 = 299_792_458.0;
+
+/// Request to measure the distance to another device with single-sided two-way ranging (SS-TWR).
+///
+/// This is not a primitive of the standard. We send a poll with the ranging bit set, to which the mac of the
+/// other device answers by itself after a fixed delay. The answer carries the time the poll was received and the
+/// time the answer was sent, from which the time of flight follows. Both devices need a phy that supports ranging.
+///
+/// The clocks of the devices don't need to be synchronized, but their relative drift adds an error of about
+/// half the drift times the reply delay.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangingRequest {
+    /// The device to range to
+    pub destination: Address,
+}
+
+impl From<RequestValue> for RangingRequest {
+    fn from(value: RequestValue) -> Self {
+        match value {
+            RequestValue::Ranging(val) => val,
+            _ => panic!("Bad cast"),
+        }
+    }
+}
+
+impl DynamicRequest for RangingRequest {
+    type Confirm = RangingConfirm;
+    type AllocationElement = core::convert::Infallible;
+}
+
+impl Request for RangingRequest {}
+
+/// The result of a [RangingRequest].
+///
+/// If we or our phy don't support ranging, the status is RANGING_NOT_SUPPORTED.
+/// If the other device doesn't answer in time, the status is NO_ACK.
+/// The time of flight and distance are only valid when the status is SUCCESS.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangingConfirm {
+    pub status: Status,
+    /// The measured time it takes a frame to get from one device to the other
+    pub time_of_flight: Duration,
+    /// The distance that goes with the time of flight
+    pub distance: Meters,
+}
+
+impl From<ConfirmValue> for RangingConfirm {
+    fn from(value: ConfirmValue) -> Self {
+        match value {
+            ConfirmValue::Ranging(val) => val,
+            _ => panic!("Bad cast"),
+        }
+    }
+}
+
+/// A distance in meters
+#[derive(Debug, Default, Clone, Copy, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct Meters(pub f32);
+
+impl Meters {
+    /// The distance light travels in the time of flight
+    pub fn from_time_of_flight(time_of_flight: Duration) -> Self {
+        Self(time_of_flight.ticks() as f32 / TICKS_PER_SECOND as f32 * SPEED_OF_LIGHT)
+    }
+}
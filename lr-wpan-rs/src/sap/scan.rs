@@ -26,7 +26,16 @@ pub struct ScanRequest {
     /// 0-14
     pub scan_duration: u8,
     pub channel_page: ChannelPage,
+    /// The channel page of each of the `scan_channels`, to scan channels of more than one page at once.
+    ///
+    /// This is not a parameter of the standard. When empty, all channels are on the `channel_page`.
+    /// Otherwise it must be as long as `scan_channels`, or the scan is refused with
+    /// [InvalidParameter](Status::InvalidParameter).
+    pub scan_channel_pages: Vec<ChannelPage, 16>,
     pub security_info: SecurityInfo,
+    /// Only used in an active scan. When true, the beacon requests carry our own address as the source,
+    /// which some profiles require. The short address is used if we have one, else the extended address.
+    pub include_source_address: bool,
 }
 
 impl From<RequestValue> for ScanRequest {
@@ -85,11 +94,17 @@ pub struct ScanConfirm {
     pub scan_type: ScanType,
     /// The channel page on which the scan
     /// was performed, as defined in 8.1.2.
+    ///
+    /// When the request had `scan_channel_pages`, this is still the `channel_page` of the request.
+    /// The page of every PAN descriptor is then in its own `channel_page`.
     pub channel_page: ChannelPage,
     /// A list of the channels given in the
     /// request which were not scanned. This
     /// parameter is not valid for ED scans.
     pub unscanned_channels: Vec<u8, 16>,
+    /// The channel page of each of the `unscanned_channels`.
+    /// Only filled in when the request had `scan_channel_pages`.
+    pub unscanned_channel_pages: Vec<ChannelPage, 16>,
     /// The number of elements returned in
     /// the appropriate result lists. This value
     /// is zero for the result of an orphan scan.
@@ -98,6 +113,9 @@ pub struct ScanConfirm {
     /// for each channel searched during an
     /// ED scan. This parameter is null for
     /// active, passive, and orphan scans.
+    ///
+    /// Unlike the PAN descriptor list, this is not stored in an allocation.
+    /// There's at most one byte per channel in the request, so it's small enough to keep inline.
     pub energy_detect_list: Vec<u8, 16>,
     pub(crate) pan_descriptor_list_allocation: super::Allocation<Option<PanDescriptor>>,
     /// Categorization of energy detected in
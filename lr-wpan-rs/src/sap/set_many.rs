@@ -0,0 +1,57 @@
+use heapless::Vec;
+
+use super::{ConfirmValue, DynamicRequest, Request, RequestValue, Status};
+use crate::pib::PibValue;
+
+/// The maximum amount of values that can be written with one [SetManyRequest]
+pub const MAX_SET_MANY_VALUES: usize = 8;
+
+/// Request to write several PIB attributes at once.
+///
+/// This is not a primitive of the standard. It works like an MLME-SET.request for every value, but the values
+/// are either all written or none of them are. Attributes whose valid range depends on other attributes
+/// (like macMinBE, which can't be larger than macMaxBE) are checked against the other values of the batch,
+/// so the order of the values doesn't matter.
+///
+/// If the same attribute is in the batch more than once, the last value wins.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SetManyRequest {
+    /// The values to write. The attribute of each value follows from its variant.
+    pub pib_attribute_values: Vec<PibValue, MAX_SET_MANY_VALUES>,
+}
+
+impl From<RequestValue> for SetManyRequest {
+    fn from(value: RequestValue) -> Self {
+        match value {
+            RequestValue::SetMany(val) => val,
+            _ => panic!("Bad cast"),
+        }
+    }
+}
+
+impl DynamicRequest for SetManyRequest {
+    type Confirm = SetManyConfirm;
+    type AllocationElement = core::convert::Infallible;
+}
+
+impl Request for SetManyRequest {}
+
+/// The result of a [SetManyRequest].
+///
+/// On SUCCESS, all values have been written. Otherwise nothing has been written and the status is the one an
+/// MLME-SET.request would have given for the first value that was rejected.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SetManyConfirm {
+    pub status: Status,
+    /// The name of the PIB attribute that was rejected, if any
+    pub pib_attribute: Option<&'static str>,
+}
+
+impl From<ConfirmValue> for SetManyConfirm {
+    fn from(value: ConfirmValue) -> Self {
+        match value {
+            ConfirmValue::SetMany(val) => val,
+            _ => panic!("Bad cast"),
+        }
+    }
+}
@@ -3,6 +3,26 @@ use super::{ConfirmValue, DynamicRequest, RequestValue, Status};
 /// The MLME-SOUNDING.request primitive is used by the next higher layer to request that the PHY respond
 /// with channel sounding information. The MLME-SOUNDING.request primitive shall be supported by all
 /// RDEVs; however, the underlying sounding capability is optional in all cases.
+///
+/// The sounding list is written into a buffer provided by the caller, so no heap is needed.
+/// Use [MacCommander::request_with_allocation](crate::mac::MacCommander::request_with_allocation)
+/// to attach it. The buffer is borrowed for as long as the returned confirm lives:
+///
+/// ```rust,ignore
+/// let mut buffer = [SoundingData::default(); 16];
+/// let confirm = commander
+///     .request_with_allocation(
+///         SoundingRequest {
+///             sounding_list_allocation: Allocation::new(),
+///         },
+///         &mut buffer,
+///     )
+///     .await;
+///
+/// for sounding_data in confirm.sounding_list() {
+///     // ...
+/// }
+/// ```
 #[derive(Debug, PartialEq, Eq)]
 pub struct SoundingRequest {
     pub sounding_list_allocation: super::Allocation<SoundingData>,
@@ -22,8 +42,7 @@ impl DynamicRequest for SoundingRequest {
     type AllocationElement = SoundingData;
 
     unsafe fn attach_allocation(&mut self, allocation: super::Allocation<Self::AllocationElement>) {
-        let _ = allocation;
-        core::unimplemented!()
+        self.sounding_list_allocation = allocation
     }
 }
 
@@ -41,10 +60,24 @@ impl DynamicRequest for SoundingRequest {
 /// UNSUPPORTED_ATTRIBUTE.
 #[derive(Debug, PartialEq, Eq)]
 pub struct SoundingConfirm {
-    pub sounding_list: super::Allocation<SoundingData>,
+    pub(crate) sounding_list_allocation: super::Allocation<SoundingData>,
+    /// The number of entries in the sounding list
+    pub sounding_list_size: usize,
     pub status: Status,
 }
 
+impl SoundingConfirm {
+    /// The list of sounding measurements, written into the allocation of the request
+    pub fn sounding_list(&self) -> &[SoundingData] {
+        if self.sounding_list_size == 0 {
+            // The allocation may not be attached when there's nothing to report
+            return &[];
+        }
+
+        &self.sounding_list_allocation.as_slice()[..self.sounding_list_size]
+    }
+}
+
 impl From<ConfirmValue> for SoundingConfirm {
     fn from(value: ConfirmValue) -> Self {
         match value {
@@ -54,9 +87,9 @@ impl From<ConfirmValue> for SoundingConfirm {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
 pub struct SoundingData {
     /// 16 ps per tick
-    time: i16,
-    amplitude: i16,
+    pub time: i16,
+    pub amplitude: i16,
 }
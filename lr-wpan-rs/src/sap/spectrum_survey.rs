@@ -0,0 +1,53 @@
+use heapless::Vec;
+
+use super::{ConfirmValue, DynamicRequest, Request, RequestValue, Status};
+use crate::ChannelPage;
+
+/// The maximum amount of channels that can be part of a spectrum survey
+pub const MAX_SURVEY_CHANNELS: usize = 32;
+
+/// Request to measure the energy on all channels the phy supports.
+///
+/// This is not a primitive of the standard. Unlike an ED scan with the MLME-SCAN.request, it can be done at any time
+/// without affecting the PAN and the MAC PIB. This can e.g. be used by a coordinator to pick the quietest channel
+/// before starting a PAN.
+///
+/// The phy is returned to the channel and page it was on before the survey.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpectrumSurveyRequest;
+
+impl From<RequestValue> for SpectrumSurveyRequest {
+    fn from(value: RequestValue) -> Self {
+        match value {
+            RequestValue::SpectrumSurvey(val) => val,
+            _ => panic!("Bad cast"),
+        }
+    }
+}
+
+impl DynamicRequest for SpectrumSurveyRequest {
+    type Confirm = SpectrumSurveyConfirm;
+    type AllocationElement = core::convert::Infallible;
+}
+
+impl Request for SpectrumSurveyRequest {}
+
+/// The result of a [SpectrumSurveyRequest].
+///
+/// If a scan is in progress, the status is SCAN_IN_PROGRESS and no measurements are done.
+/// If the phy fails, the status is PHY_ERROR and the measurements done up to that point are returned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpectrumSurveyConfirm {
+    pub status: Status,
+    /// The measured energy per channel as `(page, channel, energy)`
+    pub energy_map: Vec<(ChannelPage, u8, u8), MAX_SURVEY_CHANNELS>,
+}
+
+impl From<ConfirmValue> for SpectrumSurveyConfirm {
+    fn from(value: ConfirmValue) -> Self {
+        match value {
+            ConfirmValue::SpectrumSurvey(val) => val,
+            _ => panic!("Bad cast"),
+        }
+    }
+}
@@ -198,6 +198,12 @@ impl Duration {
         }
     }
 
+    /// The amount of *full* microseconds in this duration.
+    /// Always rounds down.
+    pub const fn micros(&self) -> i64 {
+        (self.ticks() as i128 * 1000).div_euclid(TICKS_PER_MILLI as i128) as i64
+    }
+
     #[must_use]
     pub const fn checked_add(self, duration: Duration) -> Option<Self> {
         match self.ticks.checked_add(duration.ticks) {
@@ -399,4 +405,12 @@ mod tests {
         assert_eq!(Duration::from_ticks(10) / 5, Duration::from_ticks(2));
         assert_eq!(Duration::from_ticks(10) / -5, Duration::from_ticks(-2));
     }
+
+    #[test]
+    fn micros() {
+        assert_eq!(Duration::from_micros(1500).micros(), 1500);
+        assert_eq!(Duration::from_nanos(1999).micros(), 1);
+        assert_eq!(Duration::from_nanos(-1).micros(), -1);
+        assert_eq!(Duration::from_micros(-3).micros(), -3);
+    }
 }
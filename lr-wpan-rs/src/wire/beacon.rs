@@ -291,24 +291,23 @@ impl GuaranteedTimeSlotInformation {
 impl TryWrite for GuaranteedTimeSlotInformation {
     fn try_write(self, bytes: &mut [u8], _ctx: ()) -> byte::Result<usize> {
         let offset = &mut 0;
-        assert!(self.slots.capacity() <= 7);
+        // The count has to fit in its 3 bits, which also keeps the direction mask within its 7 bits
+        assert!(self.slots.len() <= COUNT_MASK as usize);
         let permit = if self.permit { PERMIT } else { 0 };
 
         let header = ((self.slots.len() as u8) & COUNT_MASK) | permit;
         bytes.write(offset, header)?;
 
         if !self.slots.is_empty() {
-            let direction_mask = {
-                let mut dir = 0x01;
-                let mut direction_mask = 0u8;
-                for slot in &self.slots {
-                    if slot.direction_transmit() {
-                        direction_mask |= dir;
-                    }
-                    dir <<= 1;
-                }
-                direction_mask
-            };
+            // Bit n is the direction of slot n, like the reader expects
+            let direction_mask = self
+                .slots
+                .iter()
+                .enumerate()
+                .filter(|(_, slot)| slot.direction_transmit())
+                .fold(0u8, |direction_mask, (index, _)| {
+                    direction_mask | (1 << index)
+                });
 
             bytes.write(offset, direction_mask)?;
 
@@ -523,6 +522,50 @@ mod tests {
         assert_eq!(gts.slots().len(), 0);
     }
 
+    #[test]
+    fn gts_information_with_all_slots_round_trips() {
+        let directions = [
+            Direction::Transmit,
+            Direction::Receive,
+            Direction::Receive,
+            Direction::Transmit,
+            Direction::Transmit,
+            Direction::Receive,
+            Direction::Transmit,
+        ];
+
+        let slots = directions
+            .iter()
+            .enumerate()
+            .map(|(index, &direction)| GuaranteedTimeSlotDescriptor {
+                short_address: ShortAddress(0x1000 + index as u16),
+                starting_slot: 9 + index as u8,
+                length: 1,
+                direction,
+            })
+            .collect::<Vec<_, 7>>();
+
+        let gts = GuaranteedTimeSlotInformation {
+            permit: true,
+            slots,
+        };
+
+        let mut buffer = [0u8; 32];
+        let mut len = 0usize;
+        buffer.write(&mut len, gts.clone()).unwrap();
+        assert_eq!(len, 2 + 3 * 7);
+        assert_eq!(buffer[0], 0x87);
+        // The first slot is in the lowest bit
+        assert_eq!(buffer[1], 0b0101_1001);
+
+        // The reader wants a byte after the slots, like the pending address of a beacon
+        let mut read_len = 0usize;
+        let read_gts: GuaranteedTimeSlotInformation =
+            buffer[..len + 1].read(&mut read_len).unwrap();
+        assert_eq!(read_len, len);
+        assert_eq!(read_gts, gts);
+    }
+
     #[test]
     fn decode_pending_address() {
         let data = [0x00];
@@ -137,7 +137,8 @@ pub struct CoordinatorRealignmentData {
     pub channel: u8,
     /// Device address or broadcast
     pub device_address: ShortAddress,
-    /// Channel page or channel number the coordinator will use
+    /// Channel page the coordinator will use.
+    /// Only frames of the 2006 version and later can carry it, see [Frame::minimum_version](super::Frame::minimum_version).
     pub channel_page: Option<u8>,
 }
 
@@ -247,6 +248,27 @@ pub enum Command {
     GuaranteedTimeSlotRequest(GuaranteedTimeSlotCharacteristics),
 }
 
+impl Command {
+    /// Must the frame carrying this command have the AR field set (5.3)?
+    ///
+    /// Commands sent to a single device are acknowledged. The orphan notification and the beacon request are
+    /// broadcast and never are. The coordinator realignment is only acknowledged when it's directed to an orphaned device.
+    pub fn requires_ack(&self) -> bool {
+        match self {
+            Command::AssociationRequest(_)
+            | Command::AssociationResponse(_, _)
+            | Command::DisassociationNotification(_)
+            | Command::DataRequest
+            | Command::PanIdConflictNotification
+            | Command::GuaranteedTimeSlotRequest(_) => true,
+            Command::OrphanNotification | Command::BeaconRequest => false,
+            Command::CoordinatorRealignment(realignment) => {
+                realignment.device_address != ShortAddress::BROADCAST
+            }
+        }
+    }
+}
+
 impl TryWrite for Command {
     fn try_write(self, bytes: &mut [u8], _ctx: ()) -> byte::Result<usize> {
         let offset = &mut 0;
@@ -633,6 +655,14 @@ mod tests {
         );
     }
 
+    #[test]
+    fn decode_truncated_coordinator_realignment() {
+        // The device address is cut off
+        let data = [0x08, 0x23, 0x11, 0x01, 0x00, 0x0f, 0x34];
+        let mut len = 0usize;
+        assert!(data.read::<Command>(&mut len).is_err());
+    }
+
     #[test]
     fn encode_coordinator_realignment() {
         let mut data = [0u8; 32];
@@ -810,4 +840,47 @@ mod tests {
         assert_eq!(len, 1);
         assert_eq!(data[..len], [0x07]);
     }
+
+    #[test]
+    fn ack_request_per_command() {
+        let realignment = CoordinatorRealignmentData {
+            pan_id: PanId(1),
+            coordinator_address: ShortAddress(0),
+            channel: 5,
+            device_address: ShortAddress::BROADCAST,
+            channel_page: None,
+        };
+
+        assert!(Command::AssociationRequest(CapabilityInformation::from(0)).requires_ack());
+        assert!(
+            Command::AssociationResponse(ShortAddress(1), AssociationStatus::Successful)
+                .requires_ack()
+        );
+        assert!(
+            Command::DisassociationNotification(DisassociationReason::DeviceLeave).requires_ack()
+        );
+        assert!(Command::DataRequest.requires_ack());
+        assert!(Command::PanIdConflictNotification.requires_ack());
+        assert!(!Command::OrphanNotification.requires_ack());
+        assert!(!Command::BeaconRequest.requires_ack());
+        assert!(
+            Command::GuaranteedTimeSlotRequest(GuaranteedTimeSlotCharacteristics {
+                count: 1,
+                receive_only: false,
+                allocation: true,
+            })
+            .requires_ack()
+        );
+
+        // Broadcast to the PAN
+        assert!(!Command::CoordinatorRealignment(realignment).requires_ack());
+        // Directed to an orphaned device
+        assert!(
+            Command::CoordinatorRealignment(CoordinatorRealignmentData {
+                device_address: ShortAddress(3),
+                ..realignment
+            })
+            .requires_ack()
+        );
+    }
 }
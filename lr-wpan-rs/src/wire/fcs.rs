@@ -0,0 +1,70 @@
+//! Frame Check Sequence
+//!
+//! The FCS is the 16-bit ITU-T CRC at the end of every frame (5.2.1.9), which is also known as
+//! CRC-16/KERMIT: polynomial 0x1021, starting at 0, with the bits in transmission order, so least
+//! significant bit first. It's written to the frame in little endian.
+//!
+//! This is calculated bit by bit instead of with a lookup table, to save code size.
+
+/// The length of the FCS in octets
+pub const FCS_LENGTH: usize = 2;
+
+/// Calculate the FCS of the bytes of a frame
+pub fn compute_fcs(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0, |crc, byte| {
+        (0..8).fold(crc ^ *byte as u16, |crc, _| match crc & 1 {
+            0 => crc >> 1,
+            _ => (crc >> 1) ^ 0x8408,
+        })
+    })
+}
+
+/// Check the FCS at the end of the bytes of a frame.
+///
+/// Returns false if there are not enough bytes for an FCS.
+pub fn verify_fcs(bytes: &[u8]) -> bool {
+    let Some(fcs_offset) = bytes.len().checked_sub(FCS_LENGTH) else {
+        return false;
+    };
+
+    let (data, fcs) = bytes.split_at(fcs_offset);
+    compute_fcs(data).to_le_bytes() == fcs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_value() {
+        // The check value of CRC-16/KERMIT
+        assert_eq!(compute_fcs(b"123456789"), 0x2189);
+        assert_eq!(compute_fcs(&[]), 0);
+    }
+
+    #[test]
+    fn standard_example() {
+        // The example of 5.2.1.9, where the bits are given in transmission order:
+        // the acknowledgment frame 0100 0000 0000 0000 0101 0110 has the FCS 0010 0111 1001 1110
+        let ack = [0x02, 0x00, 0x6a];
+        let fcs = compute_fcs(&ack);
+        assert_eq!(fcs.to_le_bytes(), [0xe4, 0x79]);
+
+        let mut frame = [0; 5];
+        frame[..3].copy_from_slice(&ack);
+        frame[3..].copy_from_slice(&fcs.to_le_bytes());
+        assert!(verify_fcs(&frame));
+
+        // A single bit error is detected
+        frame[1] ^= 0x08;
+        assert!(!verify_fcs(&frame));
+    }
+
+    #[test]
+    fn too_short_to_verify() {
+        assert!(!verify_fcs(&[]));
+        assert!(!verify_fcs(&[0x00]));
+        // Empty data has an FCS of 0
+        assert!(verify_fcs(&[0x00, 0x00]));
+    }
+}
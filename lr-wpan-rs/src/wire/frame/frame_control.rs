@@ -59,7 +59,9 @@ impl FrameType {
 }
 
 /// Defines version information for a frame
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+///
+/// The versions are ordered from oldest to newest.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord)]
 #[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
 pub enum FrameVersion {
     /// A frame conforming to the 802.15.4-2003 standard
@@ -48,12 +48,24 @@ pub struct Header {
     /// Identifier field, and the Source PAN Identifier field shall be assumed equal to that of the destination. If this
     /// field is set to `false`, then the PAN Identifier field shall be present if and only if the corresponding address is
     /// present.
+    ///
+    /// Frames of the [FrameVersion::Ieee802154] version follow table 7-2 of the 2015 standard instead.
+    /// There, a single address is sent without its PAN ID when this field is `true`, and two extended addresses
+    /// never both have a PAN ID. When reading, a left out source PAN ID is taken from the destination.
+    /// A PAN ID that's not in the frame at all is read as the [broadcast PAN ID](PanId::broadcast).
     pub pan_id_compress: bool,
 
     /// Suppress sequence number
+    ///
+    /// If `true`, the sequence number is not present in the frame.
+    /// When reading such a frame, [Self::seq] is set to 0.
     pub seq_no_suppress: bool,
 
     /// Information element present
+    ///
+    /// Of the information elements (IEs) themselves, only the [TimeCorrection] is supported.
+    /// When reading, the others are skipped. When writing, the supported IEs are followed by a termination IE.
+    /// The field is also written as `true` when [Self::time_correction] is set, so the two can't disagree.
     pub ie_present: bool,
 
     /// Frame version
@@ -78,30 +90,100 @@ pub struct Header {
     /// Auxiliary security header. If security is enabled in this header,
     /// this field will be Some, else it will be None
     pub auxiliary_security_header: Option<AuxiliarySecurityHeader>,
+
+    /// Time correction header IE, as sent in enhanced acks.
+    /// When set, the IEs are written as present regardless of [Self::ie_present].
+    pub time_correction: Option<TimeCorrection>,
 }
 
 impl Header {
     /// Get the size of this header in octets
     pub fn get_octet_size(&self) -> usize {
         // Frame control + sequence number
-        let mut len = 3;
+        let mut len = if self.seq_no_suppress { 2 } else { 3 };
 
-        for addr in [self.destination, self.source].iter().flatten() {
-            // pan ID
+        // Termination IE
+        if self.has_ies() {
             len += 2;
+
+            if self.time_correction.is_some() {
+                len += 2 + TIME_CORRECTION_LENGTH as usize;
+            }
+        }
+
+        let presence = pan_id_presence(
+            self.version,
+            self.destination.into(),
+            self.source.into(),
+            self.pan_id_compress,
+        )
+        .unwrap_or(PanIdPresence {
+            destination: self.destination.is_some(),
+            source: self.source.is_some(),
+        });
+
+        for (addr, pan_id_present) in [
+            (self.destination, presence.destination),
+            (self.source, presence.source),
+        ] {
+            if pan_id_present {
+                len += 2;
+            }
+
             // Address length
             match addr {
-                Address::Short(..) => len += 2,
-                Address::Extended(..) => len += 8,
+                Some(Address::Short(..)) => len += 2,
+                Some(Address::Extended(..)) => len += 8,
+                None => {}
             }
         }
         len
     }
 
+    /// Get the value the PAN ID compression field must have for the given addresses.
+    ///
+    /// The source PAN ID can only be left out when both addresses are present and
+    /// they are in the same PAN (5.2.1.1.5). This matches how the header is read.
+    ///
+    /// These are the rules of the 2003 and 2006 frame versions, see [Self::pan_id_compress] for the 2015 version.
+    pub fn pan_id_compression(destination: Option<Address>, source: Option<Address>) -> bool {
+        match (destination, source) {
+            (Some(destination), Some(source)) => destination.pan_id() == source.pan_id(),
+            _ => false,
+        }
+    }
+
+    /// Whether the header IEs are written, either because [Self::ie_present] is set or because there is an IE to write
+    fn has_ies(&self) -> bool {
+        self.ie_present || self.time_correction.is_some()
+    }
+
     /// Whether this header has security enabled
     pub fn has_security(&self) -> bool {
         self.auxiliary_security_header.is_some()
     }
+
+    /// Get the oldest frame version that supports all the features used in this header.
+    ///
+    /// - Information elements, sequence number suppression and the newer frame types require the current version
+    /// - Security requires at least the 2006 version
+    ///
+    /// See [Frame::minimum_version](super::Frame::minimum_version) to also take the content of the frame into account.
+    pub fn minimum_version(&self) -> FrameVersion {
+        if self.has_ies()
+            || self.seq_no_suppress
+            || matches!(
+                self.frame_type,
+                FrameType::Multipurpose | FrameType::FragOrFragAck | FrameType::Extended
+            )
+        {
+            FrameVersion::Ieee802154
+        } else if self.has_security() {
+            FrameVersion::Ieee802154_2006
+        } else {
+            FrameVersion::Ieee802154_2003
+        }
+    }
 }
 
 impl TryRead<'_> for Header {
@@ -145,51 +227,58 @@ impl TryRead<'_> for Header {
 
         /* Decode header depending on Frame Control Fields */
 
-        let seq = bytes.read(offset)?;
-
-        let destination = match dest_addr_mode {
-            AddressMode::None => None,
-            AddressMode::Short => Some(Address::Short(bytes.read(offset)?, bytes.read(offset)?)),
-            AddressMode::Extended => {
-                Some(Address::Extended(bytes.read(offset)?, bytes.read(offset)?))
-            }
+        let seq = if seq_no_suppress {
+            0
+        } else {
+            bytes.read(offset)?
         };
 
-        if pan_id_compress {
-            destination.ok_or(byte::Error::BadInput {
+        let presence = pan_id_presence(version, dest_addr_mode, src_addr_mode, pan_id_compress)
+            .ok_or(byte::Error::BadInput {
                 err: "InvalidAddressMode",
             })?;
-        }
 
-        let source = match src_addr_mode {
-            AddressMode::None => None,
-            AddressMode::Short => {
-                if pan_id_compress {
-                    Some(Address::Short(
-                        destination.unwrap().pan_id(),
-                        bytes.read(offset)?,
-                    ))
-                } else {
-                    Some(Address::Short(bytes.read(offset)?, bytes.read(offset)?))
-                }
-            }
-            AddressMode::Extended => {
-                if pan_id_compress {
-                    Some(Address::Extended(
-                        destination.unwrap().pan_id(),
-                        bytes.read(offset)?,
-                    ))
-                } else {
-                    Some(Address::Extended(bytes.read(offset)?, bytes.read(offset)?))
-                }
-            }
+        // Without a destination address, a destination PAN ID that's present is skipped
+        let destination_pan_id: Option<PanId> = match presence.destination {
+            true => Some(bytes.read(offset)?),
+            false => None,
         };
+        let destination = read_address(
+            bytes,
+            offset,
+            dest_addr_mode,
+            destination_pan_id.unwrap_or(PanId::broadcast()),
+        )?;
+
+        let source_pan_id: Option<PanId> = match presence.source {
+            true => Some(bytes.read(offset)?),
+            false => None,
+        };
+        let source = read_address(
+            bytes,
+            offset,
+            src_addr_mode,
+            source_pan_id
+                .or(destination_pan_id)
+                .unwrap_or(PanId::broadcast()),
+        )?;
 
         let auxiliary_security_header = match security {
             true => Some(bytes.read(offset)?),
             false => None,
         };
 
+        let mut time_correction = None;
+
+        if ie_present {
+            let payload_ies_follow = read_header_ies(bytes, offset, &mut time_correction)?;
+
+            // With security, the payload IEs are encrypted and stay part of the payload
+            if payload_ies_follow && !security {
+                skip_payload_ies(bytes, offset)?;
+            }
+        }
+
         let header = Header {
             frame_type,
             frame_pending,
@@ -202,6 +291,7 @@ impl TryRead<'_> for Header {
             destination,
             source,
             auxiliary_security_header,
+            time_correction,
         };
 
         Ok((header, *offset))
@@ -224,12 +314,15 @@ where
         let src_addr_mode = AddressMode::from(self.source);
 
         let security = self.auxiliary_security_header.is_some();
+        let ie_present = self.has_ies();
 
         let frame_control_raw = ((self.frame_type as u16) << offset::FRAME_TYPE)
             | ((security as u16) << offset::SECURITY)
             | ((self.frame_pending as u16) << offset::PENDING)
             | ((self.ack_request as u16) << offset::ACK)
             | ((self.pan_id_compress as u16) << offset::PAN_ID_COMPRESS)
+            | ((self.seq_no_suppress as u16) << offset::SEQ_NO_SUPPRESS)
+            | ((ie_present as u16) << offset::IE_PRESENT)
             | ((dest_addr_mode as u16) << offset::DEST_ADDR_MODE)
             | ((self.version as u16) << offset::VERSION)
             | ((src_addr_mode as u16) << offset::SRC_ADDR_MODE);
@@ -237,28 +330,48 @@ where
         bytes.write_with(offset, frame_control_raw, LE)?;
 
         // Write Sequence Number
-        bytes.write(offset, self.seq)?;
+        if !self.seq_no_suppress {
+            bytes.write(offset, self.seq)?;
+        }
 
-        if (self.destination.is_none() || self.source.is_none()) && self.pan_id_compress {
+        if (self.destination.is_none() || self.source.is_none())
+            && self.pan_id_compress
+            && self.version != FrameVersion::Ieee802154
+        {
             return Err(EncodeError::DisallowedPanIdCompress)?;
         }
 
-        // Write addresses
-        if let Some(destination) = self.destination {
-            bytes.write_with(offset, destination, AddressEncoding::Normal)?;
+        let presence = pan_id_presence(
+            self.version,
+            dest_addr_mode,
+            src_addr_mode,
+            self.pan_id_compress,
+        )
+        .ok_or(EncodeError::DisallowedPanIdCompress)?;
+
+        // Without its own PAN ID, the source is read back in the PAN of the destination
+        if let (Some(destination), Some(source)) = (self.destination, self.source) {
+            if presence.destination && !presence.source && destination.pan_id() != source.pan_id() {
+                return Err(EncodeError::DifferentPanIds)?;
+            }
         }
 
-        match (self.source, self.pan_id_compress) {
-            (Some(source), true) => {
-                bytes.write_with(offset, source, AddressEncoding::Compressed)?;
-            }
-            (Some(source), false) => {
-                bytes.write_with(offset, source, AddressEncoding::Normal)?;
-            }
-            (None, true) => {
-                panic!("frame control request compress source address without contain this address")
+        // Write addresses
+        for (address, pan_id_present) in [
+            (self.destination, presence.destination),
+            (self.source, presence.source),
+        ] {
+            match (address, pan_id_present) {
+                (Some(address), true) => {
+                    bytes.write_with(offset, address, AddressEncoding::Normal)?;
+                }
+                (Some(address), false) => {
+                    bytes.write_with(offset, address, AddressEncoding::Compressed)?;
+                }
+                // A PAN ID without an address, which we have no value for
+                (None, true) => return Err(EncodeError::DisallowedPanIdCompress)?,
+                (None, false) => {}
             }
-            (None, false) => (),
         }
 
         if security && sec_ctx.is_none() {
@@ -274,10 +387,198 @@ where
                 None => return Err(EncodeError::UnknownError)?,
             }
         }
+
+        if ie_present {
+            if let Some(time_correction) = self.time_correction {
+                bytes.write_with(
+                    offset,
+                    header_ie_descriptor(TIME_CORRECTION, TIME_CORRECTION_LENGTH),
+                    LE,
+                )?;
+                bytes.write_with(offset, time_correction.to_bits(), LE)?;
+            }
+
+            // The other IEs aren't supported, so terminate the list to keep the frame valid
+            bytes.write_with(offset, header_ie_descriptor(HEADER_TERMINATION_2, 0), LE)?;
+        }
+
         Ok(*offset)
     }
 }
 
+/// Header IE element ID of the time correction
+const TIME_CORRECTION: u16 = 0x1e;
+/// The content length of the time correction IE
+const TIME_CORRECTION_LENGTH: u16 = 2;
+/// Header IE element ID of the termination that is followed by payload IEs
+const HEADER_TERMINATION_1: u16 = 0x7e;
+/// Header IE element ID of the termination that is followed by the payload
+const HEADER_TERMINATION_2: u16 = 0x7f;
+/// Payload IE group ID of the termination
+const PAYLOAD_TERMINATION: u16 = 0x0f;
+
+/// Bit 15 of the IE descriptor tells if it's a payload IE
+const IE_TYPE_PAYLOAD: u16 = 0x8000;
+
+fn header_ie_descriptor(element_id: u16, length: u16) -> u16 {
+    (length & 0x7f) | ((element_id & 0xff) << 7)
+}
+
+/// Read the header IEs (7.4.2). Returns true if the list was terminated with payload IEs following it.
+///
+/// A time correction IE is read into `time_correction`, the other IEs are skipped.
+/// If there's no termination IE, the list runs until the end of the bytes.
+fn read_header_ies(
+    bytes: &[u8],
+    offset: &mut usize,
+    time_correction: &mut Option<TimeCorrection>,
+) -> byte::Result<bool> {
+    while *offset < bytes.len() {
+        let descriptor: u16 = bytes.read_with(offset, LE)?;
+
+        if descriptor & IE_TYPE_PAYLOAD != 0 {
+            return Err(DecodeError::InvalidValue)?;
+        }
+
+        let length = (descriptor & 0x7f) as usize;
+        let element_id = (descriptor >> 7) & 0xff;
+
+        match element_id {
+            HEADER_TERMINATION_1 => return Ok(true),
+            HEADER_TERMINATION_2 => return Ok(false),
+            TIME_CORRECTION if length == TIME_CORRECTION_LENGTH as usize => {
+                *time_correction = Some(TimeCorrection::from_bits(bytes.read_with(offset, LE)?));
+            }
+            _ => {
+                check_len(&bytes[*offset..], length)?;
+                *offset += length;
+            }
+        }
+    }
+
+    Ok(false)
+}
+
+/// The Time Correction IE (7.4.2.7).
+///
+/// An enhanced ack can carry it to tell the sender of the acked frame how far off its timing was.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct TimeCorrection {
+    /// The time in microseconds the frame arrived before the receiver expected it.
+    /// Negative when it arrived late. Only the range of -2048 to 2047 fits in the IE.
+    pub microseconds: i16,
+    /// True if the frame is negatively acknowledged
+    pub nack: bool,
+}
+
+impl TimeCorrection {
+    const MIN_MICROSECONDS: i16 = -2048;
+    const MAX_MICROSECONDS: i16 = 2047;
+    const NACK: u16 = 0x8000;
+
+    fn from_bits(bits: u16) -> Self {
+        // Sign extend the 12 bit value
+        let microseconds = ((bits << 4) as i16) >> 4;
+
+        Self {
+            microseconds,
+            nack: bits & Self::NACK != 0,
+        }
+    }
+
+    /// The value is clamped to the range that fits
+    fn to_bits(self) -> u16 {
+        let microseconds = self
+            .microseconds
+            .clamp(Self::MIN_MICROSECONDS, Self::MAX_MICROSECONDS);
+
+        (microseconds as u16 & 0x0fff) | if self.nack { Self::NACK } else { 0 }
+    }
+}
+
+/// Skip over the payload IEs (7.4.3).
+///
+/// If there's no termination IE, the list runs until the end of the bytes.
+fn skip_payload_ies(bytes: &[u8], offset: &mut usize) -> byte::Result<()> {
+    while *offset < bytes.len() {
+        let descriptor: u16 = bytes.read_with(offset, LE)?;
+
+        if descriptor & IE_TYPE_PAYLOAD == 0 {
+            return Err(DecodeError::InvalidValue)?;
+        }
+
+        let length = (descriptor & 0x07ff) as usize;
+        let group_id = (descriptor >> 11) & 0x0f;
+
+        check_len(&bytes[*offset..], length)?;
+        *offset += length;
+
+        if group_id == PAYLOAD_TERMINATION {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Which of the PAN ID fields are present in a header
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct PanIdPresence {
+    destination: bool,
+    source: bool,
+}
+
+/// Get which PAN ID fields are present for the addressing modes and the PAN ID compression field.
+///
+/// Returns None if the PAN ID compression field can't be set with these addressing modes.
+fn pan_id_presence(
+    version: FrameVersion,
+    destination: AddressMode,
+    source: AddressMode,
+    pan_id_compress: bool,
+) -> Option<PanIdPresence> {
+    let (destination, source) = match version {
+        FrameVersion::Ieee802154_2003 | FrameVersion::Ieee802154_2006 => {
+            if pan_id_compress && destination == AddressMode::None {
+                return None;
+            }
+
+            (
+                destination != AddressMode::None,
+                source != AddressMode::None && !pan_id_compress,
+            )
+        }
+        // Table 7-2 of the 2015 standard
+        FrameVersion::Ieee802154 => match (destination, source) {
+            (AddressMode::None, AddressMode::None) => (pan_id_compress, false),
+            (_, AddressMode::None) => (!pan_id_compress, false),
+            (AddressMode::None, _) => (false, !pan_id_compress),
+            (AddressMode::Extended, AddressMode::Extended) => (!pan_id_compress, false),
+            _ => (true, !pan_id_compress),
+        },
+    };
+
+    Some(PanIdPresence {
+        destination,
+        source,
+    })
+}
+
+/// Read an address of the given mode. The PAN ID has already been read, if it was present.
+fn read_address(
+    bytes: &[u8],
+    offset: &mut usize,
+    mode: AddressMode,
+    pan_id: PanId,
+) -> byte::Result<Option<Address>> {
+    Ok(match mode {
+        AddressMode::None => None,
+        AddressMode::Short => Some(Address::Short(pan_id, bytes.read(offset)?)),
+        AddressMode::Extended => Some(Address::Extended(pan_id, bytes.read(offset)?)),
+    })
+}
+
 /// Personal Area Network Identifier
 ///
 /// A 16-bit value that identifies a PAN
@@ -11,7 +11,11 @@
 // - change &[u8] => bytes::Buf
 // - remove one variant enums
 
-use super::{beacon::Beacon, command::Command};
+use super::{
+    beacon::Beacon,
+    command::Command,
+    fcs::{FCS_LENGTH, compute_fcs, verify_fcs},
+};
 
 mod frame_control;
 pub mod header;
@@ -20,8 +24,8 @@ use byte::{BytesExt, LE, TryRead, TryWrite, ctx::Bytes};
 use ccm::aead::generic_array::typenum::consts::U16;
 use cipher::{BlockCipher, BlockEncrypt, NewBlockCipher};
 use derive_more::Display;
-use header::FrameType;
 pub use header::Header;
+use header::{FrameType, FrameVersion};
 
 use self::security::{
     DeviceDescriptorLookup, KeyDescriptorLookup, SecurityContext, SecurityError,
@@ -126,6 +130,7 @@ use self::security::{
 ///         destination: Some(Address::Short(PanId(0x1234), ShortAddress(0x5678))),
 ///         source:      Some(Address::Short(PanId(0x1234), ShortAddress(0x9abc))),
 ///         auxiliary_security_header: None,
+///         time_correction: None,
 ///     },
 ///     content: FrameContent::Data,
 ///     payload: &[0xde, 0xf0],
@@ -170,7 +175,7 @@ pub struct Frame<'p> {
     /// When creating an instance of this struct for encoding, you don't
     /// necessarily need to write an actual CRC checksum here. [`Frame::try_write`]
     /// can omit writing this checksum, for example if the transceiver hardware
-    /// automatically adds the checksum for you.
+    /// automatically adds the checksum for you, or calculate it with [`FooterMode::Fcs`].
     pub footer: [u8; 2],
 }
 
@@ -222,11 +227,17 @@ where
     AEADBLKCIPH: NewBlockCipher + BlockCipher<BlockSize = U16> + BlockEncrypt,
     KEYDESCLO: KeyDescriptorLookup<AEADBLKCIPH::KeySize>,
 {
+    /// Write the frame into the buffer.
+    ///
+    /// If the version in the header is older than the [minimum version](Frame::minimum_version) of the frame,
+    /// the minimum version is written instead.
     fn try_write(
-        self,
+        mut self,
         bytes: &mut [u8],
         context: &mut FrameSerDesContext<AEADBLKCIPH, KEYDESCLO>,
     ) -> byte::Result<usize> {
+        self.header.version = self.header.version.max(self.minimum_version());
+
         let mode = &context.footer_mode;
         let offset = &mut 0;
 
@@ -262,18 +273,52 @@ where
             FooterMode::None => {}
             // TODO: recalculate the footer after encryption?
             FooterMode::Explicit => bytes.write(offset, &self.footer[..])?,
+            FooterMode::Fcs => {
+                let fcs = compute_fcs(&bytes[..*offset]);
+                bytes.write_with(offset, fcs, LE)?;
+            }
         }
 
         Ok(*offset)
     }
 }
 
+impl Frame<'_> {
+    /// Get the oldest frame version that supports all the features used in this frame.
+    ///
+    /// On top of the requirements of [Header::minimum_version], this requires at least the 2006 version for:
+    /// - Payloads that are larger than [MAX_MAC_SAFE_PAYLOAD_SIZE](crate::consts::MAX_MAC_SAFE_PAYLOAD_SIZE)
+    /// - Coordinator realignment commands that contain a channel page
+    pub fn minimum_version(&self) -> FrameVersion {
+        let content_version = match &self.content {
+            FrameContent::Command(Command::CoordinatorRealignment(data))
+                if data.channel_page.is_some() =>
+            {
+                FrameVersion::Ieee802154_2006
+            }
+            _ => FrameVersion::Ieee802154_2003,
+        };
+
+        let payload_version = if self.payload.len() > crate::consts::MAX_MAC_SAFE_PAYLOAD_SIZE {
+            FrameVersion::Ieee802154_2006
+        } else {
+            FrameVersion::Ieee802154_2003
+        };
+
+        self.header
+            .minimum_version()
+            .max(content_version)
+            .max(payload_version)
+    }
+}
+
 impl<'a> Frame<'a> {
     /// Try to read a frame. If the frame is secured, it will be unsecured
     ///
     /// Currently, this function does not support the explicit footer mode,
     /// as the FCS has to be calculated over the payload before it is unsecured,
-    /// which isn't implemented yet
+    /// which isn't implemented yet. The FCS footer mode is supported, because
+    /// it checks the FCS before the frame is unsecured.
     ///
     /// Use [`FrameSerDesContext::no_security`] and/or [`Unimplemented`] if you
     /// do not want to use any security, or simply [`Frame::try_read`]
@@ -287,29 +332,71 @@ impl<'a> Frame<'a> {
         KEYDESCLO: KeyDescriptorLookup<AEADBLKCIPH::KeySize>,
         DEVDESCLO: DeviceDescriptorLookup,
     {
+        match Self::try_read_and_verify(buf, ctx, dev_desc_lo)? {
+            (frame, size, None) => Ok((frame, size)),
+            (_, _, Some(e)) => Err(e),
+        }
+    }
+
+    /// Try to read a frame like [`Frame::try_read_and_unsecure`], but keep a frame that can't be unsecured.
+    ///
+    /// When unsecuring fails, the frame is returned along with the error. Its payload is then left as
+    /// it was received, so it can still be encrypted and it includes the MIC. The header and
+    /// the content are never secured, so they can still be used, e.g. to report the PAN of a beacon.
+    ///
+    /// An error is only returned when the frame can't be read at all.
+    pub fn try_read_and_verify<AEADBLKCIPH, KEYDESCLO, DEVDESCLO>(
+        buf: &'a mut [u8],
+        ctx: &mut FrameSerDesContext<'_, AEADBLKCIPH, KEYDESCLO>,
+        dev_desc_lo: &mut DEVDESCLO,
+    ) -> Result<(Frame<'a>, usize, Option<SecurityError>), SecurityError>
+    where
+        AEADBLKCIPH: NewBlockCipher + BlockCipher<BlockSize = U16> + BlockEncrypt,
+        KEYDESCLO: KeyDescriptorLookup<AEADBLKCIPH::KeySize>,
+        DEVDESCLO: DeviceDescriptorLookup,
+    {
+        let (buf, footer) = match ctx.footer_mode {
+            FooterMode::Fcs => {
+                check_fcs(buf).map_err(byte::Error::from)?;
+                let (buf, footer) = buf.split_at_mut(buf.len() - FCS_LENGTH);
+                (buf, [footer[0], footer[1]])
+            }
+            _ => (buf, [0, 0]),
+        };
+
         let offset = &mut 0;
         let header: Header = buf.read(offset)?;
         let content = buf.read_with(offset, &header)?;
 
         let mut tag_size = 0;
+        let mut security_error = None;
 
         if header.has_security() {
-            if let Some(sec_ctx) = ctx.security_ctx.as_mut() {
-                tag_size = match security::unsecure_frame(
+            // Unsecuring is done in place, so keep the original payload around in case it fails
+            let mut original = [0u8; crate::consts::MAX_PHY_PACKET_SIZE];
+            let original = original
+                .get_mut(..buf.len() - *offset)
+                .ok_or(SecurityError::FrameTooLong)?;
+            original.copy_from_slice(&buf[*offset..]);
+
+            let result = match ctx.security_ctx.as_mut() {
+                Some(sec_ctx) => security::unsecure_frame(
                     &header,
                     &mut buf[*offset..],
                     sec_ctx,
                     ctx.footer_mode,
                     dev_desc_lo,
-                ) {
-                    Ok(size) => size,
-                    Err(e) => match e {
-                        SecurityError::SecurityNotEnabled => 0,
-                        _ => return Err(e),
-                    },
-                };
-            } else {
-                return Err(SecurityError::InvalidSecContext);
+                ),
+                None => Err(SecurityError::InvalidSecContext),
+            };
+
+            match result {
+                Ok(size) => tag_size = size,
+                Err(SecurityError::SecurityNotEnabled) => {}
+                Err(e) => {
+                    buf[*offset..].copy_from_slice(original);
+                    security_error = Some(e);
+                }
             }
         }
         let payload = buf.read_with(offset, Bytes::Len(buf.len() - *offset - tag_size))?;
@@ -318,10 +405,10 @@ impl<'a> Frame<'a> {
             header,
             content,
             payload,
-            footer: [0, 0],
+            footer,
         };
 
-        Ok((frame, *offset))
+        Ok((frame, *offset, security_error))
     }
 }
 
@@ -333,6 +420,10 @@ impl<'a> TryRead<'a, FooterMode> for Frame<'a> {
     ///
     /// If you expect to receive secured frames, use [`Frame::try_read_and_unsecure`] instead,
     fn try_read(bytes: &'a [u8], mode: FooterMode) -> byte::Result<(Self, usize)> {
+        if let FooterMode::Fcs = mode {
+            check_fcs(bytes)?;
+        }
+
         let offset = &mut 0;
         let header: Header = bytes.read(offset)?;
         let content = bytes.read_with(offset, &header)?;
@@ -345,7 +436,7 @@ impl<'a> TryRead<'a, FooterMode> for Frame<'a> {
                 bytes.read_with(offset, Bytes::Len(bytes.len() - *offset))?,
                 0u16,
             ),
-            FooterMode::Explicit => (
+            FooterMode::Explicit | FooterMode::Fcs => (
                 bytes.read_with(offset, Bytes::Len(bytes.len() - *offset - 2))?,
                 bytes.read_with(offset, LE)?,
             ),
@@ -364,13 +455,11 @@ impl<'a> TryRead<'a, FooterMode> for Frame<'a> {
 ///
 /// Controls whether the footer is read/written with the frame
 ///
-/// Eventually, this should support three options:
+/// There are three options:
 /// 1. Don't read or write the footer
 /// 2. Calculate the 2-byte CRC checksum and write that as the footer or check against read value
 /// 3. Read into or write the footer from the `footer` field
 ///
-/// For now, only 1 and 3 are supported.
-///
 /// [`Frame::try_write`](Frame::try_write)
 #[derive(Clone, Copy)]
 #[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
@@ -379,6 +468,23 @@ pub enum FooterMode {
     None,
     /// Read into or write the footer from the `footer` field
     Explicit,
+    /// Calculate the FCS and write it as the footer, or check the read footer against it.
+    ///
+    /// Use this when the transceiver doesn't append and check the FCS in hardware.
+    Fcs,
+}
+
+/// Check the FCS at the end of the bytes of a frame
+fn check_fcs(bytes: &[u8]) -> Result<(), DecodeError> {
+    if bytes.len() < FCS_LENGTH {
+        return Err(DecodeError::NotEnoughBytes);
+    }
+
+    if !verify_fcs(bytes) {
+        return Err(DecodeError::InvalidFcs);
+    }
+
+    Ok(())
 }
 
 impl Default for FooterMode {
@@ -473,6 +579,9 @@ pub enum DecodeError {
 
     /// The data stream contains an invalid value
     InvalidValue,
+
+    /// The FCS in the footer doesn't match the frame
+    InvalidFcs,
 }
 
 impl From<DecodeError> for byte::Error {
@@ -506,6 +615,7 @@ impl From<DecodeError> for byte::Error {
             DecodeError::SecurityEnabled => byte::Error::BadInput {
                 err: "SecurityEnabled (use Frame::try_read_and_unsecure)",
             },
+            DecodeError::InvalidFcs => byte::Error::BadInput { err: "InvalidFcs" },
         }
     }
 }
@@ -518,9 +628,12 @@ pub enum EncodeError {
     WriteError,
     /// Security is enabled but no security context is specified
     MissingSecurityCtx,
-    /// The `pan_id_compress` flag is set, but either the destination address
-    /// or source address is not present.
+    /// The `pan_id_compress` flag is set, but the addresses that are present
+    /// don't allow it in this frame version.
     DisallowedPanIdCompress,
+    /// The source address is in another PAN than the destination, but the source PAN ID
+    /// isn't present with these addresses in this frame version.
+    DifferentPanIds,
     /// Something went wrong, but it is unclear what/how it did
     UnknownError,
 }
@@ -535,6 +648,9 @@ impl From<EncodeError> for byte::Error {
             EncodeError::DisallowedPanIdCompress => byte::Error::BadInput {
                 err: "DisallowedPanIdCompress",
             },
+            EncodeError::DifferentPanIds => byte::Error::BadInput {
+                err: "DifferentPanIds",
+            },
             EncodeError::UnknownError => byte::Error::BadInput {
                 err: "UnknownError",
             },
@@ -545,7 +661,8 @@ impl From<EncodeError> for byte::Error {
 #[cfg(test)]
 mod tests {
     use crate::wire::{
-        Address, ExtendedAddress, FrameVersion, PanId, ShortAddress, beacon, command, frame::*,
+        Address, ExtendedAddress, FrameVersion, PanId, ShortAddress, TimeCorrection, beacon,
+        command, frame::*,
     };
 
     #[test]
@@ -647,6 +764,7 @@ mod tests {
                 source: Some(Address::Short(PanId(0x4321), ShortAddress(0x9abc))),
                 seq: 0x01,
                 auxiliary_security_header: None,
+                time_correction: None,
             },
             content: FrameContent::Data,
             payload: &[0xde, 0xf0],
@@ -687,6 +805,7 @@ mod tests {
                 source: Some(Address::Short(PanId(0x4321), ShortAddress(0x9abc))),
                 seq: 0xff,
                 auxiliary_security_header: None,
+                time_correction: None,
             },
             content: FrameContent::Beacon(beacon::Beacon {
                 superframe_spec: beacon::SuperframeSpecification {
@@ -739,6 +858,7 @@ mod tests {
                 source: Some(Address::Short(PanId(0x1234), ShortAddress(0x9abc))),
                 seq: 0xff,
                 auxiliary_security_header: None,
+                time_correction: None,
             },
             content: FrameContent::Acknowledgement,
             payload: &[],
@@ -777,6 +897,7 @@ mod tests {
                 source: Some(Address::Short(PanId(0x1234), ShortAddress(0x9abc))),
                 seq: 0xff,
                 auxiliary_security_header: None,
+                time_correction: None,
             },
             content: FrameContent::Command(command::Command::DataRequest),
             payload: &[],
@@ -811,6 +932,7 @@ mod tests {
             seq_no_suppress: false,
             ie_present: false,
             auxiliary_security_header: None,
+            time_correction: None,
         };
 
         assert!(
@@ -838,4 +960,629 @@ mod tests {
                 .is_ok()
         );
     }
+
+    fn version_test_frame(payload: &[u8]) -> Frame<'_> {
+        Frame {
+            header: Header {
+                ie_present: false,
+                seq_no_suppress: false,
+                frame_type: FrameType::Data,
+                frame_pending: false,
+                ack_request: false,
+                pan_id_compress: false,
+                version: FrameVersion::Ieee802154_2003,
+                destination: Some(Address::Short(PanId(0x1234), ShortAddress(0x5678))),
+                source: Some(Address::Short(PanId(0x4321), ShortAddress(0x9abc))),
+                seq: 0x01,
+                auxiliary_security_header: None,
+                time_correction: None,
+            },
+            content: FrameContent::Data,
+            payload,
+            footer: [0x00, 0x00],
+        }
+    }
+
+    #[test]
+    fn minimum_version_plain_frames() {
+        let frame = version_test_frame(&[0xde, 0xf0]);
+        assert_eq!(frame.minimum_version(), FrameVersion::Ieee802154_2003);
+
+        let mut ack = version_test_frame(&[]);
+        ack.header.frame_type = FrameType::Acknowledgement;
+        ack.header.destination = None;
+        ack.header.source = None;
+        ack.content = FrameContent::Acknowledgement;
+        assert_eq!(ack.minimum_version(), FrameVersion::Ieee802154_2003);
+
+        let mut beacon_request = version_test_frame(&[]);
+        beacon_request.header.frame_type = FrameType::MacCommand;
+        beacon_request.content = FrameContent::Command(command::Command::BeaconRequest);
+        assert_eq!(
+            beacon_request.minimum_version(),
+            FrameVersion::Ieee802154_2003
+        );
+    }
+
+    #[test]
+    fn minimum_version_header_features() {
+        let mut frame = version_test_frame(&[]);
+        frame.header.auxiliary_security_header = Some(security::AuxiliarySecurityHeader::new(
+            security::SecurityControl::new(security::SecurityLevel::ENC),
+            None,
+        ));
+        assert_eq!(
+            frame.header.minimum_version(),
+            FrameVersion::Ieee802154_2006
+        );
+        assert_eq!(frame.minimum_version(), FrameVersion::Ieee802154_2006);
+
+        let mut frame = version_test_frame(&[]);
+        frame.header.ie_present = true;
+        assert_eq!(frame.header.minimum_version(), FrameVersion::Ieee802154);
+        assert_eq!(frame.minimum_version(), FrameVersion::Ieee802154);
+
+        let mut frame = version_test_frame(&[]);
+        frame.header.seq_no_suppress = true;
+        assert_eq!(frame.minimum_version(), FrameVersion::Ieee802154);
+
+        let mut frame = version_test_frame(&[]);
+        frame.header.frame_type = FrameType::Multipurpose;
+        frame.content = FrameContent::Multipurpose;
+        assert_eq!(frame.minimum_version(), FrameVersion::Ieee802154);
+    }
+
+    #[test]
+    fn minimum_version_content() {
+        let realignment = command::CoordinatorRealignmentData {
+            pan_id: PanId(0x1234),
+            coordinator_address: ShortAddress(0x0000),
+            channel: 11,
+            device_address: ShortAddress::BROADCAST,
+            channel_page: None,
+        };
+
+        let mut frame = version_test_frame(&[]);
+        frame.header.frame_type = FrameType::MacCommand;
+        frame.content =
+            FrameContent::Command(command::Command::CoordinatorRealignment(realignment));
+        assert_eq!(frame.minimum_version(), FrameVersion::Ieee802154_2003);
+
+        frame.content = FrameContent::Command(command::Command::CoordinatorRealignment(
+            command::CoordinatorRealignmentData {
+                channel_page: Some(0),
+                ..realignment
+            },
+        ));
+        assert_eq!(frame.minimum_version(), FrameVersion::Ieee802154_2006);
+
+        let payload = [0; crate::consts::MAX_MAC_SAFE_PAYLOAD_SIZE];
+        assert_eq!(
+            version_test_frame(&payload).minimum_version(),
+            FrameVersion::Ieee802154_2003
+        );
+        let payload = [0; crate::consts::MAX_MAC_SAFE_PAYLOAD_SIZE + 1];
+        assert_eq!(
+            version_test_frame(&payload).minimum_version(),
+            FrameVersion::Ieee802154_2006
+        );
+    }
+
+    #[test]
+    fn encode_raises_version_to_minimum() {
+        let payload = [0; crate::consts::MAX_MAC_SAFE_PAYLOAD_SIZE + 1];
+        let frame = version_test_frame(&payload);
+
+        let mut buf = [0u8; crate::consts::MAX_PHY_PACKET_SIZE];
+        let mut len = 0usize;
+        buf.write_with(
+            &mut len,
+            frame,
+            &mut FrameSerDesContext::no_security(FooterMode::None),
+        )
+        .unwrap();
+
+        let decoded: Frame = buf[..len].read_with(&mut 0, FooterMode::None).unwrap();
+        assert_eq!(decoded.header.version, FrameVersion::Ieee802154_2006);
+
+        // A newer version than required is kept
+        let mut frame = version_test_frame(&[]);
+        frame.header.version = FrameVersion::Ieee802154;
+
+        let mut len = 0usize;
+        buf.write_with(
+            &mut len,
+            frame,
+            &mut FrameSerDesContext::no_security(FooterMode::None),
+        )
+        .unwrap();
+
+        let decoded: Frame = buf[..len].read_with(&mut 0, FooterMode::None).unwrap();
+        assert_eq!(decoded.header.version, FrameVersion::Ieee802154);
+    }
+
+    #[test]
+    fn encode_decode_with_fcs() {
+        let frame = version_test_frame(&[0xde, 0xf0]);
+
+        let mut buf = [0u8; 32];
+        let mut len = 0usize;
+        buf.write_with(
+            &mut len,
+            frame.clone(),
+            &mut FrameSerDesContext::no_security(FooterMode::Fcs),
+        )
+        .unwrap();
+
+        let fcs = compute_fcs(&buf[..len - 2]).to_le_bytes();
+        assert_eq!(buf[len - 2..len], fcs);
+
+        let decoded: Frame = buf[..len].read_with(&mut 0, FooterMode::Fcs).unwrap();
+        assert_eq!(decoded.header, frame.header);
+        assert_eq!(decoded.payload, frame.payload);
+        assert_eq!(decoded.footer, fcs);
+
+        let (decoded, _) = Frame::try_read_and_unsecure(
+            &mut buf[..len],
+            &mut FrameSerDesContext::no_security(FooterMode::Fcs),
+            &mut Unimplemented,
+        )
+        .unwrap();
+        assert_eq!(decoded.payload, frame.payload);
+        assert_eq!(decoded.footer, fcs);
+
+        // A single bit error is detected
+        buf[3] ^= 0x10;
+        assert!(
+            buf[..len]
+                .read_with::<Frame>(&mut 0, FooterMode::Fcs)
+                .is_err()
+        );
+        assert!(
+            Frame::try_read_and_unsecure(
+                &mut buf[..len],
+                &mut FrameSerDesContext::no_security(FooterMode::Fcs),
+                &mut Unimplemented,
+            )
+            .is_err()
+        );
+    }
+
+    fn round_trip<'b>(frame: Frame<'_>, buf: &'b mut [u8]) -> (usize, Frame<'b>) {
+        let mut len = 0usize;
+        buf.write_with(
+            &mut len,
+            frame,
+            &mut FrameSerDesContext::no_security(FooterMode::None),
+        )
+        .unwrap();
+
+        let buf: &'b [u8] = buf;
+        let decoded = buf[..len].read_with(&mut 0, FooterMode::None).unwrap();
+        (len, decoded)
+    }
+
+    const SHORT_A: Address = Address::Short(PanId(0x1234), ShortAddress(0x5678));
+    const SHORT_B: Address = Address::Short(PanId(0x1234), ShortAddress(0x9abc));
+    const EXTENDED_A: Address =
+        Address::Extended(PanId(0x1234), ExtendedAddress(0x1122334455667788));
+    const EXTENDED_B: Address =
+        Address::Extended(PanId(0x1234), ExtendedAddress(0x8877665544332211));
+
+    fn with_pan_id(address: Address, pan_id: PanId) -> Address {
+        match address {
+            Address::Short(_, address) => Address::Short(pan_id, address),
+            Address::Extended(_, address) => Address::Extended(pan_id, address),
+        }
+    }
+
+    /// Write and read a frame with the given addressing and return its length and the decoded header
+    fn addressing_round_trip(
+        version: FrameVersion,
+        destination: Option<Address>,
+        source: Option<Address>,
+        pan_id_compress: bool,
+    ) -> (usize, Header) {
+        let mut frame = version_test_frame(&[]);
+        frame.header.version = version;
+        frame.header.destination = destination;
+        frame.header.source = source;
+        frame.header.pan_id_compress = pan_id_compress;
+
+        let mut buf = [0u8; 32];
+        let (len, decoded) = round_trip(frame.clone(), &mut buf);
+        assert_eq!(frame.header.get_octet_size(), len);
+
+        (len, decoded.header)
+    }
+
+    #[test]
+    fn pan_id_compression_ver0_and_ver1() {
+        let other_pan = PanId(0x4321);
+
+        for version in [FrameVersion::Ieee802154_2003, FrameVersion::Ieee802154_2006] {
+            for (destination, source, pan_id_compress, expected_len) in [
+                (Some(SHORT_A), Some(SHORT_B), true, 9),
+                (Some(EXTENDED_A), Some(EXTENDED_B), true, 21),
+                (Some(SHORT_A), Some(EXTENDED_B), true, 15),
+                (Some(SHORT_A), Some(SHORT_B), false, 11),
+                (
+                    Some(SHORT_A),
+                    Some(with_pan_id(EXTENDED_B, other_pan)),
+                    false,
+                    17,
+                ),
+                (
+                    Some(EXTENDED_A),
+                    Some(with_pan_id(EXTENDED_B, other_pan)),
+                    false,
+                    23,
+                ),
+                (Some(SHORT_A), None, false, 7),
+                (None, Some(EXTENDED_A), false, 13),
+            ] {
+                let (len, header) =
+                    addressing_round_trip(version, destination, source, pan_id_compress);
+                assert_eq!(len, expected_len);
+                assert_eq!(header.destination, destination);
+                assert_eq!(header.source, source);
+                assert_eq!(header.pan_id_compress, pan_id_compress);
+            }
+        }
+    }
+
+    #[test]
+    fn pan_id_compression_ver2() {
+        let other_pan = PanId(0x4321);
+        let broadcast = PanId::broadcast();
+
+        // The addresses to write, and the addresses that are read back
+        for (destination, source, pan_id_compress, expected_len, expected) in [
+            // Short addresses work like the older versions
+            (
+                Some(SHORT_A),
+                Some(SHORT_B),
+                true,
+                9,
+                (Some(SHORT_A), Some(SHORT_B)),
+            ),
+            (
+                Some(SHORT_A),
+                Some(with_pan_id(EXTENDED_B, other_pan)),
+                false,
+                17,
+                (Some(SHORT_A), Some(with_pan_id(EXTENDED_B, other_pan))),
+            ),
+            (
+                Some(EXTENDED_A),
+                Some(with_pan_id(SHORT_B, other_pan)),
+                false,
+                17,
+                (Some(EXTENDED_A), Some(with_pan_id(SHORT_B, other_pan))),
+            ),
+            (
+                Some(EXTENDED_A),
+                Some(SHORT_B),
+                true,
+                15,
+                (Some(EXTENDED_A), Some(SHORT_B)),
+            ),
+            // Two extended addresses never both have a PAN ID, so the source is in the destination PAN
+            (
+                Some(EXTENDED_A),
+                Some(EXTENDED_B),
+                false,
+                21,
+                (Some(EXTENDED_A), Some(EXTENDED_B)),
+            ),
+            (
+                Some(EXTENDED_A),
+                Some(EXTENDED_B),
+                true,
+                19,
+                (
+                    Some(with_pan_id(EXTENDED_A, broadcast)),
+                    Some(with_pan_id(EXTENDED_B, broadcast)),
+                ),
+            ),
+            // A single address keeps its PAN ID, unless it's compressed
+            (Some(SHORT_A), None, false, 7, (Some(SHORT_A), None)),
+            (
+                Some(SHORT_A),
+                None,
+                true,
+                5,
+                (Some(with_pan_id(SHORT_A, broadcast)), None),
+            ),
+            (None, Some(SHORT_B), false, 7, (None, Some(SHORT_B))),
+            (
+                None,
+                Some(SHORT_B),
+                true,
+                5,
+                (None, Some(with_pan_id(SHORT_B, broadcast))),
+            ),
+        ] {
+            let (len, header) = addressing_round_trip(
+                FrameVersion::Ieee802154,
+                destination,
+                source,
+                pan_id_compress,
+            );
+            assert_eq!(len, expected_len);
+            assert_eq!((header.destination, header.source), expected);
+            assert_eq!(header.pan_id_compress, pan_id_compress);
+        }
+
+        // So a source in another PAN can't be written
+        let mut frame = version_test_frame(&[]);
+        frame.header.version = FrameVersion::Ieee802154;
+        frame.header.destination = Some(EXTENDED_A);
+        frame.header.source = Some(with_pan_id(EXTENDED_B, other_pan));
+        frame.header.pan_id_compress = false;
+
+        let mut buf = [0u8; 32];
+        assert!(matches!(
+            buf.write_with(
+                &mut 0,
+                frame,
+                &mut FrameSerDesContext::no_security(FooterMode::None),
+            ),
+            Err(byte::Error::BadInput {
+                err: "DifferentPanIds"
+            })
+        ));
+    }
+
+    #[test]
+    fn decode_ver2_pan_id_without_addresses() {
+        // A data frame with only a destination PAN ID
+        let data = [0x41, 0x20, 0x05, 0x34, 0x12, 0xaa];
+
+        let frame: Frame = data.read_with(&mut 0, FooterMode::None).unwrap();
+        assert_eq!(frame.header.version, FrameVersion::Ieee802154);
+        assert_eq!(frame.header.pan_id_compress, true);
+        assert_eq!(frame.header.destination, None);
+        assert_eq!(frame.header.source, None);
+        assert_eq!(frame.payload, &[0xaa]);
+
+        // The PAN ID can't be written, since the header has no address to take it from
+        let mut buf = [0u8; 32];
+        assert!(
+            buf.write_with(
+                &mut 0,
+                frame,
+                &mut FrameSerDesContext::no_security(FooterMode::None),
+            )
+            .is_err()
+        );
+
+        // In the older versions, this is invalid
+        let data = [0x41, 0x00, 0x05, 0x34, 0x12, 0xaa];
+        assert!(data.read_with::<Frame>(&mut 0, FooterMode::None).is_err());
+    }
+
+    #[test]
+    fn round_trip_seq_no_suppress() {
+        let mut frame = version_test_frame(&[0xde, 0xf0]);
+        frame.header.version = FrameVersion::Ieee802154;
+        frame.header.seq = 0x42;
+
+        let mut buf = [0u8; 32];
+        let (len_with_seq, decoded) = round_trip(frame.clone(), &mut buf);
+        assert_eq!(decoded, frame);
+
+        frame.header.seq_no_suppress = true;
+        frame.header.seq = 0;
+
+        let mut buf = [0u8; 32];
+        let (len_without_seq, decoded) = round_trip(frame.clone(), &mut buf);
+        assert_eq!(decoded, frame);
+        assert_eq!(len_without_seq, len_with_seq - 1);
+        assert_eq!(&buf[2..4], &[0x34, 0x12]);
+    }
+
+    #[test]
+    fn round_trip_ie_present() {
+        let mut frame = version_test_frame(&[0xde, 0xf0]);
+        frame.header.version = FrameVersion::Ieee802154;
+        frame.header.ie_present = true;
+
+        let mut buf = [0u8; 32];
+        let (_, decoded) = round_trip(frame.clone(), &mut buf);
+        assert_eq!(decoded, frame);
+    }
+
+    #[test]
+    fn enhanced_ack_with_time_correction() {
+        let data = [
+            0x02, 0x22, // Frame control of an enhanced ack with IEs present
+            0x07, // Sequence number
+            0x02, 0x0f, // Time correction IE
+            0xff, 0x8f, // -1 microsecond and a nack
+            0x80, 0x3f, // Header termination followed by the payload
+        ];
+
+        let frame: Frame = data.read_with(&mut 0, FooterMode::None).unwrap();
+        assert_eq!(frame.header.frame_type, FrameType::Acknowledgement);
+        assert_eq!(frame.header.version, FrameVersion::Ieee802154);
+        assert_eq!(
+            frame.header.time_correction,
+            Some(TimeCorrection {
+                microseconds: -1,
+                nack: true,
+            })
+        );
+        assert_eq!(frame.header.get_octet_size(), data.len());
+
+        let mut buf = [0u8; 32];
+        let (len, decoded) = round_trip(frame.clone(), &mut buf);
+        assert_eq!(decoded, frame);
+        assert_eq!(&buf[..len], &data);
+    }
+
+    #[test]
+    fn time_correction_is_clamped() {
+        let mut frame = version_test_frame(&[]);
+        frame.header.version = FrameVersion::Ieee802154;
+        frame.header.ie_present = true;
+        frame.header.time_correction = Some(TimeCorrection {
+            microseconds: 3000,
+            nack: false,
+        });
+
+        let mut buf = [0u8; 32];
+        let (_, decoded) = round_trip(frame.clone(), &mut buf);
+        assert_eq!(
+            decoded.header.time_correction,
+            Some(TimeCorrection {
+                microseconds: 2047,
+                nack: false,
+            })
+        );
+
+        frame.header.time_correction = Some(TimeCorrection {
+            microseconds: -3000,
+            nack: false,
+        });
+        let (_, decoded) = round_trip(frame, &mut buf);
+        assert_eq!(decoded.header.time_correction.unwrap().microseconds, -2048);
+    }
+
+    #[test]
+    fn time_correction_sets_ie_present() {
+        let mut frame = version_test_frame(&[]);
+        frame.header.version = FrameVersion::Ieee802154;
+        frame.header.ie_present = false;
+        frame.header.time_correction = Some(TimeCorrection {
+            microseconds: 10,
+            nack: false,
+        });
+
+        let mut buf = [0u8; 32];
+        let (len, decoded) = round_trip(frame.clone(), &mut buf);
+        assert_eq!(len, frame.header.get_octet_size());
+        assert!(decoded.header.ie_present);
+        assert_eq!(decoded.header.time_correction, frame.header.time_correction);
+        assert_eq!(frame.header.minimum_version(), FrameVersion::Ieee802154);
+    }
+
+    #[test]
+    fn decode_header_ies() {
+        let data = [
+            0x41, 0xaa, // Frame control with IEs present
+            0x05, // Sequence number
+            0x34, 0x12, 0xff, 0xff, 0x01, 0x00, // Addressing
+            0x02, 0x0d, 0xaa, 0xbb, // Header IE with 2 bytes of content
+            0x80, 0x3f, // Header termination followed by the payload
+            0xde, 0xad, // Payload
+        ];
+
+        let frame: Frame = data.read_with(&mut 0, FooterMode::None).unwrap();
+        assert!(frame.header.ie_present);
+        assert_eq!(frame.header.seq, 5);
+        assert_eq!(
+            frame.header.source,
+            Some(Address::Short(PanId(0x1234), ShortAddress(0x0001)))
+        );
+        assert_eq!(frame.payload, &[0xde, 0xad]);
+    }
+
+    #[test]
+    fn decode_payload_ies() {
+        let data = [
+            0x41, 0xab, // Frame control with IEs present and sequence number suppressed
+            0x34, 0x12, 0xff, 0xff, 0x01, 0x00, // Addressing
+            0x00, 0x3f, // Header termination followed by payload IEs
+            0x01, 0x88, 0xcc, // Payload IE with 1 byte of content
+            0x00, 0xf8, // Payload termination
+            0xde, 0xad, // Payload
+        ];
+
+        let frame: Frame = data.read_with(&mut 0, FooterMode::None).unwrap();
+        assert!(frame.header.ie_present);
+        assert!(frame.header.seq_no_suppress);
+        assert_eq!(frame.header.seq, 0);
+        assert_eq!(frame.payload, &[0xde, 0xad]);
+    }
+
+    #[test]
+    fn decode_truncated_ie() {
+        let data = [
+            0x41, 0xaa, // Frame control with IEs present
+            0x05, // Sequence number
+            0x34, 0x12, 0xff, 0xff, 0x01, 0x00, // Addressing
+            0x05, 0x0d, 0xaa, 0xbb, // Header IE with 5 bytes of content, but only 2 are there
+        ];
+
+        assert!(data.read_with::<Frame>(&mut 0, FooterMode::None).is_err());
+    }
+
+    #[test]
+    fn pan_id_compression_round_trip() {
+        let short = |pan_id| Address::Short(PanId(pan_id), ShortAddress(0x5678));
+        let extended =
+            |pan_id| Address::Extended(PanId(pan_id), ExtendedAddress(0x1122334455667788));
+
+        // (destination, source, expected compression)
+        let combinations = [
+            (None, Some(short(0x1234)), false),
+            (None, Some(extended(0x1234)), false),
+            (Some(short(0x1234)), None, false),
+            (Some(extended(0x1234)), None, false),
+            (Some(short(0x1234)), Some(short(0x1234)), true),
+            (Some(short(0x1234)), Some(short(0x4321)), false),
+            (Some(short(0x1234)), Some(extended(0x1234)), true),
+            (Some(short(0x1234)), Some(extended(0x4321)), false),
+            (Some(extended(0x1234)), Some(short(0x1234)), true),
+            (Some(extended(0x1234)), Some(short(0x4321)), false),
+            (Some(extended(0x1234)), Some(extended(0x1234)), true),
+            (Some(extended(0x1234)), Some(extended(0x4321)), false),
+        ];
+
+        for (destination, source, expected) in combinations {
+            let pan_id_compress = Header::pan_id_compression(destination, source);
+            assert_eq!(pan_id_compress, expected, "{destination:?} -> {source:?}");
+
+            let frame = Frame {
+                header: Header {
+                    ie_present: false,
+                    seq_no_suppress: false,
+                    frame_type: FrameType::MacCommand,
+                    frame_pending: false,
+                    ack_request: true,
+                    pan_id_compress,
+                    version: FrameVersion::Ieee802154_2003,
+                    destination,
+                    source,
+                    seq: 0x01,
+                    auxiliary_security_header: None,
+                    time_correction: None,
+                },
+                content: FrameContent::Command(command::Command::DataRequest),
+                payload: &[],
+                footer: [0x00, 0x00],
+            };
+
+            let mut buf = [0u8; 32];
+            let mut len = 0usize;
+            buf.write_with(
+                &mut len,
+                frame.clone(),
+                &mut FrameSerDesContext::no_security(FooterMode::None),
+            )
+            .unwrap();
+
+            // The source PAN ID is only left out when compressed
+            let expected_len = frame.header.get_octet_size() + 1 - if expected { 2 } else { 0 };
+            assert_eq!(len, expected_len, "{destination:?} -> {source:?}");
+
+            let decoded: Frame = buf[..len].read_with(&mut 0, FooterMode::None).unwrap();
+            assert_eq!(
+                decoded.header, frame.header,
+                "{destination:?} -> {source:?}"
+            );
+            assert_eq!(decoded.content, frame.content);
+        }
+    }
 }
@@ -120,6 +120,7 @@
 //!             destination,
 //!             source,
 //!             auxiliary_security_header,
+//!             time_correction: None,
 //!         },
 //!         content: FrameContent::Data,
 //!         payload,
@@ -312,7 +313,7 @@ fn calculate_nonce(source_addr: u64, frame_counter: u32, sec_level: SecurityLeve
 /// Partial implementation of 7.2.1
 ///
 /// # Panics
-/// if footer_mode is not None due to currently absent implementation of explicit footers
+/// if footer_mode is Explicit due to currently absent implementation of explicit footers
 pub(crate) fn secure_frame<AEADBLKCIPH, KEYDESCLO>(
     frame: Frame<'_>,
     context: &mut SecurityContext<AEADBLKCIPH, KEYDESCLO>,
@@ -324,7 +325,8 @@ where
     KEYDESCLO: KeyDescriptorLookup<AEADBLKCIPH::KeySize>,
 {
     match footer_mode {
-        FooterMode::None => {}
+        // The FCS is calculated over the secured frame, so it doesn't change the securing
+        FooterMode::None | FooterMode::Fcs => {}
         FooterMode::Explicit => {
             // We should panic here, as having an explicit footer is not supported
             // and it is not something that can be altered at runtime in a way that affects
@@ -397,7 +399,7 @@ where
                         let aead = Ccm::<AEADBLKCIPH, $tag_size, CcmU13>::new(&key);
 
                         let auth_enc_part = match footer_mode {
-                            FooterMode::None => &mut buffer[..offset],
+                            FooterMode::None | FooterMode::Fcs => &mut buffer[..offset],
                             FooterMode::Explicit => return Err(SecurityError::NotImplemented),
                         };
 
@@ -472,7 +474,7 @@ where
 /// Currently not implemented: 7.2.3h, 7.2.3i, 7.2.3j, 7.2.3k, 7.2.3n
 ///
 /// # Panics
-/// if footer_mode is not None due to currently absent implementation of explicit footers
+/// if footer_mode is Explicit due to currently absent implementation of explicit footers
 pub(crate) fn unsecure_frame<AEADBLKCIPH, KEYDESCLO, DEVDESCLO>(
     header: &Header,
     buffer: &mut [u8],
@@ -486,7 +488,8 @@ where
     DEVDESCLO: DeviceDescriptorLookup,
 {
     match footer_mode {
-        FooterMode::None => {}
+        // The FCS is checked and cut off before the frame gets here
+        FooterMode::None | FooterMode::Fcs => {}
         FooterMode::Explicit => {
             // We should panic here, as having an explicit footer is not supported
             // and it is not something that can be altered at runtime in a way that affects
@@ -546,7 +549,7 @@ where
                     );
 
                     let data_and_tag = match footer_mode {
-                        FooterMode::None => buffer,
+                        FooterMode::None | FooterMode::Fcs => buffer,
                         FooterMode::Explicit => unimplemented!(),
                     };
 
@@ -806,6 +809,7 @@ mod tests {
                 destination,
                 source,
                 auxiliary_security_header,
+                time_correction: None,
             },
             content: FrameContent::Data,
             payload,
@@ -27,12 +27,14 @@ mod utils;
 
 pub mod beacon;
 pub mod command;
+pub mod fcs;
 pub mod frame;
 
 pub use frame::{
     DecodeError, FooterMode, Frame, FrameContent, FrameSerDesContext,
     header::{
-        Address, AddressMode, ExtendedAddress, FrameType, FrameVersion, Header, PanId, ShortAddress,
+        Address, AddressMode, ExtendedAddress, FrameType, FrameVersion, Header, PanId,
+        ShortAddress, TimeCorrection,
     },
     security,
 };